@@ -0,0 +1,214 @@
+use gbrs_core::{
+    constants::{SCREEN_HEIGHT, SCREEN_WIDTH},
+    lcd::GreyShade
+};
+
+use crate::layout::{GAME_HEIGHT, ROW_BYTES};
+
+// Some games only draw into part of their own screen - cutscenes with black
+// bars, ports of smaller playfields - and the Playdate has pixels to spare
+// either side of the full frame. With auto zoom on, we look for the rows and
+// columns around the edge that stay one flat colour, and once the content
+// has sat inside the same rectangle for a while, zoom the blit in on it.
+//
+// Zooming in is slow and deliberate (SETTLE_SECONDS, then a glide), but
+// anything drawn outside the zoomed area zooms straight back out, so nothing
+// is ever hidden for more than a frame.
+
+const SETTLE_SECONDS: usize = 2;
+// Edges that wobble by this much (HUD flicker, a sprite poking out) still
+// count as the same rectangle
+const SLOP: usize = 4;
+// Kept around the content when zoomed, so small wobbles don't zoom us out
+const MARGIN: usize = 2;
+// In Gameboy pixels, per frame
+const GLIDE_STEP: usize = 4;
+const MIN_WIDTH: usize = 64;
+const MIN_HEIGHT: usize = 56;
+// Zooming has to make things at least this much bigger (in percent) to be
+// worth doing
+const MIN_GAIN: usize = 115;
+
+// Playdate pixels available to the zoomed picture. Unlike the normal blit,
+// it's allowed over the borders.
+const OUT_WIDTH: usize = ROW_BYTES * 8;
+const OUT_HEIGHT: usize = GAME_HEIGHT;
+
+// A rectangle of the Gameboy screen, right and bottom exclusive
+#[derive(Clone, Copy, PartialEq)]
+pub struct Crop {
+    pub left: usize,
+    pub top: usize,
+    pub right: usize,
+    pub bottom: usize
+}
+
+pub const FULL: Crop = Crop {
+    left: 0,
+    top: 0,
+    right: SCREEN_WIDTH,
+    bottom: SCREEN_HEIGHT
+};
+
+impl Crop {
+    pub fn width (&self) -> usize { self.right - self.left }
+    pub fn height (&self) -> usize { self.bottom - self.top }
+
+    pub fn is_full (&self) -> bool { *self == FULL }
+
+    fn contains (&self, other: &Crop) -> bool {
+        other.left >= self.left && other.top >= self.top &&
+            other.right <= self.right && other.bottom <= self.bottom
+    }
+
+    fn union (&self, other: &Crop) -> Crop {
+        Crop {
+            left: self.left.min(other.left),
+            top: self.top.min(other.top),
+            right: self.right.max(other.right),
+            bottom: self.bottom.max(other.bottom)
+        }
+    }
+
+    fn close_to (&self, other: &Crop) -> bool {
+        let near = |a: usize, b: usize| a.max(b) - a.min(b) <= SLOP;
+        near(self.left, other.left) && near(self.top, other.top) &&
+            near(self.right, other.right) && near(self.bottom, other.bottom)
+    }
+
+    fn with_margin (&self) -> Crop {
+        Crop {
+            left: self.left.saturating_sub(MARGIN),
+            top: self.top.saturating_sub(MARGIN),
+            right: (self.right + MARGIN).min(SCREEN_WIDTH),
+            bottom: (self.bottom + MARGIN).min(SCREEN_HEIGHT)
+        }
+    }
+
+    // Size in Playdate pixels when zoomed to fill the screen, keeping
+    // square pixels
+    pub fn output_size (&self) -> (usize, usize) {
+        if self.width() * OUT_HEIGHT <= OUT_WIDTH * self.height() {
+            (self.width() * OUT_HEIGHT / self.height(), OUT_HEIGHT)
+        } else {
+            (OUT_WIDTH, self.height() * OUT_WIDTH / self.width())
+        }
+    }
+
+    fn worth_zooming_to (&self) -> bool {
+        if self.width() < MIN_WIDTH || self.height() < MIN_HEIGHT {
+            return false
+        }
+        let (width, _) = self.output_size();
+        let (full_width, _) = FULL.output_size();
+        // Pixels per Gameboy pixel, compared with the full frame's
+        width * SCREEN_WIDTH * 100 / (self.width() * full_width) >= MIN_GAIN
+    }
+}
+
+// Moves a shown edge towards the target's. Edges only glide inwards;
+// outwards they jump, so whatever was cut off comes back at once.
+fn glide_in (shown: usize, target: usize, inwards_is_up: bool) -> usize {
+    match (shown < target, inwards_is_up) {
+        (true, true) => (shown + GLIDE_STEP).min(target),
+        (false, false) => shown.saturating_sub(GLIDE_STEP).max(target),
+        _ => target
+    }
+}
+
+fn flat (shade: &GreyShade) -> bool {
+    match shade {
+        GreyShade::Black | GreyShade::White => true,
+        _ => false
+    }
+}
+
+// The smallest rectangle outside of which every row and column is one flat
+// colour. None if the whole frame is, since a blank screen (a fade, say)
+// tells us nothing about where the content is.
+fn content_rect (frame: &[GreyShade]) -> Option<Crop> {
+    let pixel = |x: usize, y: usize| &frame[y * SCREEN_WIDTH + x];
+
+    let row_is_flat = |y: usize| {
+        let first = pixel(0, y);
+        flat(first) && (1..SCREEN_WIDTH).all(|x| pixel(x, y) == first)
+    };
+    let top = (0..SCREEN_HEIGHT).find(|&y| !row_is_flat(y))?;
+    let bottom = (0..SCREEN_HEIGHT).rev().find(|&y| !row_is_flat(y))? + 1;
+
+    let column_is_flat = |x: usize| {
+        let first = pixel(x, top);
+        flat(first) && (top + 1..bottom).all(|y| pixel(x, y) == first)
+    };
+    let left = (0..SCREEN_WIDTH).find(|&x| !column_is_flat(x))?;
+    let right = (0..SCREEN_WIDTH).rev().find(|&x| !column_is_flat(x))? + 1;
+
+    Some(Crop { left, top, right, bottom })
+}
+
+pub struct AutoCrop {
+    // What's on screen right now, gliding towards target
+    shown: Crop,
+    target: Crop,
+    // Where the content has been lately, and for how many frames
+    candidate: Option<Crop>,
+    candidate_frames: usize
+}
+
+impl AutoCrop {
+    pub fn new () -> Self {
+        Self {
+            shown: FULL,
+            target: FULL,
+            candidate: None,
+            candidate_frames: 0
+        }
+    }
+
+    pub fn shown (&self) -> Crop {
+        self.shown
+    }
+
+    // Call once per update, after emulating. Returns true if the shown crop
+    // moved.
+    pub fn update (
+        &mut self,
+        finished_frame: &[GreyShade],
+        frame_rate: usize
+    ) -> bool {
+        if let Some(content) = content_rect(finished_frame) {
+            if !self.target.contains(&content) {
+                self.target = FULL;
+            }
+
+            self.candidate = match self.candidate {
+                Some(candidate) if candidate.close_to(&content) => {
+                    self.candidate_frames += 1;
+                    Some(candidate.union(&content))
+                },
+                _ => {
+                    self.candidate_frames = 1;
+                    Some(content)
+                }
+            };
+
+            let settled = self.candidate_frames >= SETTLE_SECONDS * frame_rate;
+            if let (true, Some(candidate)) = (settled, self.candidate) {
+                let zoomed = candidate.with_margin();
+                if self.target.is_full() && zoomed.worth_zooming_to() {
+                    self.target = zoomed;
+                }
+            }
+        }
+
+        let before = self.shown;
+        let (shown, target) = (&self.shown, &self.target);
+        self.shown = Crop {
+            left: glide_in(shown.left, target.left, true),
+            top: glide_in(shown.top, target.top, true),
+            right: glide_in(shown.right, target.right, false),
+            bottom: glide_in(shown.bottom, target.bottom, false)
+        };
+        self.shown != before
+    }
+}
@@ -0,0 +1,804 @@
+use alloc::vec::Vec;
+use core::ops::RangeInclusive;
+use crankstart_sys::{LCD_COLUMNS, LCD_ROWS};
+use gbrs_core::{
+    constants::{SCREEN_HEIGHT, SCREEN_WIDTH},
+    lcd::GreyShade
+};
+
+use crate::autocrop::{self, Crop};
+use crate::dither::{self, Ditherer};
+use crate::framebuffer;
+use crate::layout::{
+    GAME_HEIGHT, GAME_WIDTH, ROW_BYTES, ROW_STRIDE, SIDEWAYS_HEIGHT,
+    SIDEWAYS_LEFT_BYTE, SIDEWAYS_WIDTH, START_BYTE
+};
+
+// Nothing in here talks to crankstart, so it can be run against plain
+// buffers as well as the real Playdate framebuffer.
+
+// Decides whether the Playdate pixel at (x, y) of the stretched picture is
+// lit for a given shade. The blits below do the same a row at a time.
+#[inline(always)]
+pub fn shade_is_white (
+    ditherer: &Ditherer,
+    shade: &GreyShade,
+    x: usize,
+    y: usize
+) -> bool {
+    level_is_white(ditherer, shade_value(shade), x, y)
+}
+
+// The same, for a shade_value (0 is black, 3 white)
+#[inline(always)]
+pub fn level_is_white (
+    ditherer: &Ditherer,
+    level: u8,
+    x: usize,
+    y: usize
+) -> bool {
+    let row = ditherer.row(y, GAMEBOY_Y[y] as usize);
+    dither::is_white(row, level, ditherer.columns()[x])
+}
+
+// With frame_blend on, each pixel is shown half way between this frame and
+// the one before, like the original LCD's ghosting, so sprites in the greys
+// don't shimmer as they move. When half way falls between two levels, it
+// goes with the newer one, so the picture never lags a whole frame behind.
+#[inline(always)]
+fn blended (before: u8, now: u8) -> u8 {
+    let sum = before + now;
+    if now > before { (sum + 1) / 2 } else { sum / 2 }
+}
+
+// The level the pixel at index of a finished frame is drawn at: blended
+// with previous (the frame before, as shade_values) if there is one, then
+// moved shift steps lighter (or darker, if negative)
+#[inline(always)]
+fn pixel_level (
+    finished_frame: &[GreyShade],
+    previous: Option<&[u8]>,
+    index: usize,
+    shift: i8
+) -> u8 {
+    let level = shade_value(&finished_frame[index]);
+    let level = match previous {
+        Some(previous) => blended(previous[index], level),
+        None => level
+    };
+    (level as i8 + shift).max(0).min(3) as u8
+}
+
+pub fn shade_value (shade: &GreyShade) -> u8 {
+    match shade {
+        GreyShade::Black => 0,
+        GreyShade::DarkGrey => 1,
+        GreyShade::LightGrey => 2,
+        GreyShade::White => 3
+    }
+}
+
+// Compares a finished frame against last_frame (our copy of the previous
+// one) and brings the copy up to date. A frame that hasn't changed doesn't
+// need blitting again, which is the cheapest frame there is.
+//
+// changed_rows gets one entry per Gameboy row, set if anything in that row
+// is different, so the rest of the screen can be left alone.
+pub fn frame_changed (
+    finished_frame: &[GreyShade],
+    last_frame: &mut Vec<u8>,
+    changed_rows: &mut Vec<bool>
+) -> bool {
+    if last_frame.len() != finished_frame.len() {
+        // Nothing real is 0xFF, so the first frame always counts as changed
+        last_frame.clear();
+        last_frame.resize(finished_frame.len(), 0xFF);
+    }
+    changed_rows.clear();
+
+    let mut changed = false;
+    let rows = finished_frame.chunks(SCREEN_WIDTH)
+        .zip(last_frame.chunks_mut(SCREEN_WIDTH));
+    for (row, last_row) in rows {
+        let mut row_changed = false;
+        for (shade, last) in row.iter().zip(last_row.iter_mut()) {
+            let value = shade_value(shade);
+            if *last != value {
+                *last = value;
+                row_changed = true;
+            }
+        }
+        changed_rows.push(row_changed);
+        changed |= row_changed;
+    }
+    changed
+}
+
+// Sets grey_rows the same way, for the rows with a pixel that comes out as
+// one of the two greys (after blending and shift), which temporal dither
+// has to redraw every refresh. Returns whether there were any.
+pub fn grey_rows (
+    finished_frame: &[GreyShade],
+    previous: Option<&[u8]>,
+    shift: i8,
+    grey_rows: &mut Vec<bool>
+) -> bool {
+    grey_rows.clear();
+    let mut any = false;
+    for row_start in (0..finished_frame.len()).step_by(SCREEN_WIDTH) {
+        let grey = (row_start..row_start + SCREEN_WIDTH).any(|index| {
+            let level = pixel_level(finished_frame, previous, index, shift);
+            level == 1 || level == 2
+        });
+        grey_rows.push(grey);
+        any |= grey;
+    }
+    any
+}
+
+// Frame blending shows each frame mixed with the one before, so a row that
+// changed last frame is still changing on screen this frame. Adds those to
+// changed_rows, and leaves this frame's in last_changed for next time.
+// Returns whether any were added.
+pub fn carry_changed_rows (
+    changed_rows: &mut Vec<bool>,
+    last_changed: &mut Vec<bool>
+) -> bool {
+    last_changed.resize(changed_rows.len(), false);
+    let mut any = false;
+    let rows = changed_rows.iter_mut().zip(last_changed.iter_mut());
+    for (changed, last) in rows {
+        let now = *changed;
+        any |= *last;
+        *changed |= *last;
+        *last = now;
+    }
+    any
+}
+
+// The Gameboy row or column shown at Playdate row or column i, on the
+// scaled-up screen. This used to divide by SCALE_FACTOR and floor, which
+// comes out the same: SCALE_FACTOR rounds down a touch from 5/3, so the
+// float answer is never below the exact one, and never by enough to reach
+// the next whole number on a screen this size.
+const fn gameboy_coord (i: usize) -> usize {
+    i * SCREEN_HEIGHT / GAME_HEIGHT
+}
+
+// gameboy_coord for every Playdate column and row, so the blit only has to
+// look them up
+pub const GAMEBOY_X: [u8; GAME_WIDTH] = {
+    let mut table = [0; GAME_WIDTH];
+    let mut x = 0;
+    while x < GAME_WIDTH {
+        table[x] = gameboy_coord(x) as u8;
+        x += 1;
+    }
+    table
+};
+const GAMEBOY_Y: [u8; GAME_HEIGHT] = {
+    let mut table = [0; GAME_HEIGHT];
+    let mut y = 0;
+    while y < GAME_HEIGHT {
+        table[y] = gameboy_coord(y) as u8;
+        y += 1;
+    }
+    table
+};
+
+// Where each Playdate row's Gameboy row starts in a finished frame
+const ROW_OFFSETS: [u16; GAME_HEIGHT] = {
+    let mut table = [0; GAME_HEIGHT];
+    let mut y = 0;
+    while y < GAME_HEIGHT {
+        table[y] = (GAMEBOY_Y[y] as usize * SCREEN_WIDTH) as u16;
+        y += 1;
+    }
+    table
+};
+
+const _: () = assert!(GAMEBOY_X[GAME_WIDTH - 1] as usize == SCREEN_WIDTH - 1);
+const _: () = assert!(GAMEBOY_Y[GAME_HEIGHT - 1] as usize == SCREEN_HEIGHT - 1);
+
+// The same for the sideways picture: the Gameboy column along each Playdate
+// row, counting up from the bottom, and the Gameboy row down each column
+// of the picture
+const SIDEWAYS_X: [u8; GAME_HEIGHT] = {
+    let mut table = [0; GAME_HEIGHT];
+    let mut y = 0;
+    while y < GAME_HEIGHT {
+        let across = GAME_HEIGHT - 1 - y;
+        table[y] = (across * SCREEN_WIDTH / SIDEWAYS_WIDTH) as u8;
+        y += 1;
+    }
+    table
+};
+const SIDEWAYS_Y: [u8; SIDEWAYS_HEIGHT] = {
+    let mut table = [0; SIDEWAYS_HEIGHT];
+    let mut down = 0;
+    while down < SIDEWAYS_HEIGHT {
+        table[down] = (down * SCREEN_HEIGHT / SIDEWAYS_HEIGHT) as u8;
+        down += 1;
+    }
+    table
+};
+
+// Index into a finished frame of the Gameboy pixel that's shown at (x, y)
+// on the scaled-up screen
+#[inline(always)]
+pub fn frame_index (x: usize, y: usize) -> usize {
+    ROW_OFFSETS[y] as usize + GAMEBOY_X[x] as usize
+}
+
+// Sets runs to the runs of Playdate rows showing a changed Gameboy row,
+// from frame_changed, ready for mark_updated_rows. Only right for the
+// uncropped picture, since the cropped one stretches rows differently.
+pub fn changed_row_runs (
+    changed_rows: &[bool],
+    runs: &mut Vec<RangeInclusive<i32>>
+) {
+    runs.clear();
+    let mut start = None;
+
+    for y in 0..GAME_HEIGHT {
+        let changed = changed_rows
+            .get(GAMEBOY_Y[y] as usize)
+            .copied()
+            .unwrap_or(true);
+        match (changed, start) {
+            (true, None) => start = Some(y),
+            (false, Some(first)) => {
+                runs.push(first as i32..=y as i32 - 1);
+                start = None;
+            },
+            _ => {}
+        }
+    }
+    if let Some(first) = start {
+        runs.push(first as i32..=GAME_HEIGHT as i32 - 1);
+    }
+}
+
+// Draws a finished Gameboy frame into a Playdate-layout 1-bit framebuffer.
+// previous is the frame before, for frame blending, and shift moves every
+// shade that many steps lighter (see contrast.rs).
+pub fn draw_frame (
+    finished_frame: &[GreyShade],
+    previous: Option<&[u8]>,
+    framebuffer: &mut [u8],
+    ditherer: &Ditherer,
+    shift: i8
+) {
+    let columns = ditherer.columns();
+    // Rows at a time, because that's how the framebuffer is laid out in
+    // memory. Each byte of 8 pixels is put together in a local and written
+    // once, and the last GAME_WIDTH % 8 columns are left off, as they
+    // always have been, so the game never spills into a border byte.
+    for y in 0..GAME_HEIGHT {
+        let gameboy_y = GAMEBOY_Y[y] as usize;
+        let row_start = ROW_OFFSETS[y] as usize;
+        let masks = ditherer.row(y, gameboy_y);
+
+        for byte in 0..GAME_WIDTH / 8 {
+            // The screen_byte is already black by default
+            let mut screen_byte: u8 = 0x00;
+            for bit in 0..8 {
+                let x = byte * 8 + bit;
+                let index = row_start + GAMEBOY_X[x] as usize;
+                let level =
+                    pixel_level(finished_frame, previous, index, shift);
+                if dither::is_white(masks, level, columns[x]) {
+                    screen_byte |= 1 << (7 - bit);
+                }
+            }
+
+            // START_BYTE horizontally centers(-ish) the screen
+            framebuffer::draw_byte_at(
+                framebuffer, ROW_STRIDE, START_BYTE + byte, y, screen_byte
+            );
+        }
+    }
+}
+
+// Where each Playdate column and row of a scaled picture comes from, for
+// draw_frame_scaled. They're only worked out again when the crop or the
+// size changes (a new display mode, or auto zoom moving), so the blit
+// looks them up rather than dividing for every pixel.
+pub struct BlitTables {
+    crop: Crop,
+    size: (usize, usize),
+    // The picture, in whole bytes across for the same reason as START_BYTE
+    left_byte: usize,
+    width_bytes: usize,
+    top: usize,
+    height: usize,
+    // The Gameboy column for each Playdate column of the picture, and the
+    // Gameboy row for each row. Outside it they're 0 and never looked at.
+    columns: [u8; LCD_COLUMNS as usize],
+    rows: [u8; LCD_ROWS as usize]
+}
+
+impl BlitTables {
+    pub fn new () -> Self {
+        let mut tables = Self {
+            crop: autocrop::FULL,
+            size: (0, 0),
+            left_byte: 0,
+            width_bytes: 0,
+            top: 0,
+            height: 0,
+            columns: [0; LCD_COLUMNS as usize],
+            rows: [0; LCD_ROWS as usize]
+        };
+        tables.build();
+        tables
+    }
+
+    // Blows crop up to size Playdate pixels, in the middle of the screen.
+    // It can stretch one way more than the other. A size bigger than the
+    // screen is cut down to it.
+    pub fn fit (&mut self, crop: &Crop, size: (usize, usize)) {
+        if self.crop == *crop && self.size == size { return }
+        self.crop = *crop;
+        self.size = size;
+        self.build();
+    }
+
+    fn build (&mut self) {
+        let crop = self.crop;
+        let (width, height) = self.size;
+        let height = height.min(GAME_HEIGHT);
+        self.width_bytes = (width / 8).min(ROW_BYTES);
+        let width = self.width_bytes * 8;
+        self.left_byte = (ROW_BYTES - self.width_bytes) / 2;
+        self.top = (GAME_HEIGHT - height) / 2;
+        self.height = height;
+
+        self.columns = [0; LCD_COLUMNS as usize];
+        let left = self.left_byte * 8;
+        for x in left..left + width {
+            self.columns[x] =
+                (crop.left + (x - left) * crop.width() / width) as u8;
+        }
+        self.rows = [0; LCD_ROWS as usize];
+        for y in self.top..self.top + height {
+            self.rows[y] =
+                (crop.top + (y - self.top) * crop.height() / height) as u8;
+        }
+    }
+}
+
+// Draws a picture laid out by tables, with everything around it blacked
+// out. That's the whole screen, borders and all, for the zoomed and
+// resized display modes.
+pub fn draw_frame_scaled (
+    finished_frame: &[GreyShade],
+    previous: Option<&[u8]>,
+    framebuffer: &mut [u8],
+    tables: &BlitTables,
+    ditherer: &Ditherer,
+    shift: i8
+) {
+    let first_byte = tables.left_byte;
+    let last_byte = first_byte + tables.width_bytes;
+
+    for y in 0..GAME_HEIGHT {
+        let inside_y = y >= tables.top && y < tables.top + tables.height;
+        let gameboy_y = tables.rows[y] as usize;
+        let row_start = gameboy_y * SCREEN_WIDTH;
+        let masks = ditherer.row(y, gameboy_y);
+
+        for byte_x in 0..ROW_BYTES {
+            let inside = inside_y && byte_x >= first_byte && byte_x < last_byte;
+            let mut screen_byte: u8 = 0x00;
+
+            if inside {
+                for bit in 0..8 {
+                    let x = byte_x * 8 + bit;
+                    let gameboy_x = tables.columns[x] as usize;
+                    let index = row_start + gameboy_x;
+                    let level =
+                        pixel_level(finished_frame, previous, index, shift);
+                    let column = ditherer.column(x, gameboy_x);
+                    if dither::is_white(masks, level, column) {
+                        screen_byte |= 1 << (7 - bit);
+                    }
+                }
+            }
+
+            framebuffer::draw_byte_at(
+                framebuffer, ROW_STRIDE, byte_x, y, screen_byte
+            );
+        }
+    }
+}
+
+// Draws a finished frame a quarter turn round, for the sideways display
+// mode. Each Playdate row shows one Gameboy column, the first at the
+// bottom, and each Playdate column one of its rows. The dither goes by
+// across and down as the picture's seen, so it looks the same as it does
+// the normal way up. Everything around the picture is blacked out.
+pub fn draw_frame_sideways (
+    finished_frame: &[GreyShade],
+    previous: Option<&[u8]>,
+    framebuffer: &mut [u8],
+    ditherer: &Ditherer,
+    shift: i8
+) {
+    let first_byte = SIDEWAYS_LEFT_BYTE;
+    let last_byte = first_byte + SIDEWAYS_HEIGHT / 8;
+
+    for y in 0..GAME_HEIGHT {
+        let across = GAME_HEIGHT - 1 - y;
+        let gameboy_x = SIDEWAYS_X[y] as usize;
+        let column = ditherer.column(across, gameboy_x);
+
+        for byte_x in 0..ROW_BYTES {
+            let mut screen_byte: u8 = 0x00;
+
+            if byte_x >= first_byte && byte_x < last_byte {
+                for bit in 0..8 {
+                    let down = (byte_x - first_byte) * 8 + bit;
+                    let gameboy_y = SIDEWAYS_Y[down] as usize;
+                    let index = gameboy_y * SCREEN_WIDTH + gameboy_x;
+                    let level =
+                        pixel_level(finished_frame, previous, index, shift);
+                    let masks = ditherer.row(down, gameboy_y);
+                    if dither::is_white(masks, level, column) {
+                        screen_byte |= 1 << (7 - bit);
+                    }
+                }
+            }
+
+            framebuffer::draw_byte_at(
+                framebuffer, ROW_STRIDE, byte_x, y, screen_byte
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use alloc::vec;
+
+    const DITHER: dither::Dither = dither::Dither::CrossHatch;
+
+    fn blank_framebuffer () -> Vec<u8> {
+        vec![0x55; ROW_STRIDE * LCD_ROWS as usize]
+    }
+
+    fn frame_of (shade: GreyShade) -> Vec<GreyShade> {
+        vec![shade; SCREEN_WIDTH * SCREEN_HEIGHT]
+    }
+
+    #[test]
+    fn packs_a_known_row () {
+        // Gameboy column 0 black and the rest white. Scaled up, column 0
+        // is Playdate columns 0 and 1, so they're the first two bits.
+        let mut frame = frame_of(GreyShade::White);
+        frame[0] = GreyShade::Black;
+        let mut framebuffer = blank_framebuffer();
+        draw_frame(
+            &frame[..], None, &mut framebuffer[..], DITHER.ditherer(), 0
+        );
+
+        let row = &framebuffer[..ROW_STRIDE];
+        assert_eq!(row[START_BYTE], 0b0011_1111);
+        for byte in START_BYTE + 1..START_BYTE + GAME_WIDTH / 8 {
+            assert_eq!(row[byte], 0xFF, "byte {}", byte);
+        }
+        // Border bytes aren't touched
+        assert_eq!(row[START_BYTE - 1], 0x55);
+        assert_eq!(row[START_BYTE + GAME_WIDTH / 8], 0x55);
+    }
+
+    #[test]
+    fn packs_alternating_columns () {
+        // Gameboy columns 0, 1, 2 are Playdate columns 0-1, 2-3 and 4,
+        // so black, white, black comes out 00 11 0 and carries on that way
+        let mut frame = frame_of(GreyShade::White);
+        for (x, shade) in frame[..SCREEN_WIDTH].iter_mut().enumerate() {
+            if x % 2 == 0 { *shade = GreyShade::Black }
+        }
+        let mut framebuffer = blank_framebuffer();
+        draw_frame(
+            &frame[..], None, &mut framebuffer[..], DITHER.ditherer(), 0
+        );
+
+        let mut expected = 0;
+        for bit in 0..8 {
+            if GAMEBOY_X[bit] % 2 == 1 { expected |= 1 << (7 - bit) }
+        }
+        assert_eq!(expected, 0b0011_0110);
+        assert_eq!(framebuffer[START_BYTE], expected);
+    }
+
+    // A busy frame with all four shades in it, no two rows alike. The
+    // preview's tests use it too.
+    pub fn synthetic_frame (seed: usize) -> Vec<GreyShade> {
+        let shades = [
+            GreyShade::Black, GreyShade::DarkGrey,
+            GreyShade::LightGrey, GreyShade::White
+        ];
+        (0..SCREEN_WIDTH * SCREEN_HEIGHT)
+            .map(|i| shades[(i * 7 + i / 13 + seed) % 4])
+            .collect()
+    }
+
+    // How the blit used to work: one read, mask and write of the
+    // framebuffer for every Playdate pixel
+    fn draw_frame_per_pixel (
+        finished_frame: &[GreyShade],
+        previous: Option<&[u8]>,
+        framebuffer: &mut [u8],
+        ditherer: &Ditherer,
+        shift: i8
+    ) {
+        for y in 0..GAME_HEIGHT {
+            for x in 0..GAME_WIDTH / 8 * 8 {
+                let index = frame_index(x, y);
+                let level =
+                    pixel_level(finished_frame, previous, index, shift);
+                let byte = y * ROW_STRIDE + START_BYTE + x / 8;
+                let mask = 1 << (7 - x % 8);
+                if level_is_white(ditherer, level, x, y) {
+                    framebuffer[byte] |= mask;
+                } else {
+                    framebuffer[byte] &= !mask;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn byte_blit_matches_the_per_pixel_one () {
+        let frame = synthetic_frame(0);
+        let mut previous = vec![];
+        let mut changed_rows = vec![];
+        let before = synthetic_frame(1);
+        frame_changed(&before[..], &mut previous, &mut changed_rows);
+
+        for dither in dither::DITHERS {
+            for ditherer in dither.ditherers() {
+                for shift in -1..=1 {
+                    for previous in [None, Some(&previous[..])].iter() {
+                        let mut by_byte = blank_framebuffer();
+                        let mut by_pixel = blank_framebuffer();
+                        draw_frame(
+                            &frame[..], *previous, &mut by_byte[..],
+                            ditherer, shift
+                        );
+                        draw_frame_per_pixel(
+                            &frame[..], *previous, &mut by_pixel[..],
+                            ditherer, shift
+                        );
+                        assert!(by_byte == by_pixel, "shift {}", shift);
+                    }
+                }
+            }
+        }
+    }
+
+    // Golden frames: testdata/golden/frame.bin (a test card, one shade
+    // value per Gameboy pixel) drawn through each display configuration
+    // and compared against the framebuffer checked in beside it. After a
+    // change to the picture that's meant, make them again with
+    //
+    //   PLAYBOY_BLESS=1 cargo test golden
+    //
+    // and look over what changed before checking them in.
+    fn golden_path (name: &str) -> std::string::String {
+        std::format!(
+            "{}/testdata/golden/{}", env!("CARGO_MANIFEST_DIR"), name
+        )
+    }
+
+    fn golden_frame () -> Vec<GreyShade> {
+        let path = golden_path("frame.bin");
+        let levels = std::fs::read(&path[..]).expect("reading frame.bin");
+        assert_eq!(levels.len(), SCREEN_WIDTH * SCREEN_HEIGHT);
+        levels
+            .iter()
+            .map(|level| match level {
+                0 => GreyShade::Black,
+                1 => GreyShade::DarkGrey,
+                2 => GreyShade::LightGrey,
+                _ => GreyShade::White
+            })
+            .collect()
+    }
+
+    fn check_golden (name: &str, framebuffer: &[u8]) {
+        let path = golden_path(name);
+        if std::env::var("PLAYBOY_BLESS").is_ok() {
+            std::fs::write(&path[..], framebuffer).expect("writing golden");
+            return
+        }
+        let golden = std::fs::read(&path[..])
+            .unwrap_or_else(|e| panic!("reading {}: {}", path, e));
+        assert_eq!(golden.len(), framebuffer.len(), "{} size", name);
+
+        let mut differing = 0;
+        let mut first = None;
+        for (index, (want, got)) in golden.iter().zip(framebuffer).enumerate()
+        {
+            let bits = want ^ got;
+            if bits == 0 { continue }
+            differing += bits.count_ones();
+            if first.is_none() {
+                let bit = bits.leading_zeros() as usize;
+                let x = index % ROW_STRIDE * 8 + bit;
+                first = Some((x, index / ROW_STRIDE));
+            }
+        }
+        if let Some((x, y)) = first {
+            panic!(
+                "{}: {} pixels differ, the first at ({}, {})",
+                name, differing, x, y
+            );
+        }
+    }
+
+    #[test]
+    fn golden_frames () {
+        let frame = golden_frame();
+        let mut previous = vec![];
+        let mut changed_rows = vec![];
+        frame_changed(&frame[..], &mut previous, &mut changed_rows);
+
+        for dither in dither::DITHERS {
+            let ditherer = dither.ditherer();
+            let mut framebuffer = blank_framebuffer();
+            draw_frame(&frame[..], None, &mut framebuffer[..], ditherer, 0);
+            check_golden(
+                &std::format!("normal_{}.bin", dither.name())[..],
+                &framebuffer[..]
+            );
+        }
+
+        let ditherer = DITHER.ditherer();
+        let mut framebuffer = blank_framebuffer();
+        draw_frame(&frame[..], None, &mut framebuffer[..], ditherer, 1);
+        check_golden("normal_lighter.bin", &framebuffer[..]);
+
+        // Blending with itself changes nothing
+        let mut framebuffer = blank_framebuffer();
+        draw_frame(
+            &frame[..], Some(&previous[..]), &mut framebuffer[..], ditherer, 0
+        );
+        check_golden("normal_cross_hatch.bin", &framebuffer[..]);
+
+        let mut tables = BlitTables::new();
+        tables.fit(&autocrop::FULL, (ROW_BYTES * 8, GAME_HEIGHT));
+        let mut framebuffer = blank_framebuffer();
+        draw_frame_scaled(
+            &frame[..], None, &mut framebuffer[..], &tables, ditherer, 0
+        );
+        check_golden("full_width.bin", &framebuffer[..]);
+
+        let crop = autocrop::Crop {
+            left: 40, top: 36, right: 120, bottom: 108
+        };
+        tables.fit(&crop, (GAME_WIDTH, GAME_HEIGHT));
+        let mut framebuffer = blank_framebuffer();
+        draw_frame_scaled(
+            &frame[..], None, &mut framebuffer[..], &tables, ditherer, 0
+        );
+        check_golden("zoomed.bin", &framebuffer[..]);
+
+        let mut framebuffer = blank_framebuffer();
+        draw_frame_sideways(
+            &frame[..], None, &mut framebuffer[..], ditherer, 0
+        );
+        check_golden("sideways.bin", &framebuffer[..]);
+    }
+
+    // The tables stand in for dividing by SCALE_FACTOR and flooring, which
+    // is what the blit used to do for every pixel
+    #[test]
+    fn tables_match_the_float_maths () {
+        use crate::layout::SCALE_FACTOR;
+        let float_coord = |i: usize| (i as f32 / SCALE_FACTOR).floor() as u8;
+        for x in 0..GAME_WIDTH {
+            assert_eq!(GAMEBOY_X[x], float_coord(x), "x {}", x);
+        }
+        for y in 0..GAME_HEIGHT {
+            assert_eq!(GAMEBOY_Y[y], float_coord(y), "y {}", y);
+            let offset = float_coord(y) as usize * SCREEN_WIDTH;
+            assert_eq!(ROW_OFFSETS[y] as usize, offset, "y {}", y);
+        }
+    }
+
+    #[test]
+    fn frame_index_matches_the_float_maths () {
+        use crate::layout::SCALE_FACTOR;
+        for y in 0..GAME_HEIGHT {
+            for x in 0..GAME_WIDTH {
+                let gameboy_x = (x as f32 / SCALE_FACTOR).floor() as usize;
+                let gameboy_y = (y as f32 / SCALE_FACTOR).floor() as usize;
+                let expected = gameboy_y * SCREEN_WIDTH + gameboy_x;
+                assert_eq!(frame_index(x, y), expected, "({}, {})", x, y);
+            }
+        }
+    }
+
+    // Which bytes of each row a blit leaves alone, over a framebuffer of
+    // 0x55 that's exactly the Playdate's size. A write past its end or
+    // past a row's stride fails draw_byte_at's debug_assert.
+    fn untouched_bytes (draw: impl Fn(&mut [u8])) -> Vec<Vec<usize>> {
+        let mut framebuffer = blank_framebuffer();
+        draw(&mut framebuffer[..]);
+        framebuffer
+            .chunks(ROW_STRIDE)
+            .map(|row| (0..ROW_STRIDE).filter(|x| row[*x] == 0x55).collect())
+            .collect()
+    }
+
+    #[test]
+    fn blits_stay_inside_their_rows () {
+        let frame = frame_of(GreyShade::White);
+        let ditherer = DITHER.ditherer();
+        let normal = untouched_bytes(|framebuffer| {
+            draw_frame(&frame[..], None, framebuffer, ditherer, 0)
+        });
+        let last_byte = START_BYTE + GAME_WIDTH / 8;
+        let drawn: Vec<usize> = (START_BYTE..last_byte).collect();
+        assert!(last_byte <= ROW_BYTES);
+        // Down to the last row of the screen, and no further
+        assert_eq!(normal.len(), LCD_ROWS as usize);
+        for row in normal.iter() {
+            assert!(drawn.iter().all(|x| !row.contains(x)));
+            assert_eq!(row.len(), ROW_STRIDE - drawn.len());
+        }
+
+        let mut tables = BlitTables::new();
+        tables.fit(&autocrop::FULL, (1000, 1000));
+        let scaled = untouched_bytes(|framebuffer| {
+            draw_frame_scaled(
+                &frame[..], None, framebuffer, &tables, ditherer, 0
+            )
+        });
+        let sideways = untouched_bytes(|framebuffer| {
+            draw_frame_sideways(&frame[..], None, framebuffer, ditherer, 0)
+        });
+        // These black out the whole screen, but not the padding past it
+        let padding: Vec<usize> = (ROW_BYTES..ROW_STRIDE).collect();
+        for row in scaled.iter().chain(sideways.iter()) {
+            assert_eq!(row, &padding);
+        }
+    }
+
+    #[test]
+    fn frame_index_covers_the_frame () {
+        assert_eq!(frame_index(0, 0), 0);
+        let last = frame_index(GAME_WIDTH - 1, GAME_HEIGHT - 1);
+        assert_eq!(last, SCREEN_WIDTH * SCREEN_HEIGHT - 1);
+    }
+
+    #[test]
+    fn oversized_areas_are_cut_down () {
+        let mut tables = BlitTables::new();
+        tables.fit(&autocrop::FULL, (1000, 1000));
+        assert_eq!(tables.left_byte, 0);
+        assert_eq!(tables.width_bytes, ROW_BYTES);
+        assert_eq!(tables.top, 0);
+        assert_eq!(tables.height, GAME_HEIGHT);
+        assert_eq!(tables.rows[GAME_HEIGHT - 1] as usize, SCREEN_HEIGHT - 1);
+        let last_column = tables.columns[ROW_BYTES * 8 - 1] as usize;
+        assert_eq!(last_column, SCREEN_WIDTH - 1);
+    }
+
+    #[test]
+    fn tables_centre_the_picture () {
+        let mut tables = BlitTables::new();
+        tables.fit(&autocrop::FULL, (SCREEN_WIDTH * 2, SCREEN_HEIGHT));
+        assert_eq!(tables.width_bytes, SCREEN_WIDTH * 2 / 8);
+        assert_eq!(tables.left_byte, (ROW_BYTES - tables.width_bytes) / 2);
+        assert_eq!(tables.top, (GAME_HEIGHT - SCREEN_HEIGHT) / 2);
+        // 2:1 across and 1:1 down
+        let left = tables.left_byte * 8;
+        assert_eq!(tables.columns[left + 3], 1);
+        assert_eq!(tables.rows[tables.top + 3], 3);
+    }
+}
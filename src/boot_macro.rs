@@ -0,0 +1,192 @@
+use alloc::{format, string::String, vec::Vec};
+use anyhow::Error;
+use crankstart::system::System;
+use gbrs_core::cpu::Cpu;
+
+use crate::storage;
+
+// A boot macro is the input from a cold boot up to wherever you stopped
+// recording (usually the title menu), one byte of buttons per emulated
+// frame. When the game boots, it's played back as fast as we can go, which
+// gets you past unskippable logos and intros. Pressing anything hands the
+// game back straight away.
+//
+// The file is MAGIC followed by the bytes, bit 0 to 7 being A, B, Up,
+// Down, Left, Right, Start and Select.
+const MAGIC: &[u8] = b"PBMC";
+
+pub fn boot_macro_path (game_name: &str) -> String {
+    let filename = format!("{}.boot.macro", game_name);
+    storage::in_dir(storage::SYSTEM_DIR, &filename[..])
+}
+
+// The Gameboy's buttons as they're set for this frame
+pub fn read_inputs (cpu: &Cpu) -> u8 {
+    let joypad = &cpu.mem.joypad;
+    let buttons = [
+        joypad.a_pressed,
+        joypad.b_pressed,
+        joypad.up_pressed,
+        joypad.down_pressed,
+        joypad.left_pressed,
+        joypad.right_pressed,
+        joypad.start_pressed,
+        joypad.select_pressed
+    ];
+    buttons
+        .iter()
+        .enumerate()
+        .fold(0, |bits, (i, &pressed)| bits | (pressed as u8) << i)
+}
+
+pub fn set_inputs (cpu: &mut Cpu, bits: u8) {
+    let joypad = &mut cpu.mem.joypad;
+    let bit = |i: u8| bits & (1 << i) != 0;
+    joypad.a_pressed = bit(0);
+    joypad.b_pressed = bit(1);
+    joypad.up_pressed = bit(2);
+    joypad.down_pressed = bit(3);
+    joypad.left_pressed = bit(4);
+    joypad.right_pressed = bit(5);
+    joypad.start_pressed = bit(6);
+    joypad.select_pressed = bit(7);
+}
+
+// IF, and the joypad's bit in it
+const INTERRUPT_FLAGS: u16 = 0xFF0F;
+const JOYPAD_INTERRUPT: u8 = 1 << 4;
+
+// Call just before emulating a frame, with read_inputs from the frame
+// before. The real joypad interrupt fires when a button goes down, and
+// games waiting in HALT or STOP for a press won't wake up without it.
+// Buttons that stay held don't fire it again.
+pub fn raise_joypad_interrupt (cpu: &mut Cpu, last_inputs: u8) {
+    let flags = cpu.mem.read(INTERRUPT_FLAGS);
+    let raised = with_joypad_interrupt(flags, read_inputs(cpu), last_inputs);
+    if raised != flags { cpu.mem.write(INTERRUPT_FLAGS, raised) }
+}
+
+// IF as it should be after a frame going from last_inputs to inputs
+fn with_joypad_interrupt (flags: u8, inputs: u8, last_inputs: u8) -> u8 {
+    let newly_pressed = inputs & !last_inputs;
+    if newly_pressed == 0 { flags } else { flags | JOYPAD_INTERRUPT }
+}
+
+pub fn save (game_name: &str, inputs: &[u8]) -> Result<(), Error> {
+    let mut data = Vec::from(MAGIC);
+    data.extend_from_slice(inputs);
+    storage::write_file(&boot_macro_path(game_name)[..], &data[..])
+}
+
+pub struct BootMacro {
+    inputs: Vec<u8>,
+    position: usize
+}
+
+impl BootMacro {
+    // None if the game doesn't have one
+    pub fn load (game_name: &str) -> Option<Self> {
+        let path = &boot_macro_path(game_name)[..];
+        let data = storage::read_file(path).ok()?;
+
+        if !data.starts_with(MAGIC) {
+            log!("Ignoring {}, it isn't a boot macro", path);
+            return None
+        }
+        Some(Self {
+            inputs: Vec::from(&data[MAGIC.len()..]),
+            position: 0
+        })
+    }
+
+    // Emulates as many frames of the macro as fit in budget_ms. Only the
+    // last one gets drawn, so the faster this goes the less you see of it.
+    // Returns true once it's all been played.
+    pub fn play (
+        &mut self,
+        cpu: &mut Cpu,
+        budget_ms: usize
+    ) -> Result<bool, Error> {
+        let system = System::get();
+        let started = system.get_current_time_milliseconds()?;
+
+        while self.position < self.inputs.len() {
+            let last_inputs = match self.position {
+                0 => 0,
+                position => self.inputs[position - 1]
+            };
+            set_inputs(cpu, self.inputs[self.position]);
+            raise_joypad_interrupt(cpu, last_inputs);
+            cpu.step_one_frame();
+            self.position += 1;
+
+            let now = system.get_current_time_milliseconds()?;
+            if now.wrapping_sub(started) >= budget_ms { break }
+        }
+        Ok(self.position >= self.inputs.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: u8 = 1 << 0;
+    const START: u8 = 1 << 6;
+    const SELECT: u8 = 1 << 7;
+
+    // IF after each frame of inputs, starting from nothing held and the
+    // game clearing the flag between frames, the way its handler would
+    fn raised_per_frame (inputs: &[u8]) -> Vec<bool> {
+        let mut last_inputs = 0;
+        inputs
+            .iter()
+            .map(|&inputs| {
+                let flags = with_joypad_interrupt(0, inputs, last_inputs);
+                last_inputs = inputs;
+                flags & JOYPAD_INTERRUPT != 0
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fires_on_a_press_but_not_while_held () {
+        assert_eq!(
+            raised_per_frame(&[0, A, A, A, 0, A]),
+            [false, true, false, false, false, true]
+        );
+    }
+
+    #[test]
+    fn letting_go_does_nothing () {
+        assert_eq!(
+            raised_per_frame(&[A | START, START, 0]),
+            [true, false, false]
+        );
+    }
+
+    #[test]
+    fn another_button_going_down_fires_again () {
+        // Like the crank's Select arriving with Start still held
+        assert_eq!(
+            raised_per_frame(&[START, START | SELECT, SELECT]),
+            [true, true, false]
+        );
+    }
+
+    #[test]
+    fn leaves_the_other_interrupts_alone () {
+        let flags = 0b0000_0101;
+        assert_eq!(with_joypad_interrupt(flags, 0, 0), flags);
+        assert_eq!(with_joypad_interrupt(flags, A, A), flags);
+        assert_eq!(
+            with_joypad_interrupt(flags, A, 0),
+            flags | JOYPAD_INTERRUPT
+        );
+        // and one the game hasn't handled yet stays raised
+        assert_eq!(
+            with_joypad_interrupt(JOYPAD_INTERRUPT, A, A),
+            JOYPAD_INTERRUPT
+        );
+    }
+}
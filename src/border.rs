@@ -0,0 +1,123 @@
+use anyhow::Error;
+use crankstart::graphics::{Graphics, LCDColor, LCDSolidColor};
+use crankstart_sys::{LCDBitmapDrawMode, LCD_COLUMNS, LCD_ROWS};
+use euclid::{point2, rect, size2};
+
+use crate::layout::{GAME_LEFT, GAME_RIGHT};
+use crate::strings::{tr, Text};
+
+// Borders are drawn procedurally rather than from images, which keeps the
+// pdx small and lets them fit around wherever the game screen is.
+// They're only drawn when something changes (a new game, a closed menu, a
+// different theme), never every frame, since the blit leaves them alone.
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum BorderTheme {
+    // Solid black, like Playboy has always had
+    Plain,
+    // White with a thin line around the game
+    Frame,
+    // A rough outline of the original DMG's shell
+    Shell
+}
+
+const THEMES: &[BorderTheme] = &[
+    BorderTheme::Plain,
+    BorderTheme::Frame,
+    BorderTheme::Shell
+];
+
+impl BorderTheme {
+    pub fn name (self) -> &'static str {
+        match self {
+            BorderTheme::Plain => "plain",
+            BorderTheme::Frame => "frame",
+            BorderTheme::Shell => "shell"
+        }
+    }
+
+    // For the options menu
+    pub fn label (self) -> &'static str {
+        tr(match self {
+            BorderTheme::Plain => Text::BorderPlain,
+            BorderTheme::Frame => Text::BorderFrame,
+            BorderTheme::Shell => Text::BorderShell
+        })
+    }
+
+    pub fn from_name (name: &str) -> Option<Self> {
+        THEMES.iter().copied().find(|theme| theme.name() == name)
+    }
+
+    pub fn next (self) -> Self {
+        let index = THEMES.iter().position(|theme| *theme == self).unwrap();
+        THEMES[(index + 1) % THEMES.len()]
+    }
+}
+
+const BLACK: LCDColor = LCDColor::Solid(LCDSolidColor::kColorBlack);
+const WHITE: LCDColor = LCDColor::Solid(LCDSolidColor::kColorWhite);
+
+fn fill_borders (color: LCDColor) -> Result<(), Error> {
+    let graphics = Graphics::get();
+    let height = LCD_ROWS as i32;
+    let right_width = LCD_COLUMNS as i32 - GAME_RIGHT as i32;
+
+    graphics.fill_rect(rect(0, 0, GAME_LEFT as i32, height), color)?;
+    graphics.fill_rect(
+        rect(GAME_RIGHT as i32, 0, right_width, height), color
+    )?;
+    Ok(())
+}
+
+fn draw_frame () -> Result<(), Error> {
+    let graphics = Graphics::get();
+    let height = LCD_ROWS as i32;
+
+    fill_borders(WHITE)?;
+    graphics.fill_rect(rect(GAME_LEFT as i32 - 4, 0, 2, height), BLACK)?;
+    graphics.fill_rect(rect(GAME_RIGHT as i32 + 2, 0, 2, height), BLACK)?;
+    Ok(())
+}
+
+fn draw_shell () -> Result<(), Error> {
+    let graphics = Graphics::get();
+    let height = LCD_ROWS as i32;
+    let left = GAME_LEFT as i32;
+    let right = GAME_RIGHT as i32;
+    let right_width = LCD_COLUMNS as i32 - right;
+
+    fill_borders(WHITE)?;
+
+    // The dark bezel around the screen, with the shell's edge outside it
+    graphics.fill_rect(rect(left - 12, 0, 12, height), BLACK)?;
+    graphics.fill_rect(rect(right, 0, 12, height), BLACK)?;
+    graphics.draw_rect(rect(4, 4, left - 24, height - 8), BLACK)?;
+    graphics.draw_rect(
+        rect(right + 20, 4, right_width - 24, height - 8), BLACK
+    )?;
+
+    // Battery light on the left
+    graphics.fill_ellipse(point2(22, 60), size2(10, 10), 0., 360., BLACK)?;
+    graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeCopy)?;
+    graphics.draw_text("BAT", point2(14, 76))?;
+
+    // B and A on the right, in their familiar diagonal
+    let b_x = right + 22;
+    let a_x = b_x + 22;
+    let button = size2(18, 18);
+    graphics.fill_ellipse(point2(b_x, 150), button, 0., 360., BLACK)?;
+    graphics.fill_ellipse(point2(a_x, 130), button, 0., 360., BLACK)?;
+    graphics.draw_text("B", point2(b_x + 4, 172))?;
+    graphics.draw_text("A", point2(a_x + 4, 152))?;
+
+    Ok(())
+}
+
+pub fn draw (theme: BorderTheme) -> Result<(), Error> {
+    match theme {
+        BorderTheme::Plain => fill_borders(BLACK),
+        BorderTheme::Frame => draw_frame(),
+        BorderTheme::Shell => draw_shell()
+    }
+}
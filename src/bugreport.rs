@@ -0,0 +1,78 @@
+use alloc::{format, string::String};
+use anyhow::Error;
+use crankstart::system::System;
+
+use crate::framebuffer;
+use crate::log;
+use crate::settings::bool_name;
+use crate::storage;
+use crate::{State, FRAME_RATE};
+
+// Everything we'd otherwise have to ask for one file at a time, in one text
+// file. The ROM itself never goes in, only what identifies it.
+pub const BUGREPORT_PATH: &str = "system/bugreport.txt";
+
+pub fn write (state: &State) -> Result<(), Error> {
+    let system = System::get();
+    let (seconds, _) = system.get_seconds_since_epoch()?;
+    let time = system.convert_epoch_to_datetime(seconds as u32)?;
+
+    let mut report = String::new();
+    let mut line = |text: &str| {
+        report.push_str(text);
+        report.push('\n');
+    };
+
+    line("Playboy bug report");
+    line("Please attach this file to your issue at");
+    line("https://github.com/adamsoutar/playboy/issues - thank you!");
+    line("");
+    line(&format!(
+        "Written {}-{:02}-{:02} {:02}:{:02}:{:02}",
+        time.year, time.month, time.day, time.hour, time.minute, time.second
+    ));
+    line(&format!("Playboy {}", env!("CARGO_PKG_VERSION")));
+    line(&format!("Target frame rate: {}", FRAME_RATE));
+
+    line("");
+    line("== Game ==");
+    if state.processor.is_some() {
+        line(&format!("Title: {}", state.game_name));
+        line(&format!("{}", state.rom_id));
+    } else {
+        line("No game running");
+    }
+    line(&format!(
+        "Out of bounds framebuffer writes: {}",
+        framebuffer::out_of_bounds_writes()
+    ));
+    let stats = &state.frame_stats;
+    line(&format!(
+        "Frame headroom: {}% over {} frames, {} unchanged frames not blitted",
+        stats.headroom_percent(FRAME_RATE),
+        stats.frames(),
+        stats.skipped_blits()
+    ));
+    line(&format!(
+        "Rows sent to the LCD: {} a frame",
+        stats.rows_marked_per_frame()
+    ));
+
+    line("");
+    line("== Settings ==");
+    for setting in state.settings.to_text().lines() {
+        line(setting);
+    }
+    line(&format!(
+        "Display inverted right now: {}", bool_name(state.inverted)
+    ));
+    line(&format!(
+        "Storage writes failing: {}", bool_name(storage::writes_failing())
+    ));
+
+    line("");
+    line("== Recent log ==");
+    log::for_each_line(|log_line| line(log_line));
+
+    storage::write_file(BUGREPORT_PATH, report.as_bytes())
+}
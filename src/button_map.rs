@@ -0,0 +1,148 @@
+use alloc::{string::String, vec::Vec};
+use crankstart_sys::PDButtons;
+
+use crate::hotkeys;
+
+// Which Playdate input presses each Gameboy button, from the map_a,
+// map_b, map_up ... map_select settings. Each one is a Playdate button
+// ("b"), buttons held together ("b+up"), "crank_forward" or "crank_back"
+// for whatever Start/Select is set to press Start and Select with, or
+// "none". So map_a=b and map_b=a swap A and B, and map_start=a+b puts
+// Start on a chord.
+//
+// A chord that's held eats its buttons, like a hotkey does, so the game
+// doesn't see them on their own as well. It's worked out into a
+// ButtonMap when the settings are read, so nothing's parsed per frame.
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Source {
+    Buttons(PDButtons),
+    CrankForward,
+    CrankBack,
+    Nothing
+}
+
+// In the same order as boot_macro's bits: A, B, Up, Down, Left, Right,
+// Start, Select
+const KEYS: [&str; 8] = [
+    "map_a", "map_b", "map_up", "map_down",
+    "map_left", "map_right", "map_start", "map_select"
+];
+
+const DEFAULTS: [Source; 8] = [
+    Source::Buttons(PDButtons::kButtonA),
+    Source::Buttons(PDButtons::kButtonB),
+    Source::Buttons(PDButtons::kButtonUp),
+    Source::Buttons(PDButtons::kButtonDown),
+    Source::Buttons(PDButtons::kButtonLeft),
+    Source::Buttons(PDButtons::kButtonRight),
+    Source::CrankForward,
+    Source::CrankBack
+];
+
+fn held (buttons: PDButtons, chord: PDButtons) -> bool {
+    (buttons & chord) == chord
+}
+
+fn is_chord (buttons: PDButtons) -> bool {
+    buttons.0.count_ones() > 1
+}
+
+impl Source {
+    fn parse (text: &str) -> Option<Self> {
+        match text {
+            "crank_forward" => return Some(Source::CrankForward),
+            "crank_back" => return Some(Source::CrankBack),
+            "none" => return Some(Source::Nothing),
+            _ => {}
+        }
+        let mut buttons = 0;
+        for name in text.split('+') {
+            let (_, button) = hotkeys::BUTTONS
+                .iter()
+                .find(|(button_name, _)| *button_name == name.trim())?;
+            buttons |= button.0;
+        }
+        Some(Source::Buttons(PDButtons(buttons)))
+    }
+
+    fn text (self) -> String {
+        match self {
+            Source::Buttons(buttons) => hotkeys::chord_text(buttons),
+            Source::CrankForward => String::from("crank_forward"),
+            Source::CrankBack => String::from("crank_back"),
+            Source::Nothing => String::from("none")
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct ButtonMap {
+    sources: [Source; 8]
+}
+
+impl ButtonMap {
+    pub fn new () -> Self {
+        Self { sources: DEFAULTS }
+    }
+
+    pub fn is_key (key: &str) -> bool {
+        KEYS.contains(&key)
+    }
+
+    // Returns whether value made sense for key
+    pub fn set (&mut self, key: &str, value: &str) -> bool {
+        let index = match KEYS.iter().position(|known| *known == key) {
+            Some(index) => index,
+            None => return false
+        };
+        match Source::parse(value) {
+            Some(source) => {
+                self.sources[index] = source;
+                true
+            },
+            None => false
+        }
+    }
+
+    // Every key and its value, for writing the settings out
+    pub fn entries (&self) -> Vec<(&'static str, String)> {
+        KEYS.iter()
+            .zip(self.sources.iter())
+            .map(|(key, source)| (*key, source.text()))
+            .collect()
+    }
+
+    // The Gameboy buttons in boot_macro's bits, from the Playdate buttons
+    // held and what Start/Select made of the crank this frame
+    pub fn resolve (
+        &self,
+        buttons: PDButtons,
+        crank_forward: bool,
+        crank_back: bool
+    ) -> u8 {
+        let mut consumed = 0;
+        for source in self.sources.iter() {
+            if let Source::Buttons(chord) = source {
+                if is_chord(*chord) && held(buttons, *chord) {
+                    consumed |= chord.0;
+                }
+            }
+        }
+        let loose = PDButtons(buttons.0 & !consumed);
+
+        let mut bits = 0;
+        for (i, source) in self.sources.iter().enumerate() {
+            let pressed = match source {
+                Source::Buttons(chord) if is_chord(*chord) =>
+                    held(buttons, *chord),
+                Source::Buttons(button) => held(loose, *button),
+                Source::CrankForward => crank_forward,
+                Source::CrankBack => crank_back,
+                Source::Nothing => false
+            };
+            if pressed { bits |= 1 << i }
+        }
+        bits
+    }
+}
@@ -0,0 +1,88 @@
+use alloc::{format, string::String};
+use anyhow::Error;
+use crankstart::graphics::{Graphics, LCDColor, LCDSolidColor};
+use crankstart_sys::{LCDBitmapDrawMode, PDButtons, LCD_COLUMNS};
+use euclid::{point2, rect};
+
+use crate::header::{self, CartProblem};
+use crate::input;
+use crate::strings::{self, tr, trf, Text};
+
+// Shown instead of booting a ROM that header::check_cart turns away, since
+// gbrs panics on a mapper it doesn't have and on the Playdate that's a
+// crash back to the launcher with no word why. The picker already says so
+// under the list, but anything else that boots a game (the integrity
+// screen, restarting it, recording a boot macro) ends up here too, as does a
+// ROM file the picker couldn't read. It stays up until A or B takes you
+// back to the list, and there's no Cpu behind it.
+pub struct CartErrorScreen {
+    text: String,
+    // The press that booted the game is still down on the first update
+    fresh: bool
+}
+
+impl CartErrorScreen {
+    pub fn new (rom: &[u8], problem: &CartProblem) -> Result<Self, Error> {
+        let cart = header::cart_type(rom);
+        let cart_name = match header::cart_name(cart) {
+            Some(name) => format!("{} ({:02x})", name, cart),
+            None => format!("{:02x}", cart)
+        };
+        let advice = match problem {
+            CartProblem::BadLogo | CartProblem::BadChecksum =>
+                tr(Text::CartErrorBadHeader),
+            CartProblem::Unsupported(_) => tr(Text::CartErrorUnsupported),
+            CartProblem::ColourOnly => tr(Text::CartErrorColourOnly)
+        };
+        let text = trf(Text::CartError, &[
+            &header::title(rom), &cart_name, &advice
+        ]);
+        Self::with_text(text)
+    }
+
+    // error is what went wrong reading the file at path
+    pub fn unreadable (path: &str, error: &str) -> Result<Self, Error> {
+        let text = trf(Text::CartErrorUnreadable, &[&path, &error]);
+        Self::with_text(text)
+    }
+
+    fn with_text (text: String) -> Result<Self, Error> {
+        let screen = Self {
+            text: strings::wrap(&text[..], LCD_COLUMNS as i32 - 40),
+            fresh: true
+        };
+        screen.redraw()?;
+        Ok(screen)
+    }
+
+    pub fn redraw (&self) -> Result<(), Error> {
+        let graphics = Graphics::get();
+        graphics.clear(LCDColor::Solid(LCDSolidColor::kColorWhite))?;
+
+        // The same top bar as the picker it came from
+        graphics.fill_rect(
+            rect(0, 0, LCD_COLUMNS as i32, 30),
+            LCDColor::Solid(LCDSolidColor::kColorBlack)
+        )?;
+        graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeInverted)?;
+        let title = strings::ellipsize(
+            tr(Text::CartErrorTitle), LCD_COLUMNS as i32 - 12
+        );
+        graphics.draw_text(&title[..], point2(6, 6))?;
+
+        graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeCopy)?;
+        graphics.draw_text(&self.text[..], point2(20, 50))?;
+        Ok(())
+    }
+
+    // Returns true once it's been dismissed. The caller opens the picker.
+    pub fn update (&mut self) -> Result<bool, Error> {
+        let (_, btns_down, _) = input::button_state()?;
+        if self.fresh {
+            self.fresh = false;
+            return Ok(false)
+        }
+        let pressed = |button: PDButtons| (btns_down & button) == button;
+        Ok(pressed(PDButtons::kButtonA) || pressed(PDButtons::kButtonB))
+    }
+}
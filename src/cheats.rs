@@ -0,0 +1,272 @@
+use alloc::{format, string::String, vec, vec::Vec};
+use anyhow::{anyhow, Error};
+use gbrs_core::cpu::Cpu;
+
+use crate::storage;
+
+// Game Genie and GameShark codes, from {game}.cheats. One code per line,
+// optionally followed by a note about what it does, eg.
+//
+//   00A-17B-C49  infinite lives
+//   010138CD     start with 99 coins
+//
+// A Game Genie sits between the cartridge and the Gameboy and changes what
+// comes back when the game reads one address of its ROM, optionally only
+// while the real byte there is a particular one. gbrs doesn't let us get at
+// ROM reads, but the cartridge can't tell what bank the code was meant for
+// either, so patching every bank with a matching byte before boot does the
+// same thing. That does mean turning cheats on or off only changes Game
+// Genie codes next time the game starts.
+//
+// A GameShark writes a byte into RAM once a frame, which we do after each
+// emulated frame.
+
+const BANK_SIZE: usize = 0x4000;
+
+#[derive(Clone, Copy)]
+enum Cheat {
+    GameGenie { address: u16, value: u8, compare: Option<u8> },
+    GameShark { address: u16, value: u8 }
+}
+
+pub fn cheats_path (game_name: &str) -> String {
+    format!("{}.cheats", game_name)
+}
+
+fn hex_digits (text: &str) -> Result<Vec<u8>, Error> {
+    text.chars()
+        .filter(|c| *c != '-')
+        .map(|c| {
+            c.to_digit(16)
+                .map(|digit| digit as u8)
+                .ok_or_else(|| anyhow!("{} isn't a hex digit", c))
+        })
+        .collect()
+}
+
+// ABC-DEF or ABC-DEF-GHI. AB is the new value, and the address is CDE with
+// F in front, flipped. G and I are the compare byte, rotated and scrambled,
+// and H doesn't matter.
+fn parse_game_genie (digits: &[u8]) -> Result<Cheat, Error> {
+    let value = digits[0] << 4 | digits[1];
+    let address = ((digits[5] ^ 0xF) as u16) << 12 |
+        (digits[2] as u16) << 8 | (digits[3] as u16) << 4 | digits[4] as u16;
+    if address as usize >= BANK_SIZE * 2 {
+        return Err(anyhow!("{:04x} isn't in the ROM", address))
+    }
+    let compare = if digits.len() == 9 {
+        Some((digits[6] << 4 | digits[8]).rotate_right(2) ^ 0xBA)
+    } else {
+        None
+    };
+    Ok(Cheat::GameGenie { address, value, compare })
+}
+
+// TTVVLLHH: a type, the value, and the address low byte first. Type 01 is a
+// plain write. The 9x types pick a CGB RAM bank, which gbrs doesn't have.
+fn parse_game_shark (digits: &[u8]) -> Result<Cheat, Error> {
+    let byte = |index: usize| digits[index * 2] << 4 | digits[index * 2 + 1];
+    if byte(0) != 0x00 && byte(0) != 0x01 {
+        return Err(anyhow!("type {:02x} isn't supported", byte(0)))
+    }
+    let address = (byte(3) as u16) << 8 | byte(2) as u16;
+    // Cartridge and work RAM. Anywhere else and a write does something
+    // other than change a number.
+    if address < 0xA000 || address > 0xDFFF {
+        return Err(anyhow!("{:04x} isn't in RAM", address))
+    }
+    Ok(Cheat::GameShark { address, value: byte(1) })
+}
+
+fn parse_cheat (code: &str) -> Result<Cheat, Error> {
+    let digits = hex_digits(code)?;
+    match digits.len() {
+        8 if !code.contains('-') => parse_game_shark(&digits[..]),
+        6 | 9 => parse_game_genie(&digits[..]),
+        _ => Err(anyhow!("not a Game Genie or GameShark code"))
+    }
+}
+
+fn parse_cheats (text: &str) -> Vec<Cheat> {
+    let mut cheats = vec![];
+    for line in text.lines() {
+        let line = line.trim();
+        if line.len() == 0 || line.starts_with('#') { continue }
+
+        // Anything after the code is a note for whoever wrote the file
+        let code = line.split_whitespace().next().unwrap_or(line);
+        match parse_cheat(code) {
+            Ok(cheat) => cheats.push(cheat),
+            Err(e) => log!("Ignoring cheat \"{}\": {}", line, e)
+        }
+    }
+    cheats
+}
+
+pub struct Cheats {
+    cheats: Vec<Cheat>
+}
+
+impl Cheats {
+    // None when there's no file, or nothing in it worked
+    pub fn load (game_name: &str) -> Option<Self> {
+        let bytes = storage::read_file(&cheats_path(game_name)[..]).ok()?;
+        let text = core::str::from_utf8(&bytes[..]).ok()?;
+        let cheats = parse_cheats(text);
+        if cheats.len() == 0 { return None }
+
+        log!("Loaded {} cheats for {}", cheats.len(), game_name);
+        Some(Self { cheats })
+    }
+
+    // Whether switching cheats on or off needs a restart to fully happen
+    pub fn patches_rom (&self) -> bool {
+        self.cheats
+            .iter()
+            .any(|cheat| matches!(cheat, Cheat::GameGenie { .. }))
+    }
+
+    // The Game Genie codes, before the ROM goes to gbrs
+    pub fn patch_rom (&self, rom: &mut [u8]) {
+        for cheat in &self.cheats {
+            let (address, value, compare) = match *cheat {
+                Cheat::GameGenie { address, value, compare } =>
+                    (address as usize, value, compare),
+                Cheat::GameShark { .. } => continue
+            };
+
+            // Everything below 4000 is always bank 0. Above that, it's
+            // whichever bank is switched in.
+            let offsets: Vec<usize> = if address < BANK_SIZE {
+                vec![address]
+            } else {
+                (1..rom.len() / BANK_SIZE)
+                    .map(|bank| bank * BANK_SIZE + address - BANK_SIZE)
+                    .collect()
+            };
+
+            let mut patched = 0;
+            for offset in offsets {
+                if offset >= rom.len() { continue }
+                if compare.map_or(true, |compare| rom[offset] == compare) {
+                    rom[offset] = value;
+                    patched += 1;
+                }
+            }
+            if patched == 0 {
+                log!("Game Genie code for {:04x} matched nothing", address);
+            }
+        }
+    }
+
+    // The GameShark codes, after each frame
+    pub fn poke (&self, cpu: &mut Cpu) {
+        for cheat in &self.cheats {
+            if let Cheat::GameShark { address, value } = *cheat {
+                cpu.mem.write(address, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn game_genie_codes () {
+        // 0x4a17 (B flipped is 4) becomes 00, if it's c8 now
+        assert!(matches!(
+            parse_cheat("00A-17B-C49"),
+            Ok(Cheat::GameGenie {
+                address: 0x4A17, value: 0x00, compare: Some(0xC8)
+            })
+        ));
+        // No compare byte
+        assert!(matches!(
+            parse_cheat("3E1-23F"),
+            Ok(Cheat::GameGenie { address: 0x0123, value: 0x3E, compare: None })
+        ));
+        // Lower case is fine too
+        assert!(matches!(
+            parse_cheat("3e1-23f"),
+            Ok(Cheat::GameGenie { address: 0x0123, .. })
+        ));
+    }
+
+    #[test]
+    fn game_genie_codes_stay_in_the_rom () {
+        // F flipped is 8, so 0x8123, which is VRAM
+        assert!(parse_cheat("3E1-237").is_err());
+    }
+
+    #[test]
+    fn game_shark_codes () {
+        assert!(matches!(
+            parse_cheat("010138CD"),
+            Ok(Cheat::GameShark { address: 0xCD38, value: 0x01 })
+        ));
+        assert!(matches!(
+            parse_cheat("0063A0A0"),
+            Ok(Cheat::GameShark { address: 0xA0A0, value: 0x63 })
+        ));
+    }
+
+    #[test]
+    fn game_shark_codes_only_write_ram () {
+        // The CGB bank types
+        assert!(parse_cheat("910138CD").is_err());
+        // I/O and the ROM
+        assert!(parse_cheat("010100FF").is_err());
+        assert!(parse_cheat("01010040").is_err());
+    }
+
+    #[test]
+    fn rejects_garbage () {
+        let codes = [
+            "", "00A-17", "00A-17B-C4", "0101-38CD", "XYZ-123", "lives"
+        ];
+        for code in codes.iter() {
+            assert!(parse_cheat(code).is_err(), "{:?}", code);
+        }
+    }
+
+    #[test]
+    fn skips_bad_lines () {
+        let text = "\
+            # Super Mario Land\n\
+            00A-17B-C49  infinite lives\n\
+            \n\
+            not a code\n\
+            910138CD\n  \
+            010138CD\tstart with 99 coins\r\n";
+        let cheats = parse_cheats(text);
+        assert_eq!(cheats.len(), 2);
+        assert!(matches!(cheats[0], Cheat::GameGenie { .. }));
+        assert!(matches!(cheats[1], Cheat::GameShark { .. }));
+    }
+
+    #[test]
+    fn patches_every_matching_bank () {
+        let mut rom = vec![0; BANK_SIZE * 4];
+        rom[BANK_SIZE + 0x0A17] = 0xC8;
+        rom[BANK_SIZE * 3 + 0x0A17] = 0xC8;
+        rom[BANK_SIZE * 2 + 0x0A17] = 0x12;
+        let cheats = Cheats { cheats: parse_cheats("00A-17B-C49") };
+        assert!(cheats.patches_rom());
+        cheats.patch_rom(&mut rom[..]);
+        assert_eq!(rom[BANK_SIZE + 0x0A17], 0x00);
+        assert_eq!(rom[BANK_SIZE * 2 + 0x0A17], 0x12);
+        assert_eq!(rom[BANK_SIZE * 3 + 0x0A17], 0x00);
+    }
+
+    #[test]
+    fn patches_bank_0_without_comparing () {
+        let mut rom = vec![0x55; BANK_SIZE * 2];
+        let cheats = Cheats { cheats: parse_cheats("3E1-23F") };
+        cheats.patch_rom(&mut rom[..]);
+        assert_eq!(rom[0x0123], 0x3E);
+        // Only the one byte
+        assert_eq!(rom.iter().filter(|byte| **byte != 0x55).count(), 1);
+    }
+}
@@ -0,0 +1,24 @@
+// Plain CRC-32 (the zlib/PNG one). Table-free since it only ever runs over
+// save-sized buffers at boot, or a chunk of a ROM at a time, not every frame.
+pub fn crc32 (data: &[u8]) -> u32 {
+    crc32_finish(crc32_update(CRC32_START, data))
+}
+
+// For data that arrives in pieces: start from CRC32_START, update with each
+// piece in order, then finish
+pub const CRC32_START: u32 = 0xFFFFFFFF;
+
+pub fn crc32_update (mut crc: u32, data: &[u8]) -> u32 {
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (!(crc & 1)).wrapping_add(1);
+            crc = (crc >> 1) ^ (0xEDB88320 & mask);
+        }
+    }
+    crc
+}
+
+pub fn crc32_finish (crc: u32) -> u32 {
+    !crc
+}
@@ -0,0 +1,114 @@
+use anyhow::Error;
+use crankstart::graphics::{Graphics, LCDColor, LCDSolidColor};
+use crankstart_sys::LCDBitmapDrawMode;
+use euclid::{point2, rect};
+use gbrs_core::lcd::GreyShade;
+
+use crate::blit;
+use crate::layout::GAME_LEFT;
+
+// Four shades don't survive dithering equally. A dark dungeon that's all
+// black and dark grey turns into a near-solid black mess, and a washed-out
+// scene of light grey on white loses its detail the other way. With
+// adaptive contrast on, each frame's histogram decides whether to move
+// every shade one step lighter (or darker) in the blit.
+//
+// The decision is smoothed over about SMOOTHING_FRAMES so transitions and
+// flashes don't make the picture pump.
+
+// Percent of the frame the dark (or light) half has to cover
+const SKEW_PERCENT: usize = 85;
+// ...and how much of that has to be the grey in it. Without any grey there's
+// no detail to bring out, and plain black (or white) screens are left alone.
+const GREY_PERCENT: usize = 20;
+const SMOOTHING_FRAMES: isize = 15;
+// The smoothed vote, out of 1000, needed to engage and to let go again
+const ENGAGE_AT: isize = 600;
+const RELEASE_AT: isize = 400;
+
+// Lives up in the left border, out of the way of the banner
+const INDICATOR_LEFT: i32 = 4;
+const INDICATOR_TOP: i32 = 28;
+
+// Which way this one frame wants to go: 1 lighter, -1 darker, 0 neither
+fn vote (finished_frame: &[GreyShade]) -> isize {
+    let mut counts = [0usize; 4];
+    for shade in finished_frame {
+        counts[blit::shade_value(shade) as usize] += 1;
+    }
+
+    let total = finished_frame.len().max(1);
+    let percent = |count: usize| count * 100 / total;
+
+    if percent(counts[0] + counts[1]) >= SKEW_PERCENT &&
+        percent(counts[1]) >= GREY_PERCENT {
+        1
+    } else if percent(counts[2] + counts[3]) >= SKEW_PERCENT &&
+        percent(counts[2]) >= GREY_PERCENT {
+        -1
+    } else {
+        0
+    }
+}
+
+pub struct AdaptiveContrast {
+    // Running average of the votes, times 1000
+    smoothed: isize,
+    shift: i8
+}
+
+impl AdaptiveContrast {
+    pub fn new () -> Self {
+        Self { smoothed: 0, shift: 0 }
+    }
+
+    // For the blit
+    pub fn shift (&self) -> i8 {
+        self.shift
+    }
+
+    // Call once per update, after emulating. Returns true if the shift
+    // changed, in which case the frame needs blitting again.
+    pub fn update (&mut self, finished_frame: &[GreyShade]) -> bool {
+        let vote = vote(finished_frame) * 1000;
+        self.smoothed += (vote - self.smoothed) / SMOOTHING_FRAMES;
+
+        let shift = if self.smoothed >= ENGAGE_AT {
+            1
+        } else if self.smoothed <= -ENGAGE_AT {
+            -1
+        } else if self.smoothed.abs() < RELEASE_AT {
+            0
+        } else {
+            // In between, stay as we are
+            self.shift
+        };
+
+        let changed = shift != self.shift;
+        self.shift = shift;
+        changed
+    }
+
+    // A little marker so you can tell when the picture's been adjusted.
+    // Like the toast, call this after the blit. When it goes away, the
+    // border needs redrawing.
+    pub fn draw_indicator (&self) -> Result<(), Error> {
+        let label = match self.shift {
+            0 => return Ok(()),
+            shift if shift > 0 => "C+",
+            _ => "C-"
+        };
+
+        let graphics = Graphics::get();
+        let width = GAME_LEFT as i32 - INDICATOR_LEFT * 2;
+        graphics.fill_rect(
+            rect(INDICATOR_LEFT, INDICATOR_TOP, width.min(28), 20),
+            LCDColor::Solid(LCDSolidColor::kColorWhite)
+        )?;
+        graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeCopy)?;
+        graphics.draw_text(
+            label, point2(INDICATOR_LEFT + 2, INDICATOR_TOP + 1)
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,197 @@
+use alloc::string::String;
+use anyhow::{anyhow, Error};
+
+// The simulator can send a running game lines of text (and so can msg over
+// a device's USB serial console). We treat them as a little command
+// language for poking at the emulator while developing:
+//
+//   screenshot                  the current frame, in screenshots/
+//   savestate <slot>            save a state to states/{game}.slot<N>.state
+//   loadstate <slot>            load it back (undoable, like any load)
+//   dumpmem <address> <length>  hex dump to the log, eg. dumpmem 0xc000 64
+//   press <buttons> <frames>    hold Gameboy buttons, eg. press a+start 10
+//   loadrom <path>              boot a ROM from the data folder
+//
+// Numbers can be decimal, or hex with 0x or $ in front. Commands run at the
+// top of the next update, and everything they have to say goes to the log.
+
+// Anything bigger floods the console
+const MAX_DUMP: usize = 0x1000;
+
+// Same bits as a boot macro uses
+const BUTTONS: &[&str] = &[
+    "a", "b", "up", "down", "left", "right", "start", "select"
+];
+
+pub enum DevCommand {
+    Screenshot,
+    SaveState(u8),
+    LoadState(u8),
+    DumpMem { address: u16, length: usize },
+    Press { buttons: u8, frames: usize },
+    LoadRom(String)
+}
+
+fn parse_number (text: &str) -> Result<usize, Error> {
+    let hex = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("$"));
+    let parsed = match hex {
+        Some(digits) => usize::from_str_radix(digits, 16),
+        None => text.parse::<usize>()
+    };
+    parsed.map_err(|_| anyhow!("{} isn't a number", text))
+}
+
+fn parse_slot (text: &str) -> Result<u8, Error> {
+    let slot = parse_number(text)?;
+    if slot > 9 { return Err(anyhow!("slots go from 0 to 9")) }
+    Ok(slot as u8)
+}
+
+// "a+start"
+fn parse_buttons (text: &str) -> Result<u8, Error> {
+    text.split('+').try_fold(0, |bits, name| {
+        match BUTTONS.iter().position(|button| *button == name) {
+            Some(bit) => Ok(bits | 1 << bit),
+            None => Err(anyhow!("{} isn't a Gameboy button", name))
+        }
+    })
+}
+
+pub fn parse (line: &str) -> Result<DevCommand, Error> {
+    let line = line.trim();
+    let mut words = line.split_whitespace();
+    let name = words.next().ok_or_else(|| anyhow!("empty command"))?;
+    let mut argument = |what: &str| {
+        words.next().ok_or_else(|| anyhow!("{} needs a {}", name, what))
+    };
+
+    let command = match name {
+        "screenshot" => DevCommand::Screenshot,
+        "savestate" => DevCommand::SaveState(parse_slot(argument("slot")?)?),
+        "loadstate" => DevCommand::LoadState(parse_slot(argument("slot")?)?),
+        "dumpmem" => {
+            let address = parse_number(argument("address")?)?;
+            let length = parse_number(argument("length")?)?;
+            if address > 0xFFFF {
+                return Err(anyhow!("{:x} is past the end of memory", address))
+            }
+            if length == 0 || length > MAX_DUMP {
+                return Err(anyhow!("length has to be 1 to {}", MAX_DUMP))
+            }
+            DevCommand::DumpMem { address: address as u16, length }
+        },
+        "press" => {
+            let buttons = parse_buttons(argument("button")?)?;
+            let frames = parse_number(argument("number of frames")?)?;
+            if frames == 0 {
+                return Err(anyhow!("press needs 1 frame or more"))
+            }
+            DevCommand::Press { buttons, frames }
+        },
+        // Paths can have spaces in, so this takes the rest of the line
+        "loadrom" => {
+            let path = line[name.len()..].trim();
+            if path.len() == 0 { return Err(anyhow!("loadrom needs a path")) }
+            return Ok(DevCommand::LoadRom(String::from(path)))
+        },
+        _ => return Err(anyhow!("unknown command {}", name))
+    };
+
+    match words.next() {
+        Some(extra) => Err(anyhow!("didn't expect {} after {}", extra, name)),
+        None => Ok(command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed (line: &str) -> DevCommand {
+        match parse(line) {
+            Ok(command) => command,
+            Err(e) => panic!("{:?}: {}", line, e)
+        }
+    }
+
+    #[test]
+    fn screenshot () {
+        assert!(matches!(parsed("screenshot"), DevCommand::Screenshot));
+        assert!(parse("screenshot now").is_err());
+    }
+
+    #[test]
+    fn save_and_load_state () {
+        assert!(matches!(parsed("savestate 3"), DevCommand::SaveState(3)));
+        assert!(matches!(parsed("loadstate 0x9"), DevCommand::LoadState(9)));
+        assert!(parse("savestate").is_err());
+        assert!(parse("savestate 10").is_err());
+        assert!(parse("loadstate one").is_err());
+    }
+
+    #[test]
+    fn dumpmem () {
+        assert!(matches!(
+            parsed("dumpmem 0xc000 64"),
+            DevCommand::DumpMem { address: 0xC000, length: 64 }
+        ));
+        assert!(matches!(
+            parsed("dumpmem $ff80 $7f"),
+            DevCommand::DumpMem { address: 0xFF80, length: 0x7F }
+        ));
+        assert!(parse("dumpmem 0xc000").is_err());
+        assert!(parse("dumpmem 0x10000 1").is_err());
+        assert!(parse("dumpmem 0xc000 0").is_err());
+        assert!(parse("dumpmem 0xc000 0x1001").is_err());
+    }
+
+    #[test]
+    fn press () {
+        assert!(matches!(
+            parsed("press a+start 10"),
+            DevCommand::Press { buttons: 0b0100_0001, frames: 10 }
+        ));
+        assert!(matches!(
+            parsed("press select 1"),
+            DevCommand::Press { buttons: 0b1000_0000, frames: 1 }
+        ));
+        assert!(parse("press a+x 10").is_err());
+        assert!(parse("press a 0").is_err());
+        assert!(parse("press a").is_err());
+    }
+
+    #[test]
+    fn loadrom_keeps_spaces () {
+        match parsed("loadrom roms/Super Mario Land.gb") {
+            DevCommand::LoadRom(path) =>
+                assert_eq!(path, "roms/Super Mario Land.gb"),
+            _ => panic!("not loadrom")
+        }
+        assert!(parse("loadrom").is_err());
+        assert!(parse("loadrom   ").is_err());
+    }
+
+    #[test]
+    fn rejects_garbage () {
+        let lines = [
+            "", "   ", "jump", "SCREENSHOT", "savestate 1 2", "\u{1F47E}"
+        ];
+        for line in lines.iter() {
+            assert!(parse(line).is_err(), "{:?}", line);
+        }
+        assert!(parse_number("0x").is_err());
+        assert!(parse_number("-1").is_err());
+    }
+
+    #[test]
+    fn ignores_surrounding_whitespace () {
+        assert!(matches!(parsed("  screenshot\r\n"), DevCommand::Screenshot));
+        assert!(matches!(parsed("savestate   2 \t"), DevCommand::SaveState(2)));
+        match parsed("loadrom tetris.gb \n") {
+            DevCommand::LoadRom(path) => assert_eq!(path, "tetris.gb"),
+            _ => panic!("not loadrom")
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use crankstart_sys::{LCD_COLUMNS, LCD_ROWS};
+use gbrs_core::constants::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+use crate::strings::{tr, Text};
+
+// How big the Gameboy screen is drawn. Stretched is the normal blit (see
+// layout.rs), with the borders either side. The others go through
+// blit::draw_frame_scaled, which blacks out everything around the picture,
+// so nothing from the last mode is left behind when it changes. Sideways
+// has a blit of its own that does the same.
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum DisplayMode {
+    // As tall as the Playdate screen, keeping the Gameboy's shape
+    Stretched,
+    // One Playdate pixel per Gameboy pixel. Small, but perfectly sharp,
+    // and the cheapest to draw.
+    PixelPerfect,
+    // The whole Playdate screen, wider than it should be
+    FullWidth,
+    // A quarter turn, for holding the Playdate with the crank at the
+    // bottom. The Gameboy's width fits the Playdate's 240 rows at 1.5x,
+    // which is bigger than Stretched. The d-pad turns with it.
+    Sideways
+}
+
+const MODES: &[DisplayMode] = &[
+    DisplayMode::Stretched,
+    DisplayMode::PixelPerfect,
+    DisplayMode::FullWidth,
+    DisplayMode::Sideways
+];
+
+impl DisplayMode {
+    // For the display_mode setting
+    pub fn name (self) -> &'static str {
+        match self {
+            DisplayMode::Stretched => "stretched",
+            DisplayMode::PixelPerfect => "pixel_perfect",
+            DisplayMode::FullWidth => "full_width",
+            DisplayMode::Sideways => "sideways"
+        }
+    }
+
+    // For the options menu
+    pub fn label (self) -> &'static str {
+        tr(match self {
+            DisplayMode::Stretched => Text::DisplayStretched,
+            DisplayMode::PixelPerfect => Text::DisplayPixelPerfect,
+            DisplayMode::FullWidth => Text::DisplayFullWidth,
+            DisplayMode::Sideways => Text::DisplaySideways
+        })
+    }
+
+    pub fn from_name (name: &str) -> Option<Self> {
+        MODES.iter().copied().find(|mode| mode.name() == name)
+    }
+
+    pub fn next (self) -> Self {
+        let index = MODES.iter().position(|mode| *mode == self).unwrap();
+        MODES[(index + 1) % MODES.len()]
+    }
+
+    // Playdate pixels the picture covers, for draw_frame_scaled. None for
+    // Stretched and Sideways, which have blits of their own.
+    pub fn output_size (self) -> Option<(usize, usize)> {
+        match self {
+            DisplayMode::Stretched | DisplayMode::Sideways => None,
+            DisplayMode::PixelPerfect => Some((SCREEN_WIDTH, SCREEN_HEIGHT)),
+            DisplayMode::FullWidth =>
+                Some((LCD_COLUMNS as usize, LCD_ROWS as usize))
+        }
+    }
+}
+
+// Whole bytes, so the 1:1 picture lands exactly in the middle
+const _: () = assert!(SCREEN_WIDTH % 8 == 0);
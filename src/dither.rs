@@ -0,0 +1,248 @@
+use crate::blit;
+use crate::layout::GAME_WIDTH;
+use crate::strings::{tr, Text};
+
+// How the two greys are drawn with only black and white pixels, picked
+// with the dither setting. Each one is a tile of TILE_WIDTH by TILE_HEIGHT
+// pixels, worked out at compile time into a bit mask per row and shade, so
+// the blit only has to look a pixel up rather than work it out.
+//
+// The tiles are normally lined up with the Playdate's pixels, which keeps
+// them still on a still screen but makes them swim against a scrolling
+// background. The anchored one is lined up with the Gameboy's pixels
+// instead, so the pattern moves along with whatever it's drawn on.
+//
+// With temporal_dither on, every display refresh swaps between each
+// pattern and the same one a column along, and the LCD's slowness blends
+// the two into something much more like a real grey. Moving along rather
+// than flipping keeps the same share of pixels lit, so the greys stay the
+// shades they were.
+
+// Big enough for the cross-hatch's 3 and 2 and Bayer's 4, and a multiple
+// of all of them so moving a pattern along wraps cleanly
+const TILE_WIDTH: usize = 12;
+const TILE_HEIGHT: usize = 4;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Dither {
+    CrossHatch,
+    Bayer,
+    Anchored
+}
+
+pub const DITHERS: &[Dither] = &[
+    Dither::CrossHatch,
+    Dither::Bayer,
+    Dither::Anchored
+];
+
+impl Dither {
+    // For the dither setting
+    pub fn name (self) -> &'static str {
+        match self {
+            Dither::CrossHatch => "cross_hatch",
+            Dither::Bayer => "bayer",
+            Dither::Anchored => "anchored"
+        }
+    }
+
+    // For the options menu
+    pub fn label (self) -> &'static str {
+        tr(match self {
+            Dither::CrossHatch => Text::DitherCrossHatch,
+            Dither::Bayer => Text::DitherBayer,
+            Dither::Anchored => Text::DitherAnchored
+        })
+    }
+
+    pub fn from_name (name: &str) -> Option<Self> {
+        DITHERS.iter().copied().find(|dither| dither.name() == name)
+    }
+
+    pub fn next (self) -> Self {
+        let index = DITHERS.iter().position(|dither| *dither == self).unwrap();
+        DITHERS[(index + 1) % DITHERS.len()]
+    }
+
+    // For pictures that stay still
+    pub fn ditherer (self) -> &'static Ditherer {
+        &self.ditherers()[0]
+    }
+
+    // The pattern and its partner, for temporal_dither to alternate between
+    pub fn ditherers (self) -> &'static [Ditherer; 2] {
+        match self {
+            Dither::CrossHatch => &CROSS_HATCH,
+            Dither::Bayer => &BAYER,
+            Dither::Anchored => &ANCHORED
+        }
+    }
+}
+
+// The original pattern. Light grey is a checkerboard, and dark grey the
+// same but lighting every third pixel rather than every other.
+const fn cross_hatch_white (level: usize, x: usize, y: usize) -> bool {
+    match level {
+        0 => false,
+        1 => (x + y % 2) % 3 == 0,
+        2 => (x + y % 2) % 2 == 0,
+        _ => true
+    }
+}
+
+const BAYER_MATRIX: [[usize; 4]; 4] = [
+    [0, 8, 2, 10],
+    [12, 4, 14, 6],
+    [3, 11, 1, 9],
+    [15, 7, 13, 5]
+];
+
+// A third and two thirds of the pixels lit, spread as evenly as they go
+const fn bayer_white (level: usize, x: usize, y: usize) -> bool {
+    BAYER_MATRIX[y % 4][x % 4] < level * 16 / 3
+}
+
+pub struct Ditherer {
+    // For each row of the tile, a mask per shade with bit x set where
+    // column x is white
+    masks: [[u16; 4]; TILE_HEIGHT],
+    anchored: bool
+}
+
+// offset moves the pattern that many columns along
+const fn build (bayer: bool, anchored: bool, offset: usize) -> Ditherer {
+    let mut masks = [[0; 4]; TILE_HEIGHT];
+    let mut y = 0;
+    while y < TILE_HEIGHT {
+        let mut level = 0;
+        while level < 4 {
+            let mut x = 0;
+            while x < TILE_WIDTH {
+                let white = if bayer {
+                    bayer_white(level, x + offset, y)
+                } else {
+                    cross_hatch_white(level, x + offset, y)
+                };
+                if white { masks[y][level] |= 1 << x }
+                x += 1;
+            }
+            level += 1;
+        }
+        y += 1;
+    }
+    Ditherer { masks, anchored }
+}
+
+static CROSS_HATCH: [Ditherer; 2] =
+    [build(false, false, 0), build(false, false, 1)];
+static BAYER: [Ditherer; 2] = [build(true, false, 0), build(true, false, 1)];
+static ANCHORED: [Ditherer; 2] =
+    [build(false, true, 0), build(false, true, 1)];
+
+// Which column of the tile each Playdate column of the stretched picture
+// falls in, lined up either way
+const fn tile_columns (gameboy_x: &[u8; GAME_WIDTH]) -> [u8; GAME_WIDTH] {
+    let mut columns = [0; GAME_WIDTH];
+    let mut x = 0;
+    while x < GAME_WIDTH {
+        columns[x] = (gameboy_x[x] as usize % TILE_WIDTH) as u8;
+        x += 1;
+    }
+    columns
+}
+
+const fn screen_columns () -> [u8; GAME_WIDTH] {
+    let mut columns = [0; GAME_WIDTH];
+    let mut x = 0;
+    while x < GAME_WIDTH {
+        columns[x] = (x % TILE_WIDTH) as u8;
+        x += 1;
+    }
+    columns
+}
+
+static SCREEN_COLUMNS: [u8; GAME_WIDTH] = screen_columns();
+static GAMEBOY_COLUMNS: [u8; GAME_WIDTH] = tile_columns(&blit::GAMEBOY_X);
+
+impl Ditherer {
+    // The masks for Playdate row y, which shows Gameboy row gameboy_y
+    #[inline(always)]
+    pub fn row (&self, y: usize, gameboy_y: usize) -> &[u16; 4] {
+        let tile_y = if self.anchored { gameboy_y } else { y };
+        &self.masks[tile_y % TILE_HEIGHT]
+    }
+
+    // Tile columns for every column of the stretched picture
+    pub fn columns (&self) -> &'static [u8; GAME_WIDTH] {
+        if self.anchored { &GAMEBOY_COLUMNS } else { &SCREEN_COLUMNS }
+    }
+
+    // The tile column for Playdate column x showing Gameboy column
+    // gameboy_x, for pictures that aren't stretched the usual way
+    #[inline(always)]
+    pub fn column (&self, x: usize, gameboy_x: usize) -> u8 {
+        let tile_x = if self.anchored { gameboy_x } else { x };
+        (tile_x % TILE_WIDTH) as u8
+    }
+}
+
+// Whether a pixel of shade level (0 is black, 3 white) is lit, given its
+// row's masks and its column in the tile
+#[inline(always)]
+pub fn is_white (row: &[u16; 4], level: u8, column: u8) -> bool {
+    (row[level as usize] >> column) & 1 != 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Lit pixels of each shade over one whole tile
+    fn lit (ditherer: &Ditherer, level: u8) -> usize {
+        let mut count = 0;
+        for y in 0..TILE_HEIGHT {
+            for x in 0..TILE_WIDTH {
+                let row = ditherer.row(y, y);
+                if is_white(row, level, ditherer.column(x, x)) { count += 1 }
+            }
+        }
+        count
+    }
+
+    #[test]
+    fn black_and_white_are_solid () {
+        for dither in DITHERS {
+            for ditherer in dither.ditherers() {
+                assert_eq!(lit(ditherer, 0), 0);
+                assert_eq!(lit(ditherer, 3), TILE_WIDTH * TILE_HEIGHT);
+            }
+        }
+    }
+
+    #[test]
+    fn cross_hatch_density () {
+        // A third of the pixels for dark grey and half for light
+        for dither in [Dither::CrossHatch, Dither::Anchored].iter() {
+            for ditherer in dither.ditherers() {
+                assert_eq!(lit(ditherer, 1), 16);
+                assert_eq!(lit(ditherer, 2), 24);
+            }
+        }
+    }
+
+    #[test]
+    fn bayer_density () {
+        // 5 and 10 of every 16, as near a third and two thirds as it goes
+        for ditherer in Dither::Bayer.ditherers() {
+            assert_eq!(lit(ditherer, 1), 15);
+            assert_eq!(lit(ditherer, 2), 30);
+        }
+    }
+
+    #[test]
+    fn names_round_trip () {
+        for dither in DITHERS {
+            assert!(Dither::from_name(dither.name()) == Some(*dither));
+        }
+    }
+}
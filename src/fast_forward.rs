@@ -0,0 +1,77 @@
+use anyhow::Error;
+use core::fmt::Write;
+use crankstart::graphics::{Graphics, LCDColor, LCDSolidColor};
+use crankstart_sys::LCDBitmapDrawMode;
+use euclid::{point2, rect};
+
+use crate::layout::GAME_LEFT;
+use crate::line::Line;
+
+// Fast forward runs fast_forward_speed frames per update and only shows the
+// last. It's on while it's toggled from the quick menu, while a
+// hold_fast_forward hotkey is held, or, with crank_fast_forward on, while
+// the crank is spun steadily - handy for grinding.
+//
+// A spin has to keep up SPIN_DEGREES_PER_SECOND for SPIN_FRAMES in a row
+// before it counts, so a turn or flick for Start and Select doesn't set it
+// off, and it carries on until the crank slows to under half that. While
+// it's on, start_select.rs holds the crank's own presses back until the
+// crank stops, and drops them if it turned into a spin.
+
+// About a turn a second
+const SPIN_DEGREES_PER_SECOND: f32 = 360.;
+const SPIN_FRAMES: usize = 5;
+
+// Under the mash assist marker
+const INDICATOR_LEFT: i32 = 4;
+const INDICATOR_TOP: i32 = 76;
+
+pub struct CrankSpin {
+    // Updates in a row at spinning speed
+    fast_frames: usize,
+    spinning: bool
+}
+
+impl CrankSpin {
+    pub fn new () -> Self {
+        Self { fast_frames: 0, spinning: false }
+    }
+
+    // Call once per update with System::get_crank_change. Returns whether
+    // it's spinning.
+    pub fn update (&mut self, crank_change: f32, frame_rate: usize) -> bool {
+        let spin_speed = SPIN_DEGREES_PER_SECOND / frame_rate as f32;
+        let speed = crank_change.abs();
+
+        if self.spinning {
+            if speed < spin_speed / 2. {
+                self.spinning = false;
+                self.fast_frames = 0;
+            }
+        } else if speed >= spin_speed {
+            self.fast_frames += 1;
+            self.spinning = self.fast_frames >= SPIN_FRAMES;
+        } else {
+            self.fast_frames = 0;
+        }
+        self.spinning
+    }
+}
+
+// "x3" in the left border while it's going. Like the other markers, call
+// this after the blit, and redraw the border when it goes away.
+pub fn draw_indicator (speed: usize) -> Result<(), Error> {
+    let graphics = Graphics::get();
+    let width = GAME_LEFT as i32 - INDICATOR_LEFT * 2;
+    graphics.fill_rect(
+        rect(INDICATOR_LEFT, INDICATOR_TOP, width.min(28), 20),
+        LCDColor::Solid(LCDSolidColor::kColorWhite)
+    )?;
+    graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeCopy)?;
+    let mut label = Line::new();
+    let _ = write!(label, "x{}", speed);
+    graphics.draw_text(
+        label.as_str(), point2(INDICATOR_LEFT + 2, INDICATOR_TOP + 1)
+    )?;
+    Ok(())
+}
@@ -0,0 +1,262 @@
+use alloc::format;
+use anyhow::Error;
+use core::fmt::Write;
+use crankstart::{
+    graphics::{Graphics, LCDColor, LCDSolidColor},
+    system::System
+};
+use crankstart_sys::LCDBitmapDrawMode;
+use euclid::{point2, rect};
+
+use crate::layout::GAME_LEFT;
+use crate::line::Line;
+
+// Keeps track of how much of each frame's time budget we actually use.
+//
+// NOTE: There's nothing for us to do with the spare time ourselves - once
+//   update returns, the Playdate OS sleeps until the next frame is due at
+//   the refresh rate we asked for. What we *can* do is hand back more of
+//   the budget by skipping work, like re-blitting a frame that hasn't
+//   changed.
+//
+// When updates keep going over budget, the game slows down with them, so
+// we start leaving every other frame undrawn (see behind). The game's
+// still emulated every update, so it keeps its speed, and the screen only
+// misses a frame now and then. It stops as soon as a drawn update fits
+// in the budget again.
+pub struct FrameStats {
+    // When the current update started, and the one before (None before the
+    // first)
+    start_ms: usize,
+    last_start_ms: Option<usize>,
+    // Running totals since the game started
+    frames: usize,
+    busy_ms: usize,
+    skipped_blits: usize,
+    rows_marked: usize,
+    // Drawn updates in a row that went over budget, and whether the last
+    // update was left undrawn for it
+    over_budget: usize,
+    skipped_draw: bool,
+    // Since the last console report
+    report_frames: usize,
+    report_busy_ms: usize,
+    report_rows_marked: usize,
+    report_skipped_draws: usize,
+    // For the perf_overlay: this update's milliseconds in the emulator and
+    // the blit, and the once-a-second numbers - updates a second and the
+    // average update's milliseconds - with the second they're being
+    // counted over
+    emulate_ms: usize,
+    blit_ms: usize,
+    shown_fps: usize,
+    shown_average_ms: usize,
+    window_start_ms: Option<usize>,
+    window_frames: usize,
+    window_busy_ms: usize
+}
+
+// Seconds between the headroom lines on the console
+const REPORT_SECONDS: usize = 10;
+// A longer wait than this between game updates means something else had
+// the screen: the system menu, or one of our own menus
+const RESUME_GAP_MS: usize = 250;
+// Drawn updates in a row over budget before frames start being left
+// undrawn, so one slow update (a save, a bank-heavy scene) doesn't do it
+const BEHIND_UPDATES: usize = 3;
+
+// The overlay goes in the left border, under the other markers and above
+// where a toast would be
+const OVERLAY_LEFT: i32 = 4;
+const OVERLAY_TOP: i32 = 108;
+const OVERLAY_LINE_HEIGHT: i32 = 20;
+
+impl FrameStats {
+    pub fn new () -> Self {
+        Self {
+            start_ms: 0,
+            last_start_ms: None,
+            frames: 0,
+            busy_ms: 0,
+            skipped_blits: 0,
+            rows_marked: 0,
+            over_budget: 0,
+            skipped_draw: false,
+            report_frames: 0,
+            report_busy_ms: 0,
+            report_rows_marked: 0,
+            report_skipped_draws: 0,
+            emulate_ms: 0,
+            blit_ms: 0,
+            shown_fps: 0,
+            shown_average_ms: 0,
+            window_start_ms: None,
+            window_frames: 0,
+            window_busy_ms: 0
+        }
+    }
+
+    // For timing the parts of an update, with emulation_done and blit_done
+    pub fn now_ms () -> Result<usize, Error> {
+        System::get().get_current_time_milliseconds()
+    }
+
+    pub fn emulation_done (&mut self, started_ms: usize) -> Result<(), Error> {
+        self.emulate_ms = Self::now_ms()?.wrapping_sub(started_ms);
+        Ok(())
+    }
+
+    pub fn blit_done (&mut self, started_ms: usize) -> Result<(), Error> {
+        self.blit_ms = Self::now_ms()?.wrapping_sub(started_ms);
+        Ok(())
+    }
+
+    // Returns true when the game's coming back after being away for a
+    // while, since anything like the crank that was read across the gap is
+    // stale
+    pub fn frame_started (&mut self) -> Result<bool, Error> {
+        self.start_ms = System::get().get_current_time_milliseconds()?;
+        let resumed = match self.last_start_ms {
+            Some(last) => self.start_ms.wrapping_sub(last) > RESUME_GAP_MS,
+            None => false
+        };
+        self.last_start_ms = Some(self.start_ms);
+        Ok(resumed)
+    }
+
+    // Whether we're far enough behind that this update shouldn't draw the
+    // game. Never two in a row, so it can't turn into a slideshow.
+    pub fn behind (&self) -> bool {
+        self.over_budget >= BEHIND_UPDATES && !self.skipped_draw
+    }
+
+    // skipped_draw is whether the update left the game undrawn because
+    // we're behind, where blit_skipped is the frame not having changed
+    pub fn frame_finished (
+        &mut self,
+        blit_skipped: bool,
+        skipped_draw: bool,
+        rows_marked: usize,
+        frame_rate: usize
+    ) -> Result<(), Error> {
+        let now = System::get().get_current_time_milliseconds()?;
+        let busy_ms = now.wrapping_sub(self.start_ms);
+        self.busy_ms += busy_ms;
+        self.frames += 1;
+        if blit_skipped { self.skipped_blits += 1 }
+        self.rows_marked += rows_marked;
+
+        // Undrawn updates are quick by design, so only drawn ones say
+        // whether we've caught up
+        self.skipped_draw = skipped_draw;
+        if skipped_draw {
+            self.report_skipped_draws += 1;
+        } else if busy_ms * frame_rate > 1000 {
+            self.over_budget += 1;
+        } else {
+            self.over_budget = 0;
+        }
+
+        self.report_frames += 1;
+        self.report_busy_ms += busy_ms;
+        self.report_rows_marked += rows_marked;
+        if self.report_frames == REPORT_SECONDS * frame_rate {
+            self.report(frame_rate);
+        }
+
+        self.window_frames += 1;
+        self.window_busy_ms += busy_ms;
+        let window_start = *self.window_start_ms.get_or_insert(now);
+        let window_ms = now.wrapping_sub(window_start);
+        if window_ms >= 1000 {
+            self.shown_fps = self.window_frames * 1000 / window_ms;
+            self.shown_average_ms = self.window_busy_ms / self.window_frames;
+            self.window_start_ms = Some(now);
+            self.window_frames = 0;
+            self.window_busy_ms = 0;
+        }
+        Ok(())
+    }
+
+    // Updates a second, milliseconds emulating and blitting last update,
+    // and the average update's milliseconds over the last second:
+    //
+    //   15fps
+    //   E41ms
+    //   B9ms
+    //   ~55ms
+    //
+    // Like the other markers, call this after the blit, and redraw the
+    // border when it goes away.
+    pub fn draw_overlay (&self) -> Result<(), Error> {
+        let mut lines = [Line::new(), Line::new(), Line::new(), Line::new()];
+        // Nothing here can be too long for a Line
+        let _ = write!(lines[0], "{}fps", self.shown_fps);
+        let _ = write!(lines[1], "E{}ms", self.emulate_ms);
+        let _ = write!(lines[2], "B{}ms", self.blit_ms);
+        let _ = write!(lines[3], "~{}ms", self.shown_average_ms);
+
+        let graphics = Graphics::get();
+        let height = OVERLAY_LINE_HEIGHT * lines.len() as i32;
+        graphics.fill_rect(
+            rect(
+                OVERLAY_LEFT,
+                OVERLAY_TOP,
+                GAME_LEFT as i32 - OVERLAY_LEFT * 2,
+                height
+            ),
+            LCDColor::Solid(LCDSolidColor::kColorWhite)
+        )?;
+        graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeCopy)?;
+        for (i, line) in lines.iter().enumerate() {
+            let top = OVERLAY_TOP + OVERLAY_LINE_HEIGHT * i as i32;
+            graphics.draw_text(line.as_str(), point2(OVERLAY_LEFT + 2, top))?;
+        }
+        Ok(())
+    }
+
+    // Straight to the console rather than through log!, so it doesn't push
+    // anything useful out of the log ring
+    fn report (&mut self, frame_rate: usize) {
+        let budget_ms = self.report_frames * 1000 / frame_rate;
+        let headroom = 100 - (self.report_busy_ms * 100 / budget_ms) as isize;
+        // What we could manage if the refresh rate weren't holding us back
+        let busy_ms = self.report_busy_ms.max(1) as f32;
+        let possible_fps = self.report_frames as f32 * 1000. / busy_ms;
+        System::log_to_console(&format!(
+            "{}% headroom ({:.1} fps possible), {} rows marked per frame, \
+                {} of {} frames left undrawn",
+            headroom,
+            possible_fps,
+            self.report_rows_marked / self.report_frames,
+            self.report_skipped_draws,
+            self.report_frames
+        ));
+        self.report_frames = 0;
+        self.report_busy_ms = 0;
+        self.report_rows_marked = 0;
+        self.report_skipped_draws = 0;
+    }
+
+    // How much of the frame budget is left over on average, as a
+    // percentage. Negative when we can't keep up.
+    pub fn headroom_percent (&self, frame_rate: usize) -> isize {
+        if self.frames == 0 { return 100 }
+        let budget_ms = self.frames * 1000 / frame_rate;
+        100 - (self.busy_ms * 100 / budget_ms) as isize
+    }
+
+    pub fn skipped_blits (&self) -> usize {
+        self.skipped_blits
+    }
+
+    // Out of LCD_ROWS, on average
+    pub fn rows_marked_per_frame (&self) -> usize {
+        if self.frames == 0 { return 0 }
+        self.rows_marked / self.frames
+    }
+
+    pub fn frames (&self) -> usize {
+        self.frames
+    }
+}
@@ -0,0 +1,35 @@
+// Counts writes that fell outside the framebuffer in release builds, where
+// we'd rather drop a byte than crash or scribble over the next row.
+static mut OUT_OF_BOUNDS_WRITES: usize = 0;
+
+pub fn out_of_bounds_writes () -> usize {
+    unsafe { OUT_OF_BOUNDS_WRITES }
+}
+
+// Writes 8 horizontal pixels to the Playdate framebuffer.
+// byte_x is in bytes (ie. pixels / 8) and row_stride is the number of bytes
+// in one framebuffer row.
+#[inline(always)]
+pub fn draw_byte_at (
+    framebuffer: &mut [u8],
+    row_stride: usize,
+    byte_x: usize,
+    y: usize,
+    byte: u8
+) {
+    let in_bounds = byte_x < row_stride &&
+        y * row_stride + byte_x < framebuffer.len();
+
+    debug_assert!(
+        in_bounds,
+        "framebuffer write out of bounds at byte x {} (pixel x {}), y {}",
+        byte_x, byte_x * 8, y
+    );
+
+    if !in_bounds {
+        unsafe { OUT_OF_BOUNDS_WRITES += 1; }
+        return
+    }
+
+    framebuffer[y * row_stride + byte_x] = byte;
+}
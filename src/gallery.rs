@@ -0,0 +1,320 @@
+use alloc::{format, string::String, vec, vec::Vec};
+use anyhow::Error;
+use crankstart::{
+    file::FileSystem,
+    graphics::{Bitmap, Graphics, LCDColor, LCDSolidColor}
+};
+use crankstart_sys::{
+    LCDBitmapDrawMode, LCDBitmapFlip, PDButtons, LCD_COLUMNS, LCD_ROWS
+};
+use euclid::{point2, rect, size2};
+
+use crate::blit;
+use crate::dither::Dither;
+use crate::input;
+use crate::keyboard::TextEntry;
+use crate::layout::{GAME_HEIGHT, GAME_LEFT, GAME_WIDTH};
+use crate::menu::{ListMenu, MenuEvent};
+use crate::screenshot;
+use crate::storage::{self, FileStore, PlaydateFiles};
+use crate::strings::{self, tr, trf, Text};
+
+const BAR_HEIGHT: i32 = 24;
+// Longer names don't fit in the bar
+const MAX_NAME_LENGTH: usize = 24;
+
+struct Shot {
+    // Just the name, screenshot::shot_path has the folder
+    filename: String,
+    // "2022-08-06 14:03", or empty if the filesystem couldn't say
+    taken: String,
+    // Sorts the same way as the date does
+    sort_key: (u32, u32, u32, u32, u32, u32)
+}
+
+fn find_shots () -> Vec<Shot> {
+    let file_system = FileSystem::get();
+    let mut shots = vec![];
+
+    for filename in screenshot::list_shots() {
+        let path = screenshot::shot_path(&filename[..]);
+        let (taken, sort_key) = match file_system.stat(&path[..]) {
+            Ok(stat) => (
+                format!(
+                    "{}-{:02}-{:02} {:02}:{:02}",
+                    stat.m_year, stat.m_month, stat.m_day,
+                    stat.m_hour, stat.m_minute
+                ),
+                (
+                    stat.m_year as u32, stat.m_month as u32,
+                    stat.m_day as u32, stat.m_hour as u32,
+                    stat.m_minute as u32, stat.m_second as u32
+                )
+            ),
+            Err(_) => (String::new(), (0, 0, 0, 0, 0, 0))
+        };
+        shots.push(Shot { filename, taken, sort_key });
+    }
+
+    // Newest first. Shots from the same second fall back to the counter in
+    // their names.
+    shots.sort_by(|a, b| {
+        b.sort_key.cmp(&a.sort_key).then(b.filename.cmp(&a.filename))
+    });
+    shots
+}
+
+// Renders a screenshot into a bitmap the size of the game screen, dithered
+// the same way the game itself is drawn.
+fn load_image (filename: &str, dither: Dither) -> Result<Bitmap, Error> {
+    let ditherer = dither.ditherer();
+    let bytes = storage::read_file(filename)?;
+    let frame = screenshot::decode(&bytes[..])?;
+
+    let graphics = Graphics::get();
+    let bitmap = graphics.new_bitmap(
+        size2(GAME_WIDTH as i32, GAME_HEIGHT as i32),
+        LCDColor::Solid(LCDSolidColor::kColorWhite)
+    )?;
+
+    // There's no direct pixel access here, so the black parts go in as
+    // runs to keep the number of fill_rect calls down
+    graphics.with_context(&bitmap, || {
+        for y in 0..GAME_HEIGHT {
+            let mut run_start = None;
+            for x in 0..=GAME_WIDTH {
+                let black = x < GAME_WIDTH && {
+                    let shade = &frame[blit::frame_index(x, y)];
+                    !blit::shade_is_white(ditherer, shade, x, y)
+                };
+
+                match (black, run_start) {
+                    (true, None) => run_start = Some(x),
+                    (false, Some(start)) => {
+                        let width = (x - start) as i32;
+                        graphics.fill_rect(
+                            rect(start as i32, y as i32, width, 1),
+                            LCDColor::Solid(LCDSolidColor::kColorBlack)
+                        )?;
+                        run_start = None;
+                    },
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    })?;
+
+    Ok(bitmap)
+}
+
+// Browses the screenshots in the data folder one at a time, full-screen
+pub struct Gallery {
+    shots: Vec<Shot>,
+    index: usize,
+    // The shot being looked at, or None if it couldn't be read
+    image: Option<Bitmap>,
+    // Drawn the same way as the game
+    dither: Dither,
+    // Up while asking whether to delete the current shot
+    confirm: Option<ListMenu>,
+    // Up while typing a new name for it
+    rename: Option<TextEntry>
+}
+
+impl Gallery {
+    // Like the other screens, this draws straight away
+    pub fn new (dither: Dither) -> Result<Self, Error> {
+        let mut gallery = Self {
+            shots: find_shots(),
+            index: 0,
+            image: None,
+            dither,
+            confirm: None,
+            rename: None
+        };
+        gallery.load_current();
+        gallery.redraw()?;
+        Ok(gallery)
+    }
+
+    fn load_current (&mut self) {
+        self.image = None;
+        if self.index >= self.shots.len() { return }
+
+        let filename = &self.shots[self.index].filename[..];
+        let path = screenshot::shot_path(filename);
+        match load_image(&path[..], self.dither) {
+            Ok(image) => self.image = Some(image),
+            Err(e) => log!("Couldn't show {}: {}", filename, e)
+        }
+    }
+
+    fn draw_bar (top: i32, text: &str) -> Result<(), Error> {
+        let graphics = Graphics::get();
+        graphics.fill_rect(
+            rect(0, top, LCD_COLUMNS as i32, BAR_HEIGHT),
+            LCDColor::Solid(LCDSolidColor::kColorBlack)
+        )?;
+        graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeInverted)?;
+        let text = strings::ellipsize(text, LCD_COLUMNS as i32 - 12);
+        graphics.draw_text(&text[..], point2(6, top + 4))?;
+        Ok(())
+    }
+
+    pub fn redraw (&self) -> Result<(), Error> {
+        if let Some(confirm) = &self.confirm {
+            return confirm.draw()
+        }
+        if let Some(rename) = &self.rename {
+            return rename.redraw()
+        }
+
+        let graphics = Graphics::get();
+        graphics.clear(LCDColor::Solid(LCDSolidColor::kColorWhite))?;
+
+        if self.shots.len() == 0 {
+            graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeCopy)?;
+            graphics.draw_text(tr(Text::NoScreenshots), point2(20, 50))?;
+            return Self::draw_bar(
+                LCD_ROWS as i32 - BAR_HEIGHT, tr(Text::GalleryBack)
+            )
+        }
+
+        let shot = &self.shots[self.index];
+        match &self.image {
+            Some(image) => image.draw(
+                point2(GAME_LEFT as i32, 0),
+                LCDBitmapFlip::kBitmapUnflipped
+            )?,
+            None => {
+                graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeCopy)?;
+                let message = strings::wrap(
+                    tr(Text::ScreenshotUnreadable), LCD_COLUMNS as i32 - 40
+                );
+                graphics.draw_text(&message[..], point2(20, 100))?;
+            }
+        }
+
+        Self::draw_bar(0, &format!("{}  {}", shot.filename, shot.taken)[..])?;
+        Self::draw_bar(
+            LCD_ROWS as i32 - BAR_HEIGHT,
+            &trf(
+                Text::GalleryHelp,
+                &[&format!("{}/{}", self.index + 1, self.shots.len())]
+            )[..]
+        )
+    }
+
+    fn delete_current (&mut self) {
+        let filename = self.shots[self.index].filename.clone();
+        let path = screenshot::shot_path(&filename[..]);
+        if let Err(e) = PlaydateFiles.remove(&path[..]) {
+            log!("Couldn't delete {}: {}", filename, e);
+            return
+        }
+        log!("Deleted {}", filename);
+
+        self.shots.remove(self.index);
+        if self.index >= self.shots.len() && self.index > 0 {
+            self.index -= 1;
+        }
+        self.load_current();
+    }
+
+    // Keeps the shot_ prefix and .pgm ending, so it's still a screenshot
+    fn rename_current (&mut self, name: &str) {
+        if name.len() == 0 { return }
+
+        let shot = &mut self.shots[self.index];
+        let new_filename = format!(
+            "{}{}{}", screenshot::SHOT_PREFIX, name, screenshot::SHOT_EXTENSION
+        );
+        if new_filename == shot.filename { return }
+        let path = screenshot::shot_path(&shot.filename[..]);
+        let new_path = screenshot::shot_path(&new_filename[..]);
+        if PlaydateFiles.size(&new_path[..]).is_ok() {
+            log!(
+                "Not renaming {}, {} already exists",
+                shot.filename, new_filename
+            );
+            return
+        }
+
+        match PlaydateFiles.rename(&path[..], &new_path[..]) {
+            Ok(()) => {
+                log!("Renamed {} to {}", shot.filename, new_filename);
+                shot.filename = new_filename;
+            },
+            Err(e) => log!("{}", e)
+        }
+    }
+
+    // Returns true once they've backed out
+    pub fn update (&mut self) -> Result<bool, Error> {
+        if let Some(confirm) = &mut self.confirm {
+            match confirm.update()? {
+                Some(MenuEvent::Picked(1)) => self.delete_current(),
+                Some(_) => {},
+                None => return Ok(false)
+            }
+            self.confirm = None;
+            self.redraw()?;
+            return Ok(false)
+        }
+
+        if let Some(rename) = &mut self.rename {
+            match rename.update()? {
+                Some(Some(name)) => self.rename_current(&name[..]),
+                Some(None) => {},
+                None => return Ok(false)
+            }
+            self.rename = None;
+            self.redraw()?;
+            return Ok(false)
+        }
+
+        let (_, btns_down, _) = input::button_state()?;
+        let pressed = |button: PDButtons| (btns_down & button) == button;
+
+        if pressed(PDButtons::kButtonB) { return Ok(true) }
+        if self.shots.len() == 0 { return Ok(false) }
+
+        let count = self.shots.len();
+        if pressed(PDButtons::kButtonRight) && count > 1 {
+            self.index = (self.index + 1) % count;
+            self.load_current();
+            self.redraw()?;
+        } else if pressed(PDButtons::kButtonLeft) && count > 1 {
+            self.index = (self.index + count - 1) % count;
+            self.load_current();
+            self.redraw()?;
+        } else if pressed(PDButtons::kButtonUp) {
+            let filename = &self.shots[self.index].filename[..];
+            let name = &filename[
+                screenshot::SHOT_PREFIX.len()..
+                    filename.len() - screenshot::SHOT_EXTENSION.len()
+            ];
+            // Spaces are a pain in filenames on a computer
+            self.rename = Some(TextEntry::new(
+                tr(Text::RenameScreenshot),
+                name,
+                MAX_NAME_LENGTH,
+                |c| c != ' '
+            )?);
+        } else if pressed(PDButtons::kButtonA) {
+            let title = trf(
+                Text::DeleteQuestion, &[&self.shots[self.index].filename]
+            );
+            self.confirm = Some(ListMenu::new(
+                &title[..],
+                vec![
+                    String::from(tr(Text::KeepIt)),
+                    String::from(tr(Text::Delete))
+                ]
+            )?);
+        }
+
+        Ok(false)
+    }
+}
@@ -0,0 +1,339 @@
+use alloc::{format, string::String};
+use anyhow::{anyhow, Error};
+
+use crate::strings::{trf, Text};
+
+// Every Gameboy ROM carries a header at 0x100-0x14F describing the cart
+const TITLE_START: usize = 0x134;
+const TITLE_END: usize = 0x144;
+
+// The game's title as gbrs reports it to the save callbacks - the header
+// title bytes up to the first NUL, with padding trimmed off.
+pub fn title (rom: &[u8]) -> String {
+    let mut title = String::new();
+    if rom.len() < TITLE_END { return title }
+
+    for &byte in &rom[TITLE_START..TITLE_END] {
+        if byte == 0 { break }
+        title.push(byte as char);
+    }
+
+    String::from(title.trim())
+}
+
+const HEADER_CHECKSUM: usize = 0x14D;
+
+// The checksum byte the header carries. It's only 8 bits, but it's enough
+// to tell most ROMs apart.
+pub fn header_checksum (rom: &[u8]) -> u8 {
+    if rom.len() <= HEADER_CHECKSUM { return 0 }
+    rom[HEADER_CHECKSUM]
+}
+
+const CGB_FLAG: usize = 0x143;
+
+// Whether the cart says it can make use of a Gameboy Color (0x80), or
+// needs one (0xC0)
+pub fn supports_cgb (rom: &[u8]) -> bool {
+    if rom.len() <= CGB_FLAG { return false }
+    rom[CGB_FLAG] & 0x80 != 0
+}
+
+// Colour-only carts. gbrs has none of the Gameboy Color's hardware (its
+// palettes, second VRAM bank or double speed), so these can't run.
+pub fn needs_cgb (rom: &[u8]) -> bool {
+    rom.get(CGB_FLAG) == Some(&0xC0)
+}
+
+const ROM_SIZE: usize = 0x148;
+
+// A file that was cut off while it was being copied still has a header
+// saying how big the whole ROM is. gbrs would run off the end of it as soon
+// as the game switched to a bank that isn't there.
+pub fn check_rom_size (rom: &[u8]) -> Result<(), Error> {
+    if rom.len() <= ROM_SIZE {
+        return Err(anyhow!("only {} bytes, too short for a header", rom.len()))
+    }
+    // 32KB shifted left by the code. Anything past 8 is a bad header
+    // rather than a short file, and gbrs can have a go at those.
+    let code = rom[ROM_SIZE];
+    if code > 8 { return Ok(()) }
+    let declared = (32 * 1024) << code;
+    if rom.len() < declared {
+        return Err(anyhow!(
+            "{} bytes, but the header says {}", rom.len(), declared
+        ))
+    }
+    Ok(())
+}
+
+const CART_TYPE: usize = 0x147;
+const RAM_SIZE: usize = 0x149;
+const SGB_FLAG: usize = 0x146;
+
+// What the cart type byte says is on the cart, as the header spec names
+// them, and whether gbrs has the mapper for it
+const CART_TYPES: &[(u8, &str, bool)] = &[
+    (0x00, "ROM", true),
+    (0x01, "MBC1", true),
+    (0x02, "MBC1+RAM", true),
+    (0x03, "MBC1+RAM+BATTERY", true),
+    (0x05, "MBC2", true),
+    (0x06, "MBC2+BATTERY", true),
+    (0x08, "ROM+RAM", true),
+    (0x09, "ROM+RAM+BATTERY", true),
+    (0x0B, "MMM01", false),
+    (0x0C, "MMM01+RAM", false),
+    (0x0D, "MMM01+RAM+BATTERY", false),
+    (0x0F, "MBC3+TIMER+BATTERY", true),
+    (0x10, "MBC3+TIMER+RAM+BATTERY", true),
+    (0x11, "MBC3", true),
+    (0x12, "MBC3+RAM", true),
+    (0x13, "MBC3+RAM+BATTERY", true),
+    (0x19, "MBC5", true),
+    (0x1A, "MBC5+RAM", true),
+    (0x1B, "MBC5+RAM+BATTERY", true),
+    (0x1C, "MBC5+RUMBLE", true),
+    (0x1D, "MBC5+RUMBLE+RAM", true),
+    (0x1E, "MBC5+RUMBLE+RAM+BATTERY", true),
+    (0x20, "MBC6", false),
+    (0x22, "MBC7+SENSOR+RUMBLE+RAM+BATTERY", false),
+    (0xFC, "POCKET CAMERA", false),
+    (0xFD, "BANDAI TAMA5", false),
+    (0xFE, "HuC3", false),
+    (0xFF, "HuC1+RAM+BATTERY", false)
+];
+
+pub fn cart_type (rom: &[u8]) -> u8 {
+    if rom.len() <= CART_TYPE { return 0 }
+    rom[CART_TYPE]
+}
+
+// "MBC3+RAM+BATTERY", or None for a type byte nobody's heard of
+pub fn cart_name (cart_type: u8) -> Option<&'static str> {
+    CART_TYPES
+        .iter()
+        .find(|(code, _, _)| *code == cart_type)
+        .map(|(_, name, _)| *name)
+}
+
+fn cart_supported (cart_type: u8) -> bool {
+    CART_TYPES
+        .iter()
+        .any(|(code, _, supported)| *code == cart_type && *supported)
+}
+
+// Work out the same sum as the boot ROM does over the title and the rest of
+// the header. A real Gameboy locks up on a cart that gets it wrong, so a
+// mismatch means a damaged file (or not a ROM at all) rather than a fussy
+// header.
+pub fn header_checksum_ok (rom: &[u8]) -> bool {
+    if rom.len() <= HEADER_CHECKSUM { return false }
+    let sum = rom[TITLE_START..HEADER_CHECKSUM]
+        .iter()
+        .fold(0u8, |sum, byte| sum.wrapping_sub(*byte).wrapping_sub(1));
+    sum == rom[HEADER_CHECKSUM]
+}
+
+// In KB, from the header's codes. The ROM size is checked against the file
+// by check_rom_size.
+fn rom_kb (rom: &[u8]) -> Option<usize> {
+    let code = *rom.get(ROM_SIZE)?;
+    if code > 8 { return None }
+    Some(32 << code)
+}
+
+// Bytes of cart RAM, from the header's code, or None if it says there's
+// none. MBC2 says none but has 512 half-bytes of its own, which emulators
+// save a byte each.
+pub fn cart_ram_size (rom: &[u8]) -> Option<usize> {
+    if matches!(cart_type(rom), 0x05 | 0x06) { return Some(512) }
+    match rom.get(RAM_SIZE) {
+        Some(2) => Some(8 * 1024),
+        Some(3) => Some(32 * 1024),
+        Some(4) => Some(128 * 1024),
+        Some(5) => Some(64 * 1024),
+        _ => None
+    }
+}
+
+// One line about the cart for the log, eg.
+// "POKEMON RED: MBC3+RAM+BATTERY, 1024KB ROM, 32KB RAM, SGB"
+pub fn summary (rom: &[u8]) -> String {
+    let cart = cart_type(rom);
+    let mut summary = format!("{}: ", title(rom));
+    match cart_name(cart) {
+        Some(name) => summary.push_str(name),
+        None => summary.push_str(&format!("cart type {:02x}", cart)[..])
+    }
+    if let Some(kb) = rom_kb(rom) {
+        summary.push_str(&format!(", {}KB ROM", kb)[..]);
+    }
+    match cart_ram_size(rom) {
+        Some(size) if size >= 1024 =>
+            summary.push_str(&format!(", {}KB RAM", size / 1024)[..]),
+        Some(size) => summary.push_str(&format!(", {}B RAM", size)[..]),
+        None => {}
+    }
+    if supports_cgb(rom) { summary.push_str(", CGB") }
+    if rom.get(SGB_FLAG) == Some(&0x03) { summary.push_str(", SGB") }
+    if !header_checksum_ok(rom) { summary.push_str(", bad header checksum") }
+    summary
+}
+
+const LOGO_START: usize = 0x104;
+
+// The Nintendo logo the boot ROM scrolls down the screen. It refuses to
+// start a cart unless every byte of it matches.
+const LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B,
+    0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E,
+    0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC,
+    0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E
+];
+
+pub fn logo_ok (rom: &[u8]) -> bool {
+    rom.get(LOGO_START..LOGO_START + LOGO.len()) == Some(&LOGO[..])
+}
+
+pub enum CartProblem {
+    BadLogo,
+    BadChecksum,
+    // The cart type byte
+    Unsupported(u8),
+    ColourOnly
+}
+
+impl CartProblem {
+    // For the picker, which says what's wrong under the list
+    pub fn describe (&self, rom_name: &str) -> String {
+        match self {
+            CartProblem::BadLogo | CartProblem::BadChecksum =>
+                trf(Text::RomBadHeader, &[&rom_name]),
+            CartProblem::Unsupported(cart) => {
+                let name = match cart_name(*cart) {
+                    Some(name) => String::from(name),
+                    None => format!("{:02x}", cart)
+                };
+                trf(Text::RomUnsupportedCart, &[&rom_name, &name])
+            },
+            CartProblem::ColourOnly => trf(Text::RomColourOnly, &[&rom_name])
+        }
+    }
+}
+
+// Whether gbrs can run this at all, for after check_rom_size. It'd crash or
+// draw garbage on any of these.
+pub fn check_cart (rom: &[u8]) -> Result<(), CartProblem> {
+    if !logo_ok(rom) { return Err(CartProblem::BadLogo) }
+    if !header_checksum_ok(rom) { return Err(CartProblem::BadChecksum) }
+    let cart = cart_type(rom);
+    if !cart_supported(cart) { return Err(CartProblem::Unsupported(cart)) }
+    if needs_cgb(rom) { return Err(CartProblem::ColourOnly) }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    // An empty 32KB cart with a header that passes everything
+    fn rom (cart: u8) -> Vec<u8> {
+        let mut rom = vec![0; 32 * 1024];
+        rom[LOGO_START..LOGO_START + LOGO.len()].copy_from_slice(&LOGO);
+        rom[TITLE_START..TITLE_START + 4].copy_from_slice(b"TEST");
+        rom[CART_TYPE] = cart;
+        fix_checksum(&mut rom);
+        rom
+    }
+
+    fn fix_checksum (rom: &mut [u8]) {
+        rom[HEADER_CHECKSUM] = rom[TITLE_START..HEADER_CHECKSUM]
+            .iter()
+            .fold(0u8, |sum, byte| sum.wrapping_sub(*byte).wrapping_sub(1));
+    }
+
+    #[test]
+    fn good_carts_pass () {
+        for cart in &[0x00, 0x01, 0x13, 0x1B] {
+            let rom = rom(*cart);
+            assert!(check_cart(&rom).is_ok());
+            assert!(check_rom_size(&rom).is_ok());
+        }
+        assert_eq!(title(&rom(0)), "TEST");
+    }
+
+    #[test]
+    fn rejects_a_bad_logo () {
+        let mut rom = rom(0);
+        rom[LOGO_START + 47] ^= 1;
+        assert!(matches!(check_cart(&rom), Err(CartProblem::BadLogo)));
+    }
+
+    #[test]
+    fn rejects_a_bad_header_checksum () {
+        let mut rom = rom(0);
+        rom[HEADER_CHECKSUM] ^= 1;
+        assert!(matches!(check_cart(&rom), Err(CartProblem::BadChecksum)));
+        // The title's covered by the sum too
+        let mut rom = self::rom(0);
+        rom[TITLE_START] = b'B';
+        assert!(!header_checksum_ok(&rom));
+        assert!(summary(&rom).ends_with("bad header checksum"));
+    }
+
+    #[test]
+    fn rejects_unsupported_mappers () {
+        for cart in &[0x0B, 0x20, 0xFC, 0xFF] {
+            let rom = rom(*cart);
+            assert!(matches!(
+                check_cart(&rom),
+                Err(CartProblem::Unsupported(found)) if found == *cart
+            ));
+        }
+        // Ones nobody's heard of too
+        let rom = rom(0x42);
+        assert!(matches!(check_cart(&rom), Err(CartProblem::Unsupported(_))));
+        assert!(cart_name(0x42).is_none());
+    }
+
+    #[test]
+    fn rejects_colour_only_carts () {
+        let mut rom = rom(0x19);
+        rom[CGB_FLAG] = 0xC0;
+        fix_checksum(&mut rom);
+        assert!(matches!(check_cart(&rom), Err(CartProblem::ColourOnly)));
+        rom[CGB_FLAG] = 0x80;
+        fix_checksum(&mut rom);
+        assert!(check_cart(&rom).is_ok());
+    }
+
+    #[test]
+    fn rejects_files_shorter_than_the_header_says () {
+        // 64KB declared
+        let mut rom = rom(0x01);
+        rom[ROM_SIZE] = 1;
+        assert!(check_rom_size(&rom).is_err());
+        rom.resize(64 * 1024, 0);
+        assert!(check_rom_size(&rom).is_ok());
+        // Bigger than declared is fine, it's just padded
+        rom.resize(96 * 1024, 0);
+        assert!(check_rom_size(&rom).is_ok());
+        assert!(check_rom_size(&rom[..ROM_SIZE]).is_err());
+    }
+
+    #[test]
+    fn works_out_cart_ram () {
+        let mut rom = rom(0x03);
+        rom[RAM_SIZE] = 3;
+        assert_eq!(cart_ram_size(&rom), Some(32 * 1024));
+        rom[RAM_SIZE] = 0;
+        assert_eq!(cart_ram_size(&rom), None);
+        // MBC2's is built in, whatever the header says
+        rom[CART_TYPE] = 0x06;
+        assert_eq!(cart_ram_size(&rom), Some(512));
+    }
+}
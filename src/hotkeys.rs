@@ -0,0 +1,232 @@
+use alloc::{format, string::String, vec::Vec};
+use crankstart_sys::PDButtons;
+
+use crate::quick_menu::{self, QuickAction};
+use crate::quickslot;
+use crate::savestate;
+
+// Chords bound to emulator actions, from the hotkeys setting. They're
+// checked before anything else sees the buttons, and a chord that's held
+// eats its buttons for the frame, so neither the game nor the built-in
+// chords react to them as well.
+//
+// The setting is a comma-separated list of chord:action, with the buttons
+// of a chord joined by +, eg. "a+b+right:hold_fast_forward,a+b+up+down:pause"
+
+pub const BUTTONS: &[(&str, PDButtons)] = &[
+    ("a", PDButtons::kButtonA),
+    ("b", PDButtons::kButtonB),
+    ("up", PDButtons::kButtonUp),
+    ("down", PDButtons::kButtonDown),
+    ("left", PDButtons::kButtonLeft),
+    ("right", PDButtons::kButtonRight)
+];
+
+// The Playdate hasn't got any buttons to spare, so holding both of these is
+// as close as we get to a modifier. It's what the built-in chords use too.
+const MODIFIER: PDButtons = PDButtons(
+    PDButtons::kButtonA.0 | PDButtons::kButtonB.0
+);
+
+const BUILT_IN_CHORDS: &[(&str, PDButtons)] = &[
+    ("quick save", quickslot::SAVE_CHORD),
+    ("quick load", quickslot::LOAD_CHORD),
+    ("the quick menu", quick_menu::OPEN_CHORD)
+];
+
+fn held (buttons: PDButtons, chord: PDButtons) -> bool {
+    (buttons & chord) == chord
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum HotkeyAction {
+    // Fires once each time the chord goes down
+    Run(QuickAction),
+    // Fast forwards for as long as the chord is held
+    HoldFastForward,
+    // Mash assist, see mash.rs
+    HoldMash,
+    // Opens the rewind scrubber when the chord goes down, see scrubber.rs
+    Rewind
+}
+
+impl HotkeyAction {
+    fn name (self) -> &'static str {
+        match self {
+            HotkeyAction::Run(action) => action.name(),
+            HotkeyAction::HoldFastForward => "hold_fast_forward",
+            HotkeyAction::HoldMash => "hold_mash",
+            HotkeyAction::Rewind => "rewind"
+        }
+    }
+
+    // See QuickAction::available
+    fn available (self) -> bool {
+        match self {
+            HotkeyAction::Run(action) => action.available(),
+            HotkeyAction::Rewind => savestate::SUPPORTED,
+            _ => true
+        }
+    }
+
+    fn from_name (name: &str) -> Option<Self> {
+        match name {
+            "hold_fast_forward" => Some(HotkeyAction::HoldFastForward),
+            "hold_mash" => Some(HotkeyAction::HoldMash),
+            // What it was called when it went back while held
+            "rewind" | "hold_rewind" => Some(HotkeyAction::Rewind),
+            _ => QuickAction::from_name(name).map(HotkeyAction::Run)
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct Hotkey {
+    pub chord: PDButtons,
+    pub action: HotkeyAction
+}
+
+fn parse_chord (text: &str) -> Option<PDButtons> {
+    let mut chord = 0;
+    let mut count = 0;
+    for name in text.split('+') {
+        let (_, button) = BUTTONS
+            .iter()
+            .find(|(button_name, _)| *button_name == name.trim())?;
+        chord |= button.0;
+        count += 1;
+    }
+    // One button on its own would take it away from the game entirely
+    if count < 2 { None } else { Some(PDButtons(chord)) }
+}
+
+pub fn chord_text (chord: PDButtons) -> String {
+    let names: Vec<&str> = BUTTONS
+        .iter()
+        .filter(|(_, button)| held(chord, *button))
+        .map(|(name, _)| *name)
+        .collect();
+    names.join("+")
+}
+
+// Games use the d-pad with A or B all the time, so a chord without the
+// modifier will sometimes be pressed for the game and fire by accident
+pub fn may_clash_with_game (chord: PDButtons) -> bool {
+    !held(chord, MODIFIER)
+}
+
+// Logs anything about a hotkey that could get in the way
+fn warn_about_clashes (hotkey: &Hotkey) {
+    let chord = hotkey.chord;
+    if may_clash_with_game(chord) {
+        log!(
+            "Hotkey {} doesn't hold A+B, so games may press it too",
+            chord_text(chord)
+        );
+    }
+
+    // One chord that's part of the other means both go off
+    for (name, built_in) in BUILT_IN_CHORDS {
+        if held(chord, *built_in) || held(*built_in, chord) {
+            log!("Hotkey {} overlaps {}", chord_text(chord), name);
+        }
+    }
+}
+
+// "a+b+right:hold_fast_forward,a+b+up+down:pause". Empty means no hotkeys.
+pub fn parse_hotkeys (text: &str) -> Option<Vec<Hotkey>> {
+    let entries: Vec<&str> = text
+        .split(',')
+        .map(|entry| entry.trim())
+        .filter(|entry| entry.len() > 0)
+        .collect();
+
+    let hotkeys: Vec<Hotkey> = entries
+        .iter()
+        .filter_map(|entry| {
+            let hotkey = entry.find(':').and_then(|split| Some(Hotkey {
+                chord: parse_chord(&entry[..split])?,
+                action: HotkeyAction::from_name(entry[split + 1..].trim())?
+            }));
+            match &hotkey {
+                Some(hotkey) => warn_about_clashes(hotkey),
+                None => log!("Ignoring hotkey {}", entry)
+            }
+            hotkey
+        })
+        .collect();
+
+    if entries.len() > 0 && hotkeys.len() == 0 { None } else { Some(hotkeys) }
+}
+
+pub fn hotkeys_text (hotkeys: &[Hotkey]) -> String {
+    let entries: Vec<String> = hotkeys
+        .iter()
+        .map(|hotkey| format!(
+            "{}:{}", chord_text(hotkey.chord), hotkey.action.name()
+        ))
+        .collect();
+    entries.join(",")
+}
+
+// What's left of a frame's input once the hotkeys have had it
+pub struct HotkeyInput {
+    // For the game and the built-in chords
+    pub buttons: PDButtons,
+    pub fired: Vec<QuickAction>,
+    pub fast_forward: bool,
+    pub mash: bool,
+    pub rewind: bool
+}
+
+pub struct HotkeyLayer {
+    last_held: PDButtons
+}
+
+impl HotkeyLayer {
+    pub fn new () -> Self {
+        Self { last_held: PDButtons(0) }
+    }
+
+    // Call once per frame, before the buttons go anywhere else
+    pub fn update (
+        &mut self,
+        hotkeys: &[Hotkey],
+        btns_held: PDButtons
+    ) -> HotkeyInput {
+        let mut consumed = 0;
+        let mut input = HotkeyInput {
+            buttons: btns_held,
+            fired: Vec::new(),
+            fast_forward: false,
+            mash: false,
+            rewind: false
+        };
+
+        for hotkey in hotkeys {
+            // Left to the game, as if it wasn't bound
+            if !hotkey.action.available() { continue }
+            if !held(btns_held, hotkey.chord) { continue }
+            consumed |= hotkey.chord.0;
+
+            match hotkey.action {
+                HotkeyAction::Run(action) => {
+                    if !held(self.last_held, hotkey.chord) {
+                        input.fired.push(action);
+                    }
+                },
+                HotkeyAction::HoldFastForward => input.fast_forward = true,
+                HotkeyAction::HoldMash => input.mash = true,
+                HotkeyAction::Rewind => {
+                    if !held(self.last_held, hotkey.chord) {
+                        input.rewind = true;
+                    }
+                }
+            }
+        }
+
+        self.last_held = btns_held;
+        input.buttons = PDButtons(btns_held.0 & !consumed);
+        input
+    }
+}
@@ -0,0 +1,68 @@
+use anyhow::Error;
+use crankstart::{display::Display, system::System};
+use crankstart_sys::PDButtons;
+
+// The system's upside-down setting, for playing with the crank on the left.
+// We follow it (or the upside_down setting, when that overrides it) by
+// flipping the whole display and swapping the buttons round to match, so
+// the d-pad, A and B all work the way they're now facing.
+static mut FLIPPED: bool = false;
+
+pub fn flipped () -> bool {
+    unsafe { FLIPPED }
+}
+
+pub fn set_flipped (flipped: bool) -> Result<(), Error> {
+    unsafe { FLIPPED = flipped; }
+    Display::get().set_flipped(flipped, flipped)
+}
+
+// Each button and the one in its place when the Playdate's the other way up
+const FLIPPED_PAIRS: &[(PDButtons, PDButtons)] = &[
+    (PDButtons::kButtonUp, PDButtons::kButtonDown),
+    (PDButtons::kButtonLeft, PDButtons::kButtonRight),
+    (PDButtons::kButtonA, PDButtons::kButtonB)
+];
+
+fn flip_buttons (buttons: PDButtons) -> PDButtons {
+    let mut flipped = 0;
+    for (one, other) in FLIPPED_PAIRS {
+        if (buttons & *one) == *one { flipped |= other.0 }
+        if (buttons & *other) == *other { flipped |= one.0 }
+    }
+    PDButtons(flipped)
+}
+
+const DPAD: PDButtons = PDButtons(
+    PDButtons::kButtonUp.0 | PDButtons::kButtonDown.0 |
+        PDButtons::kButtonLeft.0 | PDButtons::kButtonRight.0
+);
+
+// Each direction and the d-pad button pointing that way with the Playdate
+// held sideways, crank at the bottom
+const SIDEWAYS_DPAD: &[(PDButtons, PDButtons)] = &[
+    (PDButtons::kButtonUp, PDButtons::kButtonLeft),
+    (PDButtons::kButtonRight, PDButtons::kButtonUp),
+    (PDButtons::kButtonDown, PDButtons::kButtonRight),
+    (PDButtons::kButtonLeft, PDButtons::kButtonDown)
+];
+
+// The buttons turned for the sideways display mode, so Up is up for the
+// picture. Only the game gets these, since everything else is still drawn
+// the normal way up.
+pub fn sideways_buttons (buttons: PDButtons) -> PDButtons {
+    let mut turned = buttons.0 & !DPAD.0;
+    for (direction, button) in SIDEWAYS_DPAD {
+        if (buttons & *button) == *button { turned |= direction.0 }
+    }
+    PDButtons(turned)
+}
+
+// Held, pushed and released, like System::get_button_state, but the right
+// way round when the display's flipped. Everything reads the buttons
+// through here.
+pub fn button_state () -> Result<(PDButtons, PDButtons, PDButtons), Error> {
+    let (held, pushed, released) = System::get().get_button_state()?;
+    if !flipped() { return Ok((held, pushed, released)) }
+    Ok((flip_buttons(held), flip_buttons(pushed), flip_buttons(released)))
+}
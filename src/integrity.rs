@@ -0,0 +1,172 @@
+use alloc::{format, string::String, vec, vec::Vec};
+use anyhow::Error;
+use crankstart::file::FileSystem;
+
+use crate::menu::{ListMenu, MenuEvent};
+use crate::saves::{self, SaveCheck};
+use crate::storage::{self, FileStore, PlaydateFiles};
+use crate::strings::{tr, trf, Text};
+
+// When a game is picked, we check its files before booting it so that
+// people find out about a corrupt save *before* they play on top of it for
+// an hour.
+
+pub enum Problem {
+    // The battery save is damaged. backup says whether there's a good
+    // backup we could restore instead.
+    BadBatterySave { backup: bool }
+}
+
+impl Problem {
+    fn describe (&self) -> &'static str {
+        match self {
+            Problem::BadBatterySave { .. } => tr(Text::BadBatterySave)
+        }
+    }
+}
+
+// Checks everything belonging to game_name and returns what's wrong.
+// Saves that pass are copied to the backup.
+pub fn check (game_name: &str) -> Vec<Problem> {
+    let store = PlaydateFiles;
+    let save_path = &saves::save_path(game_name)[..];
+    let backup_path = &saves::backup_path(game_name)[..];
+    let mut problems = vec![];
+
+    match saves::check_in(&store, save_path) {
+        SaveCheck::Missing => {},
+        SaveCheck::Intact(data) => {
+            let backed_up =
+                saves::write_checked_in(&store, backup_path, &data[..]);
+            if let Err(e) = backed_up {
+                log!("Couldn't back up {}: {}", save_path, e);
+            }
+        },
+        SaveCheck::Corrupt | SaveCheck::Unreadable => {
+            log!("{} failed its integrity check", save_path);
+            let backup = match saves::check_in(&store, backup_path) {
+                SaveCheck::Intact(_) => true,
+                _ => false
+            };
+            problems.push(Problem::BadBatterySave { backup });
+        }
+    }
+
+    problems
+}
+
+#[derive(Clone, Copy)]
+enum Fix {
+    RestoreBackup,
+    DeleteSave,
+    PlayAnyway
+}
+
+// "Restore backup from 2022-08-06", if the filesystem can tell us when it
+// was written
+fn restore_label (game_name: &str) -> String {
+    match FileSystem::get().stat(&saves::backup_path(game_name)[..]) {
+        Ok(stat) => trf(Text::RestoreBackupFrom, &[&format!(
+            "{}-{:02}-{:02}", stat.m_year, stat.m_month, stat.m_day
+        )]),
+        Err(_) => String::from(tr(Text::RestoreBackup))
+    }
+}
+
+// Walks through each problem in turn, asking what to do about it
+pub struct IntegrityScreen {
+    game_name: String,
+    problems: Vec<Problem>,
+    menu: ListMenu,
+    fixes: Vec<Fix>
+}
+
+impl IntegrityScreen {
+    pub fn new (
+        game_name: &str,
+        problems: Vec<Problem>
+    ) -> Result<Self, Error> {
+        let (menu, fixes) = Self::build_menu(game_name, &problems[0])?;
+        Ok(Self {
+            game_name: String::from(game_name),
+            problems,
+            menu,
+            fixes
+        })
+    }
+
+    fn build_menu (
+        game_name: &str,
+        problem: &Problem
+    ) -> Result<(ListMenu, Vec<Fix>), Error> {
+        let mut items = vec![];
+        let mut fixes = vec![];
+
+        match problem {
+            Problem::BadBatterySave { backup } => {
+                if *backup {
+                    items.push(restore_label(game_name));
+                    fixes.push(Fix::RestoreBackup);
+                }
+                items.push(String::from(tr(Text::DeleteSave)));
+                fixes.push(Fix::DeleteSave);
+            }
+        }
+        items.push(String::from(tr(Text::PlayAnyway)));
+        fixes.push(Fix::PlayAnyway);
+
+        Ok((ListMenu::new(problem.describe(), items)?, fixes))
+    }
+
+    fn apply (&self, fix: Fix) -> Result<(), Error> {
+        let store = PlaydateFiles;
+        let save_path = &saves::save_path(&self.game_name[..])[..];
+
+        match fix {
+            Fix::RestoreBackup => {
+                let backup_path = &saves::backup_path(&self.game_name[..])[..];
+                let data = storage::read_file(backup_path)?;
+                saves::write_checked_in(&store, save_path, &data[..])?;
+                log!("Restored {} from its backup", save_path);
+            },
+            Fix::DeleteSave => {
+                store.remove(save_path)?;
+                let checksum_path = &saves::checksum_path(save_path)[..];
+                if store.size(checksum_path).is_ok() {
+                    store.remove(checksum_path)?;
+                }
+                log!("Deleted {}", save_path);
+            },
+            Fix::PlayAnyway => {}
+        }
+
+        Ok(())
+    }
+
+    pub fn redraw (&self) -> Result<(), Error> {
+        self.menu.draw()
+    }
+
+    // Returns true once every problem has been dealt with
+    pub fn update (&mut self) -> Result<bool, Error> {
+        let fix = match self.menu.update()? {
+            Some(MenuEvent::Picked(index)) => self.fixes[index],
+            // Backing out means the same as carrying on regardless
+            Some(MenuEvent::Closed) => Fix::PlayAnyway,
+            None => return Ok(false)
+        };
+
+        if let Err(e) = self.apply(fix) {
+            log!("Couldn't fix the save: {}", e);
+        }
+
+        self.problems.remove(0);
+        if self.problems.len() == 0 { return Ok(true) }
+
+        let (menu, fixes) =
+            Self::build_menu(&self.game_name[..], &self.problems[0])?;
+        self.menu = menu;
+        self.fixes = fixes;
+        Ok(false)
+    }
+}
@@ -0,0 +1,255 @@
+use alloc::{format, string::String, vec::Vec};
+use anyhow::Error;
+use crankstart::{
+    graphics::{Graphics, LCDColor, LCDSolidColor},
+    system::System
+};
+use crankstart_sys::{LCDBitmapDrawMode, PDButtons, LCD_COLUMNS};
+use euclid::{point2, rect};
+
+use crate::input;
+use crate::strings::{self, tr, Text};
+
+// Text entry for a console without a keyboard, for anything that wants a
+// name typing in. The crank spins through the characters, A types the one
+// in the middle, B rubs out the last one and left/right jump between the
+// groups. Up is done, Down gives up.
+//
+// Like the ListMenu, features open one of these over themselves rather than
+// each doing their own.
+
+const GROUPS: &[&str] = &["abcdefghijklmnopqrstuvwxyz", "0123456789", " "];
+// Degrees of crank per character
+const CRANK_STEP: f32 = 15.;
+
+const BAR_HEIGHT: i32 = 30;
+const PREVIEW_TOP: i32 = 46;
+const PREVIEW_HEIGHT: i32 = 34;
+const WHEEL_TOP: i32 = 104;
+const CELL_WIDTH: i32 = 36;
+const CELL_HEIGHT: i32 = 34;
+// Characters shown either side of the selected one
+const NEIGHBOURS: i32 = 4;
+const GROUPS_TOP: i32 = 158;
+// Room for two lines, for the longer translations
+const HELP_TOP: i32 = 198;
+
+pub struct TextEntry {
+    title: String,
+    text: String,
+    max_length: usize,
+    // Every character the filter let through, and which group it's from
+    characters: Vec<(char, usize)>,
+    selected: usize,
+    // Crank turned since the last step, in degrees
+    crank: f32
+}
+
+impl TextEntry {
+    // allowed narrows down the characters on offer, eg. to keep spaces out
+    // of filenames. Like the other screens, this draws straight away.
+    pub fn new (
+        title: &str,
+        initial: &str,
+        max_length: usize,
+        allowed: fn(char) -> bool
+    ) -> Result<Self, Error> {
+        let characters: Vec<(char, usize)> = GROUPS
+            .iter()
+            .enumerate()
+            .flat_map(|(group, chars)| chars.chars().map(move |c| (c, group)))
+            .filter(|(c, _)| allowed(*c))
+            .collect();
+        let text: String = initial
+            .chars()
+            .filter(|c| characters.iter().any(|(allowed, _)| allowed == c))
+            .take(max_length)
+            .collect();
+
+        let entry = Self {
+            title: String::from(title),
+            text,
+            max_length,
+            characters,
+            selected: 0,
+            crank: 0.
+        };
+        entry.redraw()?;
+        Ok(entry)
+    }
+
+    fn group (&self) -> usize {
+        self.characters.get(self.selected).map_or(0, |(_, group)| *group)
+    }
+
+    // Where the group after (or before) the selected one starts, going
+    // round to the other end
+    fn group_start (&self, forwards: bool) -> usize {
+        let count = self.characters.len();
+        let current = self.group();
+        let group_of = |i: usize| self.characters[i].1;
+        let starts: Vec<usize> = (0..count)
+            .filter(|&i| i == 0 || group_of(i - 1) != group_of(i))
+            .collect();
+        let position = starts
+            .iter()
+            .position(|&start| group_of(start) == current)
+            .unwrap_or(0);
+        if forwards {
+            starts[(position + 1) % starts.len()]
+        } else {
+            starts[(position + starts.len() - 1) % starts.len()]
+        }
+    }
+
+    fn draw_character (
+        &self,
+        c: char,
+        left: i32,
+        top: i32,
+        selected: bool
+    ) -> Result<(), Error> {
+        let graphics = Graphics::get();
+        if selected {
+            graphics.fill_rect(
+                rect(left, top, CELL_WIDTH, CELL_HEIGHT),
+                LCDColor::Solid(LCDSolidColor::kColorBlack)
+            )?;
+            graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeInverted)?;
+        } else {
+            graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeCopy)?;
+        }
+
+        let mut buffer = [0; 4];
+        let (label, offset) = if c == ' ' {
+            (tr(Text::SpaceKey), -8)
+        } else {
+            (&*c.encode_utf8(&mut buffer), 0)
+        };
+        graphics.draw_text(
+            label, point2(left + CELL_WIDTH / 2 - 5 + offset, top + 8)
+        )?;
+        Ok(())
+    }
+
+    pub fn redraw (&self) -> Result<(), Error> {
+        let graphics = Graphics::get();
+        let width = LCD_COLUMNS as i32;
+        graphics.clear(LCDColor::Solid(LCDSolidColor::kColorWhite))?;
+
+        graphics.fill_rect(
+            rect(0, 0, width, BAR_HEIGHT),
+            LCDColor::Solid(LCDSolidColor::kColorBlack)
+        )?;
+        graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeInverted)?;
+        let title = strings::ellipsize(&self.title[..], width - 12);
+        graphics.draw_text(&title[..], point2(6, 6))?;
+
+        // What's been typed so far, in a box, with a cursor on the end
+        graphics.fill_rect(
+            rect(16, PREVIEW_TOP, width - 32, PREVIEW_HEIGHT),
+            LCDColor::Solid(LCDSolidColor::kColorBlack)
+        )?;
+        graphics.fill_rect(
+            rect(18, PREVIEW_TOP + 2, width - 36, PREVIEW_HEIGHT - 4),
+            LCDColor::Solid(LCDSolidColor::kColorWhite)
+        )?;
+        graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeCopy)?;
+        let preview = format!("{}_", self.text);
+        graphics.draw_text(
+            &strings::ellipsize(&preview[..], width - 120)[..],
+            point2(26, PREVIEW_TOP + 8)
+        )?;
+        let length = format!(
+            "{}/{}", self.text.chars().count(), self.max_length
+        );
+        graphics.draw_text(
+            &length[..], point2(width - 76, PREVIEW_TOP + 8)
+        )?;
+
+        // The selected character in the middle, its neighbours either side
+        let count = self.characters.len() as i32;
+        if count > 0 {
+            let middle = (width - CELL_WIDTH) / 2;
+            for offset in -NEIGHBOURS..=NEIGHBOURS {
+                let index = (self.selected as i32 + offset).rem_euclid(count);
+                let (c, _) = self.characters[index as usize];
+                self.draw_character(
+                    c, middle + offset * CELL_WIDTH, WHEEL_TOP, offset == 0
+                )?;
+            }
+        }
+
+        // Which group we're in
+        let mut left = 30;
+        for (group, chars) in GROUPS.iter().enumerate() {
+            if !self.characters.iter().any(|(_, g)| *g == group) { continue }
+            let name = match chars.chars().next() {
+                Some(' ') => String::from(tr(Text::SpaceKey)),
+                _ => chars.chars().take(3).collect::<String>()
+            };
+            let label_width = name.chars().count() as i32 * 10 + 16;
+            if group == self.group() {
+                graphics.fill_rect(
+                    rect(left - 6, GROUPS_TOP - 4, label_width, 28),
+                    LCDColor::Solid(LCDSolidColor::kColorBlack)
+                )?;
+                graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeInverted)?;
+            } else {
+                graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeCopy)?;
+            }
+            graphics.draw_text(&name[..], point2(left, GROUPS_TOP))?;
+            left += label_width + 12;
+        }
+
+        graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeCopy)?;
+        let help = strings::wrap(tr(Text::KeyboardHelp), width - 20);
+        graphics.draw_text(&help[..], point2(10, HELP_TOP))?;
+        Ok(())
+    }
+
+    // None while they're still typing. After that, what they typed, or
+    // Some(None) if they gave up.
+    pub fn update (&mut self) -> Result<Option<Option<String>>, Error> {
+        let system = System::get();
+        let (_, btns_down, _) = input::button_state()?;
+        let pressed = |button: PDButtons| (btns_down & button) == button;
+
+        if pressed(PDButtons::kButtonUp) {
+            return Ok(Some(Some(self.text.clone())))
+        }
+        if pressed(PDButtons::kButtonDown) { return Ok(Some(None)) }
+
+        let mut changed = false;
+        let count = self.characters.len();
+
+        self.crank += system.get_crank_change()?;
+        let steps = (self.crank / CRANK_STEP) as i32;
+        if steps != 0 && count > 0 {
+            self.crank -= steps as f32 * CRANK_STEP;
+            let selected = self.selected as i32 + steps;
+            self.selected = selected.rem_euclid(count as i32) as usize;
+            changed = true;
+        }
+
+        if count > 0 && pressed(PDButtons::kButtonRight) {
+            self.selected = self.group_start(true);
+            changed = true;
+        } else if count > 0 && pressed(PDButtons::kButtonLeft) {
+            self.selected = self.group_start(false);
+            changed = true;
+        }
+
+        if pressed(PDButtons::kButtonA) && count > 0 &&
+            self.text.chars().count() < self.max_length {
+            self.text.push(self.characters[self.selected].0);
+            changed = true;
+        } else if pressed(PDButtons::kButtonB) && self.text.len() > 0 {
+            self.text.pop();
+            changed = true;
+        }
+
+        if changed { self.redraw()? }
+        Ok(None)
+    }
+}
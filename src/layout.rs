@@ -0,0 +1,52 @@
+use crankstart_sys::{LCD_COLUMNS, LCD_ROWS, LCD_ROWSIZE};
+use gbrs_core::constants::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// Everything about where the Gameboy screen lands on the Playdate screen is
+// worked out from the two sets of screen dimensions, so nothing here can go
+// stale if one of them changes.
+
+// Bytes in one row of the Playdate's 1-bit framebuffer. That's a couple
+// more than the screen is wide, so ROW_BYTES is how many of them show.
+pub const ROW_STRIDE: usize = LCD_ROWSIZE as usize;
+pub const ROW_BYTES: usize = LCD_COLUMNS as usize / 8;
+
+// We scale the Gameboy screen up until it's as tall as the Playdate's
+pub const SCALE_FACTOR: f32 = LCD_ROWS as f32 / SCREEN_HEIGHT as f32;
+
+// Size of the scaled-up Gameboy screen in Playdate pixels
+pub const GAME_WIDTH: usize = SCREEN_WIDTH * LCD_ROWS as usize / SCREEN_HEIGHT;
+pub const GAME_HEIGHT: usize = LCD_ROWS as usize;
+
+// Where the Gameboy screen would start if it were perfectly centred
+pub const START_X: usize = (LCD_COLUMNS as usize - GAME_WIDTH) / 2;
+// START_X rounded down to a whole framebuffer byte. We draw from here rather
+// than START_X because it lets us do faster framebuffer maths.
+pub const START_BYTE: usize = START_X / 8;
+
+const _: () = assert!(ROW_BYTES <= ROW_STRIDE);
+const _: () = assert!(GAME_WIDTH == 266);
+const _: () = assert!(START_X == 67);
+const _: () = assert!(START_BYTE == 8);
+const _: () = assert!(START_BYTE * 8 + GAME_WIDTH <= LCD_COLUMNS as usize);
+const _: () = assert!(SCALE_FACTOR > 1.666 && SCALE_FACTOR < 1.667);
+
+// The blit writes whole framebuffer bytes, so this is the span of Playdate
+// columns the Gameboy screen actually covers. Everything either side of it
+// is border.
+pub const GAME_LEFT: usize = START_BYTE * 8;
+pub const GAME_RIGHT: usize = GAME_LEFT + GAME_WIDTH / 8 * 8;
+
+const _: () = assert!(GAME_RIGHT == 328);
+
+// The sideways picture, as it's seen with the Playdate turned: as wide as
+// the Playdate is tall, and the Gameboy's shape. It's drawn from
+// SIDEWAYS_LEFT_BYTE, which is as near the middle as whole bytes go.
+pub const SIDEWAYS_WIDTH: usize = LCD_ROWS as usize;
+pub const SIDEWAYS_HEIGHT: usize =
+    SCREEN_HEIGHT * SIDEWAYS_WIDTH / SCREEN_WIDTH;
+pub const SIDEWAYS_LEFT_BYTE: usize =
+    (LCD_COLUMNS as usize - SIDEWAYS_HEIGHT) / 2 / 8;
+
+const _: () = assert!(SIDEWAYS_HEIGHT == 216);
+const _: () = assert!(SIDEWAYS_HEIGHT % 8 == 0);
+const _: () = assert!(SIDEWAYS_LEFT_BYTE == 11);
@@ -1,24 +1,132 @@
-#![no_std]
+// Tests run on the host, with std
+#![cfg_attr(not(test), no_std)]
 
 extern crate alloc;
 
-use core::ffi::c_void;
+use core::ffi::{c_char, c_void, CStr};
+use core::ops::RangeInclusive;
+use core::ptr::addr_of_mut;
 
-use alloc::{boxed::Box, vec, format};
-use anyhow::Error;
+use alloc::{
+    boxed::Box, collections::BTreeMap, format, string::String, vec, vec::Vec
+};
+use anyhow::{anyhow, Error};
 use crankstart::{
-    crankstart_game, file::FileSystem,
+    crankstart_game,
+    display::Display,
     graphics::{Graphics, LCDColor, LCDSolidColor},
     system::System,
-    Game, Playdate, log_to_console
+    Game, Playdate
 };
-use crankstart_sys::{FileOptions, PDButtons, LCD_ROWS};
-use euclid::num::Floor;
-
-use gbrs_core::{callbacks::*, constants::*, cpu::Cpu, lcd::GreyShade};
+use crankstart_sys::{PDButtons, PDPeripherals, LCD_ROWS};
+use gbrs_core::{callbacks::*, cpu::Cpu};
 
+#[macro_use]
+mod log;
+mod autocrop;
+mod blit;
+mod boot_macro;
+mod border;
+mod bugreport;
+mod button_map;
+mod cart_error;
+mod cheats;
+mod checksum;
+mod contrast;
+mod dither;
+mod display_mode;
+mod dev_commands;
+mod fast_forward;
+mod frame_stats;
+mod framebuffer;
+mod gallery;
+mod header;
+mod hotkeys;
+mod input;
+mod integrity;
+mod keyboard;
+mod layout;
+mod line;
+mod manual;
+mod mash;
+mod menu;
+mod migrate;
+mod minimap;
+mod model;
+mod night;
+mod options;
+mod orientation;
+mod paddle;
+mod photo;
+mod preview;
+mod printer;
+mod qr;
+mod qr_export;
+mod quick_menu;
+mod quickslot;
+mod rewind;
+mod rom_hashes;
 mod rom_picker;
+mod rtc;
+mod saves;
+mod savestate;
+mod screenshot;
+mod scrubber;
+mod serial;
+mod settings;
+mod splash;
+mod splits;
+mod start_select;
+mod storage;
+mod storage_screen;
+mod strings;
+mod testsuite;
+mod toast;
+mod turbo;
+mod undo;
+mod watchdog;
+use autocrop::AutoCrop;
+use blit::BlitTables;
+use boot_macro::BootMacro;
+use cart_error::CartErrorScreen;
+use cheats::Cheats;
+use contrast::AdaptiveContrast;
+use display_mode::DisplayMode;
+use fast_forward::CrankSpin;
+use dev_commands::DevCommand;
+use frame_stats::FrameStats;
+use gallery::Gallery;
+use hotkeys::HotkeyLayer;
+use integrity::IntegrityScreen;
+use manual::ManualViewer;
+use menu::{ListMenu, MenuEvent};
+use mash::MashAssist;
+use minimap::{Corner, Minimap};
+use model::Model;
+use night::NightMode;
+use qr_export::QrExport;
+use quick_menu::{QuickAction, QuickMenu, QuickMenuEvent};
+use quickslot::QuickSlot;
+use rewind::Rewind;
+use savestate::RomId;
+use scrubber::{Scrubber, ScrubberEvent};
+use serial::{Serial, SerialMode};
+use options::{OptionsAction, OptionsEvent, OptionsMenu};
+use orientation::Tilt;
+use paddle::CrankPaddle;
+use photo::PhotoMode;
 use rom_picker::RomPickerState;
+use settings::Settings;
+use splash::BootSplash;
+use splits::SplitTimer;
+use start_select::{StartSelect, StartSelectMode};
+use storage_screen::StorageScreen;
+use strings::{tr, Text};
+use testsuite::TestSuite;
+use toast::{Banner, Toast};
+use turbo::Turbo;
+use undo::UndoSlot;
+use watchdog::Watchdog;
 
 // On hardware, we'll target 15 FPS, which is more achievable, and still
 // playable.
@@ -29,79 +137,180 @@ use rom_picker::RomPickerState;
 const FRAME_RATE: usize = 30;
 #[cfg(all(not(target_os = "windows"), not(target_os = "macos")))]
 const FRAME_RATE: usize = 15;
-
-// This is how much we'll scale the Gameboy screen to fit it on the Playdate
-const SCALE_FACTOR: f32 = 1.6666666667;
+// The display's rate with temporal_dither on, as near twice the game's as
+// the Playdate goes
+const TEMPORAL_REFRESH_RATE: usize =
+    if FRAME_RATE * 2 > 50 { 50 } else { FRAME_RATE * 2 };
+// How long Press Start and Press Select in the options menu hold the
+// button, in emulated frames. Long enough for games that only look at the
+// buttons every few frames.
+const MENU_PRESS_FRAMES: usize = 6;
 
 // This is nasty and not very Rust-like, but it's about the best I can manage
 // given that I need to mutate state via an extern C fn.
 // TODO: Do this a different way
 static mut WANT_TO_QUIT_GAME: bool = false;
+static mut WANT_OPTIONS: bool = false;
+static mut WANT_SAVE_STATE: bool = false;
+// Lines the simulator has sent, waiting for the next update. Its callback
+// can't reach State either, so they wait here, and only dev_command_queue
+// touches them.
+static mut DEV_COMMANDS: Vec<String> = Vec::new();
+
+fn dev_command_queue () -> &'static mut Vec<String> {
+    unsafe { &mut *addr_of_mut!(DEV_COMMANDS) }
+}
 
 struct State {
     processor: Option<Cpu>,
-    // This is used to determine when the crank has changed direction
-    // (we use that for Start/Select)
-    last_crank_change: f32,
-    rom_picker: Option<RomPickerState>
+    // As gbrs names it in the save callbacks. Our other per-game files are
+    // keyed off the same name.
+    game_name: String,
+    // The file in the data folder it was loaded from, for restarting it
+    rom_path: String,
+    // Which ROM is running, so save states made with another can be refused
+    rom_id: RomId,
+    // manuals/ has notes for the running game
+    manual_path: Option<String>,
+    // How far down each game's manual was scrolled, for this session
+    manual_scrolls: BTreeMap<String, f32>,
+    // What the running cart's header says it wants to run on
+    preferred_model: Option<Model>,
+    quick_slot: QuickSlot,
+    // What the last state load replaced, so it can be undone
+    undo_slot: UndoSlot,
+    // Only there if the game has a splits file
+    splits: Option<SplitTimer>,
+    // Only there if the game has a cheats file
+    cheats: Option<Cheats>,
+    // The last few seconds, for the rewind hotkey
+    rewind: Rewind,
+    // The game is paused while it's up, like the quick menu
+    scrubber: Option<Scrubber>,
+    // Only there while auto_orientation's on in a game, since the
+    // accelerometer costs battery
+    tilt: Option<Tilt>,
+    // The game is paused while this is up
+    quick_menu: Option<QuickMenu>,
+    fast_forward: bool,
+    // For crank_fast_forward
+    crank_spin: CrankSpin,
+    // Whether the fast forward marker is up, so the border can be put back
+    // when it goes
+    showing_fast_forward: bool,
+    // Paused from the quick menu, until A or B is pressed
+    paused: bool,
+    hotkey_layer: HotkeyLayer,
+    // Gameboy buttons a press command or the options menu is holding, and
+    // for how many more frames
+    press: Option<(u8, usize)>,
+    mash: MashAssist,
+    turbo: Turbo,
+    // Only for games set up for it
+    paddle: Option<CrankPaddle>,
+    // Whatever's on the other end of the link cable
+    serial: Serial,
+    watchdog: Watchdog,
+    // Up when the watchdog thinks the game's locked up
+    frozen: Option<ListMenu>,
+    // Up for a moment before the game starts
+    splash: Option<BootSplash>,
+    // Playing back while the game boots
+    boot_macro: Option<BootMacro>,
+    // Buttons for each frame since the game was restarted to record a macro
+    recording: Option<Vec<u8>>,
+    // Turns the crank or chords into Start and Select
+    start_select: StartSelect,
+    // The Gameboy buttons last frame, to tell when one goes down
+    last_inputs: u8,
+    rom_picker: Option<RomPickerState>,
+    // Takes the picker's place when there's a testsuite folder
+    test_suite: Option<TestSuite>,
+    // Shown between picking a game and booting it if its files look damaged
+    integrity: Option<IntegrityScreen>,
+    // The picked ROM, held on to while the integrity screen is up
+    pending_rom: Option<Vec<u8>>,
+    // Up instead of the game when its cart can't be run
+    cart_error: Option<CartErrorScreen>,
+    // While this is open, the game is paused underneath it
+    options: Option<OptionsMenu>,
+    // Opened from the options menu. Also pauses the game.
+    gallery: Option<Gallery>,
+    photo: Option<PhotoMode>,
+    storage_screen: Option<StorageScreen>,
+    qr_export: Option<QrExport>,
+    manual: Option<ManualViewer>,
+    toast: Toast,
+    storage_banner: Banner,
+    settings: Settings,
+    // Whether the display is inverted right now. This is settings.invert
+    // unless the night mode schedule has stepped in.
+    inverted: bool,
+    night_mode: NightMode,
+    auto_crop: AutoCrop,
+    // The whole frame in a corner while auto zoom is in
+    minimap: Minimap,
+    // Lookups for the zoomed and resized pictures, for what's on screen
+    blit_tables: BlitTables,
+    contrast: AdaptiveContrast,
+    // Our copy of the last Gameboy frame, to tell when it hasn't changed
+    last_frame: Vec<u8>,
+    // Which Gameboy rows differed from last_frame, from blit::frame_changed,
+    // and the runs of Playdate rows that makes. Kept rather than made each
+    // frame.
+    changed_rows: Vec<bool>,
+    row_runs: Vec<RangeInclusive<i32>>,
+    // For frame_blend: the frame before last_frame, and the rows that
+    // changed in it
+    blend_frame: Vec<u8>,
+    blend_rows: Vec<bool>,
+    // Set when something has drawn over the game screen, so the next frame
+    // has to be blitted even if the game's hasn't changed
+    force_blit: bool,
+    // Which of the dither's two patterns is showing, and which Gameboy rows
+    // have greys to swap over, for temporal_dither
+    dither_phase: usize,
+    grey_rows: Vec<bool>,
+    // The display's refresh rate right now, and how far it is through
+    // FRAME_RATE's worth of them (see between_frames)
+    refresh_rate: usize,
+    pace: usize,
+    // Buttons pushed during refreshes in between frames, for the next one
+    missed_pushes: PDButtons,
+    frame_stats: FrameStats
 }
 
 impl State {
     pub fn new(_playdate: &Playdate) -> Result<Box<Self>, Error> {
-        crankstart::display::Display::get().set_refresh_rate(FRAME_RATE as f32)?;
+        Display::get().set_refresh_rate(FRAME_RATE as f32)?;
 
         unsafe {
             set_callbacks(Callbacks {
-                log: |log_str| log_to_console!("{}", log_str),
+                log: |log_str| log!("{}", log_str),
                 save: |game_name, _rom_path, save_data| {
-                    let file_system = FileSystem::get();
-                    let save_path = &format!("{}.sav", game_name)[..];
-                    let save_file = file_system
-                        .open(
-                            save_path,
-                            FileOptions::kFileWrite
-                        ).unwrap();
-                    save_file.write(&save_data[..]).unwrap();
+                    saves::save_battery(game_name, &save_data[..])
                 },
                 load: |game_name, _rom_path, expected_size| {
-                    let file_system = FileSystem::get();
-                    let save_path = &format!("{}.sav", game_name)[..];
-
-                    let stat_result = file_system.stat(save_path);
-
-                    if let Ok(stat) = stat_result {
-                        // There is a save file and we can read it!
-                        // NOTE: stat.size might not be the expected_size, but
-                        //   that error-case is already handled in gbrs' ram.rs
-                        let mut buffer = vec![0; stat.size as usize];
-                        let save_file = file_system
-                            .open(
-                                save_path,
-                                FileOptions::kFileRead | FileOptions::kFileReadData
-                            ).unwrap();
-                        save_file.read(&mut buffer).unwrap();
-                        log_to_console!("Loaded {}", save_path);
-                        buffer
-                    } else {
-                        // Error at that path, there probably just isn't a save
-                        //   file yet. Return all 0s
-                        // TODO: Should this be all 0 or all 0xFF?
-                        log_to_console!("{} not found", save_path);
-                        vec![0; expected_size]
-                    }
+                    saves::load_battery(game_name, expected_size)
                 }
             })
         }
 
+        // Before anything goes looking for its files
+        migrate::run();
+
         // Let's write a handy little helper file to point new folk in the
         // right direction.
-        let file_system = FileSystem::get();
-        let help_file = file_system
-            .open(
-                "Game ROMs go here",
-                FileOptions::kFileWrite
-            ).unwrap();
-        help_file.write(&[]).unwrap();
+        if let Err(e) = storage::write_file("Game ROMs go here", &[]) {
+            log!("Couldn't write the help file: {}", e);
+        }
+
+        let settings = Settings::load();
+        Display::get().set_inverted(settings.invert)?;
+        // Before anything is drawn or added to the system menu
+        // NOTE: The system menu items keep the language they were added in,
+        //   so changing it only reaches them next launch.
+        strings::set_language(settings.language_code());
 
         // Provide a menu item for going back to the rom picker
         // Allowing you to quit a game without quitting Playboy
@@ -110,151 +319,1712 @@ impl State {
         unsafe extern "C" fn quit_game_callback (_: *mut c_void) {
             WANT_TO_QUIT_GAME = true;
         }
-        system.add_menu_item("quit game", Some(quit_game_callback))?;
+        system.add_menu_item(tr(Text::QuitGame), Some(quit_game_callback))?;
+
+        unsafe extern "C" fn options_callback (_: *mut c_void) {
+            WANT_OPTIONS = true;
+        }
+        system.add_menu_item(tr(Text::Options), Some(options_callback))?;
+
+        // That's the last of the three items we're allowed, so loading a
+        // state lives in the options menu instead
+        unsafe extern "C" fn save_state_callback (_: *mut c_void) {
+            WANT_SAVE_STATE = true;
+        }
+        if savestate::SUPPORTED {
+            system
+                .add_menu_item(tr(Text::SaveState), Some(save_state_callback))?;
+        }
+
+        unsafe extern "C" fn serial_message_callback (data: *const c_char) {
+            if let Ok(line) = CStr::from_ptr(data).to_str() {
+                dev_command_queue().push(String::from(line));
+            }
+        }
+        system.set_serial_message_callback(Some(serial_message_callback))?;
+
+        let test_suite = if testsuite::present() {
+            Some(TestSuite::new(FRAME_RATE)?)
+        } else { None };
 
         Ok(Box::new(Self {
             processor: None,
-            last_crank_change: 0.,
-            rom_picker: Some(RomPickerState::new())
+            game_name: String::new(),
+            rom_path: String::new(),
+            manual_path: None,
+            manual_scrolls: BTreeMap::new(),
+            rom_id: RomId::none(),
+            preferred_model: None,
+            quick_slot: QuickSlot::new(),
+            undo_slot: UndoSlot::new(),
+            splits: None,
+            cheats: None,
+            rewind: Rewind::new(0),
+            scrubber: None,
+            tilt: None,
+            quick_menu: None,
+            fast_forward: false,
+            crank_spin: CrankSpin::new(),
+            showing_fast_forward: false,
+            paused: false,
+            hotkey_layer: HotkeyLayer::new(),
+            press: None,
+            mash: MashAssist::new(),
+            turbo: Turbo::new(),
+            paddle: None,
+            serial: Serial::new(SerialMode::Disconnected),
+            watchdog: Watchdog::new(),
+            frozen: None,
+            splash: None,
+            boot_macro: None,
+            recording: None,
+            start_select: StartSelect::new(),
+            last_inputs: 0,
+            rom_picker: if test_suite.is_some() {
+                None
+            } else {
+                Some(RomPickerState::at_launch())
+            },
+            test_suite,
+            integrity: None,
+            pending_rom: None,
+            cart_error: None,
+            options: None,
+            gallery: None,
+            photo: None,
+            storage_screen: None,
+            qr_export: None,
+            manual: None,
+            toast: Toast::new(),
+            storage_banner: Banner::new(),
+            inverted: settings.invert,
+            settings,
+            night_mode: NightMode::new(),
+            auto_crop: AutoCrop::new(),
+            minimap: Minimap::new(),
+            blit_tables: BlitTables::new(),
+            contrast: AdaptiveContrast::new(),
+            last_frame: Vec::new(),
+            changed_rows: Vec::new(),
+            row_runs: Vec::new(),
+            blend_frame: Vec::new(),
+            blend_rows: Vec::new(),
+            force_blit: true,
+            dither_phase: 0,
+            grey_rows: Vec::new(),
+            refresh_rate: FRAME_RATE,
+            pace: 0,
+            missed_pushes: PDButtons(0),
+            frame_stats: FrameStats::new()
         }))
     }
-}
 
-// This is kind of like a differential.
-// We're looking for a "change in change" in crank angle
-fn process_crank_change(new_crank: f32, old_crank: f32) -> f32 {
-    // Is this safe with floats? (no epsilon etc.)
-    if old_crank > 0. && new_crank > 0. {
-        0.
-    } else if old_crank < 0. && new_crank < 0. {
-        0.
-    } else if new_crank == 0. {
-        0.
-    } else {
-        new_crank
+    // The system setting can change while the system menu is up, so this
+    // gets checked every update
+    fn follow_flip (&mut self) -> Result<(), Error> {
+        let flipped = match self.settings.upside_down {
+            Some(flipped) => flipped,
+            None => System::get().get_flipped()?
+        };
+        if flipped == input::flipped() { return Ok(()) }
+
+        log!("Display flipped: {}", flipped);
+        input::set_flipped(flipped)
+    }
+
+    // Turns the accelerometer on and off with the setting and the game
+    fn follow_tilt (&mut self) -> Result<(), Error> {
+        let wanted = self.settings.auto_orientation && self.processor.is_some();
+        if wanted == self.tilt.is_some() { return Ok(()) }
+
+        let peripherals = if wanted {
+            PDPeripherals::kAccelerometer
+        } else {
+            PDPeripherals::kNone
+        };
+        System::get().set_peripherals_enabled(peripherals)?;
+        self.tilt = if wanted {
+            Some(Tilt::new(self.settings.display_mode))
+        } else {
+            None
+        };
+        Ok(())
+    }
+
+    // Like picking the display mode in the options, with a toast to say why
+    // it changed
+    fn turn_to (&mut self, display_mode: DisplayMode) -> Result<(), Error> {
+        log!("Turned to {}", display_mode.name());
+        self.settings.display_mode = display_mode;
+        self.settings.save();
+        self.auto_crop = AutoCrop::new();
+        let text = if display_mode == DisplayMode::Sideways {
+            Text::TurnedSideways
+        } else {
+            Text::TurnedLandscape
+        };
+        self.toast.show(tr(text), FRAME_RATE * 2);
+        // Sideways blacks out the border, so it needs putting back
+        self.redraw_screen()
+    }
+
+    fn close_options (&mut self) -> Result<(), Error> {
+        self.options = None;
+        self.redraw_screen()
+    }
+
+    // Puts back whatever was on screen before an overlay (the options menu,
+    // the gallery, the storage screen) opened over it
+    fn redraw_screen (&mut self) -> Result<(), Error> {
+        if let Some(manual) = &self.manual {
+            manual.redraw()?;
+        } else if let Some(qr_export) = &self.qr_export {
+            qr_export.redraw()?;
+        } else if let Some(storage_screen) = &self.storage_screen {
+            storage_screen.redraw()?;
+        } else if let Some(gallery) = &self.gallery {
+            gallery.redraw()?;
+        } else if let Some(photo) = &self.photo {
+            photo.redraw()?;
+        } else if let Some(frozen) = &self.frozen {
+            frozen.draw()?;
+        } else if let Some(integrity) = &self.integrity {
+            integrity.redraw()?;
+        } else if let Some(cart_error) = &self.cart_error {
+            cart_error.redraw()?;
+        } else if self.rom_picker.is_some() {
+            self.rom_picker = Some(RomPickerState::new());
+        } else if let Some(splash) = &self.splash {
+            splash.redraw()?;
+        } else {
+            // The game screen comes back with the next blit, but the border
+            // is only drawn when asked
+            border::draw(self.settings.border)?;
+            self.force_blit = true;
+        }
+
+        Ok(())
+    }
+
+    // Whether it's the game on screen, rather than something over it or
+    // instead of it
+    fn game_showing (&self) -> bool {
+        self.processor.is_some() && self.rom_picker.is_none() &&
+            self.integrity.is_none() && self.options.is_none() &&
+            self.quick_menu.is_none() && self.scrubber.is_none() &&
+            self.manual.is_none() &&
+            self.qr_export.is_none() && self.storage_screen.is_none() &&
+            self.gallery.is_none() && self.photo.is_none() &&
+            self.frozen.is_none() && self.splash.is_none()
+    }
+
+    // Temporal dither speeds the display up, but only while the game's
+    // showing, since everything else counts its timings in FRAME_RATE
+    // updates
+    fn follow_refresh_rate (&mut self) -> Result<(), Error> {
+        let rate = if self.settings.temporal_dither && self.game_showing() {
+            TEMPORAL_REFRESH_RATE
+        } else {
+            FRAME_RATE
+        };
+        if rate == self.refresh_rate { return Ok(()) }
+
+        self.refresh_rate = rate;
+        self.pace = 0;
+        Display::get().set_refresh_rate(rate as f32)?;
+        Ok(())
+    }
+
+    // The game still gets FRAME_RATE frames a second when the display's
+    // going faster, so some refreshes come in between its frames. Call once
+    // per update.
+    fn between_frames (&mut self) -> bool {
+        if self.refresh_rate == FRAME_RATE { return false }
+        self.pace += FRAME_RATE;
+        if self.pace < self.refresh_rate { return true }
+        self.pace -= self.refresh_rate;
+        false
+    }
+
+    // Blits the game's latest frame and draws the overlays on top. Returns
+    // whether the frame changed, whether the blit was skipped after all,
+    // and how many rows were marked. Between frames, nothing's emulated, so
+    // this is only here to swap the temporal dither's greys over.
+    fn draw_game (
+        &mut self,
+        between_frames: bool,
+        writes_failing: bool
+    ) -> Result<(bool, bool, usize), Error> {
+        let graphics = Graphics::get();
+        let gameboy = self.processor.as_ref().unwrap();
+
+        // last_frame is about to become this frame, so frame blending keeps
+        // a copy of it as the one before. Refreshes between frames keep the
+        // same pair.
+        let blending = self.settings.frame_blend;
+        if blending && !between_frames {
+            self.blend_frame.clear();
+            self.blend_frame.extend_from_slice(&self.last_frame[..]);
+        }
+        let frame_changed = blit::frame_changed(
+            &gameboy.gpu.finished_frame,
+            &mut self.last_frame,
+            &mut self.changed_rows
+        );
+        let blend_changed = blending && !between_frames &&
+            blit::carry_changed_rows(
+                &mut self.changed_rows, &mut self.blend_rows
+            );
+        // Nothing to blend with on the first frame
+        let previous = if blending &&
+            self.blend_frame.len() == gameboy.gpu.finished_frame.len() {
+            Some(&self.blend_frame[..])
+        } else {
+            None
+        };
+        let shift = self.contrast.shift();
+        // Rows with greys in look different every refresh, so they're
+        // drawn and marked whether the game changed them or not
+        let temporal = self.settings.temporal_dither;
+        let greys = temporal && blit::grey_rows(
+            &gameboy.gpu.finished_frame, previous, shift, &mut self.grey_rows
+        );
+        if temporal { self.dither_phase ^= 1 }
+        let blit_skipped = !frame_changed && !self.force_blit && !greys &&
+            !blend_changed;
+        let crop = self.auto_crop.shown();
+        let scaled_size = self.settings.display_mode.output_size();
+        // Anything that forced the blit (a new zoom, a contrast shift, an
+        // overlay going) can change rows the game didn't
+        let display_mode = self.settings.display_mode;
+        let whole_screen = self.force_blit || !crop.is_full() ||
+            display_mode != DisplayMode::Stretched;
+        self.force_blit = false;
+        let blit_started = FrameStats::now_ms()?;
+        if !blit_skipped {
+            let framebuffer = graphics.get_frame()?;
+            let ditherer = &self.settings.dither.ditherers()[self.dither_phase];
+            if display_mode == DisplayMode::Sideways {
+                blit::draw_frame_sideways(
+                    &gameboy.gpu.finished_frame,
+                    previous,
+                    framebuffer,
+                    ditherer,
+                    shift
+                );
+            } else if let Some(size) = scaled_size {
+                self.blit_tables.fit(&autocrop::FULL, size);
+                blit::draw_frame_scaled(
+                    &gameboy.gpu.finished_frame,
+                    previous,
+                    framebuffer,
+                    &self.blit_tables,
+                    ditherer,
+                    shift
+                );
+            } else if crop.is_full() {
+                blit::draw_frame(
+                    &gameboy.gpu.finished_frame,
+                    previous,
+                    framebuffer,
+                    ditherer,
+                    shift
+                );
+            } else {
+                // Blown up to fill as much of the screen as it can
+                self.blit_tables.fit(&crop, crop.output_size());
+                blit::draw_frame_scaled(
+                    &gameboy.gpu.finished_frame,
+                    previous,
+                    framebuffer,
+                    &self.blit_tables,
+                    ditherer,
+                    shift
+                );
+                // Drawn over the picture after each blit, since the blit
+                // covers the whole screen
+                let corner = self.settings.minimap;
+                if corner != Corner::Off {
+                    if !between_frames {
+                        self.minimap.update(&gameboy.gpu.finished_frame, &crop);
+                    }
+                    self.minimap.draw(corner, framebuffer);
+                }
+            }
+        }
+        if !between_frames { self.frame_stats.blit_done(blit_started)? }
+
+        if let Some(splits) = &self.splits {
+            splits.draw(FRAME_RATE)?;
+        }
+        self.contrast.draw_indicator()?;
+        self.mash.draw_indicator(self.settings.mash_axis)?;
+        self.start_select.draw_selector(whole_screen && !blit_skipped)?;
+        self.turbo.draw_indicator(
+            self.settings.turbo_a,
+            self.settings.turbo_b,
+            whole_screen && !blit_skipped
+        )?;
+        if self.showing_fast_forward {
+            fast_forward::draw_indicator(self.settings.fast_forward_speed)?;
+        }
+        if self.settings.perf_overlay {
+            self.frame_stats.draw_overlay()?;
+        }
+        // Counted in the game's frames
+        if between_frames {
+            self.toast.redraw()?;
+        } else {
+            self.toast.draw()?;
+        }
+        self.storage_banner.draw(if writes_failing {
+            Some(tr(Text::StorageFull))
+        } else {
+            None
+        })?;
+
+        // Only the rows showing a Gameboy row that changed get sent to the
+        // LCD, which is most of the saving on menus and dialogue screens.
+        // The overlays above are drawn through the graphics API, which marks
+        // its own rows, so there's nothing to mark when the blit was skipped.
+        let mut rows_marked = 0;
+        if !blit_skipped {
+            if whole_screen {
+                graphics.mark_updated_rows(0..=(LCD_ROWS - 1) as i32)?;
+                rows_marked = LCD_ROWS as usize;
+            } else {
+                if greys {
+                    let rows =
+                        self.changed_rows.iter_mut().zip(&self.grey_rows);
+                    for (changed, grey) in rows { *changed |= *grey }
+                }
+                blit::changed_row_runs(
+                    &self.changed_rows[..], &mut self.row_runs
+                );
+                for run in self.row_runs.iter() {
+                    rows_marked += (run.end() - run.start() + 1) as usize;
+                    graphics.mark_updated_rows(run.clone())?;
+                }
+            }
+        }
+
+        Ok((frame_changed, blit_skipped, rows_marked))
+    }
+
+    // Boots rom, or goes back to the picker if that fails part way. Whatever
+    // called this has usually put its own screen away already, so an error
+    // handed back would leave nothing up and no game either.
+    fn start_game (&mut self, rom: Vec<u8>) {
+        if let Err(e) = self.try_start_game(rom) {
+            log!("Couldn't start the game: {}", e);
+            self.processor = None;
+            self.splash = None;
+            self.cart_error = None;
+            self.rom_picker = Some(RomPickerState::new());
+        }
+    }
+
+    fn try_start_game (&mut self, rom: Vec<u8>) -> Result<(), Error> {
+        // The picker won't hand over a ROM that fails this, but everything
+        // else that boots one comes through here, and gbrs would panic on
+        // it. Nothing's set up for the game yet, so there's no Cpu to leave
+        // half made.
+        if let Err(problem) = header::check_cart(&rom[..]) {
+            log!("Not booting {}", header::summary(&rom[..]));
+            self.processor = None;
+            self.cart_error = Some(CartErrorScreen::new(&rom[..], &problem)?);
+            return Ok(())
+        }
+
+        let title = header::title(&rom[..]);
+        saves::migrate_unsafe_name(&title[..]);
+        self.game_name = storage::safe_name(&title[..]);
+        self.rom_id = RomId::of(&rom[..]);
+        self.preferred_model = Some(Model::preferred_by(&rom[..]));
+        log!("{}", header::summary(&rom[..]));
+        self.manual_path = manual::find_manual(self.rom_id, &self.rom_path[..]);
+        self.settings = self.settings.for_game(&self.game_name[..]);
+        if self.settings.quick_slot_to_disk {
+            self.quick_slot = QuickSlot::load_from_disk(&self.game_name[..]);
+            self.undo_slot = UndoSlot::load_from_disk(&self.game_name[..]);
+        } else {
+            self.quick_slot = QuickSlot::new();
+            self.undo_slot = UndoSlot::new();
+        }
+        self.splits = SplitTimer::load(&self.game_name[..]);
+        self.cheats = Cheats::load(&self.game_name[..]);
+        // Nothing could step back through it without save states
+        let rewind_kb =
+            if savestate::SUPPORTED { self.settings.rewind_kb } else { 0 };
+        self.rewind = Rewind::new(rewind_kb);
+        self.auto_crop = AutoCrop::new();
+        self.contrast = AdaptiveContrast::new();
+        self.watchdog = Watchdog::new();
+        self.last_inputs = 0;
+        self.paddle = if self.settings.crank_paddle {
+            log!("Crank paddle on, Up and Down are Start and Select");
+            Some(CrankPaddle::new(
+                self.settings.paddle_gain, self.settings.paddle_deadband
+            ))
+        } else {
+            None
+        };
+        if self.settings.serial == SerialMode::Printer {
+            log!("Game Boy Printer plugged in, prints go in {}",
+                storage::PRINTS_DIR);
+        }
+        self.serial = Serial::new(self.settings.serial);
+        // Not while recording a new one, which needs the real boot
+        self.boot_macro = if self.recording.is_some() {
+            None
+        } else {
+            BootMacro::load(&self.game_name[..])
+        };
+        if self.boot_macro.is_some() {
+            self.toast.show(tr(Text::BootMacroPlaying), FRAME_RATE * 2);
+        }
+
+        // Has to be known before gbrs loads the save
+        saves::set_cart(
+            rtc::has_rtc(&rom[..]), header::cart_ram_size(&rom[..])
+        );
+
+        // About a second. It clears the screen, and the border is put back
+        // when it goes.
+        self.splash = Some(BootSplash::new(&rom[..], FRAME_RATE)?);
+
+        // NOTE: rom is the one buffer the picker read the file into, and it's
+        //   moved (never cloned) all the way here, so the only ROM-sized
+        //   allocation we make is that one. Anything that needs the ROM
+        //   later should borrow it from the Cpu rather than keep a copy.
+        //   Game Genie codes patch it in place for the same reason.
+        let mut rom = rom;
+        if let Some(cheats) = &self.cheats {
+            if self.settings.cheats { cheats.patch_rom(&mut rom[..]) }
+        }
+        let mut cpu = Cpu::from_rom_bytes(rom);
+        cpu.frame_rate = FRAME_RATE;
+        self.settings.model.apply(&mut cpu);
+        self.processor = Some(cpu);
+        self.force_blit = true;
+        self.frame_stats = FrameStats::new();
+        Ok(())
+    }
+
+    // The running game's ROM again, for restarting it. Says so if it can't.
+    fn reload_rom (&mut self) -> Option<Vec<u8>> {
+        let rom = storage::read_file(&self.rom_path[..])
+            .and_then(|rom| header::check_rom_size(&rom[..]).map(|()| rom));
+        match rom {
+            Ok(rom) => Some(rom),
+            Err(e) => {
+                log!("Couldn't reload {}: {}", self.rom_path, e);
+                self.toast.show(tr(Text::RestartFailed), FRAME_RATE * 2);
+                None
+            }
+        }
+    }
+
+    fn open_frozen_prompt (&mut self) -> Result<(), Error> {
+        let mut items = vec![
+            String::from(tr(Text::SaveEmergencyState)),
+            String::from(tr(Text::ResetGame)),
+            String::from(tr(Text::KeepWaiting))
+        ];
+        if !savestate::SUPPORTED { items.remove(0); }
+        self.frozen = Some(ListMenu::new(tr(Text::GameFrozenTitle), items)?);
+        Ok(())
+    }
+
+    // Returns true once the prompt is done with
+    fn update_frozen_prompt (
+        &mut self,
+        event: MenuEvent
+    ) -> Result<bool, Error> {
+        // Without save states the first item's left out, and the rest move
+        // up one
+        let event = match event {
+            MenuEvent::Picked(index) if !savestate::SUPPORTED =>
+                MenuEvent::Picked(index + 1),
+            event => event
+        };
+        match event {
+            MenuEvent::Picked(0) => {
+                let gameboy = match &self.processor {
+                    Some(gameboy) => gameboy,
+                    None => return Ok(true)
+                };
+                let path = savestate::emergency_state_path(&self.game_name[..]);
+                let written = savestate::capture(gameboy, self.rom_id)
+                    .and_then(|state| {
+                        storage::write_file(&path[..], &state[..])
+                    });
+                let label = match written {
+                    Ok(()) => {
+                        log!("Saved {}", path);
+                        tr(Text::EmergencyStateSaved)
+                    },
+                    Err(e) => {
+                        log!("Couldn't save {}: {}", path, e);
+                        tr(Text::EmergencyStateFailed)
+                    }
+                };
+                // Stays up, since they'll want to reset or wait next
+                if let Some(frozen) = &mut self.frozen {
+                    frozen.set_item(0, String::from(label))?;
+                }
+                Ok(false)
+            },
+            MenuEvent::Picked(1) => {
+                self.reset_game()?;
+                Ok(true)
+            },
+            _ => {
+                self.watchdog.snooze();
+                Ok(true)
+            }
+        }
+    }
+
+    // Tidies up after the running game, if there is one
+    fn leave_game (&mut self) {
+        if self.processor.is_none() { return }
+        saves::flush_now();
+
+        if framebuffer::out_of_bounds_writes() > 0 {
+            log!(
+                "{} framebuffer writes were out of bounds",
+                framebuffer::out_of_bounds_writes()
+            );
+        }
+
+        if self.settings.quick_slot_to_disk {
+            let flushed = self.quick_slot.flush_to_disk(&self.game_name[..]);
+            if let Err(e) = flushed {
+                log!("Couldn't keep the quick save: {}", e);
+            }
+            let flushed = self.undo_slot.flush_to_disk(&self.game_name[..]);
+            if let Err(e) = flushed {
+                log!("Couldn't keep the undo state: {}", e);
+            }
+        }
+
+        self.processor = None;
+        self.preferred_model = None;
+        self.quick_menu = None;
+        self.scrubber = None;
+        self.fast_forward = false;
+        self.paused = false;
+        self.splash = None;
+        self.boot_macro = None;
+        self.recording = None;
+        self.press = None;
+        self.paddle = None;
+        self.frozen = None;
+        self.settings = self.settings.global();
+
+        // Nothing the old game was in the middle of should carry over to
+        // the next one, on the buttons or on the screen
+        self.start_select = StartSelect::new();
+        self.crank_spin = CrankSpin::new();
+        self.turbo = Turbo::new();
+        self.missed_pushes = PDButtons(0);
+        self.showing_fast_forward = false;
+        self.last_frame.clear();
+        self.changed_rows.clear();
+        self.blend_frame.clear();
+        self.blend_rows.clear();
+    }
+
+    // A fresh boot of the running game, from the ROM on disk (so without
+    // any Game Genie patches that have been turned off since)
+    fn reset_game (&mut self) -> Result<(), Error> {
+        if let Some(rom) = self.reload_rom() {
+            self.leave_game();
+            self.start_game(rom);
+        }
+        Ok(())
+    }
+
+    fn undo_load (&mut self) -> &'static str {
+        match self.processor.as_mut() {
+            Some(cpu) => match self.undo_slot.undo(cpu, self.rom_id) {
+                Ok(true) => tr(Text::LoadUndone),
+                Ok(false) => tr(Text::NothingToUndo),
+                Err(e) => {
+                    log!("Couldn't undo the load: {}", e);
+                    tr(Text::UndoFailed)
+                }
+            },
+            None => tr(Text::NoGameRunning)
+        }
+    }
+
+    // The state from the system menu's "Save state", kept on disk so it
+    // lasts through the Playdate being turned off
+    fn save_state_file (&mut self) -> &'static str {
+        let gameboy = match &self.processor {
+            Some(gameboy) => gameboy,
+            None => return tr(Text::NoGameRunning)
+        };
+        let path = savestate::state_path(&self.game_name[..]);
+        let written = savestate::capture(gameboy, self.rom_id)
+            .and_then(|state| storage::write_file(&path[..], &state[..]));
+        match written {
+            Ok(()) => {
+                log!("Saved {}", path);
+                tr(Text::StateSaved)
+            },
+            Err(e) => {
+                log!("Couldn't save {}: {}", path, e);
+                tr(Text::StateSaveFailed)
+            }
+        }
+    }
+
+    fn load_state_file (&mut self) -> &'static str {
+        let gameboy = match self.processor.as_mut() {
+            Some(gameboy) => gameboy,
+            None => return tr(Text::NoGameRunning)
+        };
+        let path = savestate::state_path(&self.game_name[..]);
+        let state = match storage::read_file(&path[..]) {
+            Ok(state) => state,
+            Err(_) => return tr(Text::NoSavedState)
+        };
+
+        let force = self.settings.force_state_loads;
+        if !force && savestate::made_with_other_rom(&state[..], self.rom_id) {
+            log!("{} was saved with a different ROM", path);
+            return tr(Text::StateOtherRom)
+        }
+        let loaded =
+            self.undo_slot.load_over(gameboy, &state[..], self.rom_id, force);
+        match loaded {
+            Ok(()) => {
+                log!("Loaded {}", path);
+                self.force_blit = true;
+                tr(Text::StateLoaded)
+            },
+            Err(e) => {
+                log!("Couldn't load {}: {}", path, e);
+                tr(Text::StateLoadFailed)
+            }
+        }
+    }
+
+    fn toggle_invert (&mut self) -> Result<(), Error> {
+        // Counts as the user's preference from now on, including after the
+        // night mode window ends
+        self.inverted = !self.inverted;
+        self.settings.invert = self.inverted;
+        self.settings.save();
+        Display::get().set_inverted(self.inverted)
+    }
+
+    // Only called with a game running, since that's the only time the quick
+    // menu can open
+    fn run_quick_action (&mut self, action: QuickAction) -> Result<(), Error> {
+        let gameboy = match self.processor.as_mut() {
+            Some(gameboy) => gameboy,
+            None => return Ok(())
+        };
+
+        let message = match action {
+            QuickAction::QuickSave =>
+                Some(self.quick_slot.save(gameboy, self.rom_id)),
+            QuickAction::QuickLoad => Some(self.quick_slot.load(
+                gameboy,
+                &mut self.undo_slot,
+                self.rom_id,
+                self.settings.force_state_loads
+            )),
+            QuickAction::UndoLoad => Some(self.undo_load()),
+            QuickAction::FastForward => {
+                self.fast_forward = !self.fast_forward;
+                Some(tr(if self.fast_forward {
+                    Text::FastForwardOn
+                } else {
+                    Text::FastForwardOff
+                }))
+            },
+            QuickAction::Invert => {
+                self.toggle_invert()?;
+                None
+            },
+            QuickAction::Pause => {
+                self.paused = !self.paused;
+                None
+            },
+            QuickAction::Screenshot => {
+                match screenshot::save(&gameboy.gpu.finished_frame) {
+                    Ok(path) => {
+                        log!("Saved {}", path);
+                        Some(tr(Text::ScreenshotSaved))
+                    },
+                    Err(e) => {
+                        log!("Couldn't save a screenshot: {}", e);
+                        Some(tr(Text::ScreenshotFailed))
+                    }
+                }
+            },
+            QuickAction::Options => {
+                saves::flush_now();
+                self.options = Some(OptionsMenu::new(self)?);
+                None
+            }
+        };
+
+        if let Some(message) = message {
+            self.toast.show(message, FRAME_RATE);
+        }
+        Ok(())
+    }
+
+    // Anything that goes wrong is returned for the log, since that's where
+    // the simulator shows us the results
+    fn run_dev_command (&mut self, command: DevCommand) -> Result<(), Error> {
+        if let DevCommand::LoadRom(path) = &command {
+            let rom = storage::read_file(&path[..])?;
+            header::check_rom_size(&rom[..])?;
+            if let Err(problem) = header::check_cart(&rom[..]) {
+                return Err(anyhow!(problem.describe(&path[..])))
+            }
+            self.leave_game();
+            // Whatever was open would otherwise carry on over the top
+            self.rom_picker = None;
+            self.integrity = None;
+            self.cart_error = None;
+            self.options = None;
+            self.gallery = None;
+            self.photo = None;
+            self.frozen = None;
+            self.storage_screen = None;
+            self.qr_export = None;
+            self.manual = None;
+            self.rom_path = path.clone();
+            self.start_game(rom);
+            if self.processor.is_some() { log!("Booted {}", path) }
+            return Ok(())
+        }
+
+        let gameboy = self.processor
+            .as_mut()
+            .ok_or_else(|| anyhow!("no game running"))?;
+        match command {
+            DevCommand::Screenshot => {
+                let path = screenshot::save(&gameboy.gpu.finished_frame)?;
+                log!("Saved {}", path);
+            },
+            DevCommand::SaveState(slot) => {
+                let path = savestate::slot_path(&self.game_name[..], slot);
+                let state = savestate::capture(gameboy, self.rom_id)?;
+                storage::write_file(&path[..], &state[..])?;
+                log!("Saved {}", path);
+            },
+            DevCommand::LoadState(slot) => {
+                let path = savestate::slot_path(&self.game_name[..], slot);
+                let state = storage::read_file(&path[..])?;
+                self.undo_slot.load_over(
+                    gameboy,
+                    &state[..],
+                    self.rom_id,
+                    self.settings.force_state_loads
+                )?;
+                self.force_blit = true;
+                log!("Loaded {}", path);
+            },
+            DevCommand::DumpMem { address, length } => {
+                let end = (address as usize + length).min(0x10000);
+                for row in (address as usize..end).step_by(16) {
+                    let mut line = format!("{:04x}:", row);
+                    for byte_address in row..(row + 16).min(end) {
+                        let byte = gameboy.mem.read(byte_address as u16);
+                        line.push_str(&format!(" {:02x}", byte));
+                    }
+                    log!("{}", line);
+                }
+            },
+            DevCommand::Press { buttons, frames } => {
+                self.press = Some((buttons, frames));
+                log!("Holding {:08b} for {} frames", buttons, frames);
+            },
+            // Done above, since it doesn't need a game running
+            DevCommand::LoadRom(_) => {}
+        }
+        Ok(())
+    }
+
+    // Returns whether the options menu should close afterwards. Settings
+    // stay open so you can flick through the values.
+    fn run_option (&mut self, action: OptionsAction) -> Result<bool, Error> {
+        match action {
+            OptionsAction::CycleBorder => {
+                self.settings.border = self.settings.border.next();
+                self.settings.save();
+                Ok(false)
+            },
+            OptionsAction::CycleModel => {
+                self.settings.model = self.settings.model.next();
+                self.settings.save();
+                if self.processor.is_some() {
+                    self.toast.show(tr(Text::ModelNextBoot), FRAME_RATE * 2);
+                }
+                Ok(false)
+            },
+            OptionsAction::CycleLanguage => {
+                let codes = strings::languages();
+                let next = match codes
+                    .iter()
+                    .position(|code| *code == self.settings.language)
+                {
+                    Some(index) if index + 1 < codes.len() =>
+                        codes[index + 1].clone(),
+                    // "auto" isn't in the list, and comes after the last one
+                    Some(_) => String::from("auto"),
+                    None => codes[0].clone()
+                };
+                self.settings.language = next;
+                self.settings.save();
+                strings::set_language(self.settings.language_code());
+                Ok(false)
+            },
+            OptionsAction::ToggleInvert => {
+                self.toggle_invert()?;
+                Ok(false)
+            },
+            OptionsAction::ToggleAutoZoom => {
+                self.settings.auto_zoom = !self.settings.auto_zoom;
+                self.settings.save();
+                if !self.settings.auto_zoom {
+                    self.auto_crop = AutoCrop::new();
+                }
+                Ok(false)
+            },
+            OptionsAction::CycleMinimap => {
+                self.settings.minimap = self.settings.minimap.next();
+                self.settings.save();
+                self.force_blit = true;
+                Ok(false)
+            },
+            OptionsAction::CycleDisplayMode => {
+                self.settings.display_mode = self.settings.display_mode.next();
+                self.settings.save();
+                self.auto_crop = AutoCrop::new();
+                Ok(false)
+            },
+            OptionsAction::ToggleAutoOrientation => {
+                self.settings.auto_orientation =
+                    !self.settings.auto_orientation;
+                self.settings.save();
+                Ok(false)
+            },
+            OptionsAction::CycleDither => {
+                self.settings.dither = self.settings.dither.next();
+                self.settings.save();
+                self.force_blit = true;
+                Ok(false)
+            },
+            OptionsAction::ToggleTemporalDither => {
+                self.settings.temporal_dither = !self.settings.temporal_dither;
+                self.settings.save();
+                self.dither_phase = 0;
+                // The menu's still up, so the display's left alone until
+                // it's closed (see follow_refresh_rate)
+                self.force_blit = true;
+                Ok(false)
+            },
+            OptionsAction::ToggleFrameBlend => {
+                self.settings.frame_blend = !self.settings.frame_blend;
+                self.settings.save();
+                // Whatever's in there is from the last time it was on
+                self.blend_frame.clear();
+                self.blend_rows.clear();
+                self.force_blit = true;
+                Ok(false)
+            },
+            OptionsAction::TogglePerGame => {
+                let per_game = !self.settings.per_game();
+                self.settings.set_per_game(per_game);
+                self.settings.save();
+                // Turning it off can put the picture back how it was
+                self.auto_crop = AutoCrop::new();
+                self.force_blit = true;
+                Ok(false)
+            },
+            OptionsAction::TogglePerfOverlay => {
+                self.settings.perf_overlay = !self.settings.perf_overlay;
+                self.settings.save();
+                Ok(false)
+            },
+            OptionsAction::ToggleAdaptiveContrast => {
+                self.settings.adaptive_contrast =
+                    !self.settings.adaptive_contrast;
+                self.settings.save();
+                if !self.settings.adaptive_contrast {
+                    self.contrast = AdaptiveContrast::new();
+                }
+                Ok(false)
+            },
+            OptionsAction::ToggleNightMode => {
+                self.settings.night_mode = !self.settings.night_mode;
+                self.settings.save();
+                self.night_mode.reset();
+                Ok(false)
+            },
+            OptionsAction::CycleNightStart => {
+                self.settings.night_start =
+                    (self.settings.night_start + 1) % 24;
+                self.settings.save();
+                self.night_mode.reset();
+                Ok(false)
+            },
+            OptionsAction::CycleNightEnd => {
+                self.settings.night_end = (self.settings.night_end + 1) % 24;
+                self.settings.save();
+                self.night_mode.reset();
+                Ok(false)
+            },
+            OptionsAction::ToggleQuickSlotToDisk => {
+                self.settings.quick_slot_to_disk =
+                    !self.settings.quick_slot_to_disk;
+                self.settings.save();
+                Ok(false)
+            },
+            OptionsAction::ToggleSplitsResetOnLoad => {
+                self.settings.splits_reset_on_load =
+                    !self.settings.splits_reset_on_load;
+                self.settings.save();
+                Ok(false)
+            },
+            OptionsAction::CycleStartSelect => {
+                self.settings.start_select = self.settings.start_select.next();
+                self.settings.save();
+                self.start_select = StartSelect::new();
+                Ok(false)
+            },
+            OptionsAction::CycleCrankDeadZone => {
+                self.settings.crank_dead_zone =
+                    start_select::next_dead_zone(self.settings.crank_dead_zone);
+                self.settings.save();
+                Ok(false)
+            },
+            OptionsAction::ToggleCrankFastForward => {
+                self.settings.crank_fast_forward =
+                    !self.settings.crank_fast_forward;
+                self.settings.save();
+                self.crank_spin = CrankSpin::new();
+                self.start_select = StartSelect::new();
+                Ok(false)
+            },
+            OptionsAction::ToggleTurboA => {
+                self.settings.turbo_a = !self.settings.turbo_a;
+                self.settings.save();
+                Ok(false)
+            },
+            OptionsAction::ToggleTurboB => {
+                self.settings.turbo_b = !self.settings.turbo_b;
+                self.settings.save();
+                Ok(false)
+            },
+            OptionsAction::CycleTurboPeriod => {
+                self.settings.turbo_period =
+                    turbo::next_period(self.settings.turbo_period);
+                self.settings.save();
+                Ok(false)
+            },
+            OptionsAction::ToggleCheats => {
+                self.settings.cheats = !self.settings.cheats;
+                self.settings.save();
+                let patches_rom = self.cheats
+                    .as_ref()
+                    .map_or(false, |cheats| cheats.patches_rom());
+                if patches_rom {
+                    self.toast
+                        .show(tr(Text::GameGenieNextBoot), FRAME_RATE * 2);
+                }
+                Ok(false)
+            },
+            OptionsAction::LoadState => {
+                let message = self.load_state_file();
+                self.toast.show(message, FRAME_RATE * 2);
+                Ok(true)
+            },
+            OptionsAction::UndoLoad => {
+                let message = self.undo_load();
+                self.toast.show(message, FRAME_RATE * 2);
+                Ok(true)
+            },
+            OptionsAction::PressStart => {
+                self.press = Some((1 << 6, MENU_PRESS_FRAMES));
+                Ok(true)
+            },
+            OptionsAction::PressSelect => {
+                self.press = Some((1 << 7, MENU_PRESS_FRAMES));
+                Ok(true)
+            },
+            OptionsAction::ResetGame => {
+                self.reset_game()?;
+                Ok(true)
+            },
+            OptionsAction::OpenGallery => {
+                self.gallery = Some(Gallery::new(self.settings.dither)?);
+                Ok(true)
+            },
+            OptionsAction::OpenPhotoMode => {
+                if let Some(gameboy) = &self.processor {
+                    self.photo = Some(PhotoMode::new(
+                        &gameboy.gpu.finished_frame,
+                        self.settings.border,
+                        self.settings.dither
+                    )?);
+                }
+                Ok(true)
+            },
+            OptionsAction::OpenManual => {
+                let path = match &self.manual_path {
+                    Some(path) => path,
+                    None => return Ok(true)
+                };
+                let scroll = self.manual_scrolls
+                    .get(&self.game_name)
+                    .copied()
+                    .unwrap_or(0.);
+                match ManualViewer::new(&self.game_name[..], path, scroll) {
+                    Ok(manual) => self.manual = Some(manual),
+                    Err(e) => {
+                        log!("Couldn't open {}: {}", path, e);
+                        self.toast
+                            .show(tr(Text::ManualUnreadable), FRAME_RATE * 2);
+                    }
+                }
+                Ok(true)
+            },
+            OptionsAction::OpenStorage => {
+                let running_game = if self.processor.is_some() {
+                    Some(&self.game_name[..])
+                } else {
+                    None
+                };
+                self.storage_screen = Some(StorageScreen::new(running_game)?);
+                Ok(true)
+            },
+            OptionsAction::ExportSaveQr => {
+                if self.processor.is_none() {
+                    self.toast.show(tr(Text::NoGameRunning), FRAME_RATE * 2);
+                    return Ok(true)
+                }
+                // It reads the save back from the file
+                saves::flush_now();
+                match QrExport::new(&self.game_name[..])? {
+                    Ok(qr_export) => self.qr_export = Some(qr_export),
+                    Err(message) => self.toast.show(message, FRAME_RATE * 2)
+                }
+                Ok(true)
+            },
+            OptionsAction::RecordBootMacro => {
+                if self.processor.is_none() {
+                    self.toast.show(tr(Text::NoGameRunning), FRAME_RATE * 2);
+                    return Ok(true)
+                }
+
+                if let Some(inputs) = self.recording.take() {
+                    let message = match boot_macro::save(
+                        &self.game_name[..], &inputs[..]
+                    ) {
+                        Ok(()) => tr(Text::BootMacroSaved),
+                        Err(e) => {
+                            log!("Couldn't save the boot macro: {}", e);
+                            tr(Text::BootMacroFailed)
+                        }
+                    };
+                    self.toast.show(message, FRAME_RATE * 2);
+                    return Ok(true)
+                }
+
+                // Recording starts from a cold boot, so restart the game
+                let rom = match self.reload_rom() {
+                    Some(rom) => rom,
+                    None => return Ok(true)
+                };
+                self.leave_game();
+                self.recording = Some(Vec::new());
+                self.start_game(rom);
+                self.toast.show(tr(Text::RecordingBootMacro), FRAME_RATE * 3);
+                Ok(true)
+            },
+            OptionsAction::DumpLog => {
+                match log::dump() {
+                    Ok(()) => self.toast
+                        .show(tr(Text::LogWritten), FRAME_RATE * 2),
+                    Err(e) => {
+                        log!("Couldn't dump the log: {}", e);
+                        self.toast.show(tr(Text::LogFailed), FRAME_RATE * 2);
+                    }
+                }
+                Ok(true)
+            },
+            OptionsAction::WriteBugReport => {
+                match bugreport::write(self) {
+                    Ok(()) => self.toast
+                        .show(tr(Text::BugReportWritten), FRAME_RATE * 2),
+                    Err(e) => {
+                        log!("Couldn't write the bug report: {}", e);
+                        self.toast
+                            .show(tr(Text::BugReportFailed), FRAME_RATE * 2);
+                    }
+                }
+                Ok(true)
+            }
+        }
     }
 }
 
 impl Game for State {
     fn update(&mut self, playdate: &mut Playdate) -> Result<(), Error> {
         let system = System::get();
-        let graphics = Graphics::get();
+
+        // Before anything reads the buttons, even on the very first update
+        self.follow_flip()?;
+        self.follow_tilt()?;
+
+        // The suite runs its own Cpus, and nothing else happens until it's
+        // done with
+        if let Some(test_suite) = &mut self.test_suite {
+            if test_suite.update()? {
+                self.test_suite = None;
+                self.rom_picker = Some(RomPickerState::new());
+            }
+            return Ok(())
+        }
 
         unsafe {
             if WANT_TO_QUIT_GAME {
                 WANT_TO_QUIT_GAME = false;
+                self.leave_game();
+                self.cart_error = None;
                 self.rom_picker = Some(RomPickerState::new());
-                self.processor = None;
             }
         }
 
+        self.follow_refresh_rate()?;
+
+        // Taken out first so running them is free to borrow self
+        let dev_commands = core::mem::take(dev_command_queue());
+        for line in dev_commands {
+            log!("> {}", line);
+            let result = dev_commands::parse(&line[..])
+                .and_then(|command| self.run_dev_command(command));
+            if let Err(e) = result { log!("{}", e) }
+        }
+
+        let was_inverted = self.inverted;
+        let night_message =
+            self.night_mode.update(&self.settings, &mut self.inverted)?;
+        if let Some(message) = night_message {
+            self.toast.show(message, FRAME_RATE * 2);
+        }
+        if self.inverted != was_inverted {
+            Display::get().set_inverted(self.inverted)?;
+        }
+
+        unsafe {
+            if WANT_SAVE_STATE {
+                WANT_SAVE_STATE = false;
+                let message = self.save_state_file();
+                self.toast.show(message, FRAME_RATE * 2);
+            }
+        }
+
+        unsafe {
+            if WANT_OPTIONS {
+                WANT_OPTIONS = false;
+                if self.options.is_none() {
+                    // The game's paused as long as the menu's up, and it
+                    // might be left there until the Playdate sleeps
+                    saves::flush_now();
+                    self.options = Some(OptionsMenu::new(self)?);
+                }
+            }
+        }
+
+        if let Some(options) = &mut self.options {
+            match options.update()? {
+                Some(OptionsEvent::Run(action)) => {
+                    if self.run_option(action)? {
+                        self.close_options()?;
+                    } else {
+                        // Labels read from State, so the menu can't be
+                        // borrowed from it while they're rebuilt
+                        if let Some(mut options) = self.options.take() {
+                            options.refresh(self)?;
+                            self.options = Some(options);
+                        }
+                        return Ok(())
+                    }
+                },
+                Some(OptionsEvent::Closed) => self.close_options()?,
+                None => {
+                    // The highlight might have moved onto or off a display
+                    // item, so the same again for the preview
+                    if let Some(mut options) = self.options.take() {
+                        options.update_preview(self)?;
+                        self.options = Some(options);
+                    }
+                    return Ok(())
+                }
+            }
+        }
+
+        if let Some(manual) = &mut self.manual {
+            if manual.update()? {
+                self.manual_scrolls
+                    .insert(self.game_name.clone(), manual.scroll());
+                self.manual = None;
+                self.redraw_screen()?;
+            } else { return Ok(()) }
+        }
+
+        if let Some(qr_export) = &mut self.qr_export {
+            if qr_export.update()? {
+                self.qr_export = None;
+                self.redraw_screen()?;
+            } else { return Ok(()) }
+        }
+
+        if let Some(storage_screen) = &mut self.storage_screen {
+            if storage_screen.update()? {
+                self.storage_screen = None;
+                self.redraw_screen()?;
+            } else { return Ok(()) }
+        }
+
+        if let Some(gallery) = &mut self.gallery {
+            if gallery.update()? {
+                self.gallery = None;
+                self.redraw_screen()?;
+            } else { return Ok(()) }
+        }
+
+        // The game stays frozen underneath until they're done
+        if let Some(photo) = &mut self.photo {
+            if photo.update()? {
+                self.photo = None;
+                self.redraw_screen()?;
+            } else { return Ok(()) }
+        }
+
+        if let Some(frozen) = &mut self.frozen {
+            let event = match frozen.update()? {
+                Some(event) => event,
+                None => return Ok(())
+            };
+            if self.update_frozen_prompt(event)? {
+                self.frozen = None;
+                self.redraw_screen()?;
+            }
+            return Ok(())
+        }
+
+        if let Some(integrity) = &mut self.integrity {
+            if integrity.update()? {
+                self.integrity = None;
+                if let Some(rom) = self.pending_rom.take() {
+                    self.start_game(rom);
+                }
+            } else { return Ok(()) }
+        }
+
         if let Some(rom_picker) = &mut self.rom_picker {
             let maybe_picked_game = rom_picker.update(playdate)?;
 
+            if let Some(error) = rom_picker.take_read_error() {
+                let path = rom_picker.selected_path();
+                self.rom_picker = None;
+                self.cart_error =
+                    Some(CartErrorScreen::unreadable(&path[..], &error[..])?);
+                return Ok(())
+            }
+
             if let Some(picked_game) = maybe_picked_game {
-                let mut cpu = Cpu::from_rom_bytes(picked_game);
-                cpu.frame_rate = FRAME_RATE;
-                self.processor = Some(cpu);
+                self.rom_path = rom_picker.selected_path();
                 self.rom_picker = None;
-                graphics.clear(LCDColor::Solid(LCDSolidColor::kColorBlack))?;
+
+                let title = header::title(&picked_game[..]);
+                saves::migrate_unsafe_name(&title[..]);
+                let game_name = storage::safe_name(&title[..]);
+                let problems = integrity::check(&game_name[..]);
+                if problems.len() > 0 {
+                    self.integrity =
+                        Some(IntegrityScreen::new(&game_name[..], problems)?);
+                    self.pending_rom = Some(picked_game);
+                    return Ok(())
+                }
+
+                self.start_game(picked_game);
             // Else they're still picking
             } else { return Ok(()) }
         }
 
-        let gameboy = self.processor.as_mut().unwrap();
-
-        let crank_change = system.get_crank_change()?;
-        let processed_crank =
-            process_crank_change(crank_change, self.last_crank_change);
-        self.last_crank_change = crank_change;
-
-        let (btns_held, _, _) = system.get_button_state()?;
-
-        // TODO: Raise the joypad interrupt
-        gameboy.mem.joypad.a_pressed =
-            (btns_held & PDButtons::kButtonA) == PDButtons::kButtonA;
-        gameboy.mem.joypad.b_pressed =
-            (btns_held & PDButtons::kButtonB) == PDButtons::kButtonB;
-        gameboy.mem.joypad.up_pressed =
-            (btns_held & PDButtons::kButtonUp) == PDButtons::kButtonUp;
-        gameboy.mem.joypad.down_pressed =
-            (btns_held & PDButtons::kButtonDown) == PDButtons::kButtonDown;
-        gameboy.mem.joypad.left_pressed =
-            (btns_held & PDButtons::kButtonLeft) == PDButtons::kButtonLeft;
-        gameboy.mem.joypad.right_pressed =
-            (btns_held & PDButtons::kButtonRight) == PDButtons::kButtonRight;
-        gameboy.mem.joypad.start_pressed = processed_crank > 0.;
-        gameboy.mem.joypad.select_pressed = processed_crank < 0.;
-
-        // Actually *run* the Gameboy game.
-        gameboy.step_one_frame();
-
-        // Draw screen
-        let playdate_x_pixels =
-            (SCREEN_WIDTH as f32 * SCALE_FACTOR).floor() as usize;
-        let playdate_y_pixels = LCD_ROWS as usize;
-
-        // I've got a speculation that writing in X rows is better because
-        // that's how the framebuffer is written out in memory, but I'm not
-        // sure.
-        let framebuffer = graphics.get_frame()?;
-
-        for y in 0..playdate_y_pixels {
-            let mut screen_byte: u8 = 0x00;
-            for x in 0..playdate_x_pixels {
-                let gameboy_x = (x as f32 / SCALE_FACTOR).floor() as usize;
-                let gameboy_y = (y as f32 / SCALE_FACTOR).floor() as usize;
-                let gameboy_lcd_index = gameboy_y * SCREEN_WIDTH + gameboy_x;
-                let shade_at =
-                    &gameboy.gpu.finished_frame[gameboy_lcd_index];
-
-                let bit_index = 7 - (x % 8);
-
-                match shade_at {
-                    GreyShade::Black => {
-                        // The screen_byte is already black by default
-                    },
-                    GreyShade::DarkGrey => {
-                        // Same as below but draws every 3 pixels rather than 2
-                        let should_be_white = (x + y % 2) % 3 == 0;
-                        if should_be_white {
-                            screen_byte |= 1 << bit_index;
-                        }
-                    },
-                    GreyShade::LightGrey => {
-                        // This is a frame-stable cross-hatching calculation
-                        // On even Y rows, we draw pixels on every even X coord,
-                        // On odd Y rows, we draw pixels on every odd X coord
-                        let should_be_white = (x + y % 2) % 2 == 0;
-                        if should_be_white {
-                            screen_byte |= 1 << bit_index;
-                        }
-                    },
-                    GreyShade::White => {
-                        screen_byte |= 1 << bit_index;
+        // Checked after the picker, since booting can end up here
+        if let Some(cart_error) = &mut self.cart_error {
+            if cart_error.update()? {
+                self.cart_error = None;
+                self.rom_picker = Some(RomPickerState::new());
+            }
+            return Ok(())
+        }
+
+        if let Some(quick_menu) = &mut self.quick_menu {
+            if let Some(event) = quick_menu.update()? {
+                self.quick_menu = None;
+                self.redraw_screen()?;
+                if let QuickMenuEvent::Run(action) = event {
+                    self.run_quick_action(action)?;
+                }
+            }
+            return Ok(())
+        }
+
+        if let Some(scrubber) = &mut self.scrubber {
+            if let Some(event) = scrubber.update(&self.rewind)? {
+                self.scrubber = None;
+                let gameboy = self.processor.as_mut();
+                if let (ScrubberEvent::GoBackTo(index), Some(gameboy)) =
+                    (event, gameboy)
+                {
+                    // So the blit shows the state it went back to, not the
+                    // frame from before the scrubber came up
+                    if self.rewind.go_back_to(gameboy, self.rom_id, index) {
+                        gameboy.step_one_frame();
                     }
                 }
+                self.redraw_screen()?;
+            }
+            return Ok(())
+        }
+
+        if let Some(splash) = &mut self.splash {
+            if splash.update() {
+                self.splash = None;
+                Graphics::get()
+                    .clear(LCDColor::Solid(LCDSolidColor::kColorBlack))?;
+                border::draw(self.settings.border)?;
+                self.force_blit = true;
+            }
+            return Ok(())
+        }
+
+        // Only if booting went wrong somewhere start_game didn't catch,
+        // but the picker's better than a blank screen
+        if self.processor.is_none() {
+            self.rom_picker = Some(RomPickerState::new());
+            return Ok(())
+        }
+
+        // Nothing's emulated, and the buttons are kept for the next frame
+        if self.between_frames() {
+            let (_, pushed, _) = input::button_state()?;
+            self.missed_pushes = PDButtons(self.missed_pushes.0 | pushed.0);
+            self.draw_game(true, storage::writes_failing())?;
+            return Ok(())
+        }
+
+        if saves::take_save_failure() {
+            self.toast
+                .show(tr(Text::SaveFailed), FRAME_RATE * 3);
+        }
+
+        let resumed = self.frame_stats.frame_started()?;
+
+        // Hotkeys get the buttons first, and whatever they don't use is left
+        // for the built-in chords and the game
+        let (all_held, btns_down, _) = input::button_state()?;
+        let hotkey_input =
+            self.hotkey_layer.update(&self.settings.hotkeys[..], all_held);
+        for action in hotkey_input.fired.iter().copied() {
+            self.run_quick_action(action)?;
+        }
+        // The options hotkey takes over the screen
+        if self.options.is_some() { return Ok(()) }
+        let btns_held = if self.settings.display_mode == DisplayMode::Sideways {
+            input::sideways_buttons(hotkey_input.buttons)
+        } else {
+            hotkey_input.buttons
+        };
+        let btns_down =
+            PDButtons((btns_down.0 | self.missed_pushes.0) & btns_held.0);
+        self.missed_pushes = PDButtons(0);
+
+        // Only with the game showing, since everything over it is drawn
+        // the normal way up
+        let mut turned = None;
+        if let Some(tilt) = &mut self.tilt {
+            if tilt.due(FRAME_RATE) {
+                let reading = system.get_accelerometer()?;
+                turned = tilt.sample(reading, self.settings.display_mode);
+            }
+        }
+        if let Some(display_mode) = turned { self.turn_to(display_mode)? }
+
+        let gameboy = match self.processor.as_mut() {
+            Some(gameboy) => gameboy,
+            None => return Ok(())
+        };
+
+        let mut crank_change = system.get_crank_change()?;
+        if resumed {
+            // Whatever the crank did while the system menu (or one of ours)
+            // was up shouldn't reach the game as a Start or Select
+            crank_change = 0.;
+            self.start_select = StartSelect::new();
+            self.crank_spin = CrankSpin::new();
+            // This is the first we hear of the system menu, so it gets the
+            // same flush as opening the options menu. It doesn't cost
+            // anything after ours, which have flushed already.
+            saves::flush_now();
+        }
+
+        if self.paused {
+            let pressed = |button: PDButtons| (btns_down & button) == button;
+            if pressed(PDButtons::kButtonA) || pressed(PDButtons::kButtonB) {
+                self.paused = false;
+            } else {
+                // Topped up every frame so it stays until we carry on
+                self.toast.show(tr(Text::Paused), 2);
+            }
+        } else {
+            if (btns_held & quick_menu::OPEN_CHORD) == quick_menu::OPEN_CHORD {
+                let actions: Vec<QuickAction> = self.settings.quick_menu
+                    .iter()
+                    .copied()
+                    .filter(|action| action.available())
+                    .collect();
+                // It can't go round with nothing on it
+                if actions.len() > 0 {
+                    self.quick_menu = Some(QuickMenu::new(&actions[..])?);
+                    return Ok(())
+                }
+            }
+
+            if savestate::SUPPORTED {
+                let quick_message = self.quick_slot.update(
+                    btns_held,
+                    gameboy,
+                    &mut self.undo_slot,
+                    self.rom_id,
+                    self.settings.force_state_loads,
+                    FRAME_RATE
+                );
+                if let Some(message) = quick_message {
+                    self.toast.show(message, FRAME_RATE);
+                }
+            }
+        }
+
+        // The paddle has the crank to itself
+        let crank_free = self.paddle.is_none();
+        let spinning = if crank_free && self.settings.crank_fast_forward {
+            Some(self.crank_spin.update(crank_change, FRAME_RATE))
+        } else {
+            None
+        };
+        let crank_angle = if system.is_crank_docked()? {
+            None
+        } else {
+            Some(system.get_crank_angle()?)
+        };
+        let mapped = self.start_select.update(
+            if crank_free {
+                self.settings.start_select
+            } else {
+                StartSelectMode::CrankTurn
+            },
+            btns_held,
+            crank_change,
+            self.settings.flick_threshold,
+            self.settings.crank_dead_zone,
+            spinning,
+            crank_angle
+        );
+        let inputs = self.settings.button_map.resolve(
+            mapped.buttons, mapped.start, mapped.select
+        );
+        boot_macro::set_inputs(gameboy, inputs);
+        let joypad = &mut gameboy.mem.joypad;
+        let player_steering = joypad.left_pressed || joypad.right_pressed;
+        if let Some(paddle) = &mut self.paddle {
+            // The crank's busy, so Up and Down stand in for it
+            paddle.crank(crank_change);
+            joypad.start_pressed = joypad.up_pressed;
+            joypad.select_pressed = joypad.down_pressed;
+            joypad.up_pressed = false;
+            joypad.down_pressed = false;
+        }
+
+        // Actually *run* the Gameboy game. Fast forward runs a few frames
+        // and only shows the last.
+        let mut frames = if self.paused {
+            0
+        } else if self.fast_forward || hotkey_input.fast_forward ||
+            spinning == Some(true) {
+            self.settings.fast_forward_speed
+        } else {
+            1
+        };
+
+        let emulate_started = FrameStats::now_ms()?;
+
+        // A boot macro does its own emulating, with its own inputs, until
+        // anything at all is pressed or cranked. (Pressed, not held, or the A
+        // that picked the game would stop it straight away.)
+        if let Some(boot_macro) = &mut self.boot_macro {
+            let any_input = btns_down != PDButtons(0) || crank_change != 0.;
+            if any_input {
+                self.boot_macro = None;
+                self.toast.show(tr(Text::BootMacroStopped), FRAME_RATE);
+            } else {
+                frames = 0;
+                if boot_macro.play(gameboy, 1000 / FRAME_RATE * 3 / 4)? {
+                    self.boot_macro = None;
+                }
+            }
+        }
+
+        // Left out of boot macros like mash assist, since it'd muddle up
+        // the recording
+        if hotkey_input.rewind && self.recording.is_none() {
+            if self.rewind.history() == 0 {
+                self.toast.show(tr(Text::NothingToRewind), FRAME_RATE * 2);
+            } else {
+                let frame = &gameboy.gpu.finished_frame[..];
+                self.scrubber = Some(Scrubber::new(&self.rewind, frame)?);
+                return Ok(())
+            }
+        }
+
+        let mash_was_active = self.mash.active();
+        // Left out of boot macros like mash assist
+        // Whatever's mapped to the Gameboy's A and B, see button_map.rs
+        let turbo_held = |on: bool, bit: u8| on && inputs & bit != 0;
+        let turbo_a = turbo_held(self.settings.turbo_a, 1 << 0) &&
+            self.recording.is_none();
+        let turbo_b = turbo_held(self.settings.turbo_b, 1 << 1) &&
+            self.recording.is_none();
+        for _ in 0..frames {
+            // Left out of boot macros, which should be what a player
+            // actually pressed
+            self.mash.update(
+                hotkey_input.mash && self.recording.is_none(),
+                self.settings.mash_axis,
+                self.settings.mash_rate,
+                FRAME_RATE,
+                gameboy
+            );
+            self.turbo.update(
+                turbo_a, turbo_b, self.settings.turbo_period, gameboy
+            );
+            if let Some(paddle) = &mut self.paddle {
+                let steering = player_steering || self.mash.active();
+                paddle.update(steering, gameboy);
+            }
+            if let Some((buttons, frames_left)) = &mut self.press {
+                let held = boot_macro::read_inputs(gameboy) | *buttons;
+                boot_macro::set_inputs(gameboy, held);
+                *frames_left -= 1;
+                if *frames_left == 0 { self.press = None }
+            }
+            if let Some(recording) = &mut self.recording {
+                recording.push(boot_macro::read_inputs(gameboy));
+            }
+            boot_macro::raise_joypad_interrupt(gameboy, self.last_inputs);
+            self.last_inputs = boot_macro::read_inputs(gameboy);
+            gameboy.step_one_frame();
+            match self.serial.update(gameboy) {
+                Some(Ok(path)) => {
+                    log!("Printed {}", path);
+                    self.toast.show(tr(Text::Printed), FRAME_RATE * 2);
+                },
+                Some(Err(e)) => {
+                    log!("Couldn't save a print: {}", e);
+                    self.toast.show(tr(Text::PrintFailed), FRAME_RATE * 2);
+                },
+                None => {}
+            }
+            if let Some(cheats) = &self.cheats {
+                if self.settings.cheats { cheats.poke(gameboy) }
+            }
+            self.rewind.frame_done(gameboy, self.rom_id);
+
+            if let Some(splits) = &mut self.splits {
+                let run_message = splits.update(
+                    gameboy,
+                    self.undo_slot.loads(),
+                    self.settings.splits_reset_on_load
+                );
+                if let Some(message) = run_message {
+                    self.toast.show(message, FRAME_RATE * 3);
+                }
+            }
+        }
+        self.frame_stats.emulation_done(emulate_started)?;
 
-                if (x + 1) % 8 == 0 {
-                    // We've drawn a row of 8 pixels, let's commit it to the
-                    // frame buffer.
-                    // x + 64 is to horizontally center(-ish) the screen.
-                    // 67 is the actual constant, but keeping it divisible by
-                    // 8 lets us do faster framebuffer maths.
-                    let byte_index = y * 52 + (x + 64) / 8;
-                    framebuffer[byte_index] = screen_byte;
-                    screen_byte = 0x00;
+        let writes_failing = storage::writes_failing();
+        let mash_gone = mash_was_active && !self.mash.active();
+        let fast_forwarding = frames > 1;
+        let fast_forward_gone = self.showing_fast_forward && !fast_forwarding;
+        self.showing_fast_forward = fast_forwarding;
+        let overlay_gone = self.toast.take_expired() |
+            self.storage_banner.take_hidden(writes_failing) | mash_gone |
+            fast_forward_gone | self.start_select.take_selector_gone();
+        if overlay_gone {
+            border::draw(self.settings.border)?;
+            self.force_blit = true;
+        }
+
+        // Auto zoom only knows about the stretched picture
+        let stretched = self.settings.display_mode == DisplayMode::Stretched;
+        if self.settings.auto_zoom && stretched && !self.paused {
+            let finished_frame = &gameboy.gpu.finished_frame;
+            if self.auto_crop.update(finished_frame, FRAME_RATE) {
+                self.force_blit = true;
+                // The zoomed picture covers the borders, so they have to
+                // come back when it zooms out
+                if self.auto_crop.shown().is_full() {
+                    border::draw(self.settings.border)?;
                 }
             }
         }
 
-        // NOTE: This redraws the entire scren. Here we lose our little
-        //   optimisation we had before where we wouldn't redraw the borders
-        //   around the gameboy screen.
-        // TODO: Would it be quicker to check if screen_byte is going to be the
-        //   same as it was last frame, and then try and skip some updated
-        //   rows?
-        graphics.mark_updated_rows(0..=(LCD_ROWS - 1) as i32)?;
+        if self.settings.adaptive_contrast && !self.paused {
+            let was_engaged = self.contrast.shift() != 0;
+            if self.contrast.update(&gameboy.gpu.finished_frame) {
+                self.force_blit = true;
+                // Takes the indicator away
+                if was_engaged { border::draw(self.settings.border)?; }
+            }
+        }
+
+        // Running behind, so this frame goes undrawn (see frame_stats.rs),
+        // unless something's waiting to be drawn over. last_frame is still
+        // what's on screen, so the next frame's compared with that. The
+        // watchdog takes an undrawn frame as having changed.
+        let skipped_draw = self.frame_stats.behind() && !self.force_blit;
+        let (frame_changed, blit_skipped, rows_marked) = if skipped_draw {
+            (true, false, 0)
+        } else {
+            self.draw_game(false, writes_failing)?
+        };
+
+        saves::flush_when_quiet(frames, FRAME_RATE);
+        self.frame_stats.frame_finished(
+            blit_skipped, skipped_draw, rows_marked, FRAME_RATE
+        )?;
+
+        let pc = self.processor.as_ref().unwrap().registers.pc;
+        let stalled =
+            self.watchdog.update(pc, frames, frame_changed, FRAME_RATE);
+        if stalled { self.open_frozen_prompt()?; }
 
         Ok(())
     }
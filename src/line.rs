@@ -0,0 +1,33 @@
+use core::fmt::Write;
+
+// A short line of text, formatted in place with write! so things drawn
+// every frame (the markers in the borders, the split timer) cost no
+// allocation of ours. Anything past LINE_BYTES is an error from write!,
+// and what fitted is kept.
+
+const LINE_BYTES: usize = 16;
+
+pub struct Line {
+    bytes: [u8; LINE_BYTES],
+    len: usize
+}
+
+impl Line {
+    pub fn new () -> Self {
+        Self { bytes: [0; LINE_BYTES], len: 0 }
+    }
+
+    pub fn as_str (&self) -> &str {
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or("")
+    }
+}
+
+impl Write for Line {
+    fn write_str (&mut self, text: &str) -> core::fmt::Result {
+        let end = self.len + text.len();
+        if end > LINE_BYTES { return Err(core::fmt::Error) }
+        self.bytes[self.len..end].copy_from_slice(text.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
@@ -0,0 +1,160 @@
+use alloc::{format, string::String};
+use anyhow::Error;
+use core::fmt::{self, Write};
+#[cfg(not(test))]
+use core::ptr::addr_of_mut;
+use crankstart::system::System;
+
+use crate::storage;
+
+// Console logs disappear as soon as the Playdate is unplugged, so we keep
+// the most recent lines around in memory too, and can dump them to a file.
+//
+// Lines are formatted straight into fixed slots, so logging never touches
+// the allocator. That keeps it safe to call when memory is tight.
+const LINE_LENGTH: usize = 96;
+const LINE_COUNT: usize = 64;
+
+pub const LOG_PATH: &str = "system/playboy.log";
+
+struct LogRing {
+    lines: [[u8; LINE_LENGTH]; LINE_COUNT],
+    lengths: [u8; LINE_COUNT],
+    // Index of the slot the next line goes into
+    next: usize,
+    // How many slots have ever been filled, capped at LINE_COUNT
+    filled: usize
+}
+
+const EMPTY: LogRing = LogRing {
+    lines: [[0; LINE_LENGTH]; LINE_COUNT],
+    lengths: [0; LINE_COUNT],
+    next: 0,
+    filled: 0
+};
+
+#[cfg(not(test))]
+static mut RING: LogRing = EMPTY;
+
+// The only way at RING. f in for_each_line mustn't log, and nothing else
+// holds on to it, so there's never more than one of these in use.
+#[cfg(not(test))]
+fn ring () -> &'static mut LogRing {
+    unsafe { &mut *addr_of_mut!(RING) }
+}
+
+// Tests run on several threads at once, so each one logs into its own
+#[cfg(test)]
+fn ring () -> &'static mut LogRing {
+    std::thread_local! {
+        static RING: *mut LogRing = Box::into_raw(Box::new(EMPTY));
+    }
+    RING.with(|ring| unsafe { &mut **ring })
+}
+
+// Writes into one slot, silently dropping anything past the end of it
+struct SlotWriter<'a> {
+    slot: &'a mut [u8; LINE_LENGTH],
+    length: usize
+}
+
+impl<'a> Write for SlotWriter<'a> {
+    fn write_str (&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            if self.length == LINE_LENGTH { break }
+            self.slot[self.length] = byte;
+            self.length += 1;
+        }
+        Ok(())
+    }
+}
+
+// Truncation can split a multi-byte character, so only hand back the part
+// that's still valid UTF-8.
+fn slot_str (slot: &[u8]) -> &str {
+    match core::str::from_utf8(slot) {
+        Ok(s) => s,
+        Err(e) => core::str::from_utf8(&slot[..e.valid_up_to()]).unwrap_or("")
+    }
+}
+
+pub fn log (args: fmt::Arguments) {
+    let ring = ring();
+    let index = ring.next;
+    let mut writer = SlotWriter {
+        slot: &mut ring.lines[index],
+        length: 0
+    };
+    let _ = writer.write_fmt(args);
+    let length = writer.length;
+    ring.lengths[index] = length as u8;
+
+    ring.next = (index + 1) % LINE_COUNT;
+    if ring.filled < LINE_COUNT { ring.filled += 1 }
+
+    #[cfg(not(test))]
+    System::log_to_console(slot_str(&ring.lines[index][..length]));
+}
+
+// Calls f with each remembered line, oldest first
+pub fn for_each_line (mut f: impl FnMut(&str)) {
+    let ring = ring();
+    let first = (ring.next + LINE_COUNT - ring.filled) % LINE_COUNT;
+    for i in 0..ring.filled {
+        let index = (first + i) % LINE_COUNT;
+        let length = ring.lengths[index] as usize;
+        f(slot_str(&ring.lines[index][..length]));
+    }
+}
+
+// Writes the ring buffer out to LOG_PATH, in the system folder
+pub fn dump () -> Result<(), Error> {
+    let system = System::get();
+    let (seconds, _) = system.get_seconds_since_epoch()?;
+    let time = system.convert_epoch_to_datetime(seconds as u32)?;
+
+    let mut contents = String::new();
+    contents.push_str(&format!(
+        "Playboy log, dumped {}-{:02}-{:02} {:02}:{:02}:{:02}\n\n",
+        time.year, time.month, time.day, time.hour, time.minute, time.second
+    ));
+    for_each_line(|line| {
+        contents.push_str(line);
+        contents.push('\n');
+    });
+
+    storage::write_file(LOG_PATH, contents.as_bytes())
+}
+
+// Use this instead of crankstart's log_to_console! so the line is kept
+macro_rules! log {
+    ($($arg:tt)*) => {
+        $crate::log::log(format_args!($($arg)*))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn keeps_the_latest_lines () {
+        for i in 0..LINE_COUNT + 10 {
+            log!("line {}", i);
+        }
+        let long = "x".repeat(LINE_LENGTH * 2);
+        log!("{}", long);
+        // Cut off partway through a character
+        let accents = "é".repeat(LINE_LENGTH);
+        log!("a{}", accents);
+
+        let mut lines = Vec::new();
+        for_each_line(|line| lines.push(String::from(line)));
+        assert_eq!(lines.len(), LINE_COUNT);
+        assert_eq!(lines[0], "line 12");
+        assert_eq!(lines[LINE_COUNT - 3], format!("line {}", LINE_COUNT + 9));
+        assert_eq!(lines[LINE_COUNT - 2].len(), LINE_LENGTH);
+        assert_eq!(lines[LINE_COUNT - 1].len(), LINE_LENGTH - 1);
+    }
+}
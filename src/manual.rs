@@ -0,0 +1,172 @@
+use alloc::{format, string::String, vec::Vec};
+use anyhow::Error;
+use crankstart::{
+    graphics::{Graphics, LCDColor, LCDSolidColor},
+    system::System
+};
+use crankstart_sys::{LCDBitmapDrawMode, PDButtons, LCD_COLUMNS, LCD_ROWS};
+use euclid::{point2, rect};
+
+use crate::input;
+use crate::rom_picker;
+use crate::savestate::RomId;
+use crate::storage::{self, FileStore, PlaydateFiles};
+use crate::strings;
+
+// Plain text notes for a game - passwords, maps, move lists - read from
+// manuals/{ROM id}.txt, or manuals/{ROM filename}.txt if there isn't one of
+// those, and shown in a scrolling viewer.
+//
+// The text is wrapped once when it's opened. After that each line is just a
+// range into the wrapped text, so scrolling through thousands of lines
+// doesn't allocate anything.
+pub const MANUAL_DIR: &str = "manuals";
+
+const BAR_HEIGHT: i32 = 24;
+const LINE_HEIGHT: i32 = 20;
+const MARGIN: i32 = 10;
+const SCROLLBAR_WIDTH: i32 = 4;
+const TEXT_WIDTH: i32 = LCD_COLUMNS as i32 - MARGIN * 2 - SCROLLBAR_WIDTH;
+const VIEW_HEIGHT: i32 = LCD_ROWS as i32 - BAR_HEIGHT;
+// Pixels per degree of crank, so a full turn is about 18 lines
+const CRANK_SCROLL: f32 = 1.;
+// Pixels per frame while up or down is held
+const BUTTON_SCROLL: f32 = 6.;
+
+// The path of the game's manual, if it has one
+pub fn find_manual (rom_id: RomId, rom_path: &str) -> Option<String> {
+    let stem = rom_picker::rom_stem(rom_path);
+    let candidates = [
+        format!("{}/{}.txt", MANUAL_DIR, rom_id.hex()),
+        format!("{}/{}.txt", MANUAL_DIR, stem)
+    ];
+    candidates
+        .iter()
+        .find(|path| PlaydateFiles.size(&path[..]).is_ok())
+        .cloned()
+}
+
+pub struct ManualViewer {
+    title: String,
+    text: String,
+    // Where each line starts and ends in text
+    lines: Vec<(usize, usize)>,
+    // Pixels from the top. Kept as a float so slow cranking still adds up.
+    scroll: f32
+}
+
+impl ManualViewer {
+    // Like the other screens, this draws straight away. scroll is where the
+    // game's manual was left last time, if it's been open before.
+    pub fn new (
+        game_name: &str,
+        path: &str,
+        scroll: f32
+    ) -> Result<Self, Error> {
+        let bytes = storage::read_file(path)?;
+        let raw = String::from_utf8_lossy(&bytes[..])
+            .replace("\r\n", "\n")
+            .replace('\t', "    ");
+        let text = strings::wrap(&raw[..], TEXT_WIDTH);
+
+        let mut lines = Vec::new();
+        let mut start = 0;
+        for (i, c) in text.char_indices() {
+            if c == '\n' {
+                lines.push((start, i));
+                start = i + 1;
+            }
+        }
+        lines.push((start, text.len()));
+        log!("Opened {}, {} lines", path, lines.len());
+
+        let mut viewer = Self {
+            title: strings::ellipsize(game_name, LCD_COLUMNS as i32 - 12),
+            text,
+            lines,
+            scroll: 0.
+        };
+        viewer.scroll = scroll.max(0.).min(viewer.max_scroll());
+        viewer.redraw()?;
+        Ok(viewer)
+    }
+
+    // For putting it back where it was next time
+    pub fn scroll (&self) -> f32 {
+        self.scroll
+    }
+
+    fn max_scroll (&self) -> f32 {
+        let height = self.lines.len() as i32 * LINE_HEIGHT + MARGIN * 2;
+        (height - VIEW_HEIGHT).max(0) as f32
+    }
+
+    pub fn redraw (&self) -> Result<(), Error> {
+        let graphics = Graphics::get();
+        graphics.clear(LCDColor::Solid(LCDSolidColor::kColorWhite))?;
+
+        let scroll = self.scroll as i32;
+        let first = (scroll / LINE_HEIGHT) as usize;
+        let count = (VIEW_HEIGHT / LINE_HEIGHT) as usize + 2;
+
+        graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeCopy)?;
+        for (i, (start, end)) in
+            self.lines.iter().enumerate().skip(first).take(count)
+        {
+            if start == end { continue }
+            let top = BAR_HEIGHT + MARGIN + i as i32 * LINE_HEIGHT - scroll;
+            graphics.draw_text(&self.text[*start..*end], point2(MARGIN, top))?;
+        }
+
+        // Only worth a scrollbar if there's anywhere to scroll
+        let max_scroll = self.max_scroll();
+        if max_scroll > 0. {
+            let thumb = (VIEW_HEIGHT / 8).max(8);
+            let travel = (VIEW_HEIGHT - thumb) as f32;
+            let top = BAR_HEIGHT + (travel * self.scroll / max_scroll) as i32;
+            graphics.fill_rect(
+                rect(
+                    LCD_COLUMNS as i32 - SCROLLBAR_WIDTH,
+                    top,
+                    SCROLLBAR_WIDTH,
+                    thumb
+                ),
+                LCDColor::Solid(LCDSolidColor::kColorBlack)
+            )?;
+        }
+
+        // Over the top of anything scrolled up under it
+        graphics.fill_rect(
+            rect(0, 0, LCD_COLUMNS as i32, BAR_HEIGHT),
+            LCDColor::Solid(LCDSolidColor::kColorBlack)
+        )?;
+        graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeInverted)?;
+        graphics.draw_text(&self.title[..], point2(6, 3))?;
+        Ok(())
+    }
+
+    // Returns true once they've backed out
+    pub fn update (&mut self) -> Result<bool, Error> {
+        let system = System::get();
+        let (btns_held, btns_down, _) = input::button_state()?;
+        let held = |button: PDButtons| (btns_held & button) == button;
+        let pressed = |button: PDButtons| (btns_down & button) == button;
+
+        if pressed(PDButtons::kButtonB) { return Ok(true) }
+
+        let page = (VIEW_HEIGHT - LINE_HEIGHT) as f32;
+        let crank_change = system.get_crank_change()?;
+        let mut scroll = self.scroll + crank_change * CRANK_SCROLL;
+        if held(PDButtons::kButtonDown) { scroll += BUTTON_SCROLL }
+        if held(PDButtons::kButtonUp) { scroll -= BUTTON_SCROLL }
+        if pressed(PDButtons::kButtonRight) { scroll += page }
+        if pressed(PDButtons::kButtonLeft) { scroll -= page }
+        let scroll = scroll.max(0.).min(self.max_scroll());
+
+        // Only whole pixels are worth a redraw
+        let moved = scroll as i32 != self.scroll as i32;
+        self.scroll = scroll;
+        if moved { self.redraw()? }
+        Ok(false)
+    }
+}
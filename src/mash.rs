@@ -0,0 +1,114 @@
+use anyhow::Error;
+use crankstart::graphics::{Graphics, LCDColor, LCDSolidColor};
+use crankstart_sys::LCDBitmapDrawMode;
+use euclid::{point2, rect};
+use gbrs_core::cpu::Cpu;
+
+use crate::layout::GAME_LEFT;
+
+// Some games (Track & Field, the odd minigame) want left and right hammered
+// faster than the d-pad is comfortable for. While the hold_mash hotkey is
+// held, this presses the two directions on one axis in turn, at mash_rate
+// presses of each per second. The other axis still comes from the d-pad, and
+// letting go hands both back straight away.
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum MashAxis {
+    Horizontal,
+    Vertical
+}
+
+impl MashAxis {
+    // For the mash_axis setting
+    pub fn name (self) -> &'static str {
+        match self {
+            MashAxis::Horizontal => "horizontal",
+            MashAxis::Vertical => "vertical"
+        }
+    }
+
+    pub fn from_name (name: &str) -> Option<Self> {
+        match name {
+            "horizontal" => Some(MashAxis::Horizontal),
+            "vertical" => Some(MashAxis::Vertical),
+            _ => None
+        }
+    }
+}
+
+// Under the adaptive contrast marker
+const INDICATOR_LEFT: i32 = 4;
+const INDICATOR_TOP: i32 = 52;
+
+pub struct MashAssist {
+    active: bool,
+    // Frames since the trigger went down
+    frame: usize
+}
+
+impl MashAssist {
+    pub fn new () -> Self {
+        Self { active: false, frame: 0 }
+    }
+
+    pub fn active (&self) -> bool {
+        self.active
+    }
+
+    // Call once per emulated frame, after the joypad's been set from the
+    // buttons
+    pub fn update (
+        &mut self,
+        held: bool,
+        axis: MashAxis,
+        rate: usize,
+        frame_rate: usize,
+        cpu: &mut Cpu
+    ) {
+        self.active = held;
+        if !held {
+            self.frame = 0;
+            return
+        }
+
+        // A press of each direction per period, so each gets half of it. At
+        // the fastest, that's a different direction every frame.
+        let half_period = (frame_rate / (rate.max(1) * 2)).max(1);
+        let first = (self.frame / half_period) % 2 == 0;
+        self.frame += 1;
+
+        let joypad = &mut cpu.mem.joypad;
+        match axis {
+            MashAxis::Horizontal => {
+                joypad.left_pressed = first;
+                joypad.right_pressed = !first;
+            },
+            MashAxis::Vertical => {
+                joypad.up_pressed = first;
+                joypad.down_pressed = !first;
+            }
+        }
+    }
+
+    // Like the contrast marker, call this after the blit, and redraw the
+    // border when it goes away
+    pub fn draw_indicator (&self, axis: MashAxis) -> Result<(), Error> {
+        if !self.active { return Ok(()) }
+
+        let graphics = Graphics::get();
+        let width = GAME_LEFT as i32 - INDICATOR_LEFT * 2;
+        graphics.fill_rect(
+            rect(INDICATOR_LEFT, INDICATOR_TOP, width.min(28), 20),
+            LCDColor::Solid(LCDSolidColor::kColorWhite)
+        )?;
+        graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeCopy)?;
+        let label = match axis {
+            MashAxis::Horizontal => "<>",
+            MashAxis::Vertical => "^v"
+        };
+        graphics.draw_text(
+            label, point2(INDICATOR_LEFT + 2, INDICATOR_TOP + 1)
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,172 @@
+use alloc::{string::String, vec::Vec};
+use anyhow::Error;
+use crankstart::graphics::{Graphics, LCDColor, LCDSolidColor};
+use crankstart_sys::{LCDBitmapDrawMode, PDButtons, LCD_COLUMNS};
+use euclid::{point2, rect};
+
+use crate::input;
+use crate::strings;
+
+// Same look as the ROM picker
+const X_PADDING: i32 = 20;
+const Y_PADDING: i32 = 7;
+pub const TOP_BAR_HEIGHT: i32 = 30;
+const ITEM_HEIGHT: i32 = 30;
+const VISIBLE_ITEMS: usize = 5;
+
+pub enum MenuEvent {
+    // Index of the item they pressed A on
+    Picked(usize),
+    // They pressed B
+    Closed
+}
+
+// A full-screen list of text items, driven with up/down, A and B.
+// Features that need a little menu of their own build one of these rather
+// than drawing their own.
+pub struct ListMenu {
+    title: String,
+    items: Vec<String>,
+    selected: usize,
+    scroll: usize,
+    // Of the items, which can be cut down to leave room beside them
+    width: i32
+}
+
+impl ListMenu {
+    // Like RomPickerState::new, this draws the menu straight away
+    pub fn new (title: &str, items: Vec<String>) -> Result<Self, Error> {
+        let menu = Self {
+            title: String::from(title),
+            items,
+            selected: 0,
+            scroll: 0,
+            width: LCD_COLUMNS as i32
+        };
+        menu.draw()?;
+        Ok(menu)
+    }
+
+    // For things like toggles whose label shows their current value
+    pub fn set_item (
+        &mut self,
+        index: usize,
+        label: String
+    ) -> Result<(), Error> {
+        if self.items[index] == label { return Ok(()) }
+
+        self.items[index] = label;
+        if index >= self.scroll && index - self.scroll < VISIBLE_ITEMS {
+            self.draw_item(index - self.scroll)?;
+        }
+        Ok(())
+    }
+
+    pub fn selected (&self) -> usize {
+        self.selected
+    }
+
+    // The whole screen's drawn again, whatever else was on it
+    pub fn set_width (&mut self, width: i32) -> Result<(), Error> {
+        if self.width == width { return Ok(()) }
+
+        self.width = width;
+        self.draw()
+    }
+
+    pub fn update (&mut self) -> Result<Option<MenuEvent>, Error> {
+        let (_, btns_down, _) = input::button_state()?;
+
+        if (btns_down & PDButtons::kButtonB) == PDButtons::kButtonB {
+            return Ok(Some(MenuEvent::Closed))
+        }
+
+        if self.items.len() == 0 { return Ok(None) }
+
+        if (btns_down & PDButtons::kButtonDown) == PDButtons::kButtonDown &&
+            self.selected < self.items.len() - 1 {
+            self.selected += 1;
+
+            if self.selected - self.scroll >= VISIBLE_ITEMS {
+                self.scroll += 1;
+                self.draw_items()?;
+            } else {
+                self.draw_item(self.selected - self.scroll)?;
+                self.draw_item(self.selected - self.scroll - 1)?;
+            }
+        }
+
+        if (btns_down & PDButtons::kButtonUp) == PDButtons::kButtonUp &&
+            self.selected > 0 {
+            self.selected -= 1;
+
+            if self.selected < self.scroll {
+                self.scroll -= 1;
+                self.draw_items()?;
+            } else {
+                self.draw_item(self.selected - self.scroll)?;
+                self.draw_item(self.selected - self.scroll + 1)?;
+            }
+        }
+
+        if (btns_down & PDButtons::kButtonA) == PDButtons::kButtonA {
+            return Ok(Some(MenuEvent::Picked(self.selected)))
+        }
+
+        Ok(None)
+    }
+
+    pub fn draw (&self) -> Result<(), Error> {
+        let graphics = Graphics::get();
+
+        graphics.clear(LCDColor::Solid(LCDSolidColor::kColorWhite))?;
+        graphics.fill_rect(
+            rect(0, 0, LCD_COLUMNS as i32, TOP_BAR_HEIGHT),
+            LCDColor::Solid(LCDSolidColor::kColorBlack)
+        )?;
+        graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeInverted)?;
+        let title =
+            strings::ellipsize(&self.title[..], LCD_COLUMNS as i32 - 12);
+        graphics.draw_text(&title[..], point2(6, 6))?;
+
+        self.draw_items()
+    }
+
+    fn draw_items (&self) -> Result<(), Error> {
+        for i in 0..VISIBLE_ITEMS {
+            self.draw_item(i)?;
+        }
+        Ok(())
+    }
+
+    fn draw_item (&self, screen_index: usize) -> Result<(), Error> {
+        let graphics = Graphics::get();
+
+        let item_index = screen_index + self.scroll;
+        let am_selected = item_index == self.selected;
+        let scrn_index = screen_index as i32;
+
+        let top = TOP_BAR_HEIGHT + ITEM_HEIGHT * scrn_index +
+            Y_PADDING * (scrn_index + 1);
+        let width = self.width - X_PADDING * 2;
+        graphics.fill_rect(
+            rect(X_PADDING, top, width, ITEM_HEIGHT),
+            LCDColor::Solid(if am_selected {
+                LCDSolidColor::kColorBlack
+            } else {
+                LCDSolidColor::kColorWhite
+            })
+        )?;
+
+        if item_index >= self.items.len() {
+            return Ok(())
+        }
+
+        graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeNXOR)?;
+        // Translations can run a lot longer than the English
+        let label = strings::ellipsize(&self.items[item_index][..], width - 20);
+        graphics.draw_text(&label[..], point2(X_PADDING + 10, top + 6))?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,99 @@
+use alloc::string::String;
+use anyhow::{anyhow, Error};
+use crankstart::file::FileSystem;
+
+use crate::bugreport::BUGREPORT_PATH;
+use crate::log::LOG_PATH;
+use crate::screenshot;
+use crate::storage::{self, FileStore, PlaydateFiles};
+
+// Playboy used to write everything to the top of the data folder, in with
+// the ROMs. Now each kind of file has a folder of its own (see storage.rs),
+// and this moves anything left over from before into them.
+//
+// Battery saves are the one thing in here that can't be made again, so each
+// file is copied, read back and compared before the original goes. A file
+// that fails any of that stays where it was and gets another go next launch.
+// Saves are still loaded from the old place in the meantime.
+
+// Written once everything has moved, so later launches don't have to look
+const DONE_PATH: &str = "system/migrated";
+
+const SAVE_ENDINGS: &[&str] = &[".sav", ".sav.bak", ".sav.crc", ".sav.bak.crc"];
+
+fn destination (filename: &str) -> Option<String> {
+    let dir = if SAVE_ENDINGS.iter().any(|ending| filename.ends_with(ending)) {
+        storage::SAVES_DIR
+    } else if filename.ends_with(".state") {
+        storage::STATES_DIR
+    } else if screenshot::is_screenshot(filename) {
+        storage::SCREENSHOTS_DIR
+    } else if filename == storage::file_name(LOG_PATH) ||
+        filename == storage::file_name(BUGREPORT_PATH) ||
+        filename.ends_with(".boot.macro") {
+        storage::SYSTEM_DIR
+    } else {
+        return None
+    };
+    Some(storage::in_dir(dir, filename))
+}
+
+fn move_verified (
+    store: &impl FileStore,
+    from: &str,
+    to: &str
+) -> Result<(), Error> {
+    // Whatever's there already was written since the move, so it's newer
+    if store.size(to).is_ok() {
+        return Err(anyhow!("{} is already there", to))
+    }
+
+    let data = storage::read_file_in(store, from)?;
+    storage::write_file_in(store, to, &data[..])?;
+    if storage::read_file_in(store, to)? != data {
+        // Leave the original alone, and don't leave a bad copy that would
+        // be loaded instead of it
+        store.remove(to)?;
+        return Err(anyhow!("{} didn't read back the same as {}", to, from))
+    }
+    store.remove(from)
+}
+
+// Call once at launch, before anything loads or writes files
+pub fn run () {
+    let store = PlaydateFiles;
+    if store.size(DONE_PATH).is_ok() { return }
+
+    let filenames = match FileSystem::get().listfiles(".") {
+        Ok(filenames) => filenames,
+        Err(e) => {
+            log!("Couldn't look for files to move: {}", e);
+            return
+        }
+    };
+
+    let mut moved = 0;
+    let mut failed = 0;
+    for filename in filenames {
+        let to = match destination(&filename[..]) {
+            Some(to) => to,
+            None => continue
+        };
+        match move_verified(&store, &filename[..], &to[..]) {
+            Ok(()) => moved += 1,
+            Err(e) => {
+                log!("Couldn't move {}: {}", filename, e);
+                failed += 1;
+            }
+        }
+    }
+
+    if moved > 0 || failed > 0 {
+        log!("Moved {} files into folders, {} stayed behind", moved, failed);
+    }
+    if failed == 0 {
+        if let Err(e) = storage::write_file(DONE_PATH, &[]) {
+            log!("{}", e);
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use crankstart_sys::LCD_ROWS;
+use gbrs_core::{
+    constants::{SCREEN_HEIGHT, SCREEN_WIDTH},
+    lcd::GreyShade
+};
+
+use crate::autocrop::{self, Crop};
+use crate::blit;
+use crate::framebuffer;
+use crate::layout::{ROW_BYTES, ROW_STRIDE};
+use crate::strings::{tr, Text};
+
+// While auto zoom has cut the edges of the frame off, a minimap in one
+// corner shows the whole frame at quarter size (half across, half down)
+// with the zoomed-in part outlined, so whatever's coming in from off screen
+// can still be seen. It's only worked out again every other frame, but it's
+// copied back in every time the blit runs, since the zoomed blit draws over
+// the whole screen. It only ever goes in the framebuffer, so screenshots and
+// photos (which are made from the Gameboy's frame) never have it in.
+
+pub const WIDTH: usize = SCREEN_WIDTH / 2;
+pub const HEIGHT: usize = SCREEN_HEIGHT / 2;
+// Black round the picture, so it stands apart from the game behind it
+const MARGIN: usize = 4;
+const PANE_BYTES: usize = (WIDTH + MARGIN * 2) / 8;
+const PANE_HEIGHT: usize = HEIGHT + MARGIN * 2;
+
+const _: () = assert!((WIDTH + MARGIN * 2) % 8 == 0);
+
+// A 2x2 ordered dither for each Gameboy pixel block's average, so greys
+// stay grey at this size
+const THRESHOLDS: [usize; 4] = [0, 2, 3, 1];
+
+// Calls white with each pixel of the quarter-size picture of finished_frame
+// that comes out white. Rewind's thumbnails are made the same way.
+pub fn quarter_scale (
+    finished_frame: &[GreyShade],
+    mut white: impl FnMut(usize, usize)
+) {
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            // The four Gameboy pixels this one covers, 0 to 12
+            let sum: usize = [(0, 0), (1, 0), (0, 1), (1, 1)]
+                .iter()
+                .map(|(dx, dy)| {
+                    let index = (y * 2 + dy) * SCREEN_WIDTH + x * 2 + dx;
+                    blit::shade_value(&finished_frame[index]) as usize
+                })
+                .sum();
+            let threshold = THRESHOLDS[(y % 2) * 2 + x % 2];
+            if sum * 4 > threshold * 12 + 6 { white(x, y) }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Corner {
+    Off,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight
+}
+
+const CORNERS: &[Corner] = &[
+    Corner::Off,
+    Corner::TopLeft,
+    Corner::TopRight,
+    Corner::BottomLeft,
+    Corner::BottomRight
+];
+
+impl Corner {
+    // For the minimap setting
+    pub fn name (self) -> &'static str {
+        match self {
+            Corner::Off => "off",
+            Corner::TopLeft => "top_left",
+            Corner::TopRight => "top_right",
+            Corner::BottomLeft => "bottom_left",
+            Corner::BottomRight => "bottom_right"
+        }
+    }
+
+    // For the options menu
+    pub fn label (self) -> &'static str {
+        tr(match self {
+            Corner::Off => Text::Off,
+            Corner::TopLeft => Text::CornerTopLeft,
+            Corner::TopRight => Text::CornerTopRight,
+            Corner::BottomLeft => Text::CornerBottomLeft,
+            Corner::BottomRight => Text::CornerBottomRight
+        })
+    }
+
+    pub fn from_name (name: &str) -> Option<Self> {
+        CORNERS.iter().copied().find(|corner| corner.name() == name)
+    }
+
+    pub fn next (self) -> Self {
+        let index = CORNERS.iter().position(|corner| *corner == self).unwrap();
+        CORNERS[(index + 1) % CORNERS.len()]
+    }
+
+    // The pane's top left, in framebuffer bytes across and rows down
+    fn origin (self) -> Option<(usize, usize)> {
+        let right = ROW_BYTES - PANE_BYTES;
+        let bottom = LCD_ROWS as usize - PANE_HEIGHT;
+        match self {
+            Corner::Off => None,
+            Corner::TopLeft => Some((0, 0)),
+            Corner::TopRight => Some((right, 0)),
+            Corner::BottomLeft => Some((0, bottom)),
+            Corner::BottomRight => Some((right, bottom))
+        }
+    }
+}
+
+pub struct Minimap {
+    // The pane as it goes on screen, margin and all, one bit per pixel
+    pane: [u8; PANE_BYTES * PANE_HEIGHT],
+    // The one outlined on it
+    crop: Crop,
+    frames: usize
+}
+
+impl Minimap {
+    pub fn new () -> Self {
+        Self {
+            pane: [0; PANE_BYTES * PANE_HEIGHT],
+            crop: autocrop::FULL,
+            frames: 0
+        }
+    }
+
+    // Call once per emulated update while it's showing. It's drawn again
+    // every other call, or straight away when the zoom moves, so the
+    // outline keeps up with it.
+    pub fn update (&mut self, finished_frame: &[GreyShade], crop: &Crop) {
+        let due = self.frames % 2 == 0 || *crop != self.crop;
+        self.frames += 1;
+        if due { self.render(finished_frame, crop) }
+    }
+
+    fn flip (&mut self, x: usize, y: usize) {
+        let x = MARGIN + x;
+        self.pane[(MARGIN + y) * PANE_BYTES + x / 8] ^= 0x80 >> (x % 8);
+    }
+
+    fn render (&mut self, finished_frame: &[GreyShade], crop: &Crop) {
+        self.crop = *crop;
+        for byte in self.pane.iter_mut() { *byte = 0 }
+        quarter_scale(finished_frame, |x, y| self.flip(x, y));
+
+        // Inverted, so it shows up over light and dark alike
+        let (left, top) = (crop.left / 2, crop.top / 2);
+        let right = (crop.right - 1) / 2;
+        let bottom = (crop.bottom - 1) / 2;
+        for x in left..=right {
+            self.flip(x, top);
+            if bottom != top { self.flip(x, bottom) }
+        }
+        for y in top + 1..bottom {
+            self.flip(left, y);
+            if right != left { self.flip(right, y) }
+        }
+    }
+
+    pub fn draw (&self, corner: Corner, framebuffer: &mut [u8]) {
+        let (left_byte, top) = match corner.origin() {
+            Some(origin) => origin,
+            None => return
+        };
+        for (y, row) in self.pane.chunks(PANE_BYTES).enumerate() {
+            for (byte_x, byte) in row.iter().enumerate() {
+                framebuffer::draw_byte_at(
+                    framebuffer, ROW_STRIDE, left_byte + byte_x, top + y, *byte
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{vec, vec::Vec};
+
+    fn white_pixel (minimap: &Minimap, x: usize, y: usize) -> bool {
+        let x = MARGIN + x;
+        minimap.pane[(MARGIN + y) * PANE_BYTES + x / 8] & (0x80 >> (x % 8))
+            != 0
+    }
+
+    fn flat_frame (shade: GreyShade) -> Vec<GreyShade> {
+        vec![shade; SCREEN_WIDTH * SCREEN_HEIGHT]
+    }
+
+    fn render (frame: &[GreyShade]) -> Minimap {
+        let mut minimap = Minimap::new();
+        minimap.render(frame, &autocrop::FULL);
+        minimap
+    }
+
+    // White pixels inside the full frame's outline
+    fn white_inside (minimap: &Minimap) -> usize {
+        (1..HEIGHT - 1)
+            .flat_map(|y| (1..WIDTH - 1).map(move |x| (x, y)))
+            .filter(|&(x, y)| white_pixel(minimap, x, y))
+            .count()
+    }
+
+    #[test]
+    fn shades_come_through () {
+        let inside = (WIDTH - 2) * (HEIGHT - 2);
+        let count = |shade| white_inside(&render(&flat_frame(shade)));
+        assert_eq!(count(GreyShade::White), inside);
+        assert_eq!(count(GreyShade::LightGrey), inside * 3 / 4);
+        assert_eq!(count(GreyShade::DarkGrey), inside / 4);
+        assert_eq!(count(GreyShade::Black), 0);
+    }
+
+    #[test]
+    fn each_pixel_is_a_block_of_four () {
+        let mut frame = flat_frame(GreyShade::Black);
+        for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)].iter() {
+            frame[(30 + dy) * SCREEN_WIDTH + 20 + dx] = GreyShade::White;
+        }
+        let minimap = render(&frame[..]);
+        assert!(white_pixel(&minimap, 10, 15));
+        assert!(!white_pixel(&minimap, 11, 15));
+        assert!(!white_pixel(&minimap, 10, 16));
+    }
+
+    #[test]
+    fn outlines_the_crop () {
+        let mut minimap = Minimap::new();
+        let crop = Crop { left: 20, top: 10, right: 100, bottom: 90 };
+        minimap.render(&flat_frame(GreyShade::Black)[..], &crop);
+        let (left, top, right, bottom) = (10, 5, 49, 44);
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let on_edge = (x == left || x == right) &&
+                    y >= top && y <= bottom ||
+                    (y == top || y == bottom) && x >= left && x <= right;
+                assert_eq!(white_pixel(&minimap, x, y), on_edge, "{} {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn margin_stays_black () {
+        let minimap = render(&flat_frame(GreyShade::White)[..]);
+        for y in 0..PANE_HEIGHT {
+            for x in 0..PANE_BYTES * 8 {
+                let inside = x >= MARGIN && x < MARGIN + WIDTH &&
+                    y >= MARGIN && y < MARGIN + HEIGHT;
+                let byte = minimap.pane[y * PANE_BYTES + x / 8];
+                let white = byte & (0x80 >> (x % 8)) != 0;
+                if !inside { assert!(!white, "{} {}", x, y) }
+            }
+        }
+    }
+
+    #[test]
+    fn renders_every_other_update () {
+        let mut minimap = Minimap::new();
+        let crop = autocrop::FULL;
+        minimap.update(&flat_frame(GreyShade::White)[..], &crop);
+        let first = minimap.pane;
+        minimap.update(&flat_frame(GreyShade::Black)[..], &crop);
+        assert!(minimap.pane == first);
+        minimap.update(&flat_frame(GreyShade::Black)[..], &crop);
+        assert!(minimap.pane != first);
+
+        // but a zoom that's moved doesn't wait its turn
+        let before = minimap.pane;
+        let zoomed = Crop { left: 8, top: 8, right: 152, bottom: 136 };
+        minimap.update(&flat_frame(GreyShade::Black)[..], &zoomed);
+        assert!(minimap.pane != before);
+    }
+
+    #[test]
+    fn corners_stay_on_screen () {
+        let mut screen = vec![0xFF; ROW_STRIDE * LCD_ROWS as usize];
+        let minimap = Minimap::new();
+        for corner in CORNERS.iter() {
+            minimap.draw(*corner, &mut screen[..]);
+        }
+        // Four corners' worth blacked out, and nothing else touched
+        let black = screen.iter().filter(|byte| **byte == 0).count();
+        assert_eq!(black, PANE_BYTES * PANE_HEIGHT * 4);
+        let column = |byte_x: usize, y: usize| screen[y * ROW_STRIDE + byte_x];
+        assert_eq!(column(0, 0), 0);
+        assert_eq!(column(ROW_BYTES - 1, LCD_ROWS as usize - 1), 0);
+        assert_eq!(column(PANE_BYTES, 0), 0xFF);
+        assert_eq!(column(ROW_BYTES, 0), 0xFF);
+    }
+}
@@ -0,0 +1,68 @@
+use gbrs_core::cpu::Cpu;
+
+use crate::header;
+use crate::strings::{tr, Text};
+
+// Which Gameboy Playboy claims to be. Games tell the models apart by the
+// value the boot ROM leaves in A, and some change what they do (or unlock
+// extra palettes) based on it. We don't emulate the differences in the
+// hardware itself, only what the game can see at boot.
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Model {
+    // The original Gameboy
+    Dmg,
+    // Gameboy Pocket
+    Mgb,
+    // A Gameboy Color running a non-colour game
+    GbcDmg
+}
+
+const MODELS: &[Model] = &[Model::Dmg, Model::Mgb, Model::GbcDmg];
+
+impl Model {
+    pub fn name (self) -> &'static str {
+        match self {
+            Model::Dmg => "dmg",
+            Model::Mgb => "mgb",
+            Model::GbcDmg => "cgb"
+        }
+    }
+
+    pub fn from_name (name: &str) -> Option<Self> {
+        MODELS.iter().copied().find(|model| model.name() == name)
+    }
+
+    pub fn next (self) -> Self {
+        let index = MODELS.iter().position(|model| *model == self).unwrap();
+        MODELS[(index + 1) % MODELS.len()]
+    }
+
+    // For the options menu
+    pub fn label (self) -> &'static str {
+        tr(match self {
+            Model::Dmg => Text::ModelDmg,
+            Model::Mgb => Text::ModelMgb,
+            Model::GbcDmg => Text::ModelGbc
+        })
+    }
+
+    // What the cart's header says it would rather run on
+    pub fn preferred_by (rom: &[u8]) -> Self {
+        if header::supports_cgb(rom) { Model::GbcDmg } else { Model::Dmg }
+    }
+
+    // The A register as the boot ROM leaves it
+    fn boot_a (self) -> u8 {
+        match self {
+            Model::Dmg => 0x01,
+            Model::Mgb => 0xFF,
+            Model::GbcDmg => 0x11
+        }
+    }
+
+    // Call on a freshly made Cpu, before its first frame
+    pub fn apply (self, cpu: &mut Cpu) {
+        cpu.registers.a = self.boot_a();
+    }
+}
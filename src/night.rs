@@ -0,0 +1,86 @@
+use anyhow::Error;
+use crankstart::system::System;
+
+use crate::settings::Settings;
+use crate::strings::{tr, Text};
+
+// How often we look at the clock. The boundaries are on the hour, so being
+// a few seconds late to notice one doesn't matter.
+const CHECK_INTERVAL_MS: usize = 10_000;
+
+fn in_window (hour: u8, start: u8, end: u8) -> bool {
+    if start <= end {
+        hour >= start && hour < end
+    } else {
+        // The window wraps past midnight, eg. 22 -> 7
+        hour >= start || hour < end
+    }
+}
+
+fn local_hour () -> Result<u8, Error> {
+    let system = System::get();
+    let (seconds, _) = system.get_seconds_since_epoch()?;
+    let offset = system.get_timezone_offset()?;
+    let local = (seconds as i64 + offset as i64) as u32;
+    Ok(system.convert_epoch_to_datetime(local)?.hour)
+}
+
+// Flips the display inversion on and off at the scheduled hours. It only
+// acts when a boundary is crossed, so inverting by hand part way through
+// the night sticks until the next one.
+pub struct NightMode {
+    // Whether we were inside the window last time we looked. None until the
+    // first check, so starting Playboy at night counts as a crossing.
+    was_in_window: Option<bool>,
+    last_check_ms: Option<usize>
+}
+
+impl NightMode {
+    pub fn new () -> Self {
+        Self {
+            was_in_window: None,
+            last_check_ms: None
+        }
+    }
+
+    // Call when the schedule settings change so they take effect straight
+    // away rather than at the next boundary
+    pub fn reset (&mut self) {
+        self.was_in_window = None;
+        self.last_check_ms = None;
+    }
+
+    // Updates inverted if we've just crossed a boundary, and returns a
+    // message explaining the change for a toast.
+    pub fn update (
+        &mut self,
+        settings: &Settings,
+        inverted: &mut bool
+    ) -> Result<Option<&'static str>, Error> {
+        if !settings.night_mode { return Ok(None) }
+
+        let now = System::get().get_current_time_milliseconds()?;
+        if let Some(last_check) = self.last_check_ms {
+            if now.wrapping_sub(last_check) < CHECK_INTERVAL_MS {
+                return Ok(None)
+            }
+        }
+        self.last_check_ms = Some(now);
+
+        let in_window = in_window(
+            local_hour()?, settings.night_start, settings.night_end
+        );
+        if self.was_in_window == Some(in_window) { return Ok(None) }
+        self.was_in_window = Some(in_window);
+
+        let now_inverted = if in_window { true } else { settings.invert };
+        if now_inverted == *inverted { return Ok(None) }
+        *inverted = now_inverted;
+
+        Ok(Some(if in_window {
+            tr(Text::NightModeStarted)
+        } else {
+            tr(Text::NightModeOver)
+        }))
+    }
+}
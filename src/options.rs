@@ -0,0 +1,321 @@
+use alloc::{format, string::String, vec::Vec};
+use anyhow::Error;
+use crankstart::graphics::Graphics;
+use crankstart_sys::{LCD_COLUMNS, LCD_ROWS};
+
+use crate::display_mode::DisplayMode;
+use crate::dither::Dither;
+use crate::menu::{ListMenu, MenuEvent};
+use crate::preview::{self, Preview};
+use crate::savestate;
+use crate::strings::{tr, tr_bool, trf, Text};
+use crate::turbo;
+use crate::{State, FRAME_RATE};
+
+// The Playdate system menu only has room for three of our own items, so
+// everything else lives in this menu, opened from the "options" item.
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum OptionsAction {
+    CycleBorder,
+    CycleModel,
+    CycleLanguage,
+    ToggleInvert,
+    CycleDisplayMode,
+    ToggleAutoOrientation,
+    CycleDither,
+    ToggleTemporalDither,
+    ToggleFrameBlend,
+    ToggleAutoZoom,
+    CycleMinimap,
+    ToggleAdaptiveContrast,
+    ToggleNightMode,
+    CycleNightStart,
+    CycleNightEnd,
+    ToggleQuickSlotToDisk,
+    ToggleSplitsResetOnLoad,
+    CycleStartSelect,
+    CycleCrankDeadZone,
+    ToggleCrankFastForward,
+    ToggleTurboA,
+    ToggleTurboB,
+    CycleTurboPeriod,
+    ToggleCheats,
+    TogglePerGame,
+    LoadState,
+    UndoLoad,
+    PressStart,
+    PressSelect,
+    ResetGame,
+    OpenManual,
+    OpenGallery,
+    OpenPhotoMode,
+    OpenStorage,
+    ExportSaveQr,
+    RecordBootMacro,
+    TogglePerfOverlay,
+    DumpLog,
+    WriteBugReport
+}
+
+const ACTIONS: &[OptionsAction] = &[
+    OptionsAction::CycleBorder,
+    OptionsAction::CycleModel,
+    OptionsAction::CycleLanguage,
+    OptionsAction::ToggleInvert,
+    OptionsAction::CycleDisplayMode,
+    OptionsAction::ToggleAutoOrientation,
+    OptionsAction::CycleDither,
+    OptionsAction::ToggleTemporalDither,
+    OptionsAction::ToggleFrameBlend,
+    OptionsAction::ToggleAutoZoom,
+    OptionsAction::CycleMinimap,
+    OptionsAction::ToggleAdaptiveContrast,
+    OptionsAction::ToggleNightMode,
+    OptionsAction::CycleNightStart,
+    OptionsAction::CycleNightEnd,
+    OptionsAction::ToggleQuickSlotToDisk,
+    OptionsAction::ToggleSplitsResetOnLoad,
+    OptionsAction::CycleStartSelect,
+    OptionsAction::CycleCrankDeadZone,
+    OptionsAction::ToggleCrankFastForward,
+    OptionsAction::ToggleTurboA,
+    OptionsAction::ToggleTurboB,
+    OptionsAction::CycleTurboPeriod,
+    OptionsAction::ToggleCheats,
+    OptionsAction::TogglePerGame,
+    OptionsAction::LoadState,
+    OptionsAction::UndoLoad,
+    OptionsAction::PressStart,
+    OptionsAction::PressSelect,
+    OptionsAction::ResetGame,
+    OptionsAction::OpenManual,
+    OptionsAction::OpenGallery,
+    OptionsAction::OpenPhotoMode,
+    OptionsAction::OpenStorage,
+    OptionsAction::ExportSaveQr,
+    OptionsAction::RecordBootMacro,
+    OptionsAction::TogglePerfOverlay,
+    OptionsAction::DumpLog,
+    OptionsAction::WriteBugReport
+];
+
+// Some items only make sense for some games, and are left out otherwise
+fn available (action: OptionsAction, state: &State) -> bool {
+    match action {
+        OptionsAction::OpenManual => state.manual_path.is_some(),
+        OptionsAction::ToggleCheats => state.cheats.is_some(),
+        OptionsAction::OpenPhotoMode | OptionsAction::TogglePerGame |
+        OptionsAction::PressStart | OptionsAction::PressSelect |
+        OptionsAction::ResetGame => state.processor.is_some(),
+        OptionsAction::LoadState =>
+            state.processor.is_some() && savestate::SUPPORTED,
+        OptionsAction::UndoLoad | OptionsAction::ToggleQuickSlotToDisk |
+        OptionsAction::ToggleSplitsResetOnLoad => savestate::SUPPORTED,
+        _ => true
+    }
+}
+
+// Labels show the current value of whatever the item changes
+fn label (action: OptionsAction, state: &State) -> String {
+    let settings = &state.settings;
+    match action {
+        OptionsAction::CycleBorder =>
+            trf(Text::BorderLabel, &[&settings.border.label()]),
+        OptionsAction::CycleModel => {
+            let mut text = trf(Text::ModelLabel, &[&settings.model.label()]);
+            if let Some(preferred) = state.preferred_model {
+                if preferred != settings.model {
+                    text.push_str(
+                        &trf(Text::CartPrefers, &[&preferred.label()])[..]
+                    );
+                }
+            }
+            text
+        },
+        OptionsAction::CycleLanguage => {
+            let language = if settings.language == "auto" {
+                tr(Text::LanguageAuto)
+            } else {
+                &settings.language[..]
+            };
+            trf(Text::LanguageLabel, &[&language])
+        },
+        OptionsAction::ToggleInvert =>
+            trf(Text::InvertLabel, &[&tr_bool(state.inverted)]),
+        OptionsAction::CycleDisplayMode =>
+            trf(Text::DisplayModeLabel, &[&settings.display_mode.label()]),
+        OptionsAction::ToggleAutoOrientation => trf(
+            Text::AutoOrientationLabel,
+            &[&tr_bool(settings.auto_orientation)]
+        ),
+        OptionsAction::CycleDither =>
+            trf(Text::DitherLabel, &[&settings.dither.label()]),
+        OptionsAction::ToggleTemporalDither => trf(
+            Text::TemporalDitherLabel,
+            &[&tr_bool(settings.temporal_dither)]
+        ),
+        OptionsAction::ToggleFrameBlend =>
+            trf(Text::FrameBlendLabel, &[&tr_bool(settings.frame_blend)]),
+        OptionsAction::ToggleAutoZoom =>
+            trf(Text::AutoZoomLabel, &[&tr_bool(settings.auto_zoom)]),
+        OptionsAction::CycleMinimap =>
+            trf(Text::MinimapLabel, &[&settings.minimap.label()]),
+        OptionsAction::ToggleAdaptiveContrast => trf(
+            Text::AdaptiveContrastLabel,
+            &[&tr_bool(settings.adaptive_contrast)]
+        ),
+        OptionsAction::ToggleNightMode =>
+            trf(Text::NightModeLabel, &[&tr_bool(settings.night_mode)]),
+        OptionsAction::CycleNightStart => trf(
+            Text::NightFromLabel, &[&format!("{:02}:00", settings.night_start)]
+        ),
+        OptionsAction::CycleNightEnd => trf(
+            Text::NightUntilLabel, &[&format!("{:02}:00", settings.night_end)]
+        ),
+        OptionsAction::ToggleQuickSlotToDisk => trf(
+            Text::KeepQuickSaveLabel, &[&tr_bool(settings.quick_slot_to_disk)]
+        ),
+        OptionsAction::ToggleSplitsResetOnLoad => trf(
+            Text::SplitsResetLabel, &[&tr_bool(settings.splits_reset_on_load)]
+        ),
+        OptionsAction::CycleStartSelect => trf(
+            Text::StartSelectLabel, &[&settings.start_select.label()]
+        ),
+        OptionsAction::CycleCrankDeadZone =>
+            trf(Text::CrankDeadZoneLabel, &[&settings.crank_dead_zone]),
+        OptionsAction::ToggleCrankFastForward => trf(
+            Text::CrankFastForwardLabel,
+            &[&tr_bool(settings.crank_fast_forward)]
+        ),
+        OptionsAction::ToggleTurboA =>
+            trf(Text::TurboALabel, &[&tr_bool(settings.turbo_a)]),
+        OptionsAction::ToggleTurboB =>
+            trf(Text::TurboBLabel, &[&tr_bool(settings.turbo_b)]),
+        OptionsAction::CycleTurboPeriod => trf(
+            Text::TurboSpeedLabel,
+            &[&turbo::rate_label(settings.turbo_period, FRAME_RATE)]
+        ),
+        OptionsAction::ToggleCheats =>
+            trf(Text::CheatsLabel, &[&tr_bool(settings.cheats)]),
+        OptionsAction::TogglePerGame =>
+            trf(Text::PerGameLabel, &[&tr_bool(settings.per_game())]),
+        OptionsAction::LoadState => String::from(tr(Text::LoadState)),
+        OptionsAction::UndoLoad => String::from(tr(Text::UndoLoad)),
+        OptionsAction::PressStart => String::from(tr(Text::PressStart)),
+        OptionsAction::PressSelect => String::from(tr(Text::PressSelect)),
+        OptionsAction::ResetGame => String::from(tr(Text::ResetGame)),
+        OptionsAction::OpenManual => String::from(tr(Text::Manual)),
+        OptionsAction::OpenGallery => String::from(tr(Text::Screenshots)),
+        OptionsAction::OpenPhotoMode => String::from(tr(Text::PhotoMode)),
+        OptionsAction::OpenStorage => String::from(tr(Text::Storage)),
+        OptionsAction::ExportSaveQr => String::from(tr(Text::ExportSaveQr)),
+        OptionsAction::RecordBootMacro => String::from(tr(
+            if state.recording.is_some() {
+                Text::StopBootMacro
+            } else {
+                Text::RecordBootMacro
+            }
+        )),
+        OptionsAction::TogglePerfOverlay =>
+            trf(Text::PerfOverlayLabel, &[&tr_bool(settings.perf_overlay)]),
+        OptionsAction::DumpLog => String::from(tr(Text::DumpLog)),
+        OptionsAction::WriteBugReport =>
+            String::from(tr(Text::CreateBugReport))
+    }
+}
+
+pub enum OptionsEvent {
+    Run(OptionsAction),
+    Closed
+}
+
+pub struct OptionsMenu {
+    menu: ListMenu,
+    // The ones from ACTIONS that are available, in menu order
+    actions: Vec<OptionsAction>,
+    // Made the first time a display item's highlighted with a game loaded
+    preview: Option<Preview>,
+    // What the pane's showing, if it's up, so it's only drawn again when
+    // that changes
+    previewing: Option<(Dither, DisplayMode)>
+}
+
+impl OptionsMenu {
+    pub fn new (state: &State) -> Result<Self, Error> {
+        let actions: Vec<OptionsAction> = ACTIONS
+            .iter()
+            .copied()
+            .filter(|action| available(*action, state))
+            .collect();
+        let items: Vec<String> = actions
+            .iter()
+            .map(|action| label(*action, state))
+            .collect();
+
+        let mut options = Self {
+            menu: ListMenu::new(tr(Text::OptionsTitle), items)?,
+            actions,
+            preview: None,
+            previewing: None
+        };
+        options.update_preview(state)?;
+        Ok(options)
+    }
+
+    // Call after changing a setting so the labels show the new values
+    pub fn refresh (&mut self, state: &State) -> Result<(), Error> {
+        for (index, action) in self.actions.iter().enumerate() {
+            self.menu.set_item(index, label(*action, state))?;
+        }
+        self.update_preview(state)
+    }
+
+    // Call after update too. With a game loaded, the display items get a
+    // pane beside them showing the paused game with their current value
+    // (see preview.rs), and the list narrows to make room.
+    pub fn update_preview (&mut self, state: &State) -> Result<(), Error> {
+        let highlighted = self.actions[self.menu.selected()];
+        let display_item = matches!(
+            highlighted,
+            OptionsAction::CycleDither | OptionsAction::CycleDisplayMode
+        );
+        let gameboy = match &state.processor {
+            Some(gameboy) if display_item => gameboy,
+            _ => {
+                if self.previewing.take().is_some() {
+                    self.menu.set_width(LCD_COLUMNS as i32)?;
+                }
+                return Ok(())
+            }
+        };
+
+        let settings = &state.settings;
+        let showing = (settings.dither, settings.display_mode);
+        if self.previewing == Some(showing) { return Ok(()) }
+        self.previewing = Some(showing);
+        self.menu.set_width((preview::PANE_LEFT_BYTE * 8) as i32)?;
+
+        let preview = self.preview.get_or_insert_with(Preview::new);
+        preview.render(
+            &gameboy.gpu.finished_frame,
+            settings.dither,
+            settings.display_mode,
+            state.contrast.shift()
+        );
+        let graphics = Graphics::get();
+        preview.copy_pane(graphics.get_frame()?);
+        graphics.mark_updated_rows(
+            preview::PANE_TOP as i32..=(LCD_ROWS - 1) as i32
+        )
+    }
+
+    pub fn update (&mut self) -> Result<Option<OptionsEvent>, Error> {
+        Ok(match self.menu.update()? {
+            Some(MenuEvent::Picked(index)) =>
+                Some(OptionsEvent::Run(self.actions[index])),
+            Some(MenuEvent::Closed) => Some(OptionsEvent::Closed),
+            None => None
+        })
+    }
+}
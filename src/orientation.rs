@@ -0,0 +1,176 @@
+use crate::display_mode::DisplayMode;
+
+// With auto_orientation on, the accelerometer decides between the sideways
+// display mode and whichever one they'd picked for landscape. It's read a
+// few times a second, and only switches once the Playdate's clearly been
+// held the other way for a whole second. Lying flat, or anywhere near it,
+// counts for neither, so it never flip-flops on a table.
+//
+// The readings are in g, with y pointing out of the bottom edge and x out
+// of the crank side. Upright is about (0, 1, 0), and sideways (crank at the
+// bottom, see display_mode.rs) about (1, 0, 0). Upside down counts as
+// landscape too, since the flip's already taken care of elsewhere.
+
+const SAMPLES_PER_SECOND: usize = 4;
+// How far gravity has to lean along an edge to count at all
+const MIN_LEAN: f32 = 0.6;
+// and how much more along that edge than the other
+const MIN_MARGIN: f32 = 0.3;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Held {
+    Landscape,
+    Sideways
+}
+
+fn held_from (x: f32, y: f32) -> Option<Held> {
+    if x >= MIN_LEAN && x - y.abs() >= MIN_MARGIN {
+        Some(Held::Sideways)
+    } else if y.abs() >= MIN_LEAN && y.abs() - x.abs() >= MIN_MARGIN {
+        Some(Held::Landscape)
+    } else {
+        None
+    }
+}
+
+pub struct Tilt {
+    // Updates since the last sample
+    frames: usize,
+    // What the samples in a row have agreed on, and how many there were
+    leaning: Option<Held>,
+    samples: usize,
+    // The mode to go back to from sideways
+    landscape_mode: DisplayMode
+}
+
+impl Tilt {
+    pub fn new (display_mode: DisplayMode) -> Self {
+        Self {
+            frames: 0,
+            leaning: None,
+            samples: 0,
+            landscape_mode: if display_mode == DisplayMode::Sideways {
+                DisplayMode::Stretched
+            } else {
+                display_mode
+            }
+        }
+    }
+
+    // Whether it's time for another reading, at frame_rate updates a second
+    pub fn due (&mut self, frame_rate: usize) -> bool {
+        self.frames += 1;
+        if self.frames < (frame_rate / SAMPLES_PER_SECOND).max(1) {
+            return false
+        }
+        self.frames = 0;
+        true
+    }
+
+    // Call with each reading. Returns the display mode to change to, once
+    // there is one.
+    pub fn sample (
+        &mut self,
+        (x, y, _): (f32, f32, f32),
+        display_mode: DisplayMode
+    ) -> Option<DisplayMode> {
+        let held = held_from(x, y);
+        if held != self.leaning {
+            self.leaning = held;
+            self.samples = 0;
+        }
+        let held = held?;
+        // Picked from the options since the last switch
+        if display_mode != DisplayMode::Sideways {
+            self.landscape_mode = display_mode;
+        }
+
+        self.samples += 1;
+        if self.samples < SAMPLES_PER_SECOND { return None }
+        let wanted = match held {
+            Held::Landscape => self.landscape_mode,
+            Held::Sideways => DisplayMode::Sideways
+        };
+        if wanted == display_mode { None } else { Some(wanted) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UPRIGHT: (f32, f32, f32) = (0.05, 0.98, 0.1);
+    const SIDEWAYS: (f32, f32, f32) = (0.97, -0.1, 0.2);
+    const FLAT: (f32, f32, f32) = (0.1, 0.15, -0.98);
+
+    // Samples until it wants a change, or gives up after a dozen
+    fn samples_to_switch (
+        tilt: &mut Tilt,
+        reading: (f32, f32, f32),
+        mode: DisplayMode
+    ) -> Option<(usize, DisplayMode)> {
+        (1..=12).find_map(|count| {
+            tilt.sample(reading, mode).map(|wanted| (count, wanted))
+        })
+    }
+
+    #[test]
+    fn switches_after_a_second () {
+        let mut tilt = Tilt::new(DisplayMode::PixelPerfect);
+        let switched = samples_to_switch(
+            &mut tilt, SIDEWAYS, DisplayMode::PixelPerfect
+        );
+        assert!(switched == Some((SAMPLES_PER_SECOND, DisplayMode::Sideways)));
+
+        // and back to the mode it came from
+        let switched = samples_to_switch(
+            &mut tilt, UPRIGHT, DisplayMode::Sideways
+        );
+        let back = Some((SAMPLES_PER_SECOND, DisplayMode::PixelPerfect));
+        assert!(switched == back);
+    }
+
+    #[test]
+    fn a_wobble_starts_the_count_again () {
+        let mut tilt = Tilt::new(DisplayMode::Stretched);
+        for _ in 1..SAMPLES_PER_SECOND {
+            assert!(tilt.sample(SIDEWAYS, DisplayMode::Stretched).is_none());
+        }
+        assert!(tilt.sample(UPRIGHT, DisplayMode::Stretched).is_none());
+        assert!(tilt.sample(SIDEWAYS, DisplayMode::Stretched).is_none());
+    }
+
+    #[test]
+    fn lying_flat_never_switches () {
+        let mut tilt = Tilt::new(DisplayMode::Sideways);
+        let switched = samples_to_switch(
+            &mut tilt, FLAT, DisplayMode::Sideways
+        );
+        assert!(switched.is_none());
+        // Halfway round is as unclear as flat
+        let switched = samples_to_switch(
+            &mut tilt, (0.7, 0.7, 0.), DisplayMode::Sideways
+        );
+        assert!(switched.is_none());
+    }
+
+    #[test]
+    fn upside_down_is_landscape () {
+        let mut tilt = Tilt::new(DisplayMode::Sideways);
+        let switched = samples_to_switch(
+            &mut tilt, (0., -0.99, 0.1), DisplayMode::Sideways
+        );
+        assert!(switched == Some((SAMPLES_PER_SECOND, DisplayMode::Stretched)));
+        // Nothing more to do once it's there
+        assert!(tilt.sample(UPRIGHT, DisplayMode::Stretched).is_none());
+    }
+
+    #[test]
+    fn samples_a_few_times_a_second () {
+        let mut tilt = Tilt::new(DisplayMode::Stretched);
+        let due = (0..30).filter(|_| tilt.due(30)).count();
+        assert_eq!(due, 30 / (30 / SAMPLES_PER_SECOND));
+        let mut tilt = Tilt::new(DisplayMode::Stretched);
+        assert_eq!((0..2).filter(|_| tilt.due(2)).count(), 2);
+    }
+}
@@ -0,0 +1,85 @@
+use gbrs_core::cpu::Cpu;
+
+// Crank paddle mode, for Alleyway, Arkanoid and the like. The crank moves a
+// virtual paddle, and we chase it with Left and Right the way a player would:
+// held solid while it's a long way off, in shorter and shorter pulses as it
+// closes in, and let go inside the deadband. That gets surprisingly close to
+// an analog paddle through a d-pad.
+//
+// We can't see where the game's paddle actually is, so we keep our own guess
+// by assuming it moves PADDLE_SPEED pixels for every frame Left or Right is
+// held. When the real one stops early against a wall the guess drifts, but
+// cranking to that end of the range catches the two up again.
+//
+// While it's on, the crank isn't Start and Select any more, so Up and Down
+// are instead. Paddle games don't use them for anything else.
+
+// Gameboy pixels the virtual paddle can travel, end to end
+const RANGE: f32 = 160.;
+// How far most paddle games move theirs per frame
+const PADDLE_SPEED: f32 = 2.;
+// Frames in one pulse of Left or Right
+const PERIOD: usize = 6;
+// Pixels off at which the pulses run into each other and it's held solid
+const FULL_PRESS_DISTANCE: f32 = 16.;
+
+pub struct CrankPaddle {
+    // Pixels per full turn of the crank
+    gain: f32,
+    // Pixels either side of the target that count as there
+    deadband: f32,
+    target: f32,
+    // Where we think the game's paddle is
+    position: f32,
+    frame: usize
+}
+
+impl CrankPaddle {
+    pub fn new (gain: usize, deadband: usize) -> Self {
+        Self {
+            gain: gain as f32,
+            deadband: deadband as f32,
+            // The paddles we know of all start in the middle
+            target: RANGE / 2.,
+            position: RANGE / 2.,
+            frame: 0
+        }
+    }
+
+    // Call once per update with System::get_crank_change
+    pub fn crank (&mut self, degrees: f32) {
+        self.target = (self.target + degrees / 360. * self.gain)
+            .max(0.)
+            .min(RANGE);
+    }
+
+    // Call once per emulated frame, after the joypad's been set from the
+    // buttons. While the player's holding Left or Right themselves, those
+    // win, and still move our guess along.
+    pub fn update (&mut self, player_steering: bool, cpu: &mut Cpu) {
+        let joypad = &mut cpu.mem.joypad;
+        self.frame = (self.frame + 1) % PERIOD;
+
+        let (left, right) = if player_steering {
+            (joypad.left_pressed, joypad.right_pressed)
+        } else {
+            let distance = self.target - self.position;
+            let press = if distance.abs() <= self.deadband {
+                false
+            } else {
+                // How many frames of each period to hold it for, always at
+                // least one so small corrections still happen
+                let duty = (distance.abs() / FULL_PRESS_DISTANCE).min(1.);
+                let held_frames = ((duty * PERIOD as f32) as usize).max(1);
+                self.frame < held_frames
+            };
+            (press && distance < 0., press && distance > 0.)
+        };
+
+        joypad.left_pressed = left;
+        joypad.right_pressed = right;
+        if left { self.position -= PADDLE_SPEED }
+        if right { self.position += PADDLE_SPEED }
+        self.position = self.position.max(0.).min(RANGE);
+    }
+}
@@ -0,0 +1,218 @@
+use alloc::{format, vec::Vec};
+use anyhow::Error;
+use crankstart::{
+    graphics::{Graphics, LCDColor, LCDSolidColor},
+    system::System
+};
+use crankstart_sys::{LCDBitmapDrawMode, PDButtons, LCD_ROWS};
+use euclid::{point2, rect};
+use gbrs_core::{
+    constants::{SCREEN_HEIGHT, SCREEN_WIDTH},
+    lcd::GreyShade
+};
+
+use crate::blit;
+use crate::border::{self, BorderTheme};
+use crate::dither::Dither;
+use crate::framebuffer;
+use crate::input;
+use crate::layout::{
+    GAME_HEIGHT, GAME_LEFT, GAME_WIDTH, ROW_STRIDE, START_BYTE
+};
+use crate::screenshot;
+use crate::strings::{tr, Text};
+
+// For a proper look at the pixel art: the game stays paused on the frame it
+// was showing, the crank zooms into it (1x to 4x, nearest neighbour) and the
+// d-pad pans around. A saves what's on screen as a screenshot, B goes back to
+// the game exactly where it was.
+//
+// This draws on its own rather than through the game blit, from a copy of
+// finished_frame taken on the way in, so nothing it does can get in the way
+// of normal play.
+
+const MIN_ZOOM: f32 = 1.;
+const MAX_ZOOM: f32 = 4.;
+const ZOOM_STEP: f32 = 0.25;
+// Degrees of crank per ZOOM_STEP
+const CRANK_STEP: f32 = 20.;
+// Gameboy pixels per update at 1x, and proportionally fewer further in, so
+// the picture always moves across the screen at the same speed
+const PAN_SPEED: f32 = 4.;
+
+const LABEL_LEFT: i32 = 4;
+const LABEL_WIDTH: i32 = GAME_LEFT as i32 - LABEL_LEFT * 2;
+
+pub struct PhotoMode {
+    // blit::shade_values, laid out like finished_frame
+    frame: Vec<u8>,
+    border: BorderTheme,
+    dither: Dither,
+    zoom: f32,
+    // Middle of the view, in Gameboy pixels
+    centre_x: f32,
+    centre_y: f32,
+    // Crank turned since the last step, in degrees
+    crank: f32,
+    // How the last snap went, until the view changes
+    status: Option<Text>
+}
+
+impl PhotoMode {
+    // Like the other screens, this draws straight away
+    pub fn new (
+        finished_frame: &[GreyShade],
+        border: BorderTheme,
+        dither: Dither
+    ) -> Result<Self, Error> {
+        let photo = Self {
+            frame: finished_frame.iter().map(blit::shade_value).collect(),
+            border,
+            dither,
+            zoom: MIN_ZOOM,
+            centre_x: SCREEN_WIDTH as f32 / 2.,
+            centre_y: SCREEN_HEIGHT as f32 / 2.,
+            crank: 0.,
+            status: None
+        };
+        photo.redraw()?;
+        Ok(photo)
+    }
+
+    // Keeps the view from wandering off the edge of the frame
+    fn clamp_centre (&mut self) {
+        let half_width = SCREEN_WIDTH as f32 / self.zoom / 2.;
+        let half_height = SCREEN_HEIGHT as f32 / self.zoom / 2.;
+        self.centre_x = self.centre_x
+            .max(half_width)
+            .min(SCREEN_WIDTH as f32 - half_width);
+        self.centre_y = self.centre_y
+            .max(half_height)
+            .min(SCREEN_HEIGHT as f32 - half_height);
+    }
+
+    // What's in view, blown back up to a whole Gameboy frame. It's what a
+    // snap saves, and what's drawn.
+    fn view (&self) -> Vec<u8> {
+        let left = self.centre_x - SCREEN_WIDTH as f32 / self.zoom / 2.;
+        let top = self.centre_y - SCREEN_HEIGHT as f32 / self.zoom / 2.;
+        let mut view = Vec::with_capacity(self.frame.len());
+
+        for y in 0..SCREEN_HEIGHT {
+            // Both are positive, so truncating is the same as flooring
+            let source_y = (top + y as f32 / self.zoom) as usize;
+            let source_y = source_y.min(SCREEN_HEIGHT - 1);
+            for x in 0..SCREEN_WIDTH {
+                let source_x = (left + x as f32 / self.zoom) as usize;
+                let source_x = source_x.min(SCREEN_WIDTH - 1);
+                view.push(self.frame[source_y * SCREEN_WIDTH + source_x]);
+            }
+        }
+        view
+    }
+
+    // Dithered the same way as the game, over the same part of the screen
+    fn draw_view (&self) -> Result<(), Error> {
+        let graphics = Graphics::get();
+        let framebuffer = graphics.get_frame()?;
+        let view = self.view();
+        let ditherer = self.dither.ditherer();
+
+        for y in 0..GAME_HEIGHT {
+            for byte in 0..GAME_WIDTH / 8 {
+                let mut screen_byte: u8 = 0x00;
+                for bit in 0..8 {
+                    let x = byte * 8 + bit;
+                    let level = view[blit::frame_index(x, y)];
+                    if blit::level_is_white(ditherer, level, x, y) {
+                        screen_byte |= 1 << (7 - bit);
+                    }
+                }
+                framebuffer::draw_byte_at(
+                    framebuffer, ROW_STRIDE, START_BYTE + byte, y, screen_byte
+                );
+            }
+        }
+        graphics.mark_updated_rows(0..=(LCD_ROWS - 1) as i32)?;
+        Ok(())
+    }
+
+    // The zoom and the controls, in the left border
+    fn draw_labels (&self) -> Result<(), Error> {
+        let graphics = Graphics::get();
+        graphics.fill_rect(
+            rect(LABEL_LEFT, 4, LABEL_WIDTH, 132),
+            LCDColor::Solid(LCDSolidColor::kColorWhite)
+        )?;
+        graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeCopy)?;
+
+        let zoom = format!("{:.2}x", self.zoom);
+        graphics.draw_text(&zoom[..], point2(LABEL_LEFT + 2, 6))?;
+        graphics.draw_text(tr(Text::PhotoHelp), point2(LABEL_LEFT + 2, 30))?;
+        if let Some(status) = self.status {
+            graphics.draw_text(tr(status), point2(LABEL_LEFT + 2, 114))?;
+        }
+        Ok(())
+    }
+
+    pub fn redraw (&self) -> Result<(), Error> {
+        border::draw(self.border)?;
+        self.draw_view()?;
+        self.draw_labels()
+    }
+
+    fn snap (&mut self) {
+        self.status = match screenshot::save_levels(&self.view()[..]) {
+            Ok(path) => {
+                log!("Saved {}", path);
+                Some(Text::PhotoSaved)
+            },
+            Err(e) => {
+                log!("Couldn't save a photo: {}", e);
+                Some(Text::PhotoFailed)
+            }
+        };
+    }
+
+    // Returns true once they've backed out
+    pub fn update (&mut self) -> Result<bool, Error> {
+        let (btns_held, btns_down, _) = input::button_state()?;
+        let held = |button: PDButtons| (btns_held & button) == button;
+        let pressed = |button: PDButtons| (btns_down & button) == button;
+
+        if pressed(PDButtons::kButtonB) { return Ok(true) }
+        if pressed(PDButtons::kButtonA) {
+            self.snap();
+            self.draw_labels()?;
+            return Ok(false)
+        }
+
+        let (zoom, centre_x, centre_y) =
+            (self.zoom, self.centre_x, self.centre_y);
+
+        self.crank += System::get().get_crank_change()?;
+        let steps = (self.crank / CRANK_STEP) as i32;
+        if steps != 0 {
+            self.crank -= steps as f32 * CRANK_STEP;
+            self.zoom = (self.zoom + steps as f32 * ZOOM_STEP)
+                .max(MIN_ZOOM)
+                .min(MAX_ZOOM);
+        }
+
+        let pan = PAN_SPEED / self.zoom;
+        if held(PDButtons::kButtonLeft) { self.centre_x -= pan }
+        if held(PDButtons::kButtonRight) { self.centre_x += pan }
+        if held(PDButtons::kButtonUp) { self.centre_y -= pan }
+        if held(PDButtons::kButtonDown) { self.centre_y += pan }
+        self.clamp_centre();
+
+        let moved = self.zoom != zoom ||
+            self.centre_x != centre_x || self.centre_y != centre_y;
+        if moved {
+            self.status = None;
+            self.draw_view()?;
+            self.draw_labels()?;
+        }
+        Ok(false)
+    }
+}
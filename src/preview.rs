@@ -0,0 +1,143 @@
+use alloc::{vec, vec::Vec};
+use crankstart_sys::LCD_ROWS;
+use gbrs_core::lcd::GreyShade;
+
+use crate::autocrop;
+use crate::blit::{self, BlitTables};
+use crate::display_mode::DisplayMode;
+use crate::dither::Dither;
+use crate::layout::{ROW_BYTES, ROW_STRIDE};
+use crate::menu;
+
+// The display items in the options menu show what they'd look like on the
+// paused game, in a pane on the right half of the screen. The frame is
+// blitted into a screen of its own, the way the game's would be with that
+// dither and display mode, and the middle of it is copied across. That's at
+// the size the game's really drawn, so dithers can be compared properly.
+pub const PANE_BYTES: usize = ROW_BYTES / 2;
+pub const PANE_LEFT_BYTE: usize = ROW_BYTES - PANE_BYTES;
+pub const PANE_TOP: usize = menu::TOP_BAR_HEIGHT as usize;
+pub const PANE_HEIGHT: usize = LCD_ROWS as usize - PANE_TOP;
+
+// Where in the scratch screen the pane's picture comes from
+const SOURCE_LEFT_BYTE: usize = (ROW_BYTES - PANE_BYTES) / 2;
+const SOURCE_TOP: usize = (LCD_ROWS as usize - PANE_HEIGHT) / 2;
+
+pub struct Preview {
+    scratch: Vec<u8>,
+    tables: BlitTables
+}
+
+impl Preview {
+    pub fn new () -> Self {
+        Self {
+            scratch: vec![0xFF; ROW_STRIDE * LCD_ROWS as usize],
+            tables: BlitTables::new()
+        }
+    }
+
+    // Draws frame into the scratch screen. Auto zoom and frame blending are
+    // left out, since they're about the game moving.
+    pub fn render (
+        &mut self,
+        frame: &[GreyShade],
+        dither: Dither,
+        display_mode: DisplayMode,
+        shift: i8
+    ) {
+        // The stretched blit leaves the borders alone
+        for byte in self.scratch.iter_mut() { *byte = 0xFF }
+
+        let ditherer = &dither.ditherers()[0];
+        let scratch = &mut self.scratch[..];
+        if display_mode == DisplayMode::Sideways {
+            blit::draw_frame_sideways(frame, None, scratch, ditherer, shift);
+        } else if let Some(size) = display_mode.output_size() {
+            self.tables.fit(&autocrop::FULL, size);
+            blit::draw_frame_scaled(
+                frame, None, scratch, &self.tables, ditherer, shift
+            );
+        } else {
+            blit::draw_frame(frame, None, scratch, ditherer, shift);
+        }
+    }
+
+    // Copies the middle of the last render into the pane on screen, which
+    // is a Playdate-layout framebuffer
+    pub fn copy_pane (&self, screen: &mut [u8]) {
+        for y in 0..PANE_HEIGHT {
+            let from = (SOURCE_TOP + y) * ROW_STRIDE + SOURCE_LEFT_BYTE;
+            let to = (PANE_TOP + y) * ROW_STRIDE + PANE_LEFT_BYTE;
+            screen[to..to + PANE_BYTES]
+                .copy_from_slice(&self.scratch[from..from + PANE_BYTES]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blit::tests::synthetic_frame;
+
+    fn screen_of (preview: &Preview) -> Vec<u8> {
+        let mut screen = vec![0x55; ROW_STRIDE * LCD_ROWS as usize];
+        preview.copy_pane(&mut screen[..]);
+        screen
+    }
+
+    #[test]
+    fn pane_is_the_middle_of_the_real_blit () {
+        let frame = synthetic_frame(3);
+        let mut preview = Preview::new();
+        preview.render(&frame[..], Dither::Bayer, DisplayMode::Stretched, 0);
+        let screen = screen_of(&preview);
+
+        let mut real = vec![0xFF; ROW_STRIDE * LCD_ROWS as usize];
+        blit::draw_frame(
+            &frame[..], None, &mut real[..], &Dither::Bayer.ditherers()[0], 0
+        );
+        for y in 0..PANE_HEIGHT {
+            let from = (SOURCE_TOP + y) * ROW_STRIDE + SOURCE_LEFT_BYTE;
+            let to = (PANE_TOP + y) * ROW_STRIDE + PANE_LEFT_BYTE;
+            assert_eq!(
+                screen[to..to + PANE_BYTES], real[from..from + PANE_BYTES]
+            );
+        }
+    }
+
+    #[test]
+    fn leaves_the_menu_alone () {
+        let frame = synthetic_frame(1);
+        let mut preview = Preview::new();
+        let mode = DisplayMode::FullWidth;
+        preview.render(&frame[..], Dither::CrossHatch, mode, 0);
+        let screen = screen_of(&preview);
+        for (index, byte) in screen.iter().enumerate() {
+            let (x, y) = (index % ROW_STRIDE, index / ROW_STRIDE);
+            let in_pane =
+                y >= PANE_TOP && x >= PANE_LEFT_BYTE && x < ROW_BYTES;
+            if !in_pane { assert_eq!(*byte, 0x55, "byte {} of row {}", x, y) }
+        }
+    }
+
+    #[test]
+    fn follows_the_settings () {
+        let frame = synthetic_frame(2);
+        let mut preview = Preview::new();
+        let mut panes = Vec::new();
+        for dither in crate::dither::DITHERS.iter() {
+            preview.render(&frame[..], *dither, DisplayMode::Stretched, 0);
+            panes.push(screen_of(&preview));
+        }
+        preview.render(&frame[..], Dither::Bayer, DisplayMode::Sideways, 0);
+        panes.push(screen_of(&preview));
+        for (i, pane) in panes.iter().enumerate() {
+            assert!(!panes[..i].contains(pane), "pane {} is a repeat", i);
+        }
+        // and nothing's left over from the last one
+        let first = &panes[0];
+        let mode = DisplayMode::Stretched;
+        preview.render(&frame[..], Dither::CrossHatch, mode, 0);
+        assert!(screen_of(&preview) == *first);
+    }
+}
@@ -0,0 +1,458 @@
+use alloc::{vec, vec::Vec};
+
+// The Game Boy Printer, as far as the serial port can tell. Games send it
+// packets a byte at a time:
+//
+//   0x88 0x33, command, compression, length (2 bytes, little end first),
+//   that many bytes of data, checksum (2 bytes), then two bytes of nothing
+//   that the printer answers with 0x81 ("I'm here") and its status.
+//
+// The checksum is the sum of everything from the command to the end of the
+// data. Data packets are tiles in the usual 2 bits a pixel, 20 to a row,
+// and a print packet turns whatever's been sent since into a picture,
+// through the palette it comes with. This only decodes; serial.rs hands
+// it the bytes and writes the pictures out.
+
+const MAGIC: [u8; 2] = [0x88, 0x33];
+const ALIVE: u8 = 0x81;
+
+const INIT: u8 = 0x01;
+const PRINT: u8 = 0x02;
+const DATA: u8 = 0x04;
+
+// Status bits
+const CHECKSUM_ERROR: u8 = 1 << 0;
+const PRINTING: u8 = 1 << 1;
+const IMAGE_FULL: u8 = 1 << 2;
+const UNPROCESSED: u8 = 1 << 3;
+
+pub const PRINT_WIDTH: usize = 160;
+const TILES_PER_ROW: usize = PRINT_WIDTH / 8;
+const TILE_BYTES: usize = 16;
+// The printer's memory holds a Game Boy screen's worth, 9 packets of two
+// tile rows each
+const MAX_IMAGE_BYTES: usize = 9 * 2 * TILES_PER_ROW * TILE_BYTES;
+// Status checks answered with "printing" after a print, so games that wait
+// for it to start and then finish see both
+const PRINTING_REPLIES: u8 = 2;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Stage {
+    Magic(usize),
+    Command,
+    Compression,
+    Length(usize),
+    Data,
+    Checksum(usize),
+    Alive,
+    Status
+}
+
+// A finished print, as blit::shade_value levels (3 is white), PRINT_WIDTH
+// to a row
+pub struct Printout {
+    pub height: usize,
+    pub levels: Vec<u8>
+}
+
+pub struct Printer {
+    stage: Stage,
+    command: u8,
+    compressed: bool,
+    length: usize,
+    data: Vec<u8>,
+    checksum: u16,
+    sum: u16,
+    // Tile data sent since the last print
+    image: Vec<u8>,
+    status: u8,
+    printing_replies: u8,
+    // Finished and waiting for take_printout
+    printout: Option<Printout>
+}
+
+impl Printer {
+    pub fn new () -> Self {
+        Self {
+            stage: Stage::Magic(0),
+            command: 0,
+            compressed: false,
+            length: 0,
+            data: vec![],
+            checksum: 0,
+            sum: 0,
+            image: vec![],
+            status: 0,
+            printing_replies: 0,
+            printout: None
+        }
+    }
+
+    // One byte from the game, and the byte the printer sends back at the
+    // same time
+    pub fn exchange (&mut self, byte: u8) -> u8 {
+        let mut reply = 0x00;
+        self.stage = match self.stage {
+            Stage::Magic(index) if byte == MAGIC[index] => {
+                if index + 1 == MAGIC.len() {
+                    self.sum = 0;
+                    Stage::Command
+                } else {
+                    Stage::Magic(index + 1)
+                }
+            },
+            // 0x88 0x88 0x33 is still a packet starting
+            Stage::Magic(_) if byte == MAGIC[0] => Stage::Magic(1),
+            Stage::Magic(_) => Stage::Magic(0),
+            Stage::Command => {
+                self.add_to_sum(byte);
+                self.command = byte;
+                Stage::Compression
+            },
+            Stage::Compression => {
+                self.add_to_sum(byte);
+                self.compressed = byte & 1 != 0;
+                Stage::Length(0)
+            },
+            Stage::Length(0) => {
+                self.add_to_sum(byte);
+                self.length = byte as usize;
+                Stage::Length(1)
+            },
+            Stage::Length(_) => {
+                self.add_to_sum(byte);
+                self.length |= (byte as usize) << 8;
+                self.data.clear();
+                if self.length == 0 { Stage::Checksum(0) } else { Stage::Data }
+            },
+            Stage::Data => {
+                self.add_to_sum(byte);
+                self.data.push(byte);
+                if self.data.len() == self.length {
+                    Stage::Checksum(0)
+                } else {
+                    Stage::Data
+                }
+            },
+            Stage::Checksum(0) => {
+                self.checksum = byte as u16;
+                Stage::Checksum(1)
+            },
+            Stage::Checksum(_) => {
+                self.checksum |= (byte as u16) << 8;
+                self.run_packet();
+                Stage::Alive
+            },
+            Stage::Alive => {
+                reply = ALIVE;
+                Stage::Status
+            },
+            Stage::Status => {
+                reply = self.status;
+                Stage::Magic(0)
+            }
+        };
+        reply
+    }
+
+    pub fn take_printout (&mut self) -> Option<Printout> {
+        self.printout.take()
+    }
+
+    fn add_to_sum (&mut self, byte: u8) {
+        self.sum = self.sum.wrapping_add(byte as u16);
+    }
+
+    fn run_packet (&mut self) {
+        if self.checksum != self.sum {
+            self.status |= CHECKSUM_ERROR;
+            return
+        }
+        self.status &= !CHECKSUM_ERROR;
+
+        // Printing's over after a couple of status checks
+        if self.printing_replies > 0 {
+            self.printing_replies -= 1;
+            if self.printing_replies == 0 { self.status &= !PRINTING }
+        }
+
+        match self.command {
+            INIT => {
+                self.image.clear();
+                self.status = 0;
+                self.printing_replies = 0;
+            },
+            DATA => {
+                if self.compressed {
+                    decompress(&self.data[..], &mut self.image);
+                } else {
+                    self.image.extend_from_slice(&self.data[..]);
+                }
+                self.image.truncate(MAX_IMAGE_BYTES);
+                if self.image.len() > 0 { self.status |= UNPROCESSED }
+                if self.image.len() == MAX_IMAGE_BYTES {
+                    self.status |= IMAGE_FULL;
+                }
+            },
+            PRINT => {
+                // Sheets, margins, palette, exposure. No sheets is only
+                // feeding the paper.
+                let sheets = self.data.get(0).copied().unwrap_or(0);
+                let palette = self.data.get(2).copied().unwrap_or(0);
+                if sheets > 0 && self.image.len() > 0 {
+                    self.printout = Some(render(&self.image[..], palette));
+                }
+                self.image.clear();
+                self.status &= !(UNPROCESSED | IMAGE_FULL);
+                self.status |= PRINTING;
+                self.printing_replies = PRINTING_REPLIES;
+            },
+            // Status checks (0x0f) and anything we don't know just get the
+            // status back
+            _ => {}
+        }
+    }
+}
+
+// Run-length packed data: a control byte with the top bit set is followed
+// by one byte to repeat (control & 0x7f) + 2 times, and one without it by
+// control + 1 bytes as they are
+fn decompress (data: &[u8], out: &mut Vec<u8>) {
+    let mut index = 0;
+    while index < data.len() {
+        let control = data[index];
+        index += 1;
+        if control & 0x80 != 0 {
+            let byte = match data.get(index) {
+                Some(byte) => *byte,
+                None => break
+            };
+            index += 1;
+            for _ in 0..(control & 0x7F) as usize + 2 { out.push(byte) }
+        } else {
+            let end = (index + control as usize + 1).min(data.len());
+            out.extend_from_slice(&data[index..end]);
+            index = end;
+        }
+    }
+}
+
+// The palette gives each of the four colours a shade, two bits each from
+// the bottom, 0 being white. Games that send 0 mean the usual 0xe4.
+fn render (image: &[u8], palette: u8) -> Printout {
+    let palette = if palette == 0 { 0xE4 } else { palette };
+    let row_bytes = TILES_PER_ROW * TILE_BYTES;
+    let tile_rows = image.len() / row_bytes;
+    let height = tile_rows * 8;
+    let mut levels = vec![3; PRINT_WIDTH * height];
+
+    for tile_row in 0..tile_rows {
+        for tile in 0..TILES_PER_ROW {
+            let start = tile_row * row_bytes + tile * TILE_BYTES;
+            let bytes = &image[start..start + TILE_BYTES];
+            for y in 0..8 {
+                let low = bytes[y * 2];
+                let high = bytes[y * 2 + 1];
+                for x in 0..8 {
+                    let bit = 7 - x;
+                    let colour = ((high >> bit) & 1) << 1 | ((low >> bit) & 1);
+                    let shade = (palette >> (colour * 2)) & 3;
+                    let row = tile_row * 8 + y;
+                    levels[row * PRINT_WIDTH + tile * 8 + x] = 3 - shade;
+                }
+            }
+        }
+    }
+
+    Printout { height, levels }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checksum (command: u8, compression: u8, data: &[u8]) -> u16 {
+        let length = data.len() as u16;
+        [command, compression, length as u8, (length >> 8) as u8]
+            .iter()
+            .chain(data)
+            .fold(0u16, |sum, byte| sum.wrapping_add(*byte as u16))
+    }
+
+    // Sends a whole packet the way a game would, returning what the
+    // printer answered the last two bytes with
+    fn send (
+        printer: &mut Printer,
+        command: u8,
+        compression: u8,
+        data: &[u8],
+        checksum: u16
+    ) -> (u8, u8) {
+        let length = data.len() as u16;
+        let mut bytes = vec![
+            MAGIC[0], MAGIC[1], command, compression,
+            length as u8, (length >> 8) as u8
+        ];
+        bytes.extend_from_slice(data);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+        for byte in bytes {
+            assert_eq!(printer.exchange(byte), 0);
+        }
+        (printer.exchange(0), printer.exchange(0))
+    }
+
+    fn send_ok (printer: &mut Printer, command: u8, data: &[u8]) -> u8 {
+        let sum = checksum(command, 0, data);
+        send(printer, command, 0, data, sum).1
+    }
+
+    fn print (printer: &mut Printer, palette: u8) {
+        send_ok(printer, PRINT, &[1, 0x13, palette, 0x40]);
+    }
+
+    // A tile row of tiles that are all one colour
+    fn tile_row (colour: u8) -> Vec<u8> {
+        let low = if colour & 1 != 0 { 0xFF } else { 0x00 };
+        let high = if colour & 2 != 0 { 0xFF } else { 0x00 };
+        [low, high].repeat(TILES_PER_ROW * 8)
+    }
+
+    #[test]
+    fn answers_each_packet () {
+        let mut printer = Printer::new();
+        assert_eq!(send(&mut printer, INIT, 0, &[], INIT as u16), (ALIVE, 0));
+    }
+
+    #[test]
+    fn only_starts_on_the_magic_bytes () {
+        let mut printer = Printer::new();
+        // Junk, and half a magic, don't start a packet
+        for byte in &[0x00, 0x33, 0x88, 0x00, 0x33] {
+            printer.exchange(*byte);
+        }
+        assert!(printer.stage == Stage::Magic(0));
+        // A repeated first byte still does
+        printer.exchange(0x88);
+        printer.exchange(0x88);
+        printer.exchange(0x33);
+        assert!(printer.stage == Stage::Command);
+    }
+
+    #[test]
+    fn flags_bad_checksums () {
+        let mut printer = Printer::new();
+        let data = tile_row(3);
+        let sum = checksum(DATA, 0, &data);
+        let (alive, status) = send(&mut printer, DATA, 0, &data, sum + 1);
+        assert_eq!(alive, ALIVE);
+        assert_eq!(status, CHECKSUM_ERROR);
+        // and drops the packet
+        assert!(printer.image.is_empty());
+
+        // The checksum's over the header as well as the data
+        assert_eq!(checksum(DATA, 0, &[1, 2]), 4 + 2 + 1 + 2);
+        let status = send_ok(&mut printer, DATA, &data);
+        assert_eq!(status, UNPROCESSED);
+    }
+
+    #[test]
+    fn checksums_wrap () {
+        let mut printer = Printer::new();
+        let data = vec![0xFF; 640];
+        let sum = checksum(DATA, 0, &data);
+        assert!((sum as u32) < 640 * 0xFF);
+        assert_eq!(send(&mut printer, DATA, 0, &data, sum).1, UNPROCESSED);
+    }
+
+    #[test]
+    fn compressed_data_matches_uncompressed () {
+        let plain = tile_row(2);
+
+        // A row of colour 2 is 0x00 0xff over and over, so it packs as
+        // runs of two literal bytes
+        let mut packed = vec![];
+        for _ in 0..TILES_PER_ROW * 8 {
+            packed.extend_from_slice(&[1, 0, 0xFF]);
+        }
+        let mut unpacked = vec![];
+        decompress(&packed, &mut unpacked);
+        assert_eq!(unpacked, plain);
+
+        let mut printer = Printer::new();
+        let sum = checksum(DATA, 1, &packed);
+        assert_eq!(send(&mut printer, DATA, 1, &packed, sum).1, UNPROCESSED);
+        assert_eq!(printer.image, plain);
+    }
+
+    #[test]
+    fn decompresses_runs () {
+        let mut out = vec![];
+        // 5 of 0xaa, then 2 bytes as they are
+        decompress(&[0x83, 0xAA, 0x01, 0x12, 0x34], &mut out);
+        assert_eq!(out, [0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0x12, 0x34]);
+        // Cut off in the middle gives what there was
+        out.clear();
+        decompress(&[0x05, 0x12, 0x34], &mut out);
+        assert_eq!(out, [0x12, 0x34]);
+        out.clear();
+        decompress(&[0x01, 0x12, 0x34, 0x83], &mut out);
+        assert_eq!(out, [0x12, 0x34]);
+    }
+
+    #[test]
+    fn prints_what_was_sent () {
+        let mut printer = Printer::new();
+        send_ok(&mut printer, INIT, &[]);
+        send_ok(&mut printer, DATA, &tile_row(0));
+        send_ok(&mut printer, DATA, &tile_row(3));
+        print(&mut printer, 0);
+
+        let printout = printer.take_printout().unwrap();
+        assert_eq!(printout.height, 16);
+        assert_eq!(printout.levels.len(), PRINT_WIDTH * 16);
+        // The usual palette: colour 0 is white and 3 is black
+        assert!(printout.levels[..PRINT_WIDTH * 8].iter().all(|l| *l == 3));
+        assert!(printout.levels[PRINT_WIDTH * 8..].iter().all(|l| *l == 0));
+        assert!(printer.take_printout().is_none());
+        assert!(printer.image.is_empty());
+    }
+
+    #[test]
+    fn prints_through_the_palette () {
+        let mut printer = Printer::new();
+        send_ok(&mut printer, DATA, &tile_row(1));
+        // Inverted, so colour 1 is dark grey rather than light
+        print(&mut printer, 0x1B);
+        let printout = printer.take_printout().unwrap();
+        assert!(printout.levels.iter().all(|l| *l == 1));
+    }
+
+    #[test]
+    fn feeding_paper_prints_nothing () {
+        let mut printer = Printer::new();
+        send_ok(&mut printer, DATA, &tile_row(3));
+        send_ok(&mut printer, PRINT, &[0, 0x13, 0xE4, 0x40]);
+        assert!(printer.take_printout().is_none());
+    }
+
+    #[test]
+    fn says_its_printing_for_a_while () {
+        let mut printer = Printer::new();
+        send_ok(&mut printer, DATA, &tile_row(3));
+        print(&mut printer, 0);
+        for _ in 0..PRINTING_REPLIES - 1 {
+            assert_eq!(send_ok(&mut printer, 0x0F, &[]), PRINTING);
+        }
+        assert_eq!(send_ok(&mut printer, 0x0F, &[]), 0);
+    }
+
+    #[test]
+    fn fills_up () {
+        let mut printer = Printer::new();
+        for _ in 0..MAX_IMAGE_BYTES / (TILES_PER_ROW * TILE_BYTES) + 1 {
+            send_ok(&mut printer, DATA, &tile_row(3));
+        }
+        assert_eq!(printer.image.len(), MAX_IMAGE_BYTES);
+        assert_eq!(printer.status, UNPROCESSED | IMAGE_FULL);
+    }
+}
@@ -0,0 +1,330 @@
+use alloc::{vec, vec::Vec};
+use anyhow::{anyhow, Error};
+
+// Just enough of a QR code encoder to put binary data on screen: always
+// version 6 (41x41 modules) at error correction level L, in byte mode. One
+// size keeps things simple - no version info block, one alignment pattern -
+// and it's about as dense as a phone will read off the Playdate's screen.
+pub const SIZE: usize = 41;
+// Bytes of data one code holds
+pub const CAPACITY: usize = 134;
+
+// Version 6-L is two blocks of 68 data codewords, each with 18 codewords of
+// error correction
+const BLOCKS: usize = 2;
+const DATA_PER_BLOCK: usize = 68;
+const ECC_PER_BLOCK: usize = 18;
+const ALIGNMENT_CENTRE: usize = 34;
+// Level L's bits in the format info
+const ECC_LEVEL_BITS: u32 = 1;
+
+pub struct QrCode {
+    modules: [[bool; SIZE]; SIZE],
+    // Finder/timing/format modules, which the data and the mask steer
+    // around
+    is_function: [[bool; SIZE]; SIZE]
+}
+
+// Multiplication in GF(2^8), modulo the QR polynomial
+fn gf_multiply (x: u8, y: u8) -> u8 {
+    let mut z: u8 = 0;
+    for i in (0..8).rev() {
+        z = (z << 1) ^ ((z >> 7) * 0x1D);
+        z ^= ((y >> i) & 1) * x;
+    }
+    z
+}
+
+// Reed-Solomon generator polynomial, highest power first with the leading
+// 1 left off
+fn rs_divisor (degree: usize) -> Vec<u8> {
+    let mut result = vec![0u8; degree];
+    result[degree - 1] = 1;
+
+    let mut root: u8 = 1;
+    for _ in 0..degree {
+        for j in 0..degree {
+            result[j] = gf_multiply(result[j], root);
+            if j + 1 < degree { result[j] ^= result[j + 1] }
+        }
+        root = gf_multiply(root, 0x02);
+    }
+    result
+}
+
+fn rs_remainder (data: &[u8], divisor: &[u8]) -> Vec<u8> {
+    let mut result = vec![0u8; divisor.len()];
+    for &byte in data {
+        let factor = byte ^ result.remove(0);
+        result.push(0);
+        for (x, &y) in result.iter_mut().zip(divisor.iter()) {
+            *x ^= gf_multiply(y, factor);
+        }
+    }
+    result
+}
+
+// Mode, length, data, terminator and padding, as codewords
+fn data_codewords (data: &[u8]) -> Vec<u8> {
+    let total = BLOCKS * DATA_PER_BLOCK;
+    let mut bits: Vec<bool> = vec![];
+    fn push (value: u32, count: usize, bits: &mut Vec<bool>) {
+        for i in (0..count).rev() { bits.push((value >> i) & 1 == 1) }
+    }
+
+    // Byte mode, then an 8-bit length (versions 1-9)
+    push(0b0100, 4, &mut bits);
+    push(data.len() as u32, 8, &mut bits);
+    for &byte in data { push(byte as u32, 8, &mut bits) }
+
+    let terminator = (total * 8 - bits.len()).min(4);
+    push(0, terminator, &mut bits);
+    while bits.len() % 8 != 0 { bits.push(false) }
+
+    let mut codewords: Vec<u8> = bits
+        .chunks(8)
+        .map(|byte| byte.iter().fold(0, |acc, &bit| (acc << 1) | bit as u8))
+        .collect();
+    for pad in [0xEC, 0x11].iter().cycle() {
+        if codewords.len() >= total { break }
+        codewords.push(*pad);
+    }
+    codewords
+}
+
+// Splits into blocks, adds each one's error correction and interleaves
+// them the way the spec wants
+fn interleave (codewords: &[u8]) -> Vec<u8> {
+    let divisor = rs_divisor(ECC_PER_BLOCK);
+    let blocks: Vec<&[u8]> = codewords.chunks(DATA_PER_BLOCK).collect();
+    let eccs: Vec<Vec<u8>> = blocks
+        .iter()
+        .map(|block| rs_remainder(block, &divisor[..]))
+        .collect();
+
+    let mut result = vec![];
+    for i in 0..DATA_PER_BLOCK {
+        for block in &blocks { result.push(block[i]) }
+    }
+    for i in 0..ECC_PER_BLOCK {
+        for ecc in &eccs { result.push(ecc[i]) }
+    }
+    result
+}
+
+fn mask_applies (mask: u8, x: usize, y: usize) -> bool {
+    match mask {
+        0 => (x + y) % 2 == 0,
+        1 => y % 2 == 0,
+        2 => x % 3 == 0,
+        3 => (x + y) % 3 == 0,
+        4 => (x / 3 + y / 2) % 2 == 0,
+        5 => x * y % 2 + x * y % 3 == 0,
+        6 => (x * y % 2 + x * y % 3) % 2 == 0,
+        _ => ((x + y) % 2 + x * y % 3) % 2 == 0
+    }
+}
+
+impl QrCode {
+    pub fn encode (data: &[u8]) -> Result<Self, Error> {
+        if data.len() > CAPACITY {
+            return Err(anyhow!(
+                "{} bytes won't fit in a QR code ({} max)",
+                data.len(), CAPACITY
+            ))
+        }
+
+        let mut code = Self {
+            modules: [[false; SIZE]; SIZE],
+            is_function: [[false; SIZE]; SIZE]
+        };
+        code.draw_function_patterns();
+        code.draw_codewords(&interleave(&data_codewords(data)[..])[..]);
+
+        // Try each mask and keep the one that scans best
+        let mut best = (0, usize::MAX);
+        for mask in 0..8 {
+            code.apply_mask(mask);
+            code.draw_format_bits(mask);
+            let penalty = code.penalty();
+            if penalty < best.1 { best = (mask, penalty) }
+            // Masks are XOR, so a second go takes it off again
+            code.apply_mask(mask);
+        }
+        code.apply_mask(best.0);
+        code.draw_format_bits(best.0);
+        Ok(code)
+    }
+
+    pub fn dark (&self, x: usize, y: usize) -> bool {
+        self.modules[y][x]
+    }
+
+    fn set_function (&mut self, x: usize, y: usize, dark: bool) {
+        self.modules[y][x] = dark;
+        self.is_function[y][x] = true;
+    }
+
+    fn draw_finder (&mut self, centre_x: isize, centre_y: isize) {
+        for dy in -4..=4isize {
+            for dx in -4..=4isize {
+                let (x, y) = (centre_x + dx, centre_y + dy);
+                if x < 0 || y < 0 || x >= SIZE as isize || y >= SIZE as isize {
+                    continue
+                }
+                let distance = dx.abs().max(dy.abs());
+                self.set_function(
+                    x as usize, y as usize, distance != 2 && distance != 4
+                );
+            }
+        }
+    }
+
+    fn draw_function_patterns (&mut self) {
+        // Timing patterns first, since the finders overwrite their ends
+        for i in 0..SIZE {
+            self.set_function(6, i, i % 2 == 0);
+            self.set_function(i, 6, i % 2 == 0);
+        }
+
+        let far = SIZE as isize - 4;
+        self.draw_finder(3, 3);
+        self.draw_finder(far, 3);
+        self.draw_finder(3, far);
+
+        for dy in -2..=2isize {
+            for dx in -2..=2isize {
+                let x = (ALIGNMENT_CENTRE as isize + dx) as usize;
+                let y = (ALIGNMENT_CENTRE as isize + dy) as usize;
+                self.set_function(x, y, dx.abs().max(dy.abs()) != 1);
+            }
+        }
+
+        // Reserve the format areas, filled in once a mask is picked
+        self.draw_format_bits(0);
+    }
+
+    fn draw_format_bits (&mut self, mask: u8) {
+        let data = ECC_LEVEL_BITS << 3 | mask as u32;
+        let mut remainder = data;
+        for _ in 0..10 {
+            remainder = (remainder << 1) ^ ((remainder >> 9) * 0x537);
+        }
+        let bits = (data << 10 | remainder) ^ 0x5412;
+        let bit = |i: usize| (bits >> i) & 1 == 1;
+
+        // Around the top-left finder
+        for i in 0..6 { self.set_function(8, i, bit(i)) }
+        self.set_function(8, 7, bit(6));
+        self.set_function(8, 8, bit(7));
+        self.set_function(7, 8, bit(8));
+        for i in 9..15 { self.set_function(14 - i, 8, bit(i)) }
+
+        // And again, split between the other two
+        for i in 0..8 { self.set_function(SIZE - 1 - i, 8, bit(i)) }
+        for i in 8..15 { self.set_function(8, SIZE - 15 + i, bit(i)) }
+        // The one module that's always dark
+        self.set_function(8, SIZE - 8, true);
+    }
+
+    // Zig-zags up and down two columns at a time from the bottom right
+    fn draw_codewords (&mut self, codewords: &[u8]) {
+        let total_bits = codewords.len() * 8;
+        let mut i = 0;
+        let mut right = SIZE - 1;
+
+        loop {
+            // Column 6 is all timing pattern
+            if right == 6 { right = 5 }
+            let upward = (right + 1) & 2 == 0;
+
+            for vertical in 0..SIZE {
+                let y = if upward { SIZE - 1 - vertical } else { vertical };
+                for j in 0..2 {
+                    let x = right - j;
+                    if self.is_function[y][x] || i >= total_bits { continue }
+                    self.modules[y][x] =
+                        (codewords[i / 8] >> (7 - i % 8)) & 1 == 1;
+                    i += 1;
+                }
+            }
+
+            if right < 2 { break }
+            right -= 2;
+        }
+    }
+
+    fn apply_mask (&mut self, mask: u8) {
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                if !self.is_function[y][x] && mask_applies(mask, x, y) {
+                    self.modules[y][x] = !self.modules[y][x];
+                }
+            }
+        }
+    }
+
+    // The spec's four rules for what makes a code hard to scan
+    fn penalty (&self) -> usize {
+        let mut penalty = 0;
+        let line = |index: usize, i: usize, rows: bool| {
+            if rows { self.modules[index][i] } else { self.modules[i][index] }
+        };
+
+        // Same colour five or more in a row, and things that look like
+        // finder patterns
+        const FINDER_LIKE: [bool; 11] = [
+            true, false, true, true, true, false, true,
+            false, false, false, false
+        ];
+        for &rows in [true, false].iter() {
+            for index in 0..SIZE {
+                let mut run = 1;
+                for i in 1..SIZE {
+                    if line(index, i, rows) == line(index, i - 1, rows) {
+                        run += 1;
+                        if run == 5 { penalty += 3 } else if run > 5 {
+                            penalty += 1
+                        }
+                    } else {
+                        run = 1;
+                    }
+                }
+
+                for start in 0..=SIZE - FINDER_LIKE.len() {
+                    let matches = |reversed: bool| {
+                        FINDER_LIKE.iter().enumerate().all(|(i, &dark)| {
+                            let i = if reversed {
+                                FINDER_LIKE.len() - 1 - i
+                            } else { i };
+                            line(index, start + i, rows) == dark
+                        })
+                    };
+                    if matches(false) { penalty += 40 }
+                    if matches(true) { penalty += 40 }
+                }
+            }
+        }
+
+        // 2x2 blocks of one colour
+        for y in 0..SIZE - 1 {
+            for x in 0..SIZE - 1 {
+                let colour = self.modules[y][x];
+                if self.modules[y][x + 1] == colour &&
+                    self.modules[y + 1][x] == colour &&
+                    self.modules[y + 1][x + 1] == colour {
+                    penalty += 3;
+                }
+            }
+        }
+
+        // Too far from half dark
+        let dark: usize = self.modules
+            .iter()
+            .map(|row| row.iter().filter(|&&dark| dark).count())
+            .sum();
+        let percent = dark * 100 / (SIZE * SIZE);
+        let off_by = if percent > 50 { percent - 50 } else { 50 - percent };
+        penalty + off_by / 5 * 10
+    }
+}
@@ -0,0 +1,214 @@
+use alloc::{string::String, vec, vec::Vec};
+use anyhow::{anyhow, Error};
+use crankstart::{
+    graphics::{Graphics, LCDColor, LCDSolidColor},
+    system::System
+};
+use crankstart_sys::{LCDBitmapDrawMode, PDButtons, LCD_COLUMNS, LCD_ROWS};
+use euclid::{point2, rect};
+
+use crate::checksum;
+use crate::input;
+use crate::qr::{self, QrCode};
+use crate::saves::{self, SaveCheck};
+use crate::storage::PlaydateFiles;
+use crate::strings::{self, tr, tr_bool, trf, Text};
+
+// Shows a battery save as a run of QR codes, so it can be pulled off the
+// Playdate with a phone instead of a computer and USB mode.
+//
+// The save is PackBits-compressed (cart RAM is mostly runs) and cut into
+// chunks, each of which goes out as one code in byte mode:
+//
+//   "PB" <index> <total> <CRC-32 of the payload, little endian> <payload>
+//
+// index counts from 0, and both it and total are one byte, so a save can
+// take at most 255 codes. Whatever reassembles them needs the raw bytes
+// from the scanner, not text.
+const MAGIC: &[u8] = b"PB";
+const HEADER_SIZE: usize = 8;
+const CHUNK_PAYLOAD: usize = qr::CAPACITY - HEADER_SIZE;
+const MAX_CHUNKS: usize = 255;
+
+// Pixels per module. 41 modules at 5 is 205px, leaving a quiet zone of a
+// few modules top and bottom.
+const SCALE: i32 = 5;
+const CODE_LEFT: i32 = 16;
+const CODE_TOP: i32 = (LCD_ROWS as i32 - qr::SIZE as i32 * SCALE) / 2;
+const TEXT_LEFT: i32 = CODE_LEFT * 2 + qr::SIZE as i32 * SCALE;
+const AUTO_ADVANCE_MS: usize = 1500;
+
+// PackBits: a count byte of n < 128 is followed by n + 1 literal bytes,
+// and one of 257 - n (n > 128) by a byte to repeat that many times
+fn pack_bits (data: &[u8]) -> Vec<u8> {
+    let mut packed = vec![];
+    let mut i = 0;
+
+    while i < data.len() {
+        let mut run = 1;
+        while i + run < data.len() && data[i + run] == data[i] && run < 128 {
+            run += 1;
+        }
+
+        if run > 1 {
+            packed.push((257 - run) as u8);
+            packed.push(data[i]);
+            i += run;
+            continue
+        }
+
+        // Literals go up to the next run of three or more
+        let start = i;
+        while i < data.len() && i - start < 128 {
+            let run_ahead = i + 2 < data.len() &&
+                data[i] == data[i + 1] && data[i] == data[i + 2];
+            if run_ahead { break }
+            i += 1;
+        }
+        packed.push((i - start - 1) as u8);
+        packed.extend_from_slice(&data[start..i]);
+    }
+    packed
+}
+
+fn make_chunks (save: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+    let packed = pack_bits(save);
+    let total = (packed.len() + CHUNK_PAYLOAD - 1) / CHUNK_PAYLOAD;
+    if total > MAX_CHUNKS {
+        return Err(anyhow!("{} bytes packed is too many codes", packed.len()))
+    }
+
+    Ok(packed
+        .chunks(CHUNK_PAYLOAD)
+        .enumerate()
+        .map(|(index, payload)| {
+            let mut chunk = Vec::from(MAGIC);
+            chunk.push(index as u8);
+            chunk.push(total as u8);
+            chunk.extend_from_slice(&checksum::crc32(payload).to_le_bytes());
+            chunk.extend_from_slice(payload);
+            chunk
+        })
+        .collect())
+}
+
+pub struct QrExport {
+    game_name: String,
+    chunks: Vec<Vec<u8>>,
+    index: usize,
+    code: QrCode,
+    // When on, the codes page along by themselves
+    auto: bool,
+    shown_at: usize
+}
+
+impl QrExport {
+    // Returns a message to show instead if there's nothing to export.
+    // Otherwise, like the other screens, this draws straight away.
+    pub fn new (game_name: &str) -> Result<Result<Self, &'static str>, Error> {
+        let path = &saves::save_path(game_name)[..];
+        let save = match saves::check_in(&PlaydateFiles, path) {
+            SaveCheck::Intact(save) if save.len() > 0 => save,
+            SaveCheck::Corrupt | SaveCheck::Unreadable =>
+                return Ok(Err(tr(Text::BadBatterySave))),
+            _ => return Ok(Err(tr(Text::NoBatterySave)))
+        };
+
+        let chunks = match make_chunks(&save[..]) {
+            Ok(chunks) => chunks,
+            Err(e) => {
+                log!("Can't export {} via QR: {}", path, e);
+                return Ok(Err(tr(Text::SaveTooBigForQr)))
+            }
+        };
+        log!("Exporting {} as {} QR codes", path, chunks.len());
+
+        let export = Self {
+            game_name: String::from(game_name),
+            code: QrCode::encode(&chunks[0][..])?,
+            chunks,
+            index: 0,
+            auto: false,
+            shown_at: System::get().get_current_time_milliseconds()?
+        };
+        export.redraw()?;
+        Ok(Ok(export))
+    }
+
+    fn show (&mut self, index: usize) -> Result<(), Error> {
+        self.index = index;
+        self.code = QrCode::encode(&self.chunks[index][..])?;
+        self.shown_at = System::get().get_current_time_milliseconds()?;
+        self.redraw()
+    }
+
+    pub fn redraw (&self) -> Result<(), Error> {
+        let graphics = Graphics::get();
+        graphics.clear(LCDColor::Solid(LCDSolidColor::kColorWhite))?;
+
+        // Dark modules go in as runs to keep the fill_rect calls down
+        for y in 0..qr::SIZE {
+            let mut run_start = None;
+            for x in 0..=qr::SIZE {
+                let dark = x < qr::SIZE && self.code.dark(x, y);
+                match (dark, run_start) {
+                    (true, None) => run_start = Some(x),
+                    (false, Some(start)) => {
+                        graphics.fill_rect(
+                            rect(
+                                CODE_LEFT + start as i32 * SCALE,
+                                CODE_TOP + y as i32 * SCALE,
+                                (x - start) as i32 * SCALE,
+                                SCALE
+                            ),
+                            LCDColor::Solid(LCDSolidColor::kColorBlack)
+                        )?;
+                        run_start = None;
+                    },
+                    _ => {}
+                }
+            }
+        }
+
+        let width = LCD_COLUMNS as i32 - TEXT_LEFT - 8;
+        graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeCopy)?;
+        let title = strings::ellipsize(&self.game_name[..], width);
+        graphics.draw_text(&title[..], point2(TEXT_LEFT, CODE_TOP))?;
+        let progress = trf(
+            Text::QrProgress, &[&(self.index + 1), &self.chunks.len()]
+        );
+        graphics.draw_text(&progress[..], point2(TEXT_LEFT, CODE_TOP + 30))?;
+        let help = strings::wrap(
+            &trf(Text::QrHelp, &[&tr_bool(self.auto)])[..], width
+        );
+        graphics.draw_text(&help[..], point2(TEXT_LEFT, CODE_TOP + 80))?;
+        Ok(())
+    }
+
+    // Returns true once they've backed out
+    pub fn update (&mut self) -> Result<bool, Error> {
+        let system = System::get();
+        let (_, btns_down, _) = input::button_state()?;
+        let pressed = |button: PDButtons| (btns_down & button) == button;
+        let count = self.chunks.len();
+
+        if pressed(PDButtons::kButtonB) { return Ok(true) }
+
+        if pressed(PDButtons::kButtonA) || pressed(PDButtons::kButtonRight) {
+            self.show((self.index + 1) % count)?;
+        } else if pressed(PDButtons::kButtonLeft) {
+            self.show((self.index + count - 1) % count)?;
+        } else if pressed(PDButtons::kButtonDown) {
+            self.auto = !self.auto;
+            self.shown_at = system.get_current_time_milliseconds()?;
+            self.redraw()?;
+        } else if self.auto {
+            let now = system.get_current_time_milliseconds()?;
+            if now.wrapping_sub(self.shown_at) >= AUTO_ADVANCE_MS {
+                self.show((self.index + 1) % count)?;
+            }
+        }
+
+        Ok(false)
+    }
+}
@@ -0,0 +1,246 @@
+use alloc::{string::String, vec::Vec};
+use anyhow::Error;
+use crankstart::{
+    graphics::{Graphics, LCDColor, LCDSolidColor},
+    system::System
+};
+use crankstart_sys::{
+    LCDBitmapDrawMode, PDButtons, LCD_COLUMNS, LCD_ROWS, LCD_ROWSIZE
+};
+use euclid::{point2, rect, Angle};
+
+use crate::input;
+use crate::savestate;
+use crate::strings::{self, tr, Text};
+
+// A radial menu for the things you want mid-game without going through the
+// system menu. Hold the chord to bring it up, point the crank at an action,
+// and let go of the chord to run it. Letting go without having turned the
+// crank (or with it docked) runs nothing.
+
+// Hold B + A + Left, next to the quick slot's chords
+pub const OPEN_CHORD: PDButtons = PDButtons(
+    PDButtons::kButtonA.0 | PDButtons::kButtonB.0 | PDButtons::kButtonLeft.0
+);
+
+const ITEM_WIDTH: i32 = 116;
+const ITEM_HEIGHT: i32 = 24;
+// The items sit on an ellipse this far out from the middle of the screen
+const RADIUS_X: f32 = 136.;
+const RADIUS_Y: f32 = 94.;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum QuickAction {
+    QuickSave,
+    QuickLoad,
+    UndoLoad,
+    FastForward,
+    Invert,
+    Pause,
+    Screenshot,
+    Options
+}
+
+pub const QUICK_ACTIONS: &[QuickAction] = &[
+    QuickAction::QuickSave,
+    QuickAction::QuickLoad,
+    QuickAction::UndoLoad,
+    QuickAction::FastForward,
+    QuickAction::Invert,
+    QuickAction::Pause,
+    QuickAction::Screenshot,
+    QuickAction::Options
+];
+
+impl QuickAction {
+    // For the quick_menu setting
+    pub fn name (self) -> &'static str {
+        match self {
+            QuickAction::QuickSave => "save",
+            QuickAction::QuickLoad => "load",
+            QuickAction::UndoLoad => "undo",
+            QuickAction::FastForward => "fast_forward",
+            QuickAction::Invert => "invert",
+            QuickAction::Pause => "pause",
+            QuickAction::Screenshot => "screenshot",
+            QuickAction::Options => "options"
+        }
+    }
+
+    pub fn from_name (name: &str) -> Option<Self> {
+        QUICK_ACTIONS.iter().copied().find(|action| action.name() == name)
+    }
+
+    // The ones that make or load states are kept in the setting without
+    // gbrs-save-states, but left out of the menu and the hotkeys
+    pub fn available (self) -> bool {
+        match self {
+            QuickAction::QuickSave | QuickAction::QuickLoad |
+            QuickAction::UndoLoad => savestate::SUPPORTED,
+            _ => true
+        }
+    }
+
+    fn label (self) -> &'static str {
+        tr(match self {
+            QuickAction::QuickSave => Text::QuickSaveAction,
+            QuickAction::QuickLoad => Text::QuickLoadAction,
+            QuickAction::UndoLoad => Text::UndoLoad,
+            QuickAction::FastForward => Text::FastForwardAction,
+            QuickAction::Invert => Text::InvertAction,
+            QuickAction::Pause => Text::PauseAction,
+            QuickAction::Screenshot => Text::ScreenshotAction,
+            QuickAction::Options => Text::OptionsAction
+        })
+    }
+}
+
+// "save,load,pause"
+pub fn parse_actions (text: &str) -> Option<Vec<QuickAction>> {
+    let actions: Vec<QuickAction> = text
+        .split(',')
+        .filter_map(|name| {
+            let action = QuickAction::from_name(name.trim());
+            if action.is_none() { log!("Unknown quick menu action {}", name) }
+            action
+        })
+        .collect();
+    if actions.len() == 0 { None } else { Some(actions) }
+}
+
+pub fn actions_text (actions: &[QuickAction]) -> String {
+    let names: Vec<&str> = actions.iter().map(|action| action.name()).collect();
+    names.join(",")
+}
+
+// Darkens whatever is on screen with a checkerboard, straight in the
+// framebuffer, so the menu stands out from the game under it
+fn dim_screen () -> Result<(), Error> {
+    let graphics = Graphics::get();
+    let framebuffer = graphics.get_frame()?;
+
+    // Set bits are white, so clearing every other one goes half dark
+    for (row, bytes) in framebuffer
+        .chunks_mut(LCD_ROWSIZE as usize)
+        .take(LCD_ROWS as usize)
+        .enumerate()
+    {
+        let mask = if row % 2 == 0 { 0xAA } else { 0x55 };
+        for byte in bytes { *byte &= mask }
+    }
+    graphics.mark_updated_rows(0..=(LCD_ROWS - 1) as i32)?;
+    Ok(())
+}
+
+pub enum QuickMenuEvent {
+    Run(QuickAction),
+    Cancelled
+}
+
+pub struct QuickMenu {
+    actions: Vec<QuickAction>,
+    // None until the crank has pointed at something
+    highlighted: Option<usize>
+}
+
+impl QuickMenu {
+    // Like the other screens, this draws straight away
+    pub fn new (actions: &[QuickAction]) -> Result<Self, Error> {
+        let menu = Self {
+            actions: Vec::from(actions),
+            highlighted: None
+        };
+        dim_screen()?;
+        menu.draw_hint()?;
+        for index in 0..menu.actions.len() { menu.draw_item(index)? }
+        Ok(menu)
+    }
+
+    // Item 0 is at the top, and the rest go round clockwise like the crank
+    fn item_angle (&self, index: usize) -> f32 {
+        index as f32 * 360. / self.actions.len() as f32
+    }
+
+    // Which item the crank is pointing at, each one owning the slice of
+    // the circle centred on it
+    fn item_at (&self, crank_angle: f32) -> usize {
+        let slice = 360. / self.actions.len() as f32;
+        let angle = (crank_angle + slice / 2.) % 360.;
+        (angle / slice) as usize % self.actions.len()
+    }
+
+    fn draw_item (&self, index: usize) -> Result<(), Error> {
+        let graphics = Graphics::get();
+        let (sin, cos) = Angle::degrees(self.item_angle(index)).sin_cos();
+        let centre_x = LCD_COLUMNS as f32 / 2. + RADIUS_X * sin;
+        let centre_y = LCD_ROWS as f32 / 2. - RADIUS_Y * cos;
+        let left = centre_x as i32 - ITEM_WIDTH / 2;
+        let top = centre_y as i32 - ITEM_HEIGHT / 2;
+
+        let highlighted = self.highlighted == Some(index);
+        let (fill, mode) = if highlighted {
+            (LCDSolidColor::kColorBlack, LCDBitmapDrawMode::kDrawModeInverted)
+        } else {
+            (LCDSolidColor::kColorWhite, LCDBitmapDrawMode::kDrawModeCopy)
+        };
+
+        graphics.fill_rect(
+            rect(left, top, ITEM_WIDTH, ITEM_HEIGHT),
+            LCDColor::Solid(LCDSolidColor::kColorBlack)
+        )?;
+        graphics.fill_rect(
+            rect(left + 2, top + 2, ITEM_WIDTH - 4, ITEM_HEIGHT - 4),
+            LCDColor::Solid(fill)
+        )?;
+        graphics.set_draw_mode(mode)?;
+        let label = strings::ellipsize(
+            self.actions[index].label(), ITEM_WIDTH - 12
+        );
+        graphics.draw_text(&label[..], point2(left + 6, top + 3))?;
+        Ok(())
+    }
+
+    fn draw_hint (&self) -> Result<(), Error> {
+        let graphics = Graphics::get();
+        let width = 150;
+        let left = (LCD_COLUMNS as i32 - width) / 2;
+        let top = LCD_ROWS as i32 / 2 - 24;
+
+        graphics.fill_rect(
+            rect(left, top, width, 48),
+            LCDColor::Solid(LCDSolidColor::kColorWhite)
+        )?;
+        graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeCopy)?;
+        let hint = strings::wrap(tr(Text::QuickMenuHint), width - 12);
+        graphics.draw_text(&hint[..], point2(left + 6, top + 4))?;
+        Ok(())
+    }
+
+    // Returns what to do once they let go of the chord
+    pub fn update (&mut self) -> Result<Option<QuickMenuEvent>, Error> {
+        let system = System::get();
+        let (btns_held, _, _) = input::button_state()?;
+
+        if (btns_held & OPEN_CHORD) != OPEN_CHORD {
+            return Ok(Some(match self.highlighted {
+                Some(index) => QuickMenuEvent::Run(self.actions[index]),
+                None => QuickMenuEvent::Cancelled
+            }))
+        }
+
+        // A docked crank's angle means nothing, and a crank that hasn't
+        // moved since the menu opened hasn't picked anything yet
+        if system.is_crank_docked()? { return Ok(None) }
+        if self.highlighted.is_none() && system.get_crank_change()? == 0. {
+            return Ok(None)
+        }
+
+        let pointed_at = self.item_at(system.get_crank_angle()?);
+        if self.highlighted != Some(pointed_at) {
+            let previous = self.highlighted.replace(pointed_at);
+            if let Some(previous) = previous { self.draw_item(previous)? }
+            self.draw_item(pointed_at)?;
+        }
+        Ok(None)
+    }
+}
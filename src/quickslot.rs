@@ -0,0 +1,134 @@
+use alloc::{format, string::String, vec::Vec};
+use anyhow::Error;
+use crankstart_sys::PDButtons;
+use gbrs_core::cpu::Cpu;
+
+use crate::savestate::{self, RomId};
+use crate::storage;
+use crate::strings::{tr, Text};
+use crate::undo::UndoSlot;
+
+// One save state kept in RAM for instant save/load while practicing a
+// tricky bit. No file I/O is involved, so it's much quicker than the
+// numbered slots.
+
+// Hold B + A and press Up to save
+pub const SAVE_CHORD: PDButtons = PDButtons(
+    PDButtons::kButtonA.0 | PDButtons::kButtonB.0 | PDButtons::kButtonUp.0
+);
+// Hold B + A + Down to load. This one has to be held for a moment so a
+// fumbled button press can't throw away your progress.
+pub const LOAD_CHORD: PDButtons = PDButtons(
+    PDButtons::kButtonA.0 | PDButtons::kButtonB.0 | PDButtons::kButtonDown.0
+);
+const LOAD_HOLD_MS: usize = 200;
+
+pub fn quick_state_path (game_name: &str) -> String {
+    let filename = format!("{}.quick.state", game_name);
+    storage::in_dir(storage::STATES_DIR, &filename[..])
+}
+
+pub struct QuickSlot {
+    state: Option<Vec<u8>>,
+    save_was_held: bool,
+    load_held_frames: usize
+}
+
+impl QuickSlot {
+    pub fn new () -> Self {
+        Self {
+            state: None,
+            save_was_held: false,
+            load_held_frames: 0
+        }
+    }
+
+    // Picks up a slot flushed by a previous session, if there is one
+    pub fn load_from_disk (game_name: &str) -> Self {
+        let mut slot = Self::new();
+        slot.state = storage::read_file(&quick_state_path(game_name)[..]).ok();
+        slot
+    }
+
+    pub fn flush_to_disk (&self, game_name: &str) -> Result<(), Error> {
+        match &self.state {
+            Some(state) => storage::write_file(
+                &quick_state_path(game_name)[..], &state[..]
+            ),
+            None => Ok(())
+        }
+    }
+
+    pub fn save (&mut self, cpu: &Cpu, rom_id: RomId) -> &'static str {
+        match savestate::capture(cpu, rom_id) {
+            Ok(state) => {
+                self.state = Some(state);
+                tr(Text::QuickSaved)
+            },
+            Err(e) => {
+                log!("Couldn't quick save: {}", e);
+                tr(Text::StateSaveFailed)
+            }
+        }
+    }
+
+    pub fn load (
+        &self,
+        cpu: &mut Cpu,
+        undo: &mut UndoSlot,
+        rom_id: RomId,
+        force: bool
+    ) -> &'static str {
+        let state = match &self.state {
+            Some(state) => state,
+            None => return tr(Text::NothingQuickSaved)
+        };
+
+        // A slot flushed to disk can end up paired with the wrong ROM if
+        // files get renamed
+        if !force && savestate::made_with_other_rom(&state[..], rom_id) {
+            return tr(Text::QuickSaveOtherRom)
+        }
+
+        match undo.load_over(cpu, &state[..], rom_id, force) {
+            Ok(()) => tr(Text::QuickLoaded),
+            Err(e) => {
+                log!("Quick load failed: {}", e);
+                tr(Text::QuickLoadFailed)
+            }
+        }
+    }
+
+    // Returns a message to confirm what happened, if anything did
+    pub fn update (
+        &mut self,
+        btns_held: PDButtons,
+        cpu: &mut Cpu,
+        undo: &mut UndoSlot,
+        rom_id: RomId,
+        force: bool,
+        frame_rate: usize
+    ) -> Option<&'static str> {
+        let save_held = (btns_held & SAVE_CHORD) == SAVE_CHORD;
+        let load_held = (btns_held & LOAD_CHORD) == LOAD_CHORD;
+
+        // Saving happens on the press, not every frame it's held
+        let just_saved = save_held && !self.save_was_held;
+        self.save_was_held = save_held;
+        if just_saved { return Some(self.save(cpu, rom_id)) }
+
+        if !load_held {
+            self.load_held_frames = 0;
+            return None
+        }
+
+        self.load_held_frames += 1;
+        let held_ms = self.load_held_frames * 1000 / frame_rate;
+        // Only fire once per hold
+        let first_frame_over = held_ms >= LOAD_HOLD_MS &&
+            (self.load_held_frames - 1) * 1000 / frame_rate < LOAD_HOLD_MS;
+        if !first_frame_over { return None }
+
+        Some(self.load(cpu, undo, rom_id, force))
+    }
+}
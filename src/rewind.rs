@@ -0,0 +1,347 @@
+use alloc::{collections::VecDeque, vec::Vec};
+use anyhow::{anyhow, Error};
+use core::ops::Range;
+use gbrs_core::{cpu::Cpu, lcd::GreyShade};
+
+use crate::minimap;
+use crate::savestate::{self, RomId};
+
+// Keeps a state from every SNAPSHOT_FRAMES emulated frames, going back as
+// far as rewind_kb of memory allows, for the scrubber (see scrubber.rs) to
+// pick one to go back to.
+//
+// Only the newest state is kept whole. Each one before it is stored as how
+// it differs from the one after it - XORed, so it's mostly zeros - with the
+// runs of zeros squashed. Going back a step undoes the newest difference,
+// and when the budget's used up the oldest are thrown away, since nothing
+// depends on them.
+//
+// States don't carry a picture, and getting at an old one means undoing
+// every difference after it, which is too slow to do for each turn of the
+// crank. So each one has a quarter-size 1-bit thumbnail of its frame kept
+// beside it, out of the same budget, for the scrubber to show. Once it's
+// gone back, one frame is emulated to draw. Running out of memory turns
+// rewind off for the rest of the game rather than bringing everything down.
+//
+// A difference is the older state's length (4 bytes, little endian), then
+// pieces of a 2 byte count of zeros, a 2 byte count of bytes that aren't,
+// and those bytes.
+
+pub const SNAPSHOT_FRAMES: usize = 15;
+const MAX_COUNT: usize = 0xFFFF;
+
+pub const THUMBNAIL_ROW_BYTES: usize = minimap::WIDTH / 8;
+pub const THUMBNAIL_HEIGHT: usize = minimap::HEIGHT;
+const THUMBNAIL_BYTES: usize = THUMBNAIL_ROW_BYTES * THUMBNAIL_HEIGHT;
+
+const _: () = assert!(minimap::WIDTH % 8 == 0);
+
+// Rows of THUMBNAIL_ROW_BYTES, set bits white like the framebuffer
+pub type Thumbnail = [u8; THUMBNAIL_BYTES];
+
+pub fn thumbnail (finished_frame: &[GreyShade]) -> Thumbnail {
+    let mut thumbnail = [0; THUMBNAIL_BYTES];
+    minimap::quarter_scale(finished_frame, |x, y| {
+        thumbnail[y * THUMBNAIL_ROW_BYTES + x / 8] |= 0x80 >> (x % 8);
+    });
+    thumbnail
+}
+
+fn xor_at (older: &[u8], newer: &[u8], i: usize) -> u8 {
+    older[i] ^ newer.get(i).copied().unwrap_or(0)
+}
+
+// Calls piece with each count of zeros and the range of bytes after it that
+// aren't
+fn pieces (
+    older: &[u8],
+    newer: &[u8],
+    mut piece: impl FnMut(usize, Range<usize>)
+) {
+    let mut i = 0;
+    while i < older.len() {
+        let start = i;
+        while i < older.len() && i - start < MAX_COUNT &&
+            xor_at(older, newer, i) == 0 { i += 1 }
+        let zeros = i - start;
+
+        let start = i;
+        while i < older.len() && i - start < MAX_COUNT &&
+            xor_at(older, newer, i) != 0 { i += 1 }
+        piece(zeros, start..i);
+    }
+}
+
+// All the allocating is done with try_reserve, which can fail politely
+fn reserve (bytes: &mut Vec<u8>, size: usize) -> Result<(), Error> {
+    bytes
+        .try_reserve_exact(size)
+        .map_err(|_| anyhow!("no memory for {} bytes", size))
+}
+
+fn encode (older: &[u8], newer: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut size = 4;
+    pieces(older, newer, |_, bytes| size += 4 + bytes.len());
+
+    let mut delta = Vec::new();
+    reserve(&mut delta, size)?;
+    delta.extend_from_slice(&(older.len() as u32).to_le_bytes());
+    pieces(older, newer, |zeros, bytes| {
+        delta.extend_from_slice(&(zeros as u16).to_le_bytes());
+        delta.extend_from_slice(&(bytes.len() as u16).to_le_bytes());
+        delta.extend(bytes.map(|i| xor_at(older, newer, i)));
+    });
+    Ok(delta)
+}
+
+fn decode (delta: &[u8], newer: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut length = [0; 4];
+    length.copy_from_slice(&delta[..4]);
+    let mut older = Vec::new();
+    reserve(&mut older, u32::from_le_bytes(length) as usize)?;
+
+    let newer_at = |i: usize| newer.get(i).copied().unwrap_or(0);
+    let mut pos = 4;
+    while pos < delta.len() {
+        let zeros = u16::from_le_bytes([delta[pos], delta[pos + 1]]);
+        let count = u16::from_le_bytes([delta[pos + 2], delta[pos + 3]]);
+        pos += 4;
+        for _ in 0..zeros {
+            older.push(newer_at(older.len()));
+        }
+        for byte in &delta[pos..pos + count as usize] {
+            older.push(byte ^ newer_at(older.len()));
+        }
+        pos += count as usize;
+    }
+    Ok(older)
+}
+
+// A state before the newest, as how it differs from the one after it
+struct Older {
+    delta: Vec<u8>,
+    thumbnail: Thumbnail
+}
+
+pub struct Rewind {
+    enabled: bool,
+    budget: usize,
+    newest: Option<(Vec<u8>, Thumbnail)>,
+    // Oldest first
+    older: VecDeque<Older>,
+    // Bytes in all of the above, thumbnails included
+    used: usize,
+    // Emulated since the newest state was taken
+    frames: usize
+}
+
+impl Rewind {
+    // Nothing's kept with a budget of 0
+    pub fn new (budget_kb: usize) -> Self {
+        Self {
+            enabled: budget_kb > 0,
+            budget: budget_kb * 1024,
+            newest: None,
+            older: VecDeque::new(),
+            used: 0,
+            frames: 0
+        }
+    }
+
+    fn give_up (&mut self, e: Error) {
+        log!("Rewind is off until the next game: {}", e);
+        *self = Self::new(0);
+    }
+
+    fn push (
+        &mut self,
+        state: Vec<u8>,
+        thumbnail: Thumbnail
+    ) -> Result<(), Error> {
+        if let Some((newest, newest_thumbnail)) = self.newest.take() {
+            let delta = encode(&newest[..], &state[..])?;
+            self.older
+                .try_reserve(1)
+                .map_err(|_| anyhow!("no memory for another state"))?;
+            self.used = self.used - newest.len() + delta.len();
+            self.older.push_back(Older {
+                delta,
+                thumbnail: newest_thumbnail
+            });
+        }
+        self.used += state.len() + THUMBNAIL_BYTES;
+        self.newest = Some((state, thumbnail));
+
+        while self.used > self.budget {
+            match self.older.pop_front() {
+                Some(older) =>
+                    self.used -= older.delta.len() + THUMBNAIL_BYTES,
+                None => return Err(anyhow!("a state is bigger than rewind_kb"))
+            }
+        }
+        Ok(())
+    }
+
+    // Call after each emulated frame
+    pub fn frame_done (&mut self, cpu: &Cpu, rom_id: RomId) {
+        if !self.enabled { return }
+
+        self.frames += 1;
+        if self.frames < SNAPSHOT_FRAMES { return }
+        self.frames = 0;
+        let pushed = savestate::capture(cpu, rom_id).and_then(|state| {
+            self.push(state, thumbnail(&cpu.gpu.finished_frame))
+        });
+        if let Err(e) = pushed {
+            self.give_up(e);
+        }
+    }
+
+    // How many states there are to go back to. They're numbered from 0,
+    // the oldest.
+    pub fn history (&self) -> usize {
+        self.older.len() + self.newest.is_some() as usize
+    }
+
+    pub fn thumbnail_at (&self, index: usize) -> Option<&Thumbnail> {
+        match self.older.get(index) {
+            Some(older) => Some(&older.thumbnail),
+            None if index == self.older.len() =>
+                self.newest.as_ref().map(|(_, thumbnail)| thumbnail),
+            None => None
+        }
+    }
+
+    // Undoes differences until state index is the newest, throwing away
+    // everything after it
+    fn unwind_to (&mut self, index: usize) -> Result<(), Error> {
+        while self.older.len() > index {
+            let (newest, _) = self.newest.take().unwrap();
+            let older = self.older.pop_back().unwrap();
+            let state = decode(&older.delta[..], &newest[..])?;
+            self.used -= newest.len() + older.delta.len() + THUMBNAIL_BYTES;
+            self.used += state.len();
+            self.newest = Some((state, older.thumbnail));
+        }
+        self.frames = 0;
+        Ok(())
+    }
+
+    // Puts the game back to state index, which becomes the newest. Returns
+    // true when it did, and wants a frame emulating to show it.
+    pub fn go_back_to (
+        &mut self,
+        cpu: &mut Cpu,
+        rom_id: RomId,
+        index: usize
+    ) -> bool {
+        if index >= self.history() { return false }
+        let restored = self.unwind_to(index).and_then(|()| {
+            let (state, _) = self.newest.as_ref().unwrap();
+            savestate::restore(cpu, &state[..], rom_id, false)
+        });
+        if let Err(e) = restored {
+            self.give_up(e);
+            return false
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use gbrs_core::constants::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+    // Mostly the same from one to the next, like real states
+    fn state (seed: u8) -> Vec<u8> {
+        let mut state = vec![0x42; 4000];
+        state[seed as usize * 10] = seed;
+        state.push(seed);
+        state
+    }
+
+    fn marked (seed: u8) -> Thumbnail {
+        let mut thumbnail = [0; THUMBNAIL_BYTES];
+        thumbnail[0] = seed;
+        thumbnail
+    }
+
+    fn rewind_with (budget_kb: usize, count: u8) -> Rewind {
+        let mut rewind = Rewind::new(budget_kb);
+        for seed in 1..=count {
+            rewind.push(state(seed), marked(seed)).unwrap();
+        }
+        rewind
+    }
+
+    #[test]
+    fn differences_round_trip () {
+        let pairs = [
+            (state(1), state(2)),
+            (state(3), vec![]),
+            (vec![7; 10], state(4)),
+            (vec![1; 70000], vec![2; 70000])
+        ];
+        for (older, newer) in pairs.iter() {
+            let delta = encode(&older[..], &newer[..]).unwrap();
+            assert_eq!(decode(&delta[..], &newer[..]).unwrap(), *older);
+        }
+        // Similar states come out small
+        let delta = encode(&state(1)[..], &state(2)[..]).unwrap();
+        assert!(delta.len() < 40);
+    }
+
+    #[test]
+    fn thumbnails_go_with_their_states () {
+        let rewind = rewind_with(64, 5);
+        assert_eq!(rewind.history(), 5);
+        for index in 0..5 {
+            let thumbnail = rewind.thumbnail_at(index).unwrap();
+            assert_eq!(thumbnail[0], index as u8 + 1);
+        }
+        assert!(rewind.thumbnail_at(5).is_none());
+    }
+
+    #[test]
+    fn going_back_drops_what_came_after () {
+        let mut rewind = rewind_with(64, 5);
+        rewind.unwind_to(1).unwrap();
+        assert_eq!(rewind.history(), 2);
+        let (newest, thumbnail) = rewind.newest.as_ref().unwrap();
+        assert_eq!(*newest, state(2));
+        assert_eq!(thumbnail[0], 2);
+        assert_eq!(rewind.thumbnail_at(0).unwrap()[0], 1);
+
+        // and the budget's what's left
+        let expected = state(2).len() + THUMBNAIL_BYTES * 2 +
+            rewind.older[0].delta.len();
+        assert_eq!(rewind.used, expected);
+    }
+
+    #[test]
+    fn the_oldest_go_when_its_full () {
+        // Room for the newest state and a handful of older ones
+        let rewind = rewind_with(6, 20);
+        assert!(rewind.used <= 6 * 1024);
+        assert!(rewind.history() < 20);
+        let oldest = 20 - rewind.history() as u8 + 1;
+        assert_eq!(rewind.thumbnail_at(0).unwrap()[0], oldest);
+        let newest = rewind.history() - 1;
+        assert_eq!(rewind.thumbnail_at(newest).unwrap()[0], 20);
+    }
+
+    #[test]
+    fn thumbnails_are_quarter_size () {
+        let mut frame = vec![GreyShade::Black; SCREEN_WIDTH * SCREEN_HEIGHT];
+        for y in 0..SCREEN_HEIGHT {
+            for x in 0..16 { frame[y * SCREEN_WIDTH + x] = GreyShade::White }
+        }
+        let thumbnail = thumbnail(&frame[..]);
+        for row in thumbnail.chunks(THUMBNAIL_ROW_BYTES) {
+            assert_eq!(row[0], 0xFF);
+            assert!(row[1..].iter().all(|byte| *byte == 0));
+        }
+    }
+}
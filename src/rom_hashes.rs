@@ -0,0 +1,214 @@
+use alloc::{format, string::String, vec, vec::Vec};
+use anyhow::{anyhow, Error};
+use crankstart::file::{File, FileSystem};
+use crankstart_sys::FileOptions;
+
+use crate::checksum;
+use crate::storage;
+
+// A CRC-32 of every ROM in the picker, so copies of the same game under
+// different names can be spotted. Hashing a whole ROM takes too long for one
+// frame, so the picker feeds it a chunk at a time while nothing's being
+// pressed.
+//
+// Hashes are cached in CACHE_PATH along with each file's size and modified
+// time, and only worked out again when one of those changes. A line is
+// "crc size modified filename", with the filename last since it can have
+// spaces in.
+const CACHE_PATH: &str = "system/rom_hashes.txt";
+
+const CHUNK_SIZE: usize = 32 * 1024;
+
+#[derive(Clone)]
+struct Entry {
+    filename: String,
+    size: usize,
+    // "20220806140312", from the file's stat
+    modified: String,
+    crc: u32
+}
+
+fn parse_entry (line: &str) -> Option<Entry> {
+    let mut fields = line.splitn(4, ' ');
+    let crc = u32::from_str_radix(fields.next()?, 16).ok()?;
+    let size = fields.next()?.parse::<usize>().ok()?;
+    let modified = String::from(fields.next()?);
+    let filename = String::from(fields.next()?);
+    Some(Entry { filename, size, modified, crc })
+}
+
+fn load_cache () -> Vec<Entry> {
+    let bytes = match storage::read_file(CACHE_PATH) {
+        Ok(bytes) => bytes,
+        Err(_) => return vec![]
+    };
+    match core::str::from_utf8(&bytes[..]) {
+        Ok(text) => text.lines().filter_map(parse_entry).collect(),
+        Err(_) => vec![]
+    }
+}
+
+// Size and modified time, to tell whether a cached hash is still good
+fn stat (filename: &str) -> Result<(usize, String), Error> {
+    let stat = FileSystem::get()
+        .stat(filename)
+        .map_err(|e| anyhow!("{} not found: {}", filename, e))?;
+    let modified = format!(
+        "{}{:02}{:02}{:02}{:02}{:02}",
+        stat.m_year, stat.m_month, stat.m_day,
+        stat.m_hour, stat.m_minute, stat.m_second
+    );
+    Ok((stat.size as usize, modified))
+}
+
+// The file being hashed and the hash so far
+struct InProgress {
+    entry: Entry,
+    file: File,
+    crc: u32
+}
+
+pub struct RomHasher {
+    // Hashes for the picker's files that are known
+    entries: Vec<Entry>,
+    // Ones still to do, as (filename, size, modified)
+    queue: Vec<(String, usize, String)>,
+    current: Option<InProgress>,
+    buffer: Vec<u8>,
+    // Whether the cache file is behind
+    changed: bool
+}
+
+impl RomHasher {
+    pub fn new (filenames: &[String]) -> Self {
+        let cached = load_cache();
+        let mut entries = vec![];
+        let mut queue = vec![];
+
+        for filename in filenames {
+            let (size, modified) = match stat(&filename[..]) {
+                Ok(stat) => stat,
+                Err(e) => {
+                    log!("{}", e);
+                    continue
+                }
+            };
+            let still_good = cached.iter().find(|entry| {
+                entry.filename == *filename && entry.size == size &&
+                    entry.modified == modified
+            });
+            match still_good {
+                Some(entry) => entries.push(entry.clone()),
+                None => queue.push((filename.clone(), size, modified))
+            }
+        }
+
+        // Anything that was cached but has gone (or changed) drops out of
+        // the file next time it's written
+        let changed = entries.len() != cached.len();
+        Self {
+            entries,
+            queue,
+            current: None,
+            buffer: vec![],
+            changed
+        }
+    }
+
+    pub fn done (&self) -> bool {
+        self.current.is_none() && self.queue.len() == 0
+    }
+
+    // Reads and hashes the next chunk. Returns true when that finished off
+    // a file, since that's when duplicates can turn up.
+    pub fn step (&mut self) -> bool {
+        if self.current.is_none() {
+            let (filename, size, modified) = match self.queue.pop() {
+                Some(next) => next,
+                None => return false
+            };
+            let options = FileOptions::kFileRead | FileOptions::kFileReadData;
+            let opened = FileSystem::get().open(&filename[..], options);
+            match opened {
+                Ok(file) => {
+                    let entry = Entry { filename, size, modified, crc: 0 };
+                    self.current = Some(InProgress {
+                        entry, file, crc: checksum::CRC32_START
+                    });
+                },
+                Err(e) => {
+                    log!("Couldn't open {} to hash it: {}", filename, e);
+                    return false
+                }
+            }
+        }
+
+        self.buffer.resize(CHUNK_SIZE, 0);
+        let current = self.current.as_mut().unwrap();
+        let read = match current.file.read(&mut self.buffer[..]) {
+            Ok(read) => read,
+            Err(e) => {
+                log!("Couldn't hash {}: {}", current.entry.filename, e);
+                self.current = None;
+                return false
+            }
+        };
+        if read > 0 {
+            current.crc =
+                checksum::crc32_update(current.crc, &self.buffer[..read]);
+            return false
+        }
+
+        // That's the end of it
+        let mut finished = self.current.take().unwrap();
+        finished.entry.crc = checksum::crc32_finish(finished.crc);
+        self.entries.push(finished.entry);
+        self.changed = true;
+        // Straight away, since the picker is made afresh every time it's
+        // shown and anything unsaved would have to be hashed again
+        self.save();
+        if self.done() {
+            // The chunk buffer isn't needed any more
+            self.buffer = vec![];
+        }
+        true
+    }
+
+    pub fn save (&mut self) {
+        if !self.changed { return }
+
+        let mut text = String::new();
+        for entry in &self.entries {
+            text.push_str(&format!(
+                "{:08x} {} {} {}\n",
+                entry.crc, entry.size, entry.modified, entry.filename
+            ));
+        }
+        match storage::write_file(CACHE_PATH, text.as_bytes()) {
+            Ok(()) => self.changed = false,
+            Err(e) => log!("Couldn't save the ROM hashes: {}", e)
+        }
+    }
+
+    // Another file with exactly the same contents (going by size and
+    // CRC-32), if there is one
+    pub fn duplicate_of (&self, filename: &str) -> Option<&str> {
+        let entry = self
+            .entries
+            .iter()
+            .find(|entry| entry.filename == filename)?;
+        self.entries
+            .iter()
+            .find(|other| {
+                other.filename != entry.filename &&
+                    other.size == entry.size && other.crc == entry.crc
+            })
+            .map(|other| &other.filename[..])
+    }
+
+    // For after a file's been deleted
+    pub fn forget (&mut self, filename: &str) {
+        self.entries.retain(|entry| entry.filename != filename);
+        self.changed = true;
+    }
+}
@@ -3,34 +3,112 @@ use anyhow::Error;
 use crankstart::{
     file::FileSystem,
     graphics::{Graphics, LCDColor, LCDSolidColor},
-    system::System,
     Playdate
 };
-use crankstart_sys::{FileOptions, PDButtons, LCDBitmapDrawMode};
+use crankstart_sys::{PDButtons, LCDBitmapDrawMode};
 use euclid::{point2, rect};
 
+use crate::header;
+use crate::input;
+use crate::manual::MANUAL_DIR;
+use crate::menu::{ListMenu, MenuEvent};
+use crate::rom_hashes::RomHasher;
+use crate::storage::{self, FileStore, PlaydateFiles};
+use crate::strings::{self, tr, trf, Text};
+
 const X_PADDING: i32 = 20;
 const Y_PADDING: i32 = 7;
+// Gameboy Color games run in their DMG-compatible mode, for the ones that
+// have one. Some dumps come out as .dmg.
+pub const ROM_EXTENSIONS: &[&str] = &[".gb", ".gbc", ".dmg"];
+
+// Whatever the case, since files copied over from other systems are often
+// MARIO.GB
+fn extension_len (filename: &str) -> Option<usize> {
+  ROM_EXTENSIONS
+    .iter()
+    .find(|extension| {
+      filename
+        .len()
+        .checked_sub(extension.len())
+        .and_then(|start| filename.get(start..))
+        .map_or(false, |end| end.eq_ignore_ascii_case(extension))
+    })
+    .map(|extension| extension.len())
+}
+
+pub fn is_rom (filename: &str) -> bool {
+  extension_len(filename).is_some()
+}
+
+// "Mario.gb" -> "Mario"
+pub fn rom_stem (filename: &str) -> &str {
+  match extension_len(filename) {
+    Some(len) => &filename[..filename.len() - len],
+    None => filename
+  }
+}
+
+// The bottom of the screen says what's up with the selected game, if
+// anything
+const DETAIL_TOP: i32 = 222;
 
 pub struct RomPickerState {
-  // These do *not* include the .gb postfix.
+  // Filenames, extensions and all. They're listed without, though.
   // Eg. "Mario.gb" is listed as "Mario"
   games: Vec<String>,
   // Index of the currently selected game
   selected: usize,
   // Offsets which games we're drawing
-  scroll: usize
+  scroll: usize,
+  // Spots copies of the same ROM, a bit at a time while nothing's pressed
+  hasher: RomHasher,
+  // Up while asking which of two copies to keep, with their filenames
+  tidy: Option<(ListMenu, [String; 2])>,
+  // Set when Playboy's just been launched. If there's only the one game,
+  // there's nothing to pick, so it's booted straight away.
+  boot_only_game: bool,
+  // Why the selected game wouldn't load, shown in place of its detail
+  load_problem: Option<String>,
+  // The selected game's file couldn't be read, and the error. That wants
+  // more room than the detail line, so the caller shows it.
+  read_error: Option<String>
 }
 
 impl RomPickerState {
   // If the user picks a game, this update function returns a Vec<u8> which is a
   // loaded game ROM buffer
   pub fn update (&mut self, _playdate: &mut Playdate) -> Result<Option<Vec<u8>>, Error> {
+    if let Some((menu, copies)) = &mut self.tidy {
+      match menu.update()? {
+        Some(MenuEvent::Picked(index)) => {
+          let keep = copies[index].clone();
+          let extra = copies[1 - index].clone();
+          self.remove_copy(&keep[..], &extra[..]);
+          // Starts again with the list as it is now
+          *self = Self::new();
+        },
+        Some(MenuEvent::Closed) => {
+          self.tidy = None;
+          self.draw_initial_ui()?;
+        },
+        None => {}
+      }
+      return Ok(None)
+    }
+
     if self.games.len() == 0 { return Ok(None) }
-    
-    let system = System::get();
 
-    let (_, btns_down, _) = system.get_button_state()?;
+    if self.boot_only_game {
+      self.boot_only_game = false;
+      if self.games.len() == 1 {
+        log!("Only one game, booting {}", self.games[0]);
+        return self.read_selected()
+      }
+    }
+
+    let (_, btns_down, _) = input::button_state()?;
+    let selected_before = self.selected;
 
     if 
       (btns_down & PDButtons::kButtonDown) == PDButtons::kButtonDown &&
@@ -66,36 +144,134 @@ impl RomPickerState {
     if (btns_down & PDButtons::kButtonA) == PDButtons::kButtonA {
       // They want to select a game! 
       // Let's read it off the file system and return it
-      let game_name = &self.games[self.selected];
-      let path = &format!("{}.gb", game_name)[..];
-      
-      let file_system = FileSystem::get();
+      return self.read_selected()
+    }
 
-      let rom_stat = file_system.stat(path)?;
-      let mut rom_buffer = vec![0; rom_stat.size as usize];
+    if (btns_down & PDButtons::kButtonB) == PDButtons::kButtonB {
+      let path = self.selected_path();
+      if let Some(other) = self.hasher.duplicate_of(&path[..]) {
+        let copies = [path.clone(), String::from(other)];
+        let items = copies
+          .iter()
+          .map(|filename| trf(Text::KeepCopy, &[filename]))
+          .collect();
+        let menu = ListMenu::new(tr(Text::TidyDuplicatesTitle), items)?;
+        self.tidy = Some((menu, copies));
+        return Ok(None)
+      }
+    }
 
-      let rom_file = file_system.open(
-        path, FileOptions::kFileRead | FileOptions::kFileReadData
-      )?;
-      rom_file.read(&mut rom_buffer)?;
+    if self.selected != selected_before {
+      self.load_problem = None;
+      self.draw_detail()?;
+    }
 
-      return Ok(Some(rom_buffer))
+    // Hashing only gets the frames where nothing else is going on
+    if btns_down == PDButtons(0) && !self.hasher.done() {
+      if self.hasher.step() {
+        self.draw_whole_game_list()?;
+        self.draw_detail()?;
+      }
     }
 
     Ok(None)
   }
 
+  // Both copies already share a battery save and states, since those go by
+  // the title in the ROM header rather than the filename. The manual is the
+  // one thing that can be named after the file, so it's moved across to the
+  // copy being kept if that hasn't got its own.
+  fn remove_copy (&mut self, keep: &str, extra: &str) {
+    let store = PlaydateFiles;
+    if let Err(e) = store.remove(extra) {
+      log!("Couldn't delete {}: {}", extra, e);
+      return
+    }
+    log!("Deleted {}, a copy of {}", extra, keep);
+    self.hasher.forget(extra);
+    self.hasher.save();
+
+    let manual_of = |filename: &str| {
+      format!("{}/{}.txt", MANUAL_DIR, rom_stem(filename))
+    };
+    let (old_manual, new_manual) = (manual_of(extra), manual_of(keep));
+    if store.size(&old_manual[..]).is_ok() && store.size(&new_manual[..]).is_err() {
+      match store.rename(&old_manual[..], &new_manual[..]) {
+        Ok(()) => log!("Moved {} to {}", old_manual, new_manual),
+        Err(e) => log!("{}", e)
+      }
+    }
+  }
+
+  // A ROM that can't be read in full, or that gbrs can't run, is never
+  // handed to it. The picker says what went wrong instead, or leaves it to
+  // take_read_error if the file couldn't be read.
+  fn read_selected (&mut self) -> Result<Option<Vec<u8>>, Error> {
+    let path = self.selected_path();
+    let rom = storage::read_file(&path[..])
+      .and_then(|rom| header::check_rom_size(&rom[..]).map(|()| rom));
+    match rom {
+      Ok(rom) => match header::check_cart(&rom[..]) {
+        Ok(()) => Ok(Some(rom)),
+        Err(problem) => {
+          log!("Not loading {}: {}", path, header::summary(&rom[..]));
+          self.load_problem = Some(problem.describe(rom_stem(&path[..])));
+          self.draw_detail()?;
+          Ok(None)
+        }
+      },
+      Err(e) => {
+        log!("Couldn't load {}: {}", path, e);
+        self.read_error = Some(format!("{}", e));
+        Ok(None)
+      }
+    }
+  }
+
+  pub fn take_read_error (&mut self) -> Option<String> {
+    self.read_error.take()
+  }
+
+  // The file the selected game lives in
+  pub fn selected_path (&self) -> String {
+    self.games[self.selected].clone()
+  }
+
   fn draw_whole_game_list (&self) -> Result<(), Error> {
     if self.games.len() == 0 {
       self.draw_empty_game_list()
     } else {
-      for i in 0..min(6, self.games.len()) {
+      for i in 0..min(5, self.games.len()) {
         self.draw_game_list_item(i)?
       }
       Ok(())
     }
   }
 
+  fn draw_detail (&self) -> Result<(), Error> {
+    let graphics = Graphics::get();
+    graphics.fill_rect(
+      rect(0, DETAIL_TOP, 400, 240 - DETAIL_TOP),
+      LCDColor::Solid(LCDSolidColor::kColorWhite)
+    )?;
+    if self.games.len() == 0 { return Ok(()) }
+
+    if let Some(problem) = &self.load_problem {
+      graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeCopy)?;
+      let detail = strings::ellipsize(&problem[..], 400 - X_PADDING * 2);
+      graphics.draw_text(&detail[..], point2(X_PADDING, DETAIL_TOP))?;
+      return Ok(())
+    }
+
+    if let Some(other) = self.hasher.duplicate_of(&self.selected_path()[..]) {
+      graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeCopy)?;
+      let detail = trf(Text::DuplicateOf, &[&other]);
+      let detail = strings::ellipsize(&detail[..], 400 - X_PADDING * 2);
+      graphics.draw_text(&detail[..], point2(X_PADDING, DETAIL_TOP))?;
+    }
+    Ok(())
+  }
+
   fn draw_game_list_item (&self, index: usize) -> Result<(), Error> {    
     let graphics = Graphics::get();
 
@@ -116,7 +292,12 @@ impl RomPickerState {
     }
 
     graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeNXOR)?;
-    graphics.draw_text(&self.games[game_index][..], point2(X_PADDING + 10, top + 6))?;
+    let filename = &self.games[game_index][..];
+    graphics.draw_text(rom_stem(filename), point2(X_PADDING + 10, top + 6))?;
+
+    if self.hasher.duplicate_of(filename).is_some() {
+      graphics.draw_text(tr(Text::DuplicateBadge), point2(400 - X_PADDING - 60, top + 6))?;
+    }
 
     Ok(())
   }
@@ -125,19 +306,22 @@ impl RomPickerState {
     let graphics = Graphics::get();
 
     graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeCopy)?;
-    graphics.draw_text("No game ROMs found.
-
-Please copy some Gameboy games into
-Playboy's data folder.
-
-See:
-https://github.com/adamsoutar/playboy
-For more detailed steps :)", point2(20, 50))?;
+    let mut message = String::from(tr(Text::NoRoms));
+    let near_misses = find_near_misses().unwrap_or_else(|e| {
+      log!("Couldn't look for near misses: {}", e);
+      vec![]
+    });
+    if near_misses.len() > 0 {
+      message.push_str("\n\n");
+      message.push_str(&trf(Text::NoRomsNearMiss, &[&near_misses.join(", ")]));
+    }
+    let message = strings::wrap(&message[..], 400 - 40);
+    graphics.draw_text(&message[..], point2(20, 50))?;
 
     Ok(())
   }
 
-  fn draw_initial_ui (&mut self) -> Result<(), Error> {
+  fn draw_initial_ui (&self) -> Result<(), Error> {
     let graphics = Graphics::get();
 
     graphics.clear(LCDColor::Solid(LCDSolidColor::kColorWhite))?;
@@ -146,29 +330,29 @@ For more detailed steps :)", point2(20, 50))?;
     graphics.fill_rect(rect(0, 0, 400, 30), LCDColor::Solid(LCDSolidColor::kColorBlack))?;
 
     graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeInverted)?;
-    graphics.draw_text("Playboy - Select a game", point2(6, 6))?;
-
-    let file_system = FileSystem::get();
-
-    // Find files ending with '.gb' and push them into games
-    let files = file_system.listfiles(".")?;
-    for filename in files {
-      if filename.ends_with(".gb") {
-        let game = String::from(&filename[..filename.len() - 3]);
-        self.games.push(game);
-      }
-    }
+    let title = strings::ellipsize(tr(Text::PickerTitle), 400 - 12);
+    graphics.draw_text(&title[..], point2(6, 6))?;
 
     self.draw_whole_game_list()?;
+    self.draw_detail()?;
 
     Ok(())
   }
 
   pub fn new () -> Self {
-    let mut new_picker = Self {
-      games: vec![],
+    let games = find_games().unwrap_or_else(|e| {
+      log!("Couldn't look for games: {}", e);
+      vec![]
+    });
+    let new_picker = Self {
+      hasher: RomHasher::new(&games[..]),
+      games,
       selected: 0,
-      scroll: 0
+      scroll: 0,
+      tidy: None,
+      boot_only_game: false,
+      load_problem: None,
+      read_error: None
     };
 
     // Calling "new" also implies you want to transition to the ROM Picker
@@ -178,6 +362,50 @@ For more detailed steps :)", point2(20, 50))?;
 
     new_picker
   }
+
+  // For when Playboy starts, rather than coming back from a game
+  pub fn at_launch () -> Self {
+    let mut picker = Self::new();
+    picker.boot_only_game = true;
+    picker
+  }
+}
+
+fn find_games () -> Result<Vec<String>, Error> {
+  let file_system = FileSystem::get();
+  let mut games = vec![];
+
+  // Find ROM files and push them into games
+  let files = file_system.listfiles(".")?;
+  for filename in files {
+    if is_rom(&filename[..]) {
+      games.push(filename);
+    }
+  }
+
+  Ok(games)
+}
+
+// How many near misses the empty list names, so they fit under the help
+const NEAR_MISSES_SHOWN: usize = 2;
+
+// Files that were probably meant to be games but won't be listed, like
+// rom.gb.txt from a renamed download or a ROM still in its .zip
+fn is_near_miss (filename: &str) -> bool {
+  if filename.ends_with('/') || is_rom(filename) { return false }
+  let lower = filename.to_ascii_lowercase();
+  lower.ends_with(".zip") || ROM_EXTENSIONS
+    .iter()
+    .any(|extension| lower.contains(&format!("{}.", extension)[..]))
+}
+
+fn find_near_misses () -> Result<Vec<String>, Error> {
+  let files = FileSystem::get().listfiles(".")?;
+  Ok(files
+    .into_iter()
+    .filter(|filename| is_near_miss(&filename[..]))
+    .take(NEAR_MISSES_SHOWN)
+    .collect())
 }
 
 // I need min but not using std
@@ -0,0 +1,90 @@
+use alloc::vec::Vec;
+use crankstart::system::System;
+
+// Desktop emulators (VBA, BGB, mGBA...) keep an MBC3 cart's clock in a
+// footer after the cart RAM in its .sav file, all little endian:
+//
+//   0..20   seconds, minutes, hours, days, days high - a u32 each
+//   20..40  the same again, as last latched
+//   40..    the Unix time the file was written, as a u64 (48 byte footer)
+//           or a u32 (44 byte footer, older VBA)
+//
+// gbrs doesn't emulate the clock, so there's nothing to load it into. What
+// we can do is keep it out of cart RAM and write it back when the game
+// saves, untouched. Since a desktop emulator runs the clock forward by the
+// time since the footer was written, the clock reads the same as if the cart
+// had been sitting in a drawer.
+pub const FOOTER_SIZES: &[usize] = &[48, 44];
+const WRITTEN_SIZE: usize = 48;
+
+// Playdate time counts from 2000, Unix time from 1970
+const UNIX_OFFSET: u64 = 946_684_800;
+
+// Cart types 0x0F and 0x10, the MBC3s with a timer and a battery
+const RTC_CART_TYPES: &[u8] = &[0x0F, 0x10];
+const CART_TYPE: usize = 0x147;
+
+pub fn has_rtc (rom: &[u8]) -> bool {
+    rom.len() > CART_TYPE && RTC_CART_TYPES.contains(&rom[CART_TYPE])
+}
+
+#[derive(Clone, Copy)]
+pub struct RtcFooter {
+    live: [u32; 5],
+    latched: [u32; 5],
+    saved_at: u64
+}
+
+fn read_u32 (bytes: &[u8], at: usize) -> u32 {
+    let mut word = [0; 4];
+    word.copy_from_slice(&bytes[at..at + 4]);
+    u32::from_le_bytes(word)
+}
+
+impl RtcFooter {
+    // A clock starting from zero now, for RTC carts whose save didn't come
+    // with one
+    pub fn fresh () -> Self {
+        let now = match System::get().get_seconds_since_epoch() {
+            Ok((seconds, _)) => seconds as u64 + UNIX_OFFSET,
+            Err(_) => 0
+        };
+        Self { live: [0; 5], latched: [0; 5], saved_at: now }
+    }
+
+    // footer has to be one of FOOTER_SIZES long
+    pub fn parse (footer: &[u8]) -> Self {
+        let mut live = [0; 5];
+        let mut latched = [0; 5];
+        for i in 0..5 {
+            live[i] = read_u32(footer, i * 4);
+            latched[i] = read_u32(footer, 20 + i * 4);
+        }
+
+        let saved_at = if footer.len() >= WRITTEN_SIZE {
+            let mut word = [0; 8];
+            word.copy_from_slice(&footer[40..48]);
+            u64::from_le_bytes(word)
+        } else {
+            read_u32(footer, 40) as u64
+        };
+
+        Self { live, latched, saved_at }
+    }
+
+    // Always the 48 byte kind, which everything current reads
+    pub fn to_bytes (&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(WRITTEN_SIZE);
+        for value in self.live.iter().chain(self.latched.iter()) {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend_from_slice(&self.saved_at.to_le_bytes());
+        bytes
+    }
+
+    // For the log
+    pub fn days_hours_minutes (&self) -> (u32, u32, u32) {
+        let days = self.live[3] | (self.live[4] & 1) << 8;
+        (days, self.live[2], self.live[1])
+    }
+}
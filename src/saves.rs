@@ -0,0 +1,702 @@
+use alloc::{format, string::String, vec, vec::Vec};
+use anyhow::Error;
+use core::ptr::addr_of_mut;
+
+use crate::checksum;
+use crate::rtc::{self, RtcFooter};
+use crate::storage::{self, FileStore, PlaydateFiles};
+
+// Everything the save callbacks keep between calls. They're plain fn
+// pointers too, so there's one of these for them to share, reached through
+// save_manager.
+pub struct SaveManager {
+    // Whether the running cart has a clock, and the clock footer its save
+    // came with (see rtc.rs). start_game sets this up before gbrs asks for
+    // the save.
+    has_rtc: bool,
+    rtc_footer: Option<RtcFooter>,
+    // How much RAM the running cart's header says it has. That's how much
+    // a .sav holds, the same as desktop emulators write, whatever size gbrs
+    // keeps its own copy at. None leaves gbrs' size alone.
+    cart_ram_size: Option<usize>,
+    // The last game's name and save_path, and the file as it's put
+    // together before writing. Both are kept so a game that saves often
+    // doesn't make them again every time.
+    save_path: Option<(String, String)>,
+    file_data: Vec<u8>,
+    // The battery save waiting to be written (see FLUSH_DELAY_SECONDS)
+    pending: Option<PendingSave>,
+    // The last save written, so an identical one can be skipped and spare
+    // the flash
+    last_written: Option<PendingSave>,
+    // Whichever of those was let go of last, for the next save to copy into
+    // rather than allocating its own
+    spare: Option<PendingSave>,
+    // The gbrs callbacks can't tell State anything, so they raise this and
+    // the main loop picks it up the next time update runs
+    save_failed: bool
+}
+
+static mut SAVE_MANAGER: SaveManager = SaveManager::new();
+
+// The only way at SAVE_MANAGER. Playboy's single threaded and the
+// callbacks don't call back into each other, so only one of these is in
+// use at a time.
+fn save_manager () -> &'static mut SaveManager {
+    unsafe { &mut *addr_of_mut!(SAVE_MANAGER) }
+}
+
+impl SaveManager {
+    pub const fn new () -> Self {
+        Self {
+            has_rtc: false,
+            rtc_footer: None,
+            cart_ram_size: None,
+            save_path: None,
+            file_data: Vec::new(),
+            pending: None,
+            last_written: None,
+            spare: None,
+            save_failed: false
+        }
+    }
+
+    pub fn set_cart (&mut self, has_rtc: bool, ram_size: Option<usize>) {
+        self.has_rtc = has_rtc;
+        self.rtc_footer = None;
+        self.cart_ram_size = ram_size;
+    }
+
+    // Whether a file of len bytes could be ram_size of cart RAM, with or
+    // without a clock footer
+    fn fits_layout (&self, len: usize, ram_size: usize) -> bool {
+        len == ram_size || (self.has_rtc && len > ram_size &&
+            rtc::FOOTER_SIZES.contains(&(len - ram_size)))
+    }
+
+    // What goes after cart RAM in the file, if anything
+    fn footer_to_write (&mut self) -> Option<Vec<u8>> {
+        if !self.has_rtc { return None }
+        let footer = self.rtc_footer.get_or_insert_with(RtcFooter::fresh);
+        Some(footer.to_bytes())
+    }
+
+    // Turns a .sav into the expected_size of RAM gbrs wants. Files from
+    // other emulators can have more after cart RAM. A clock footer is kept
+    // for writing back, and anything else is dropped.
+    //
+    // Playboy used to write gbrs' RAM as it was, which is a different size
+    // to the cart's when gbrs keeps more (or less), so a file that only
+    // makes sense at gbrs' size is read that way. It's written back the new
+    // way next time the game saves.
+    fn from_file_layout (
+        &mut self,
+        buffer: &mut Vec<u8>,
+        expected_size: usize,
+        path: &str
+    ) {
+        let cart_size = self.cart_ram_size.unwrap_or(expected_size);
+        let old_layout = cart_size != expected_size &&
+            self.fits_layout(buffer.len(), expected_size) &&
+            !self.fits_layout(buffer.len(), cart_size);
+        let ram_size = if old_layout {
+            log!("{} is in Playboy's old layout, converting it", path);
+            expected_size
+        } else {
+            cart_size
+        };
+
+        self.strip_extra(buffer, ram_size, path);
+        buffer.resize(expected_size, 0);
+    }
+
+    fn strip_extra (
+        &mut self,
+        buffer: &mut Vec<u8>,
+        ram_size: usize,
+        path: &str
+    ) {
+        if buffer.len() <= ram_size { return }
+
+        let extra = buffer.len() - ram_size;
+        if self.has_rtc && rtc::FOOTER_SIZES.contains(&extra) {
+            let footer = RtcFooter::parse(&buffer[ram_size..]);
+            let (days, hours, minutes) = footer.days_hours_minutes();
+            log!(
+                "{} has a clock footer, at day {} {:02}:{:02}",
+                path, days, hours, minutes
+            );
+            self.rtc_footer = Some(footer);
+        } else {
+            log!(
+                "{} has {} bytes more than cart RAM, ignoring them",
+                path, extra
+            );
+        }
+        buffer.truncate(ram_size);
+    }
+}
+
+// Call before gbrs asks for the running cart's save
+pub fn set_cart (has_rtc: bool, ram_size: Option<usize>) {
+    save_manager().set_cart(has_rtc, ram_size)
+}
+
+// gbrs hands the callbacks the raw title, so it's made safe here as well.
+// Doing it twice changes nothing.
+pub fn save_path (game_name: &str) -> String {
+    let filename = format!("{}.sav", storage::safe_name(game_name));
+    storage::in_dir(storage::SAVES_DIR, &filename[..])
+}
+
+// Where it was before names were made safe
+fn unsafe_save_path (title: &str) -> String {
+    storage::in_dir(storage::SAVES_DIR, &format!("{}.sav", title)[..])
+}
+
+// Where saves lived before they got a folder of their own. The migration
+// should have moved them all, but one that couldn't be moved is still read
+// from here.
+pub fn old_save_path (game_name: &str) -> String {
+    format!("{}.sav", game_name)
+}
+
+// A copy of the last save that passed its integrity check, taken when the
+// game was picked
+pub fn backup_path (game_name: &str) -> String {
+    format!("{}.bak", save_path(game_name))
+}
+
+// Holds the CRC-32 of the file at path, as 8 hex digits. It sits beside the
+// save rather than inside it so .sav files stay plain cart RAM.
+pub fn checksum_path (path: &str) -> String {
+    format!("{}.crc", path)
+}
+
+pub enum SaveCheck {
+    Missing,
+    Unreadable,
+    // The file doesn't match its checksum
+    Corrupt,
+    // Either it matched or there was no checksum to compare it with. Saves
+    // from before checksums existed look like the latter.
+    Intact(Vec<u8>)
+}
+
+// Writes data to path along with its checksum file
+pub fn write_checked_in (
+    store: &impl FileStore,
+    path: &str,
+    data: &[u8]
+) -> Result<(), Error> {
+    storage::write_file_in(store, path, data)?;
+
+    let checksum = format!("{:08x}", checksum::crc32(data));
+    if let Err(e) = storage::write_file_in(
+        store, &checksum_path(path)[..], checksum.as_bytes()
+    ) {
+        // The save itself made it, which is what matters
+        log!("Couldn't write the checksum for {}: {}", path, e);
+    }
+
+    Ok(())
+}
+
+pub fn check_in (store: &impl FileStore, path: &str) -> SaveCheck {
+    if store.size(path).is_err() { return SaveCheck::Missing }
+
+    let data = match storage::read_file_in(store, path) {
+        Ok(data) => data,
+        Err(e) => {
+            log!("{}", e);
+            return SaveCheck::Unreadable
+        }
+    };
+
+    let expected = storage::read_file_in(store, &checksum_path(path)[..])
+        .ok()
+        .and_then(|text| {
+            let text = core::str::from_utf8(&text[..]).ok()?;
+            u32::from_str_radix(text.trim(), 16).ok()
+        });
+
+    match expected {
+        Some(expected) if expected != checksum::crc32(&data[..]) =>
+            SaveCheck::Corrupt,
+        _ => SaveCheck::Intact(data)
+    }
+}
+
+impl SaveManager {
+    pub fn save_battery_in (
+        &mut self,
+        store: &impl FileStore,
+        game_name: &str,
+        save_data: &[u8]
+    ) -> Result<(), Error> {
+        let cached = match &self.save_path {
+            Some((name, _)) => name == game_name,
+            None => false
+        };
+        if !cached {
+            let path = save_path(game_name);
+            self.save_path = Some((String::from(game_name), path));
+        }
+
+        // Cart RAM at the cart's size, then the clock
+        let footer = self.footer_to_write();
+        let file_data = &mut self.file_data;
+        file_data.clear();
+        file_data.extend_from_slice(save_data);
+        if let Some(size) = self.cart_ram_size {
+            file_data.resize(size, 0);
+        }
+        if let Some(footer) = footer {
+            file_data.extend_from_slice(&footer[..]);
+        }
+        let save_data = &file_data[..];
+        let save_path = &self.save_path.as_ref().unwrap().1[..];
+
+        let first_attempt = write_checked_in(store, save_path, save_data);
+        if let Err(first_error) = first_attempt {
+            // Filesystem hiccups do happen, so it's worth one more go before we
+            // tell the user their progress might be gone.
+            log!("Save failed ({}), retrying", first_error);
+            write_checked_in(store, save_path, save_data)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn load_battery_in (
+        &mut self,
+        store: &impl FileStore,
+        game_name: &str,
+        expected_size: usize
+    ) -> Vec<u8> {
+        let new_path = save_path(game_name);
+        // In case one of these couldn't be moved. The next save writes it to
+        // the new place.
+        let old_paths = [unsafe_save_path(game_name), old_save_path(game_name)];
+        let old_path = old_paths
+            .iter()
+            .find(|path| store.size(&path[..]).is_ok());
+        let save_path = match old_path {
+            Some(old_path) if store.size(&new_path[..]).is_err() => {
+                log!("Falling back to {}", old_path);
+                &old_path[..]
+            },
+            _ => &new_path[..]
+        };
+
+        match storage::read_file_in(store, save_path) {
+            Ok(mut buffer) => {
+                log!("Loaded {}", save_path);
+                self.from_file_layout(&mut buffer, expected_size, save_path);
+                buffer
+            },
+            Err(e) => {
+                // Whether there's simply no save file yet or we failed to read
+                // it, the game is better off starting fresh than crashing.
+                // TODO: Should this be all 0 or all 0xFF?
+                log!("{}", e);
+                vec![0; expected_size]
+            }
+        }
+    }
+}
+
+// gbrs hands over a battery save whenever it feels like flushing, right in
+// the middle of a frame, and some games set that off several times in a
+// row. Writing each one there and then makes a visible hitch, so the
+// callback only keeps a copy, and update writes the latest one out once
+// FLUSH_DELAY_SECONDS of frames go by without another. Anywhere that's done
+// with the game (or about to read its save file) flushes straight away, and
+// so does opening the options menu, which pauses it, and coming back from
+// the system menu.
+//
+// NOTE: crankstart doesn't pass the Playdate's lock and terminate events
+//   on to us, so a save that arrives just before the device is locked or
+//   playboy is closed from the system menu's Home waits for the next
+//   update after that. The delay is kept short for that reason.
+const FLUSH_DELAY_SECONDS: f32 = 0.5;
+
+struct PendingSave {
+    game_name: String,
+    data: Vec<u8>,
+    // Emulated frames since it came in
+    quiet_frames: usize
+}
+
+impl SaveManager {
+    // Returns whether a battery save has failed since the last time this
+    // was called, and clears the flag.
+    pub fn take_save_failure (&mut self) -> bool {
+        core::mem::replace(&mut self.save_failed, false)
+    }
+
+    fn write_save (&mut self, store: &impl FileStore, pending: PendingSave) {
+        let written = match &self.last_written {
+            Some(last) => last.game_name == pending.game_name &&
+                last.data == pending.data,
+            None => false
+        };
+        if written {
+            self.spare = Some(pending);
+            return
+        }
+
+        let result = self.save_battery_in(
+            store, &pending.game_name[..], &pending.data[..]
+        );
+        match result {
+            Ok(()) => self.spare = self.last_written.replace(pending),
+            Err(e) => {
+                log!("Save failed again ({})", e);
+                self.save_failed = true;
+                // Kept waiting, so it's tried again after another quiet
+                // spell (or the next flush) rather than lost if the game
+                // doesn't save again
+                let mut pending = pending;
+                pending.quiet_frames = 0;
+                self.pending = Some(pending);
+            }
+        }
+    }
+
+    // Call once per update with how many frames were just emulated
+    pub fn flush_when_quiet (
+        &mut self,
+        store: &impl FileStore,
+        frames_run: usize,
+        frame_rate: usize
+    ) {
+        let delay = (FLUSH_DELAY_SECONDS * frame_rate as f32) as usize;
+        let due = match &mut self.pending {
+            Some(pending) => {
+                pending.quiet_frames += frames_run;
+                pending.quiet_frames >= delay
+            },
+            None => false
+        };
+        if due { self.flush_now(store) }
+    }
+
+    pub fn flush_now (&mut self, store: &impl FileStore) {
+        if let Some(pending) = self.pending.take() {
+            self.write_save(store, pending);
+        }
+    }
+
+    pub fn save_battery (
+        &mut self,
+        store: &impl FileStore,
+        game_name: &str,
+        save_data: &[u8]
+    ) {
+        // A different game's shouldn't be left waiting behind this one
+        let other_game = match &self.pending {
+            Some(pending) => pending.game_name != game_name,
+            None => false
+        };
+        if other_game { self.flush_now(store) }
+
+        // Copied into whatever buffer there is, the one already waiting if
+        // this replaces it
+        let spare = &mut self.spare;
+        let mut pending = self.pending.take().or_else(|| spare.take())
+            .unwrap_or(PendingSave {
+                game_name: String::new(),
+                data: Vec::new(),
+                quiet_frames: 0
+            });
+        if pending.game_name != game_name {
+            pending.game_name.clear();
+            pending.game_name.push_str(game_name);
+        }
+        pending.data.clear();
+        pending.data.extend_from_slice(save_data);
+        pending.quiet_frames = 0;
+        self.pending = Some(pending);
+    }
+}
+
+pub fn take_save_failure () -> bool {
+    save_manager().take_save_failure()
+}
+
+pub fn flush_when_quiet (frames_run: usize, frame_rate: usize) {
+    save_manager().flush_when_quiet(&PlaydateFiles, frames_run, frame_rate)
+}
+
+pub fn flush_now () {
+    save_manager().flush_now(&PlaydateFiles)
+}
+
+// Used as gbrs' save callback
+pub fn save_battery (game_name: &str, save_data: &[u8]) {
+    save_manager().save_battery(&PlaydateFiles, game_name, save_data)
+}
+
+// Moves a save named after the raw title (and its checksum and backup) to
+// the safe name, as long as there's nothing there already. Meant for before
+// anything looks at the save.
+pub fn migrate_unsafe_name_in (store: &impl FileStore, title: &str) {
+    let old_path = unsafe_save_path(title);
+    let new_path = save_path(title);
+    let worth_moving = old_path != new_path &&
+        store.size(&old_path[..]).is_ok() && store.size(&new_path[..]).is_err();
+    if !worth_moving { return }
+
+    let old_backup = format!("{}.bak", old_path);
+    let new_backup = backup_path(title);
+    let moves = [
+        (checksum_path(&old_path[..]), checksum_path(&new_path[..])),
+        (checksum_path(&old_backup[..]), checksum_path(&new_backup[..])),
+        (old_backup, new_backup),
+        // Last, so a half-finished move still gets another go
+        (old_path, new_path)
+    ];
+    for (from, to) in moves.iter() {
+        if store.size(&from[..]).is_err() { continue }
+        match store.rename(&from[..], &to[..]) {
+            Ok(()) => log!("Moved {} to {}", from, to),
+            Err(e) => log!("{}", e)
+        }
+    }
+}
+
+pub fn migrate_unsafe_name (title: &str) {
+    migrate_unsafe_name_in(&PlaydateFiles, title)
+}
+
+// Used as gbrs' load callback
+pub fn load_battery (game_name: &str, expected_size: usize) -> Vec<u8> {
+    // The file has to be up to date before it's read
+    flush_now();
+    save_manager().load_battery_in(&PlaydateFiles, game_name, expected_size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryFiles;
+
+    const RAM_SIZE: usize = 8 * 1024;
+    // The .sav and its .crc
+    const WRITES_PER_SAVE: usize = 2;
+    const FRAME_RATE: usize = 60;
+    const DELAY: usize = (FLUSH_DELAY_SECONDS * FRAME_RATE as f32) as usize;
+
+    fn footer () -> Vec<u8> {
+        (0..48).collect()
+    }
+
+    #[test]
+    #[cfg_attr(feature = "simulate-write-failure", ignore)]
+    fn keeps_the_clock_footer_with_the_save () {
+        let files = MemoryFiles::new();
+        let path = save_path("CLOCK");
+        let mut file = vec![7; RAM_SIZE];
+        file.extend_from_slice(&footer());
+        files.put(&path[..], &file[..]);
+
+        let mut saves = SaveManager::new();
+        saves.set_cart(true, Some(RAM_SIZE));
+        let ram = saves.load_battery_in(&files, "CLOCK", RAM_SIZE);
+        assert_eq!(ram, vec![7; RAM_SIZE]);
+
+        saves.save_battery_in(&files, "CLOCK", &ram[..]).unwrap();
+        assert_eq!(files.get(&path[..]).unwrap(), file);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "simulate-write-failure", ignore)]
+    fn drops_the_footer_for_carts_without_a_clock () {
+        let files = MemoryFiles::new();
+        let path = save_path("NOCLOCK");
+        let mut file = vec![7; RAM_SIZE];
+        file.extend_from_slice(&footer());
+        files.put(&path[..], &file[..]);
+
+        let mut saves = SaveManager::new();
+        saves.set_cart(false, Some(RAM_SIZE));
+        let ram = saves.load_battery_in(&files, "NOCLOCK", RAM_SIZE);
+        saves.save_battery_in(&files, "NOCLOCK", &ram[..]).unwrap();
+        assert_eq!(files.get(&path[..]).unwrap(), vec![7; RAM_SIZE]);
+    }
+
+    #[test]
+    fn a_new_cart_forgets_the_last_footer () {
+        let files = MemoryFiles::new();
+        let mut file = vec![0; RAM_SIZE];
+        file.extend_from_slice(&footer());
+        files.put(&save_path("CLOCK")[..], &file[..]);
+
+        let mut saves = SaveManager::new();
+        saves.set_cart(true, Some(RAM_SIZE));
+        saves.load_battery_in(&files, "CLOCK", RAM_SIZE);
+        assert!(saves.rtc_footer.is_some());
+        saves.set_cart(true, Some(RAM_SIZE));
+        assert!(saves.rtc_footer.is_none());
+    }
+
+    #[test]
+    #[cfg_attr(feature = "simulate-write-failure", ignore)]
+    fn waits_for_the_game_to_go_quiet () {
+        let files = MemoryFiles::new();
+        let mut saves = SaveManager::new();
+        saves.save_battery(&files, "GAME", &[1; 16]);
+        saves.flush_when_quiet(&files, DELAY - 1, FRAME_RATE);
+        assert_eq!(files.writes.get(), 0);
+        saves.flush_when_quiet(&files, 1, FRAME_RATE);
+        assert_eq!(files.writes.get(), WRITES_PER_SAVE);
+        assert_eq!(files.get(&save_path("GAME")[..]).unwrap(), [1; 16]);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "simulate-write-failure", ignore)]
+    fn each_save_restarts_the_wait () {
+        let files = MemoryFiles::new();
+        let mut saves = SaveManager::new();
+        for value in 0..5 {
+            saves.save_battery(&files, "GAME", &[value; 16]);
+            saves.flush_when_quiet(&files, DELAY - 1, FRAME_RATE);
+        }
+        assert_eq!(files.writes.get(), 0);
+        saves.flush_when_quiet(&files, 1, FRAME_RATE);
+        // Only the last one's written
+        assert_eq!(files.writes.get(), WRITES_PER_SAVE);
+        assert_eq!(files.get(&save_path("GAME")[..]).unwrap(), [4; 16]);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "simulate-write-failure", ignore)]
+    fn another_games_save_goes_out_first () {
+        let files = MemoryFiles::new();
+        let mut saves = SaveManager::new();
+        saves.save_battery(&files, "ONE", &[1; 16]);
+        saves.save_battery(&files, "TWO", &[2; 16]);
+        assert_eq!(files.get(&save_path("ONE")[..]).unwrap(), [1; 16]);
+        assert!(files.get(&save_path("TWO")[..]).is_none());
+    }
+
+    #[test]
+    fn failures_are_reported_once () {
+        let files = MemoryFiles::new();
+        files.fail_renames.set(true);
+        let mut saves = SaveManager::new();
+        saves.save_battery(&files, "GAME", &[1; 16]);
+        saves.flush_now(&files);
+        assert!(saves.take_save_failure());
+        assert!(!saves.take_save_failure());
+    }
+
+    #[test]
+    #[cfg_attr(feature = "simulate-write-failure", ignore)]
+    fn reuses_the_path_and_buffer () {
+        let files = MemoryFiles::new();
+        let mut saves = SaveManager::new();
+        saves.save_battery_in(&files, "GAME", &[1; 16]).unwrap();
+        let path = saves.save_path.as_ref().unwrap().1.as_ptr();
+        let buffer = saves.file_data.as_ptr();
+        saves.save_battery_in(&files, "GAME", &[2; 16]).unwrap();
+        assert_eq!(saves.save_path.as_ref().unwrap().1.as_ptr(), path);
+        assert_eq!(saves.file_data.as_ptr(), buffer);
+
+        // and notices when the game changes
+        saves.save_battery_in(&files, "OTHER", &[3; 16]).unwrap();
+        assert_eq!(files.get(&save_path("OTHER")[..]).unwrap(), [3; 16]);
+        assert_eq!(files.get(&save_path("GAME")[..]).unwrap(), [2; 16]);
+    }
+
+    // What opening the options menu does
+    #[test]
+    #[cfg_attr(feature = "simulate-write-failure", ignore)]
+    fn flushing_writes_once () {
+        let files = MemoryFiles::new();
+        let mut saves = SaveManager::new();
+        saves.save_battery(&files, "GAME", &[1; 16]);
+        saves.save_battery(&files, "GAME", &[2; 16]);
+        saves.flush_now(&files);
+        assert_eq!(files.writes.get(), WRITES_PER_SAVE);
+        assert_eq!(files.get(&save_path("GAME")[..]).unwrap(), [2; 16]);
+
+        // Nothing new, so nothing to write
+        saves.flush_now(&files);
+        saves.flush_when_quiet(&files, DELAY * 2, FRAME_RATE);
+        assert_eq!(files.writes.get(), WRITES_PER_SAVE);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "simulate-write-failure", ignore)]
+    fn unchanged_saves_are_skipped () {
+        let files = MemoryFiles::new();
+        let mut saves = SaveManager::new();
+        saves.save_battery(&files, "GAME", &[1; 16]);
+        saves.flush_now(&files);
+        // Games often save the same thing again
+        saves.save_battery(&files, "GAME", &[1; 16]);
+        saves.flush_now(&files);
+        assert_eq!(files.writes.get(), WRITES_PER_SAVE);
+
+        saves.save_battery(&files, "GAME", &[3; 16]);
+        saves.flush_now(&files);
+        assert_eq!(files.writes.get(), WRITES_PER_SAVE * 2);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "simulate-write-failure", ignore)]
+    fn failed_saves_are_tried_again () {
+        let files = MemoryFiles::new();
+        files.fail_renames.set(true);
+        let mut saves = SaveManager::new();
+        saves.save_battery(&files, "GAME", &[1; 16]);
+        saves.flush_now(&files);
+        assert!(saves.take_save_failure());
+
+        // It never made it out, so the same data isn't skipped next time
+        files.fail_renames.set(false);
+        saves.save_battery(&files, "GAME", &[1; 16]);
+        saves.flush_now(&files);
+        assert_eq!(files.get(&save_path("GAME")[..]).unwrap(), [1; 16]);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "simulate-write-failure", ignore)]
+    fn failed_saves_are_kept_until_written () {
+        let files = MemoryFiles::new();
+        files.fail_renames.set(true);
+        let mut saves = SaveManager::new();
+        saves.save_battery(&files, "GAME", &[1; 16]);
+        saves.flush_now(&files);
+        assert!(saves.take_save_failure());
+        assert!(files.get(&save_path("GAME")[..]).is_none());
+
+        // The game doesn't save again, but it still gets there
+        files.fail_renames.set(false);
+        saves.flush_when_quiet(&files, DELAY - 1, FRAME_RATE);
+        assert!(files.get(&save_path("GAME")[..]).is_none());
+        saves.flush_when_quiet(&files, 1, FRAME_RATE);
+        assert_eq!(files.get(&save_path("GAME")[..]).unwrap(), [1; 16]);
+        assert!(!saves.take_save_failure());
+
+        // and once it's written it isn't written again
+        let writes = files.writes.get();
+        saves.flush_now(&files);
+        assert_eq!(files.writes.get(), writes);
+    }
+
+    // What the storage banner and the "save failed" toast go by
+    #[test]
+    #[cfg(feature = "simulate-write-failure")]
+    fn simulated_failures_are_reported () {
+        let files = MemoryFiles::new();
+        let mut saves = SaveManager::new();
+        saves.save_battery(&files, "GAME", &[1; 16]);
+        saves.flush_now(&files);
+        assert!(saves.take_save_failure());
+        assert!(storage::writes_failing());
+        assert!(files.get(&save_path("GAME")[..]).is_none());
+    }
+}
@@ -0,0 +1,255 @@
+use alloc::{format, string::String, vec::Vec};
+use anyhow::{anyhow, Error};
+use core::fmt;
+use gbrs_core::cpu::Cpu;
+
+use crate::checksum;
+use crate::header;
+use crate::storage;
+
+// A save state is gbrs' own snapshot of the Cpu (registers, RAM, VRAM, cart
+// RAM, timers...) behind a small header of ours, so we can tell our files
+// apart from junk and change the layout later without misreading old ones.
+//
+// Header layout:
+//   0..4   "PBST"
+//   4      version
+//   5      the ROM's header checksum byte (0x14D)
+//   6..10  CRC-32 of the ROM's bank 0, little-endian
+const MAGIC: &[u8; 4] = b"PBST";
+const VERSION: u8 = 2;
+const HEADER_SIZE: usize = 10;
+
+const BANK_SIZE: usize = 0x4000;
+
+// gbrs' side of it, Cpu::save_state and Cpu::load_state. The gbrs
+// submodule isn't pinned in this repo, so they're behind a feature that's
+// off by default, and without it everything that makes or loads a state is
+// hidden (see SUPPORTED) rather than failing.
+pub const SUPPORTED: bool = cfg!(feature = "gbrs-save-states");
+
+#[cfg(feature = "gbrs-save-states")]
+fn snapshot (cpu: &Cpu) -> Result<Vec<u8>, Error> {
+    Ok(cpu.save_state())
+}
+
+#[cfg(feature = "gbrs-save-states")]
+fn load_snapshot (cpu: &mut Cpu, snapshot: &[u8]) -> Result<(), Error> {
+    cpu.load_state(snapshot)
+        .map_err(|e| anyhow!("gbrs rejected the save state: {}", e))
+}
+
+#[cfg(not(feature = "gbrs-save-states"))]
+fn snapshot (_cpu: &Cpu) -> Result<Vec<u8>, Error> {
+    Err(anyhow!("built without gbrs-save-states"))
+}
+
+#[cfg(not(feature = "gbrs-save-states"))]
+fn load_snapshot (_cpu: &mut Cpu, _snapshot: &[u8]) -> Result<(), Error> {
+    Err(anyhow!("built without gbrs-save-states"))
+}
+
+// The one the system menu saves
+pub fn state_path (game_name: &str) -> String {
+    let filename = format!("{}.state", game_name);
+    storage::in_dir(storage::STATES_DIR, &filename[..])
+}
+
+// Numbered slots live with the quick and undo states
+pub fn slot_path (game_name: &str, slot: u8) -> String {
+    let filename = format!("{}.slot{}.state", game_name, slot);
+    storage::in_dir(storage::STATES_DIR, &filename[..])
+}
+
+// Saved from the frozen game prompt (see watchdog.rs)
+pub fn emergency_state_path (game_name: &str) -> String {
+    let filename = format!("{}.emergency.state", game_name);
+    storage::in_dir(storage::STATES_DIR, &filename[..])
+}
+
+// Identifies which ROM a state was made with. Filenames can't be trusted for
+// this - ROMs get renamed and two titles can collide - so we look at the ROM
+// itself.
+#[derive(Clone, Copy, PartialEq)]
+pub struct RomId {
+    header_checksum: u8,
+    bank0_crc: u32
+}
+
+impl RomId {
+    pub fn of (rom: &[u8]) -> Self {
+        let bank0 = &rom[..rom.len().min(BANK_SIZE)];
+        Self {
+            header_checksum: header::header_checksum(rom),
+            bank0_crc: checksum::crc32(bank0)
+        }
+    }
+
+    pub fn none () -> Self {
+        Self {
+            header_checksum: 0,
+            bank0_crc: 0
+        }
+    }
+
+    // Short enough to name files after, eg. "4e0a1b2c3d"
+    pub fn hex (&self) -> String {
+        format!("{:02x}{:08x}", self.header_checksum, self.bank0_crc)
+    }
+}
+
+// For bug reports
+impl fmt::Display for RomId {
+    fn fmt (&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Header checksum: {:02x}, bank 0 CRC-32: {:08x}",
+            self.header_checksum, self.bank0_crc
+        )
+    }
+}
+
+pub fn capture (cpu: &Cpu, rom_id: RomId) -> Result<Vec<u8>, Error> {
+    Ok(wrap(&snapshot(cpu)?[..], rom_id))
+}
+
+// Puts our header in front of a gbrs snapshot
+fn wrap (snapshot: &[u8], rom_id: RomId) -> Vec<u8> {
+    let mut state = Vec::with_capacity(HEADER_SIZE + snapshot.len());
+    state.extend_from_slice(MAGIC);
+    state.push(VERSION);
+    state.push(rom_id.header_checksum);
+    state.extend_from_slice(&rom_id.bank0_crc.to_le_bytes());
+    state.extend_from_slice(snapshot);
+    state
+}
+
+fn check_header (state: &[u8]) -> Result<(), Error> {
+    if state.len() < HEADER_SIZE || &state[..4] != MAGIC {
+        return Err(anyhow!("not a Playboy save state"))
+    }
+    if state[4] != VERSION {
+        return Err(anyhow!(
+            "save state is version {}, expected {}", state[4], VERSION
+        ))
+    }
+    Ok(())
+}
+
+fn state_rom_id (state: &[u8]) -> RomId {
+    let mut crc = [0; 4];
+    crc.copy_from_slice(&state[6..10]);
+    RomId {
+        header_checksum: state[5],
+        bank0_crc: u32::from_le_bytes(crc)
+    }
+}
+
+// Lets callers tell this apart from other reasons restore might fail, so
+// they can say so plainly. Only looks at the header, so it's cheap.
+pub fn made_with_other_rom (state: &[u8], rom_id: RomId) -> bool {
+    check_header(state).is_ok() && state_rom_id(state) != rom_id
+}
+
+// Loading another game's state into the Cpu is never going to end well, so
+// it's refused unless force is set (which only ROM hackers should want).
+pub fn restore (
+    cpu: &mut Cpu,
+    state: &[u8],
+    rom_id: RomId,
+    force: bool
+) -> Result<(), Error> {
+    load_snapshot(cpu, unwrap(state, rom_id, force)?)
+}
+
+// The gbrs snapshot in state, once the header's been checked
+fn unwrap (state: &[u8], rom_id: RomId, force: bool) -> Result<&[u8], Error> {
+    check_header(state)?;
+
+    if state_rom_id(state) != rom_id {
+        if !force {
+            return Err(anyhow!("save state is from a different ROM"))
+        }
+        log!("Loading a save state from a different ROM, as forced");
+    }
+
+    Ok(&state[HEADER_SIZE..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROM_ID: RomId = RomId {
+        header_checksum: 0x4e,
+        bank0_crc: 0x0a1b_2c3d
+    };
+    const SNAPSHOT: &[u8] = b"registers, RAM, and the rest";
+
+    #[test]
+    fn round_trips () {
+        let state = wrap(SNAPSHOT, ROM_ID);
+        assert_eq!(&state[..4], MAGIC);
+        assert_eq!(state.len(), HEADER_SIZE + SNAPSHOT.len());
+        assert_eq!(unwrap(&state[..], ROM_ID, false).unwrap(), SNAPSHOT);
+        assert!(!made_with_other_rom(&state[..], ROM_ID));
+    }
+
+    #[test]
+    fn rejects_other_versions () {
+        let mut state = wrap(SNAPSHOT, ROM_ID);
+        state[4] = VERSION - 1;
+        assert!(unwrap(&state[..], ROM_ID, false).is_err());
+        // Even forced, since it's the layout that's changed
+        assert!(unwrap(&state[..], ROM_ID, true).is_err());
+        assert!(!made_with_other_rom(&state[..], ROM_ID));
+    }
+
+    #[test]
+    fn rejects_other_header_checksums () {
+        let other = RomId { header_checksum: 0x4f, ..ROM_ID };
+        let state = wrap(SNAPSHOT, other);
+        assert!(unwrap(&state[..], ROM_ID, false).is_err());
+        assert!(made_with_other_rom(&state[..], ROM_ID));
+        assert_eq!(unwrap(&state[..], ROM_ID, true).unwrap(), SNAPSHOT);
+    }
+
+    #[test]
+    fn rejects_other_bank0_crcs () {
+        let other = RomId { bank0_crc: 0x0a1b_2c3e, ..ROM_ID };
+        let state = wrap(SNAPSHOT, other);
+        assert!(unwrap(&state[..], ROM_ID, false).is_err());
+        assert!(made_with_other_rom(&state[..], ROM_ID));
+    }
+
+    #[test]
+    fn rejects_truncated_states () {
+        let state = wrap(SNAPSHOT, ROM_ID);
+        for len in 0..HEADER_SIZE {
+            assert!(unwrap(&state[..len], ROM_ID, true).is_err());
+            assert!(!made_with_other_rom(&state[..len], ROM_ID));
+        }
+        // Just the header is a state with an empty snapshot, which is
+        // gbrs' to refuse
+        let header_only = unwrap(&state[..HEADER_SIZE], ROM_ID, false);
+        assert!(header_only.unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_junk () {
+        let mut state = wrap(SNAPSHOT, ROM_ID);
+        state[0] = b'X';
+        assert!(unwrap(&state[..], ROM_ID, true).is_err());
+    }
+
+    #[test]
+    fn ids_come_from_the_rom () {
+        let mut rom = vec![0; BANK_SIZE * 2];
+        let id = RomId::of(&rom[..]);
+        // Past bank 0 doesn't count, so bank-switched data can't change it
+        rom[BANK_SIZE] = 1;
+        assert!(RomId::of(&rom[..]) == id);
+        rom[0x100] = 1;
+        assert!(RomId::of(&rom[..]) != id);
+    }
+}
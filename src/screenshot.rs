@@ -0,0 +1,166 @@
+use alloc::{format, string::String, vec::Vec};
+use anyhow::{anyhow, Error};
+use crankstart::file::FileSystem;
+use gbrs_core::{
+    constants::{SCREEN_HEIGHT, SCREEN_WIDTH},
+    lcd::GreyShade
+};
+
+use crate::blit;
+use crate::storage;
+
+// Screenshots are binary PGMs of the raw Gameboy frame, one byte per pixel,
+// named shot_0001.pgm and so on in the screenshots folder. PGM is about the
+// simplest image format there is, and desktop image viewers open it without
+// any fuss.
+pub const SHOT_PREFIX: &str = "shot_";
+pub const SHOT_EXTENSION: &str = ".pgm";
+
+pub fn is_screenshot (filename: &str) -> bool {
+    filename.starts_with(SHOT_PREFIX) && filename.ends_with(SHOT_EXTENSION)
+}
+
+pub fn shot_path (filename: &str) -> String {
+    storage::in_dir(storage::SCREENSHOTS_DIR, filename)
+}
+
+// Names of the shots in the screenshots folder, which is empty until the
+// first one is taken
+pub fn list_shots () -> Vec<String> {
+    FileSystem::get()
+        .listfiles(storage::SCREENSHOTS_DIR)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|filename| is_screenshot(&filename[..]))
+        .collect()
+}
+
+// Four digits' worth. Past that the names would stop sorting properly.
+const MAX_SHOTS: usize = 9999;
+
+// The first free name after the highest-numbered shot already there, or
+// None once they've all been used up
+pub fn next_shot_path () -> Option<String> {
+    next_numbered_path(storage::SCREENSHOTS_DIR, SHOT_PREFIX)
+}
+
+// The same for any run of {prefix}0001.pgm, {prefix}0002.pgm and so on in
+// dir, like the Game Boy Printer's prints
+pub fn next_numbered_path (dir: &str, prefix: &str) -> Option<String> {
+    let highest = FileSystem::get()
+        .listfiles(dir)
+        .unwrap_or_default()
+        .iter()
+        .filter(|filename| {
+            filename.starts_with(prefix) && filename.ends_with(SHOT_EXTENSION)
+        })
+        .filter_map(|filename| {
+            filename[prefix.len()..filename.len() - SHOT_EXTENSION.len()]
+                .parse::<usize>()
+                .ok()
+        })
+        .max()
+        .unwrap_or(0);
+    if highest >= MAX_SHOTS { return None }
+    let filename = format!("{}{:04}{}", prefix, highest + 1, SHOT_EXTENSION);
+    Some(storage::in_dir(dir, &filename[..]))
+}
+
+// Writes levels (see encode_levels) out as the next shot, and returns its
+// path
+pub fn save_levels (levels: &[u8]) -> Result<String, Error> {
+    let path = next_shot_path()
+        .ok_or_else(|| anyhow!("out of screenshot names"))?;
+    storage::write_file(&path[..], &encode_levels(levels)[..])?;
+    Ok(path)
+}
+
+pub fn save (finished_frame: &[GreyShade]) -> Result<String, Error> {
+    let levels: Vec<u8> =
+        finished_frame.iter().map(blit::shade_value).collect();
+    save_levels(&levels[..])
+}
+
+// From blit::shade_values laid out like a finished frame. Shades go out as
+// 0, 85, 170 and 255 so a computer shows them as greys.
+pub fn encode_levels (levels: &[u8]) -> Vec<u8> {
+    encode_image(SCREEN_WIDTH, SCREEN_HEIGHT, levels)
+}
+
+// The same for a picture of any size, width levels to a row
+pub fn encode_image (width: usize, height: usize, levels: &[u8]) -> Vec<u8> {
+    let mut bytes =
+        Vec::from(format!("P5\n{} {}\n255\n", width, height).as_bytes());
+    bytes.extend(levels.iter().map(|level| level * 85));
+    bytes
+}
+
+// Pulls the next whitespace-separated header field out of a PGM, skipping
+// comment lines, and leaves pos just past it.
+fn next_field<'a> (bytes: &'a [u8], pos: &mut usize) -> Option<&'a [u8]> {
+    loop {
+        while *pos < bytes.len() && bytes[*pos].is_ascii_whitespace() {
+            *pos += 1;
+        }
+        if *pos < bytes.len() && bytes[*pos] == b'#' {
+            while *pos < bytes.len() && bytes[*pos] != b'\n' { *pos += 1 }
+        } else {
+            break
+        }
+    }
+
+    let start = *pos;
+    while *pos < bytes.len() && !bytes[*pos].is_ascii_whitespace() {
+        *pos += 1;
+    }
+    if start == *pos { None } else { Some(&bytes[start..*pos]) }
+}
+
+fn next_number (bytes: &[u8], pos: &mut usize) -> Result<usize, Error> {
+    next_field(bytes, pos)
+        .and_then(|field| core::str::from_utf8(field).ok())
+        .and_then(|field| field.parse::<usize>().ok())
+        .ok_or_else(|| anyhow!("screenshot header is damaged"))
+}
+
+// Any maxval is accepted so images touched up on a computer still load.
+// Values are split evenly into the four Gameboy shades.
+fn shade_of (value: usize, max_value: usize) -> GreyShade {
+    match (value * 4 / (max_value + 1)).min(3) {
+        0 => GreyShade::Black,
+        1 => GreyShade::DarkGrey,
+        2 => GreyShade::LightGrey,
+        _ => GreyShade::White
+    }
+}
+
+// Decodes a screenshot back into a frame laid out like gpu.finished_frame
+pub fn decode (bytes: &[u8]) -> Result<Vec<GreyShade>, Error> {
+    let mut pos = 0;
+    if next_field(bytes, &mut pos) != Some(b"P5") {
+        return Err(anyhow!("not a binary PGM"))
+    }
+
+    let width = next_number(bytes, &mut pos)?;
+    let height = next_number(bytes, &mut pos)?;
+    let max_value = next_number(bytes, &mut pos)?;
+    if width != SCREEN_WIDTH || height != SCREEN_HEIGHT {
+        return Err(anyhow!(
+            "screenshot is {}x{}, not a Gameboy frame", width, height
+        ))
+    }
+    if max_value == 0 || max_value > 255 {
+        return Err(anyhow!("unsupported PGM maxval {}", max_value))
+    }
+
+    // Exactly one whitespace byte separates the header from the pixels
+    let pixels = &bytes[(pos + 1).min(bytes.len())..];
+    if pixels.len() < width * height {
+        return Err(anyhow!("screenshot is cut short"))
+    }
+
+    Ok(pixels[..width * height]
+        .iter()
+        .map(|&value| shade_of(value as usize, max_value))
+        .collect())
+}
@@ -0,0 +1,218 @@
+use alloc::{format, string::String};
+use anyhow::Error;
+use crankstart::{
+    graphics::{Graphics, LCDColor, LCDSolidColor},
+    system::System
+};
+use crankstart_sys::{LCDBitmapDrawMode, PDButtons, LCD_COLUMNS, LCD_ROWS};
+use euclid::{point2, rect};
+use gbrs_core::lcd::GreyShade;
+
+use crate::framebuffer;
+use crate::input;
+use crate::layout::{ROW_BYTES, ROW_STRIDE};
+use crate::rewind::{self, Rewind, Thumbnail};
+use crate::strings::{self, tr, trf, Text};
+
+// The rewind hotkey stops the game and brings this up over it: the frame
+// from the point the playhead's at, twice the size of its thumbnail, and a
+// timeline of the history there is under it. The crank (or left and right)
+// moves the playhead, A carries on from there and B goes back to the
+// present as if nothing happened. Only thumbnails are drawn while it's
+// moving; the state's only restored once A's pressed (see rewind.rs).
+
+// Degrees of crank per state, which is a quarter of a second of game
+const CRANK_STEP: f32 = 30.;
+// The Gameboy's, not ours, since the history's counted in its frames
+const GAMEBOY_FRAME_RATE: f32 = 59.7;
+
+const PREVIEW_BYTES: usize = rewind::THUMBNAIL_ROW_BYTES * 2;
+const PREVIEW_HEIGHT: usize = rewind::THUMBNAIL_HEIGHT * 2;
+const PREVIEW_LEFT_BYTE: usize = (ROW_BYTES - PREVIEW_BYTES) / 2;
+const PREVIEW_TOP: usize = 12;
+
+const TIMELINE_LEFT: i32 = 40;
+const TIMELINE_WIDTH: i32 = LCD_COLUMNS as i32 - TIMELINE_LEFT * 2;
+const TIMELINE_Y: i32 = PREVIEW_TOP as i32 + PREVIEW_HEIGHT as i32 + 14;
+const PLAYHEAD_WIDTH: i32 = 6;
+const PLAYHEAD_HEIGHT: i32 = 14;
+const LABEL_TOP: i32 = TIMELINE_Y + 14;
+
+// Each thumbnail bit as two side by side
+fn doubled (byte: u8) -> [u8; 2] {
+    let mut wide: u16 = 0;
+    for bit in 0..8 {
+        if byte & (0x80 >> bit) != 0 { wide |= 0xC000 >> (bit * 2) }
+    }
+    wide.to_be_bytes()
+}
+
+pub enum ScrubberEvent {
+    GoBackTo(usize),
+    Cancelled
+}
+
+pub struct Scrubber {
+    // States there are, so the present is at this one past the newest
+    history: usize,
+    // The frame that was showing when it opened
+    now: Thumbnail,
+    position: usize,
+    crank: f32
+}
+
+impl Scrubber {
+    // Like the other screens, this draws straight away
+    pub fn new (
+        rewind: &Rewind,
+        finished_frame: &[GreyShade]
+    ) -> Result<Self, Error> {
+        let history = rewind.history();
+        let scrubber = Self {
+            history,
+            now: rewind::thumbnail(finished_frame),
+            position: history,
+            crank: 0.
+        };
+        scrubber.redraw(rewind)?;
+        Ok(scrubber)
+    }
+
+    fn seconds_ago (&self) -> f32 {
+        let frames = (self.history - self.position) * rewind::SNAPSHOT_FRAMES;
+        frames as f32 / GAMEBOY_FRAME_RATE
+    }
+
+    fn redraw (&self, rewind: &Rewind) -> Result<(), Error> {
+        let graphics = Graphics::get();
+        graphics.clear(LCDColor::Solid(LCDSolidColor::kColorBlack))?;
+        self.draw_preview(rewind)?;
+        self.draw_timeline()?;
+
+        graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeInverted)?;
+        let hint = strings::ellipsize(
+            tr(Text::ScrubberHint), TIMELINE_WIDTH
+        );
+        graphics.draw_text(
+            &hint[..], point2(TIMELINE_LEFT, LCD_ROWS as i32 - 24)
+        )?;
+        graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeCopy)?;
+        Ok(())
+    }
+
+    fn draw_preview (&self, rewind: &Rewind) -> Result<(), Error> {
+        let graphics = Graphics::get();
+        let framebuffer = graphics.get_frame()?;
+        let thumbnail = if self.position == self.history {
+            &self.now
+        } else {
+            rewind.thumbnail_at(self.position).unwrap_or(&self.now)
+        };
+
+        let rows = thumbnail.chunks(rewind::THUMBNAIL_ROW_BYTES);
+        for (y, row) in rows.enumerate() {
+            for (byte_x, byte) in row.iter().enumerate() {
+                let wide = doubled(*byte);
+                for (half, out) in wide.iter().enumerate() {
+                    let out_x = PREVIEW_LEFT_BYTE + byte_x * 2 + half;
+                    for dy in 0..2 {
+                        framebuffer::draw_byte_at(
+                            framebuffer,
+                            ROW_STRIDE,
+                            out_x,
+                            PREVIEW_TOP + y * 2 + dy,
+                            *out
+                        );
+                    }
+                }
+            }
+        }
+        let bottom = (PREVIEW_TOP + PREVIEW_HEIGHT - 1) as i32;
+        graphics.mark_updated_rows(PREVIEW_TOP as i32..=bottom)?;
+        Ok(())
+    }
+
+    fn draw_timeline (&self) -> Result<(), Error> {
+        let graphics = Graphics::get();
+        let white = LCDColor::Solid(LCDSolidColor::kColorWhite);
+        let black = LCDColor::Solid(LCDSolidColor::kColorBlack);
+
+        // Cleared first, since only this bit's drawn again as it moves
+        graphics.fill_rect(
+            rect(0, TIMELINE_Y - PLAYHEAD_HEIGHT / 2, LCD_COLUMNS as i32, 44),
+            black
+        )?;
+        graphics.fill_rect(
+            rect(TIMELINE_LEFT, TIMELINE_Y - 1, TIMELINE_WIDTH, 2),
+            white
+        )?;
+        let travel = (TIMELINE_WIDTH - PLAYHEAD_WIDTH) as usize;
+        let x = TIMELINE_LEFT +
+            (travel * self.position / self.history.max(1)) as i32;
+        graphics.fill_rect(
+            rect(
+                x,
+                TIMELINE_Y - PLAYHEAD_HEIGHT / 2,
+                PLAYHEAD_WIDTH,
+                PLAYHEAD_HEIGHT
+            ),
+            white
+        )?;
+
+        let label = if self.position == self.history {
+            String::from(tr(Text::RewindNow))
+        } else {
+            trf(Text::RewindAgo, &[&format!("{:.1}", self.seconds_ago())])
+        };
+        graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeInverted)?;
+        graphics.draw_text(&label[..], point2(TIMELINE_LEFT, LABEL_TOP))?;
+        graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeCopy)?;
+        Ok(())
+    }
+
+    fn step (&mut self, steps: i32) -> bool {
+        let position = (self.position as i32 + steps)
+            .max(0)
+            .min(self.history as i32) as usize;
+        let moved = position != self.position;
+        self.position = position;
+        moved
+    }
+
+    // Says where to go once A or B is pressed
+    pub fn update (
+        &mut self,
+        rewind: &Rewind
+    ) -> Result<Option<ScrubberEvent>, Error> {
+        let system = System::get();
+        let (_, btns_down, _) = input::button_state()?;
+        let pressed = |button: PDButtons| (btns_down & button) == button;
+
+        if pressed(PDButtons::kButtonB) {
+            return Ok(Some(ScrubberEvent::Cancelled))
+        }
+        if pressed(PDButtons::kButtonA) {
+            return Ok(Some(if self.position == self.history {
+                ScrubberEvent::Cancelled
+            } else {
+                ScrubberEvent::GoBackTo(self.position)
+            }))
+        }
+
+        let mut steps = 0;
+        if pressed(PDButtons::kButtonLeft) { steps -= 1 }
+        if pressed(PDButtons::kButtonRight) { steps += 1 }
+        if !system.is_crank_docked()? {
+            self.crank += system.get_crank_change()?;
+            let crank_steps = (self.crank / CRANK_STEP) as i32;
+            self.crank -= crank_steps as f32 * CRANK_STEP;
+            steps += crank_steps;
+        }
+
+        if steps != 0 && self.step(steps) {
+            self.draw_preview(rewind)?;
+            self.draw_timeline()?;
+        }
+        Ok(None)
+    }
+}
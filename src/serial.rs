@@ -0,0 +1,105 @@
+use alloc::string::String;
+use anyhow::{anyhow, Error};
+use gbrs_core::cpu::Cpu;
+
+use crate::printer::{Printer, PRINT_WIDTH};
+use crate::screenshot;
+use crate::storage;
+
+// What's on the other end of the link cable. gbrs leaves a transfer the
+// game starts hanging forever, so some games stall on one (checking for a
+// printer, or a partner in a link game). We finish each one for it, with
+// the byte whatever's plugged in sends back: 0xff from nothing at all, or
+// an answer from the Game Boy Printer.
+//
+// It's only looked at once per emulated frame, so a transfer finishes at
+// the end of the frame it was started in. A real one takes about a
+// millisecond, so that's late, but games wait on the interrupt or the
+// start bit rather than counting. It does make printing slow, at a byte
+// a frame - a screen's worth is well over a minute of game time.
+// Finishing on time needs a serial hook in gbrs.
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SerialMode {
+    Disconnected,
+    Printer
+}
+
+const MODES: &[SerialMode] = &[SerialMode::Disconnected, SerialMode::Printer];
+
+impl SerialMode {
+    // For the serial setting
+    pub fn name (self) -> &'static str {
+        match self {
+            SerialMode::Disconnected => "disconnected",
+            SerialMode::Printer => "printer"
+        }
+    }
+
+    pub fn from_name (name: &str) -> Option<Self> {
+        MODES.iter().copied().find(|mode| mode.name() == name)
+    }
+}
+
+const SERIAL_DATA: u16 = 0xFF01;
+const SERIAL_CONTROL: u16 = 0xFF02;
+// A transfer's going, and the Gameboy is the one clocking it. With the
+// other end clocking, nothing happens until it does, which is what a real
+// Gameboy with nothing plugged in does too.
+const TRANSFER_START: u8 = 0x80;
+const INTERNAL_CLOCK: u8 = 0x01;
+const INTERRUPT_FLAGS: u16 = 0xFF0F;
+const SERIAL_INTERRUPT: u8 = 1 << 3;
+
+const PRINT_PREFIX: &str = "print_";
+
+pub struct Serial {
+    printer: Option<Printer>
+}
+
+impl Serial {
+    pub fn new (mode: SerialMode) -> Self {
+        Self {
+            printer: match mode {
+                SerialMode::Disconnected => None,
+                SerialMode::Printer => Some(Printer::new())
+            }
+        }
+    }
+
+    // Call after each emulated frame. Returns where a finished print went,
+    // or why it couldn't be written.
+    pub fn update (&mut self, cpu: &mut Cpu) -> Option<Result<String, Error>> {
+        let control = cpu.mem.read(SERIAL_CONTROL);
+        let started = TRANSFER_START | INTERNAL_CLOCK;
+        if control & started != started { return None }
+
+        let sent = cpu.mem.read(SERIAL_DATA);
+        let received = match &mut self.printer {
+            Some(printer) => printer.exchange(sent),
+            None => 0xFF
+        };
+        cpu.mem.write(SERIAL_DATA, received);
+        cpu.mem.write(SERIAL_CONTROL, control & !TRANSFER_START);
+        let flags = cpu.mem.read(INTERRUPT_FLAGS);
+        cpu.mem.write(INTERRUPT_FLAGS, flags | SERIAL_INTERRUPT);
+
+        let printout = self.printer.as_mut()?.take_printout()?;
+        Some(save_print(printout.height, &printout.levels[..]))
+    }
+}
+
+pub fn is_print (filename: &str) -> bool {
+    filename.starts_with(PRINT_PREFIX) &&
+        filename.ends_with(screenshot::SHOT_EXTENSION)
+}
+
+// Prints go in the prints folder as print_0001.pgm and so on, the same
+// format as screenshots but as tall as the print
+fn save_print (height: usize, levels: &[u8]) -> Result<String, Error> {
+    let path = screenshot::next_numbered_path(storage::PRINTS_DIR, PRINT_PREFIX)
+        .ok_or_else(|| anyhow!("out of print names"))?;
+    let image = screenshot::encode_image(PRINT_WIDTH, height, levels);
+    storage::write_file(&path[..], &image[..])?;
+    Ok(path)
+}
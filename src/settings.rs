@@ -0,0 +1,603 @@
+use alloc::{boxed::Box, format, string::String, vec::Vec};
+
+use crate::border::BorderTheme;
+use crate::button_map::ButtonMap;
+use crate::dither::Dither;
+use crate::display_mode::DisplayMode;
+use crate::hotkeys::{self, Hotkey};
+use crate::mash::MashAxis;
+use crate::minimap::Corner;
+use crate::model::Model;
+use crate::quick_menu::{self, QuickAction, QUICK_ACTIONS};
+use crate::serial::SerialMode;
+use crate::start_select::StartSelectMode;
+use crate::storage;
+use crate::strings;
+
+// Settings live in a plain key=value text file in the data folder. That's
+// easy to edit by hand over USB and saves us pulling in a serialisation
+// crate on no_std.
+pub const SETTINGS_PATH: &str = "playboy.cfg";
+
+// A game can have its own {game}.cfg in the same format. Anything set in
+// there wins over playboy.cfg for that game, and while it's running,
+// changing one of those settings saves it back there rather than to
+// playboy.cfg. Turning "per-game picture & controls" on in the options
+// menu adds GAME_KEYS to the file.
+pub fn game_settings_path (game_name: &str) -> String {
+    format!("{}.cfg", game_name)
+}
+
+// The settings that most often want to be different from one game to the
+// next
+const GAME_KEYS: &[&str] = &[
+    "display_mode",
+    "dither",
+    "frame_blend",
+    "start_select",
+    "flick_threshold",
+    "crank_dead_zone",
+    "turbo_a",
+    "turbo_b",
+    "turbo_period",
+    "fast_forward_speed"
+];
+
+// The running game's part in the settings
+#[derive(Clone)]
+struct GameOverrides {
+    game_name: String,
+    // Everything its .cfg sets, and whether there's a .cfg to keep up to date
+    keys: Vec<String>,
+    has_file: bool,
+    // What playboy.cfg had for those
+    global: Box<Settings>
+}
+
+fn line_key (line: &str) -> &str {
+    line.split('=').next().unwrap_or(line)
+}
+
+#[derive(Clone)]
+pub struct Settings {
+    pub border: BorderTheme,
+    // Which Gameboy we tell games they're running on
+    pub model: Model,
+    // A language code with a file in strings::LANG_DIR, or "auto" to follow
+    // the system
+    pub language: String,
+    pub invert: bool,
+    // How big the game is drawn
+    pub display_mode: DisplayMode,
+    // Switch to and from sideways by how the Playdate's held (see
+    // orientation.rs)
+    pub auto_orientation: bool,
+    // How the greys are drawn (see dither.rs)
+    pub dither: Dither,
+    // Run the display faster than the game and alternate the grey patterns
+    // on every refresh, for smoother greys at the cost of battery
+    pub temporal_dither: bool,
+    // Show each frame mixed with the one before, like the original LCD's
+    // ghosting, so moving sprites don't shimmer (see blit.rs)
+    pub frame_blend: bool,
+    // Zoom in on games that only draw into part of their screen
+    pub auto_zoom: bool,
+    // Where the whole frame goes while auto zoom is zoomed in, if anywhere
+    // (see minimap.rs)
+    pub minimap: Corner,
+    // Lighten or darken the picture when a frame is nearly all dark (or
+    // light) shades
+    pub adaptive_contrast: bool,
+    // Invert automatically between these hours (local time, 0-23). The
+    // window is allowed to wrap past midnight.
+    pub night_mode: bool,
+    pub night_start: u8,
+    pub night_end: u8,
+    // Write the in-RAM quick save slot and undo state out when leaving a
+    // game, and read them back next time
+    pub quick_slot_to_disk: bool,
+    // Load save states even when they were made with a different ROM. This
+    // is for ROM hack developers rebuilding the same game over and over, so
+    // it's deliberately left out of the options menu - set it in the file.
+    pub force_state_loads: bool,
+    // Whether loading a state restarts the split timer's run. Otherwise the
+    // run carries on but won't count as a personal best.
+    pub splits_reset_on_load: bool,
+    // What's on the radial quick menu, in order clockwise from the top
+    pub quick_menu: Vec<QuickAction>,
+    // Chords for emulator actions, checked before the game gets the buttons
+    pub hotkeys: Vec<Hotkey>,
+    // Which Playdate buttons (or crank) press which Gameboy buttons, from
+    // map_a ... map_select (only in the file, see button_map.rs)
+    pub button_map: ButtonMap,
+    // What the hold_mash hotkey alternates, and how many presses of each
+    // direction it makes a second
+    pub mash_axis: MashAxis,
+    pub mash_rate: usize,
+    // Holding A or B presses it over and over, once every turbo_period
+    // emulated frames (see turbo.rs)
+    pub turbo_a: bool,
+    pub turbo_b: bool,
+    pub turbo_period: usize,
+    // Where Start and Select come from (see start_select.rs), how many
+    // degrees in one frame count as a flick for crank_flick, and how few
+    // count as the crank not moving at all
+    pub start_select: StartSelectMode,
+    pub flick_threshold: usize,
+    pub crank_dead_zone: usize,
+    // Spinning the crank fast forwards, and how many frames fast forward
+    // runs per update (only in the file)
+    pub crank_fast_forward: bool,
+    pub fast_forward_speed: usize,
+    // Whether the codes in {game}.cheats are used
+    pub cheats: bool,
+    // Memory kept for rewind's history, 0 for none (only in the file)
+    pub rewind_kb: usize,
+    // What's plugged into the link port (see serial.rs). Meant for a
+    // game's own .cfg, since only games with printer support want a
+    // printer.
+    pub serial: SerialMode,
+    // The crank steers a virtual paddle instead of pressing Start and
+    // Select (see paddle.rs). Meant for a game's own .cfg, along with how
+    // many pixels a turn of the crank moves it and how close counts as
+    // there, since those want tuning per game.
+    pub crank_paddle: bool,
+    pub paddle_gain: usize,
+    pub paddle_deadband: usize,
+    // None follows the system's upside-down setting. Only in the file, for
+    // the odd case where following it is wrong.
+    pub upside_down: Option<bool>,
+    // Frame timings in the left border (see frame_stats.rs)
+    pub perf_overlay: bool,
+    // None outside of a game
+    game: Option<GameOverrides>
+}
+
+pub fn bool_name (value: bool) -> &'static str {
+    if value { "on" } else { "off" }
+}
+
+fn parse_bool (value: &str) -> Option<bool> {
+    match value {
+        "on" => Some(true),
+        "off" => Some(false),
+        _ => None
+    }
+}
+
+fn parse_hour (value: &str) -> Option<u8> {
+    value.parse::<u8>().ok().filter(|hour| *hour < 24)
+}
+
+impl Settings {
+    pub fn defaults () -> Self {
+        Self {
+            border: BorderTheme::Plain,
+            model: Model::Dmg,
+            language: String::from("auto"),
+            invert: false,
+            display_mode: DisplayMode::Stretched,
+            auto_orientation: false,
+            dither: Dither::CrossHatch,
+            temporal_dither: false,
+            frame_blend: false,
+            auto_zoom: false,
+            minimap: Corner::Off,
+            adaptive_contrast: false,
+            night_mode: false,
+            night_start: 22,
+            night_end: 7,
+            quick_slot_to_disk: false,
+            force_state_loads: false,
+            splits_reset_on_load: false,
+            quick_menu: Vec::from(QUICK_ACTIONS),
+            hotkeys: Vec::new(),
+            button_map: ButtonMap::new(),
+            mash_axis: MashAxis::Horizontal,
+            mash_rate: 10,
+            turbo_a: false,
+            turbo_b: false,
+            turbo_period: 2,
+            start_select: StartSelectMode::CrankTurn,
+            flick_threshold: 20,
+            crank_dead_zone: 2,
+            crank_fast_forward: false,
+            fast_forward_speed: 3,
+            cheats: true,
+            rewind_kb: 2048,
+            serial: SerialMode::Disconnected,
+            crank_paddle: false,
+            paddle_gain: 160,
+            paddle_deadband: 2,
+            upside_down: None,
+            perf_overlay: false,
+            game: None
+        }
+    }
+
+    // Anything we don't understand is logged and skipped, so a damaged file
+    // can only ever cost you the settings in it, never a crash at boot.
+    fn apply (&mut self, key: &str, value: &str) -> bool {
+        let understood = match key {
+            "border" => BorderTheme::from_name(value)
+                .map(|theme| self.border = theme)
+                .is_some(),
+            "model" => Model::from_name(value)
+                .map(|model| self.model = model)
+                .is_some(),
+            "language" => {
+                self.language = String::from(value);
+                true
+            },
+            "invert" => parse_bool(value)
+                .map(|invert| self.invert = invert)
+                .is_some(),
+            "display_mode" => DisplayMode::from_name(value)
+                .map(|mode| self.display_mode = mode)
+                .is_some(),
+            "auto_orientation" => parse_bool(value)
+                .map(|auto| self.auto_orientation = auto)
+                .is_some(),
+            "dither" => Dither::from_name(value)
+                .map(|dither| self.dither = dither)
+                .is_some(),
+            "temporal_dither" => parse_bool(value)
+                .map(|temporal| self.temporal_dither = temporal)
+                .is_some(),
+            "frame_blend" => parse_bool(value)
+                .map(|blend| self.frame_blend = blend)
+                .is_some(),
+            "auto_zoom" => parse_bool(value)
+                .map(|auto_zoom| self.auto_zoom = auto_zoom)
+                .is_some(),
+            "minimap" => Corner::from_name(value)
+                .map(|corner| self.minimap = corner)
+                .is_some(),
+            "adaptive_contrast" => parse_bool(value)
+                .map(|adaptive| self.adaptive_contrast = adaptive)
+                .is_some(),
+            "night_mode" => parse_bool(value)
+                .map(|night_mode| self.night_mode = night_mode)
+                .is_some(),
+            "night_start" => parse_hour(value)
+                .map(|hour| self.night_start = hour)
+                .is_some(),
+            "night_end" => parse_hour(value)
+                .map(|hour| self.night_end = hour)
+                .is_some(),
+            "quick_slot_to_disk" => parse_bool(value)
+                .map(|to_disk| self.quick_slot_to_disk = to_disk)
+                .is_some(),
+            "force_state_loads" => parse_bool(value)
+                .map(|force| self.force_state_loads = force)
+                .is_some(),
+            "splits_reset_on_load" => parse_bool(value)
+                .map(|reset| self.splits_reset_on_load = reset)
+                .is_some(),
+            "quick_menu" => quick_menu::parse_actions(value)
+                .map(|actions| self.quick_menu = actions)
+                .is_some(),
+            "hotkeys" => hotkeys::parse_hotkeys(value)
+                .map(|hotkeys| self.hotkeys = hotkeys)
+                .is_some(),
+            "upside_down" => match value {
+                "auto" => {
+                    self.upside_down = None;
+                    true
+                },
+                _ => parse_bool(value)
+                    .map(|flipped| self.upside_down = Some(flipped))
+                    .is_some()
+            },
+            "mash_axis" => MashAxis::from_name(value)
+                .map(|axis| self.mash_axis = axis)
+                .is_some(),
+            "mash_rate" => value
+                .parse::<usize>()
+                .ok()
+                .filter(|rate| (1..=30).contains(rate))
+                .map(|rate| self.mash_rate = rate)
+                .is_some(),
+            "turbo_a" => parse_bool(value)
+                .map(|turbo| self.turbo_a = turbo)
+                .is_some(),
+            "turbo_b" => parse_bool(value)
+                .map(|turbo| self.turbo_b = turbo)
+                .is_some(),
+            "turbo_period" => value
+                .parse::<usize>()
+                .ok()
+                .filter(|frames| (2..=30).contains(frames))
+                .map(|frames| self.turbo_period = frames)
+                .is_some(),
+            "start_select" => StartSelectMode::from_name(value)
+                .map(|mode| self.start_select = mode)
+                .is_some(),
+            "flick_threshold" => value
+                .parse::<usize>()
+                .ok()
+                .filter(|degrees| (5..=90).contains(degrees))
+                .map(|degrees| self.flick_threshold = degrees)
+                .is_some(),
+            "crank_dead_zone" => value
+                .parse::<usize>()
+                .ok()
+                .filter(|degrees| *degrees <= 45)
+                .map(|degrees| self.crank_dead_zone = degrees)
+                .is_some(),
+            "crank_fast_forward" => parse_bool(value)
+                .map(|spin| self.crank_fast_forward = spin)
+                .is_some(),
+            "cheats" => parse_bool(value)
+                .map(|cheats| self.cheats = cheats)
+                .is_some(),
+            "rewind_kb" => value
+                .parse::<usize>()
+                .ok()
+                .filter(|kb| *kb <= 8192)
+                .map(|kb| self.rewind_kb = kb)
+                .is_some(),
+            "serial" => SerialMode::from_name(value)
+                .map(|mode| self.serial = mode)
+                .is_some(),
+            "fast_forward_speed" => value
+                .parse::<usize>()
+                .ok()
+                .filter(|speed| (2..=4).contains(speed))
+                .map(|speed| self.fast_forward_speed = speed)
+                .is_some(),
+            "crank_paddle" => parse_bool(value)
+                .map(|paddle| self.crank_paddle = paddle)
+                .is_some(),
+            "paddle_gain" => value
+                .parse::<usize>()
+                .ok()
+                .filter(|gain| (10..=1000).contains(gain))
+                .map(|gain| self.paddle_gain = gain)
+                .is_some(),
+            "paddle_deadband" => value
+                .parse::<usize>()
+                .ok()
+                .filter(|deadband| *deadband <= 16)
+                .map(|deadband| self.paddle_deadband = deadband)
+                .is_some(),
+            "perf_overlay" => parse_bool(value)
+                .map(|overlay| self.perf_overlay = overlay)
+                .is_some(),
+            key if ButtonMap::is_key(key) => self.button_map.set(key, value),
+            _ => false
+        };
+
+        if !understood {
+            log!("Ignoring setting {}={}", key, value);
+        }
+        understood
+    }
+
+    pub fn parse (text: &str) -> Self {
+        let mut settings = Self::defaults();
+        settings.apply_text(text);
+        settings
+    }
+
+    // Returns the keys it set
+    fn apply_text (&mut self, text: &str) -> Vec<String> {
+        let mut keys = Vec::new();
+        for line in text.lines() {
+            let line = line.trim();
+            if line.len() == 0 || line.starts_with('#') { continue }
+
+            match line.find('=') {
+                Some(split) => {
+                    let key = line[..split].trim();
+                    if self.apply(key, line[split + 1..].trim()) {
+                        keys.push(String::from(key));
+                    }
+                },
+                None => log!("Ignoring settings line \"{}\"", line)
+            }
+        }
+        keys
+    }
+
+    pub fn to_text (&self) -> String {
+        let mut text = String::new();
+        let mut set = |key: &str, value: &str| {
+            text.push_str(&format!("{}={}\n", key, value));
+        };
+
+        set("border", self.border.name());
+        set("model", self.model.name());
+        set("language", &self.language[..]);
+        set("invert", bool_name(self.invert));
+        set("display_mode", self.display_mode.name());
+        set("auto_orientation", bool_name(self.auto_orientation));
+        set("dither", self.dither.name());
+        set("temporal_dither", bool_name(self.temporal_dither));
+        set("frame_blend", bool_name(self.frame_blend));
+        set("auto_zoom", bool_name(self.auto_zoom));
+        set("minimap", self.minimap.name());
+        set("adaptive_contrast", bool_name(self.adaptive_contrast));
+        set("night_mode", bool_name(self.night_mode));
+        set("night_start", &format!("{}", self.night_start));
+        set("night_end", &format!("{}", self.night_end));
+        set("quick_slot_to_disk", bool_name(self.quick_slot_to_disk));
+        set("force_state_loads", bool_name(self.force_state_loads));
+        set("splits_reset_on_load", bool_name(self.splits_reset_on_load));
+        set("quick_menu", &quick_menu::actions_text(&self.quick_menu[..]));
+        set("hotkeys", &hotkeys::hotkeys_text(&self.hotkeys[..]));
+        for (key, value) in self.button_map.entries() {
+            set(key, &value[..]);
+        }
+        set("upside_down", match self.upside_down {
+            Some(flipped) => bool_name(flipped),
+            None => "auto"
+        });
+        set("mash_axis", self.mash_axis.name());
+        set("mash_rate", &format!("{}", self.mash_rate));
+        set("turbo_a", bool_name(self.turbo_a));
+        set("turbo_b", bool_name(self.turbo_b));
+        set("turbo_period", &format!("{}", self.turbo_period));
+        set("start_select", self.start_select.name());
+        set("flick_threshold", &format!("{}", self.flick_threshold));
+        set("crank_dead_zone", &format!("{}", self.crank_dead_zone));
+        set("crank_fast_forward", bool_name(self.crank_fast_forward));
+        set("fast_forward_speed", &format!("{}", self.fast_forward_speed));
+        set("cheats", bool_name(self.cheats));
+        set("rewind_kb", &format!("{}", self.rewind_kb));
+        set("serial", self.serial.name());
+        set("crank_paddle", bool_name(self.crank_paddle));
+        set("paddle_gain", &format!("{}", self.paddle_gain));
+        set("paddle_deadband", &format!("{}", self.paddle_deadband));
+        set("perf_overlay", bool_name(self.perf_overlay));
+
+        text
+    }
+
+    pub fn load () -> Self {
+        let bytes = match storage::read_file(SETTINGS_PATH) {
+            Ok(bytes) => bytes,
+            // No settings file yet, which is perfectly normal
+            Err(_) => return Self::defaults()
+        };
+
+        match core::str::from_utf8(&bytes[..]) {
+            Ok(text) => Self::parse(text),
+            Err(_) => {
+                log!("{} isn't valid text, using defaults", SETTINGS_PATH);
+                Self::defaults()
+            }
+        }
+    }
+
+    // The language code to actually use
+    pub fn language_code (&self) -> &str {
+        if self.language == "auto" {
+            strings::system_language()
+        } else {
+            &self.language[..]
+        }
+    }
+
+    // These settings with the game's own overrides on top, if it has any,
+    // until global is called
+    pub fn for_game (&self, game_name: &str) -> Self {
+        let global = self.global();
+        let mut settings = global.clone();
+        let path = &game_settings_path(game_name)[..];
+
+        let mut keys = Vec::new();
+        let mut has_file = false;
+        if let Ok(bytes) = storage::read_file(path) {
+            match core::str::from_utf8(&bytes[..]) {
+                Ok(text) => {
+                    keys = settings.apply_text(text);
+                    has_file = true;
+                },
+                // Left alone, rather than overwritten with nothing
+                Err(_) => log!("{} isn't valid text, ignoring it", path)
+            }
+        }
+        keys.sort();
+        keys.dedup();
+        settings.game = Some(GameOverrides {
+            game_name: String::from(game_name),
+            keys,
+            has_file,
+            global: Box::new(global)
+        });
+        settings
+    }
+
+    // What playboy.cfg says, without the game's overrides
+    pub fn global (&self) -> Self {
+        match &self.game {
+            Some(_) => Self::parse(&self.split_text().0[..]),
+            None => self.clone()
+        }
+    }
+
+    // Whether GAME_KEYS are being kept for the running game
+    pub fn per_game (&self) -> bool {
+        match &self.game {
+            Some(game) => GAME_KEYS
+                .iter()
+                .any(|key| game.keys.iter().any(|own| own == key)),
+            None => false
+        }
+    }
+
+    // Starts or stops keeping GAME_KEYS for the running game. Stopping puts
+    // them back to what playboy.cfg has.
+    pub fn set_per_game (&mut self, per_game: bool) {
+        // Anything changed since the game started is in here too
+        let global = self.global();
+        let game = match &mut self.game {
+            Some(game) => game,
+            None => return
+        };
+
+        let is_game_key = |key: &str| GAME_KEYS.contains(&key);
+        game.keys.retain(|key| !is_game_key(&key[..]));
+        game.has_file = true;
+        if per_game {
+            game.keys.extend(GAME_KEYS.iter().map(|key| String::from(*key)));
+        }
+        game.global = Box::new(global);
+
+        if !per_game {
+            let global_text = game.global.to_text();
+            let lines = global_text
+                .lines()
+                .filter(|line| is_game_key(line_key(line)));
+            for line in lines {
+                let split = line.find('=').unwrap();
+                self.apply(&line[..split], &line[split + 1..]);
+            }
+        }
+    }
+
+    // playboy.cfg's text, and the game's if there's a game. Each setting
+    // goes back to whichever file it came from.
+    fn split_text (&self) -> (String, Option<String>) {
+        let game = match &self.game {
+            Some(game) => game,
+            None => return (self.to_text(), None)
+        };
+
+        let mut global = String::new();
+        let mut own = String::new();
+        // to_text always writes the same keys in the same order
+        let lines = self.to_text();
+        let global_lines = game.global.to_text();
+        for (line, global_line) in lines.lines().zip(global_lines.lines()) {
+            if game.keys.iter().any(|key| key == line_key(line)) {
+                own.push_str(line);
+                own.push('\n');
+                global.push_str(global_line);
+            } else {
+                global.push_str(line);
+            }
+            global.push('\n');
+        }
+        (global, Some(own))
+    }
+
+    // Call this when a setting changes, not every frame
+    pub fn save (&self) {
+        let (global, own) = self.split_text();
+        if let Err(e) = storage::write_file(SETTINGS_PATH, global.as_bytes()) {
+            log!("Couldn't save settings: {}", e);
+        }
+
+        // Anything the game's file had that isn't a setting is lost, but
+        // then so is playboy.cfg's
+        let game = self.game.as_ref().filter(|game| game.has_file);
+        if let (Some(own), Some(game)) = (own, game) {
+            let path = game_settings_path(&game.game_name[..]);
+            if let Err(e) = storage::write_file(&path[..], own.as_bytes()) {
+                log!("Couldn't save {}: {}", path, e);
+            }
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use alloc::string::String;
+use anyhow::Error;
+use crankstart::graphics::{Graphics, LCDColor, LCDSolidColor};
+use crankstart_sys::{LCDBitmapDrawMode, LCD_COLUMNS, LCD_ROWS};
+use euclid::point2;
+
+use crate::header;
+use crate::strings::{self, trf, Text};
+
+// "Loading POKEMON RED - MBC3+RAM+BATTERY" for a moment before a game
+// starts, so picking one doesn't just go from the list to black to the
+// game. The game isn't run until it's gone.
+pub struct BootSplash {
+    text: String,
+    frames_left: usize
+}
+
+impl BootSplash {
+    pub fn new (rom: &[u8], frames: usize) -> Result<Self, Error> {
+        let cart = header::cart_type(rom);
+        let text = trf(Text::BootSplash, &[
+            &header::title(rom),
+            &header::cart_name(cart).unwrap_or("?")
+        ]);
+        let splash = Self {
+            text: strings::ellipsize(&text[..], LCD_COLUMNS as i32 - 12),
+            frames_left: frames
+        };
+        splash.redraw()?;
+        Ok(splash)
+    }
+
+    pub fn redraw (&self) -> Result<(), Error> {
+        let graphics = Graphics::get();
+        graphics.clear(LCDColor::Solid(LCDSolidColor::kColorBlack))?;
+        graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeInverted)?;
+        graphics.draw_text(&self.text[..], point2(6, LCD_ROWS as i32 / 2 - 8))?;
+        Ok(())
+    }
+
+    // Returns true once it's been up long enough. The caller puts the
+    // screen back.
+    pub fn update (&mut self) -> bool {
+        self.frames_left = self.frames_left.saturating_sub(1);
+        self.frames_left == 0
+    }
+}
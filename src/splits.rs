@@ -0,0 +1,296 @@
+use alloc::{format, string::String, vec, vec::Vec};
+use anyhow::{anyhow, Error};
+use core::fmt::Write;
+use crankstart::graphics::{Graphics, LCDColor, LCDSolidColor};
+use crankstart_sys::{LCDBitmapDrawMode, LCD_COLUMNS};
+use euclid::{point2, rect};
+use gbrs_core::cpu::Cpu;
+
+use crate::layout::GAME_RIGHT;
+use crate::line::Line;
+use crate::storage;
+use crate::strings::{tr, Text};
+
+// A speedrun split timer. {game}.splits.txt lists the splits in order, one
+// per line, as
+//
+//   <address> <comparison> <value> <label>
+//
+// with the address and value in hex, eg. "D35E == 01 Brock". Only the next
+// split's condition is checked after each frame, so it's one memory read
+// and a compare.
+//
+// The clock counts emulated time, so slowdown on the Playdate doesn't count
+// against you. Finished runs are written to {game}.run.txt, and the best
+// one to {game}.pb.txt, in the same "<frames> <label>" format.
+
+pub fn splits_path (game_name: &str) -> String {
+    format!("{}.splits.txt", game_name)
+}
+
+fn run_path (game_name: &str) -> String {
+    format!("{}.run.txt", game_name)
+}
+
+fn pb_path (game_name: &str) -> String {
+    format!("{}.pb.txt", game_name)
+}
+
+#[derive(Clone, Copy)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual
+}
+
+impl Comparison {
+    fn from_str (text: &str) -> Option<Self> {
+        match text {
+            "==" => Some(Comparison::Equal),
+            "!=" => Some(Comparison::NotEqual),
+            "<" => Some(Comparison::Less),
+            "<=" => Some(Comparison::LessOrEqual),
+            ">" => Some(Comparison::Greater),
+            ">=" => Some(Comparison::GreaterOrEqual),
+            _ => None
+        }
+    }
+
+    fn holds (&self, left: u8, right: u8) -> bool {
+        match self {
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Greater => left > right,
+            Comparison::GreaterOrEqual => left >= right
+        }
+    }
+}
+
+struct Split {
+    address: u16,
+    comparison: Comparison,
+    value: u8,
+    label: String
+}
+
+// Takes the next whitespace-separated field off the front of text
+fn take_field<'a> (text: &mut &'a str) -> Result<&'a str, Error> {
+    let trimmed = text.trim_start();
+    let end = trimmed.find(char::is_whitespace).unwrap_or(trimmed.len());
+    if end == 0 { return Err(anyhow!("too few fields")) }
+
+    *text = &trimmed[end..];
+    Ok(&trimmed[..end])
+}
+
+fn parse_split (line: &str) -> Result<Split, Error> {
+    let mut rest = line;
+
+    let address = u16::from_str_radix(take_field(&mut rest)?, 16)
+        .map_err(|_| anyhow!("bad address"))?;
+    let comparison = Comparison::from_str(take_field(&mut rest)?)
+        .ok_or_else(|| anyhow!("bad comparison"))?;
+    let value = u8::from_str_radix(take_field(&mut rest)?, 16)
+        .map_err(|_| anyhow!("bad value"))?;
+    // The label is everything after, spaces and all
+    let label = String::from(rest.trim());
+    if label.len() == 0 { return Err(anyhow!("missing label")) }
+
+    Ok(Split { address, comparison, value, label })
+}
+
+// Reads the frame counts out of a run/PB file
+fn parse_times (text: &str) -> Vec<usize> {
+    text.lines()
+        .filter_map(|line| line.split(' ').next())
+        .filter_map(|frames| frames.parse::<usize>().ok())
+        .collect()
+}
+
+// "1:23.4". Drawn every frame, so it's written into a Line rather than a
+// String.
+fn format_time (frames: usize, frame_rate: usize) -> Line {
+    let tenths = frames * 10 / frame_rate;
+    let mut line = Line::new();
+    let _ = write!(
+        line, "{}:{:02}.{}", tenths / 600, tenths / 10 % 60, tenths % 10
+    );
+    line
+}
+
+// "+1.2" / "-0.8"
+fn format_delta (frames: isize, frame_rate: usize) -> Line {
+    let tenths = frames.abs() as usize * 10 / frame_rate;
+    let mut line = Line::new();
+    let _ = write!(
+        line,
+        "{}{}.{}",
+        if frames < 0 { "-" } else { "+" },
+        tenths / 10,
+        tenths % 10
+    );
+    line
+}
+
+pub struct SplitTimer {
+    game_name: String,
+    splits: Vec<Split>,
+    // Cumulative frame counts at each split of the personal best
+    pb: Vec<usize>,
+    // Cumulative frame counts at each split done so far this run
+    times: Vec<usize>,
+    frames: usize,
+    // A run stops counting towards the PB once a save state is loaded into
+    // it (unless that resets it instead)
+    valid: bool,
+    // UndoSlot::loads as of last frame, so we can tell when a load happens
+    loads_seen: usize,
+    // Against the PB if there is one, otherwise the length of the segment
+    last_delta: Option<isize>
+}
+
+impl SplitTimer {
+    // None if the game doesn't have a splits file
+    pub fn load (game_name: &str) -> Option<Self> {
+        let bytes = storage::read_file(&splits_path(game_name)[..]).ok()?;
+        let text = core::str::from_utf8(&bytes[..]).ok()?;
+
+        let mut splits = vec![];
+        for line in text.lines() {
+            let line = line.trim();
+            if line.len() == 0 || line.starts_with('#') { continue }
+            match parse_split(line) {
+                Ok(split) => splits.push(split),
+                Err(e) => log!("Ignoring split \"{}\": {}", line, e)
+            }
+        }
+        if splits.len() == 0 { return None }
+
+        let pb = storage::read_file(&pb_path(game_name)[..])
+            .ok()
+            .and_then(|bytes| {
+                core::str::from_utf8(&bytes[..]).ok().map(parse_times)
+            })
+            // A PB from a different set of splits can't be compared with
+            .filter(|pb| pb.len() == splits.len())
+            .unwrap_or_default();
+
+        Some(Self {
+            game_name: String::from(game_name),
+            splits,
+            pb,
+            times: vec![],
+            frames: 0,
+            valid: true,
+            // The undo slot starts afresh with each game, same as us
+            loads_seen: 0,
+            last_delta: None
+        })
+    }
+
+    fn finished (&self) -> bool {
+        self.times.len() == self.splits.len()
+    }
+
+    fn reset (&mut self) {
+        self.times.clear();
+        self.frames = 0;
+        self.valid = true;
+        self.last_delta = None;
+    }
+
+
+    fn write_results (&self) -> Result<bool, Error> {
+        let mut text = String::new();
+        for (frames, split) in self.times.iter().zip(self.splits.iter()) {
+            text.push_str(&format!("{} {}\n", frames, split.label));
+        }
+        storage::write_file(
+            &run_path(&self.game_name[..])[..], text.as_bytes()
+        )?;
+
+        let total = self.frames;
+        let is_pb = self.pb.last().map_or(true, |pb_total| total < *pb_total);
+        if is_pb {
+            storage::write_file(
+                &pb_path(&self.game_name[..])[..], text.as_bytes()
+            )?;
+        }
+        Ok(is_pb)
+    }
+
+    // Call after each emulated frame. loads is UndoSlot::loads, and
+    // reset_on_load the splits_reset_on_load setting. Returns a message when
+    // the run ends.
+    pub fn update (
+        &mut self,
+        cpu: &Cpu,
+        loads: usize,
+        reset_on_load: bool
+    ) -> Option<&'static str> {
+        if loads != self.loads_seen {
+            self.loads_seen = loads;
+            if reset_on_load {
+                self.reset();
+            } else {
+                self.valid = false;
+            }
+        }
+
+        if self.finished() { return None }
+        self.frames += 1;
+
+        let split = &self.splits[self.times.len()];
+        let value = cpu.mem.read(split.address);
+        if !split.comparison.holds(value, split.value) { return None }
+
+        let index = self.times.len();
+        let previous = if index == 0 { 0 } else { self.times[index - 1] };
+        self.last_delta = Some(match self.pb.get(index) {
+            Some(pb) => self.frames as isize - *pb as isize,
+            None => (self.frames - previous) as isize
+        });
+        self.times.push(self.frames);
+
+        if !self.finished() { return None }
+        if !self.valid { return Some(tr(Text::RunNotCounted)) }
+
+        match self.write_results() {
+            Ok(true) => Some(tr(Text::RunFinishedPb)),
+            Ok(false) => Some(tr(Text::RunFinished)),
+            Err(e) => {
+                log!("Couldn't save the run: {}", e);
+                Some(tr(Text::RunNotSaved))
+            }
+        }
+    }
+
+    // Lives in the right-hand border, out of the way of the game. Like the
+    // toast, call this after the blit.
+    pub fn draw (&self, frame_rate: usize) -> Result<(), Error> {
+        let graphics = Graphics::get();
+        let left = GAME_RIGHT as i32;
+        let width = LCD_COLUMNS as i32 - left;
+
+        graphics.fill_rect(
+            rect(left, 28, width, 44),
+            LCDColor::Solid(LCDSolidColor::kColorWhite)
+        )?;
+        graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeCopy)?;
+
+        let mut clock = format_time(self.frames, frame_rate);
+        if !self.valid { let _ = clock.write_char('*'); }
+        graphics.draw_text(clock.as_str(), point2(left + 4, 30))?;
+
+        if let Some(delta) = self.last_delta {
+            let delta = format_delta(delta, frame_rate);
+            graphics.draw_text(delta.as_str(), point2(left + 4, 50))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,539 @@
+use anyhow::Error;
+use crankstart::graphics::{Graphics, LCDColor, LCDSolidColor};
+use crankstart_sys::{LCDBitmapDrawMode, PDButtons, LCD_COLUMNS};
+use euclid::{point2, rect};
+
+use crate::layout::GAME_RIGHT;
+use crate::strings::{tr, Text};
+
+// The Gameboy has two more buttons than the Playdate, so Start and Select
+// have to come from somewhere else. There are a few ways to do it, picked
+// with the start_select setting:
+//
+// - crank_turn: a turn of the crank, forward for Start, back for Select.
+//   The original way, and fine for games that rarely want them. It takes
+//   TURN_DEGREES of turning one way to press, so brushing the crank while
+//   holding the Playdate doesn't, and then one press is all a turn gets
+//   until the crank rests or turns back.
+// - crank_flick: a quick flick of the crank, at least flick_threshold
+//   degrees in one frame, forward for Start and back for Select. Slow
+//   turning does nothing, so leaning on the crank can't open a menu.
+// - chords: hold B and press A for Start, or hold B and press Up for
+//   Select. For games like Pokémon that want Start all the time.
+// - crank_selector: with the crank out, START and SELECT are shown in the
+//   right border, and pointing the crank up or down picks one. A presses
+//   whichever's picked instead of being A. Put the crank away to get A
+//   back.
+//
+// Crank movement under crank_dead_zone degrees in a frame counts as none at
+// all, in every mode.
+//
+// Chords have to keep B from the game until it's clear what was meant, or
+// every Start would come with a phantom B. A B on its own is held back for
+// CHORD_WINDOW frames, then let through late. A quick tap is passed on as
+// a tap of its own. Once A or Up joins in, nothing in the chord reaches the
+// game, and it's decided when B comes back up. A and B and Up all together
+// is the quick save chord, so that's neither.
+//
+// With crank_fast_forward on, spinning the crank fast forwards (see
+// fast_forward.rs), and the crank modes can't tell a spin from a turn until
+// it's been going a while. So their presses wait for the crank to stop, and
+// any movement that turned into a spin presses nothing.
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum StartSelectMode {
+    CrankTurn,
+    CrankFlick,
+    Chords,
+    CrankSelector
+}
+
+const MODES: &[StartSelectMode] = &[
+    StartSelectMode::CrankTurn,
+    StartSelectMode::CrankFlick,
+    StartSelectMode::Chords,
+    StartSelectMode::CrankSelector
+];
+
+impl StartSelectMode {
+    // For the start_select setting
+    pub fn name (self) -> &'static str {
+        match self {
+            StartSelectMode::CrankTurn => "crank_turn",
+            StartSelectMode::CrankFlick => "crank_flick",
+            StartSelectMode::Chords => "chords",
+            StartSelectMode::CrankSelector => "crank_selector"
+        }
+    }
+
+    // For the options menu
+    pub fn label (self) -> &'static str {
+        tr(match self {
+            StartSelectMode::CrankTurn => Text::StartSelectCrankTurn,
+            StartSelectMode::CrankFlick => Text::StartSelectCrankFlick,
+            StartSelectMode::Chords => Text::StartSelectChords,
+            StartSelectMode::CrankSelector => Text::StartSelectCrankSelector
+        })
+    }
+
+    pub fn from_name (name: &str) -> Option<Self> {
+        MODES.iter().copied().find(|mode| mode.name() == name)
+    }
+
+    pub fn next (self) -> Self {
+        let index = MODES.iter().position(|mode| *mode == self).unwrap();
+        MODES[(index + 1) % MODES.len()]
+    }
+}
+
+// Frames a lone B is held back for, in case it's the start of a chord
+const CHORD_WINDOW: usize = 4;
+// Frames a crank press, a chord or a held-back B tap is pressed for. Games
+// only look at the joypad once a frame, so one frame can be missed.
+const TAP_FRAMES: usize = 3;
+// Degrees crank_turn wants turning one way, without a rest, to press
+const TURN_DEGREES: f32 = 6.;
+// Frames the crank has to rest before the same way presses again
+const TURN_COOLDOWN: usize = 3;
+
+// What the options menu steps crank_dead_zone through
+const DEAD_ZONES: &[usize] = &[0, 1, 2, 3, 5, 8];
+
+pub fn next_dead_zone (dead_zone: usize) -> usize {
+    DEAD_ZONES
+        .iter()
+        .copied()
+        .find(|zone| *zone > dead_zone)
+        .unwrap_or(DEAD_ZONES[0])
+}
+
+// Where crank_selector's indicator goes in the right border
+const SELECTOR_LEFT: i32 = GAME_RIGHT as i32 + 6;
+const SELECTOR_TOP: i32 = 84;
+const SELECTOR_ITEM_HEIGHT: i32 = 20;
+
+const START_PARTNER: PDButtons = PDButtons::kButtonA;
+const SELECT_PARTNER: PDButtons = PDButtons::kButtonUp;
+const PARTNERS: PDButtons = PDButtons(START_PARTNER.0 | SELECT_PARTNER.0);
+
+fn held (buttons: PDButtons, button: PDButtons) -> bool {
+    (buttons & button) == button
+}
+
+// What crank_selector has picked
+#[derive(Clone, Copy, PartialEq)]
+enum Pick {
+    Start,
+    Select
+}
+
+impl Pick {
+    // Pointing up (0 degrees is straight up) picks the top item
+    fn at_angle (angle: f32) -> Self {
+        if angle < 90. || angle >= 270. { Pick::Start } else { Pick::Select }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Turn {
+    // The crank's still, or inside the dead zone
+    Resting,
+    // Going one way, degrees so far (negative is back), not far enough yet
+    Moving { degrees: f32 },
+    // This turn has had its press. quiet is how many frames it's rested.
+    Pressed { forward: bool, quiet: usize }
+}
+
+#[derive(Clone, Copy)]
+enum Chord {
+    Idle,
+    // B is down and being kept from the game. partners is every partner
+    // that's been down since.
+    Waiting { frames: usize, partners: PDButtons },
+    // It was just B after all
+    PassingThrough
+}
+
+// What the game gets this frame
+pub struct Mapped {
+    // The Playdate buttons to pass on, with anything held back taken out
+    pub buttons: PDButtons,
+    pub start: bool,
+    pub select: bool
+}
+
+pub struct StartSelect {
+    // For crank_turn
+    turn: Turn,
+    // For crank_flick. Clears once the crank slows down again, so one flick
+    // is one press.
+    flicked: bool,
+    chord: Chord,
+    // For crank_selector, the pick while the crank's out. The indicator's
+    // only drawn again when it changes, and the border wants drawing over
+    // it when it goes.
+    picked: Option<Pick>,
+    pick_changed: bool,
+    selector_gone: bool,
+    // The crank's movement since it last stood still, while presses are
+    // being held back for fast forward: net degrees, the most in one
+    // frame, and whether it got fast enough to be a spin
+    burst: Option<(f32, f32, bool)>,
+    // Frames left on each kind of tap
+    start_frames: usize,
+    select_frames: usize,
+    b_frames: usize
+}
+
+impl StartSelect {
+    pub fn new () -> Self {
+        Self {
+            turn: Turn::Resting,
+            flicked: false,
+            chord: Chord::Idle,
+            picked: None,
+            pick_changed: false,
+            selector_gone: false,
+            burst: None,
+            start_frames: 0,
+            select_frames: 0,
+            b_frames: 0
+        }
+    }
+
+    fn update_turn (&mut self, crank_change: f32) {
+        let forward = crank_change > 0.;
+        self.turn = match (self.turn, crank_change == 0.) {
+            (Turn::Pressed { forward, quiet }, true)
+                if quiet + 1 < TURN_COOLDOWN =>
+                Turn::Pressed { forward, quiet: quiet + 1 },
+            (_, true) => Turn::Resting,
+            (Turn::Pressed { forward: was_forward, .. }, false)
+                if was_forward == forward =>
+                Turn::Pressed { forward, quiet: 0 },
+            (Turn::Moving { degrees }, false) if (degrees > 0.) == forward =>
+                self.turned(degrees + crank_change),
+            // Starting off, or turning back
+            (_, false) => self.turned(crank_change)
+        };
+    }
+
+    // Where a turn of degrees so far leaves crank_turn, pressing if it's
+    // far enough
+    fn turned (&mut self, degrees: f32) -> Turn {
+        if degrees.abs() < TURN_DEGREES {
+            return Turn::Moving { degrees }
+        }
+        if degrees > 0. {
+            self.start_frames = TAP_FRAMES;
+        } else {
+            self.select_frames = TAP_FRAMES;
+        }
+        Turn::Pressed { forward: degrees > 0., quiet: 0 }
+    }
+
+    fn update_flick (&mut self, crank_change: f32, threshold: f32) {
+        if crank_change.abs() < threshold / 2. {
+            self.flicked = false;
+        } else if !self.flicked && crank_change.abs() >= threshold {
+            self.flicked = true;
+            if crank_change > 0. {
+                self.start_frames = TAP_FRAMES;
+            } else {
+                self.select_frames = TAP_FRAMES;
+            }
+        }
+    }
+
+    fn update_burst (
+        &mut self,
+        mode: StartSelectMode,
+        crank_change: f32,
+        threshold: f32,
+        spinning: bool
+    ) {
+        if crank_change != 0. {
+            let (net, peak, spun) = self.burst.unwrap_or((0., 0., false));
+            self.burst = Some((
+                net + crank_change,
+                peak.max(crank_change.abs()),
+                spun || spinning
+            ));
+            return
+        }
+
+        // It's stopped, so it's time to decide
+        let (net, peak, spun) = match self.burst.take() {
+            Some(burst) => burst,
+            None => return
+        };
+        let pressed = !spun && match mode {
+            StartSelectMode::CrankFlick => peak >= threshold,
+            _ => true
+        };
+        if pressed && net > 0. { self.start_frames = TAP_FRAMES }
+        if pressed && net < 0. { self.select_frames = TAP_FRAMES }
+    }
+
+    // Returns the buttons with whatever the chord is keeping back taken out
+    fn update_chord (&mut self, buttons: PDButtons) -> PDButtons {
+        let b_down = held(buttons, PDButtons::kButtonB);
+        self.chord = match self.chord {
+            Chord::Idle if b_down => Chord::Waiting {
+                frames: 0,
+                partners: PDButtons(buttons.0 & PARTNERS.0)
+            },
+            Chord::Idle => Chord::Idle,
+            Chord::Waiting { frames, partners } => {
+                let partners = PDButtons(partners.0 | (buttons.0 & PARTNERS.0));
+                if !b_down {
+                    if partners == START_PARTNER {
+                        self.start_frames = TAP_FRAMES;
+                    } else if partners == SELECT_PARTNER {
+                        self.select_frames = TAP_FRAMES;
+                    } else if partners == PDButtons(0) {
+                        self.b_frames = TAP_FRAMES;
+                    }
+                    Chord::Idle
+                } else if partners == PDButtons(0) && frames >= CHORD_WINDOW {
+                    Chord::PassingThrough
+                } else {
+                    Chord::Waiting { frames: frames + 1, partners }
+                }
+            },
+            Chord::PassingThrough if b_down => Chord::PassingThrough,
+            Chord::PassingThrough => Chord::Idle
+        };
+
+        match self.chord {
+            Chord::Waiting { partners, .. } => PDButtons(
+                buttons.0 & !PDButtons::kButtonB.0 & !partners.0
+            ),
+            _ => buttons
+        }
+    }
+
+    // Call once per update, with the buttons that are headed for the game.
+    // spinning is None unless crank_fast_forward is on, and crank_angle is
+    // None while the crank's docked.
+    pub fn update (
+        &mut self,
+        mode: StartSelectMode,
+        buttons: PDButtons,
+        crank_change: f32,
+        flick_threshold: usize,
+        dead_zone: usize,
+        spinning: Option<bool>,
+        crank_angle: Option<f32>
+    ) -> Mapped {
+        let picked = match (mode, crank_angle) {
+            (StartSelectMode::CrankSelector, Some(angle)) =>
+                Some(Pick::at_angle(angle)),
+            _ => None
+        };
+        if picked != self.picked {
+            self.pick_changed = true;
+            self.selector_gone |= picked.is_none();
+            self.picked = picked;
+        }
+
+        let crank_change = if crank_change.abs() < dead_zone as f32 {
+            0.
+        } else {
+            crank_change
+        };
+
+        let mut buttons = buttons;
+        let threshold = flick_threshold as f32;
+        match (mode, spinning) {
+            (StartSelectMode::Chords, _) =>
+                buttons = self.update_chord(buttons),
+            (StartSelectMode::CrankSelector, _) => {
+                let a = held(buttons, PDButtons::kButtonA);
+                let taken = if picked.is_some() {
+                    PDButtons::kButtonA.0
+                } else { 0 };
+                return Mapped {
+                    buttons: PDButtons(buttons.0 & !taken),
+                    start: a && picked == Some(Pick::Start),
+                    select: a && picked == Some(Pick::Select)
+                }
+            },
+            (_, Some(spinning)) =>
+                self.update_burst(mode, crank_change, threshold, spinning),
+            (StartSelectMode::CrankTurn, None) =>
+                self.update_turn(crank_change),
+            (StartSelectMode::CrankFlick, None) =>
+                self.update_flick(crank_change, threshold)
+        }
+
+        let mapped = Mapped {
+            buttons: if self.b_frames > 0 {
+                PDButtons(buttons.0 | PDButtons::kButtonB.0)
+            } else {
+                buttons
+            },
+            start: self.start_frames > 0,
+            select: self.select_frames > 0
+        };
+        self.start_frames = self.start_frames.saturating_sub(1);
+        self.select_frames = self.select_frames.saturating_sub(1);
+        self.b_frames = self.b_frames.saturating_sub(1);
+        mapped
+    }
+
+    // Whether crank_selector's indicator has gone since this was last
+    // asked, leaving the border to be drawn over it
+    pub fn take_selector_gone (&mut self) -> bool {
+        let gone = self.selector_gone;
+        self.selector_gone = false;
+        gone
+    }
+
+    // START above SELECT in the right border, with the pick in black.
+    // Call after the blit, with forced set if anything might have drawn
+    // over it. Otherwise it's only drawn when the pick changes, so its rows
+    // aren't marked every frame.
+    pub fn draw_selector (&mut self, forced: bool) -> Result<(), Error> {
+        let picked = match self.picked {
+            Some(picked) => picked,
+            None => return Ok(())
+        };
+        if !self.pick_changed && !forced { return Ok(()) }
+        self.pick_changed = false;
+
+        let graphics = Graphics::get();
+        let width = LCD_COLUMNS as i32 - SELECTOR_LEFT - 2;
+        let items = [
+            (Pick::Start, Text::SelectorStart),
+            (Pick::Select, Text::SelectorSelect)
+        ];
+        for (index, (pick, text)) in items.iter().enumerate() {
+            let top = SELECTOR_TOP + index as i32 * SELECTOR_ITEM_HEIGHT;
+            let (colour, mode) = if *pick == picked {
+                (
+                    LCDSolidColor::kColorBlack,
+                    LCDBitmapDrawMode::kDrawModeInverted
+                )
+            } else {
+                (LCDSolidColor::kColorWhite, LCDBitmapDrawMode::kDrawModeCopy)
+            };
+            graphics.fill_rect(
+                rect(SELECTOR_LEFT, top, width, SELECTOR_ITEM_HEIGHT),
+                LCDColor::Solid(colour)
+            )?;
+            graphics.set_draw_mode(mode)?;
+            graphics.draw_text(tr(*text), point2(SELECTOR_LEFT + 2, top + 1))?;
+        }
+        graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeCopy)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOTHING: PDButtons = PDButtons(0);
+
+    // Runs crank_turn over a frame of crank movement each, returning
+    // whether Start and Select were down after each one
+    fn turn (changes: &[f32], dead_zone: usize) -> Vec<(bool, bool)> {
+        let mut start_select = StartSelect::new();
+        changes
+            .iter()
+            .map(|change| {
+                let mapped = start_select.update(
+                    StartSelectMode::CrankTurn,
+                    NOTHING, *change, 30, dead_zone, None, None
+                );
+                (mapped.start, mapped.select)
+            })
+            .collect()
+    }
+
+    fn presses (changes: &[f32], dead_zone: usize) -> (usize, usize) {
+        let frames = turn(changes, dead_zone);
+        let rising = |pick: fn(&(bool, bool)) -> bool| {
+            let mut last = false;
+            frames.iter().filter(|frame| {
+                let down = pick(frame);
+                let rose = down && !last;
+                last = down;
+                rose
+            }).count()
+        };
+        (rising(|frame| frame.0), rising(|frame| frame.1))
+    }
+
+    #[test]
+    fn dead_zone_swallows_small_movement () {
+        assert_eq!(presses(&[1.5; 20], 2), (0, 0));
+        assert_eq!(presses(&[-1.5; 20], 2), (0, 0));
+        // The same movement with no dead zone adds up to a turn
+        assert_eq!(presses(&[1.5; 20], 0), (1, 0));
+    }
+
+    #[test]
+    fn forward_turn_presses_start_once () {
+        let (start, select) = presses(&[4., 4., 4., 4., 4., 0., 0.], 2);
+        assert_eq!((start, select), (1, 0));
+    }
+
+    #[test]
+    fn jitter_round_a_point_presses_nothing () {
+        // Each wobble gets past the dead zone, but never adds up to a turn
+        let wobble = [4., -4., 4., -4., 4., -4., 4., -4.];
+        assert_eq!(presses(&wobble, 2), (0, 0));
+    }
+
+    #[test]
+    fn a_long_turn_presses_once () {
+        assert_eq!(presses(&[10.; 30], 2), (1, 0));
+    }
+
+    #[test]
+    fn another_turn_waits_for_the_crank_to_rest () {
+        let mut changes = vec![10., 0., 10.];
+        assert_eq!(presses(&changes, 2), (1, 0));
+        changes = vec![10.];
+        changes.extend_from_slice(&[0.; TURN_COOLDOWN]);
+        changes.extend_from_slice(&[10.; 4]);
+        assert_eq!(presses(&changes, 2), (2, 0));
+    }
+
+    #[test]
+    fn turning_back_presses_select_straight_away () {
+        let frames = turn(&[10., -10.], 2);
+        assert!(frames[0].0 && !frames[0].1);
+        assert!(frames[1].1);
+        assert_eq!(presses(&[10., 0., -10., 0., 0., 0.], 2), (1, 1));
+    }
+
+    #[test]
+    fn press_is_held_for_tap_frames () {
+        let frames = turn(&[10., 0., 0., 0., 0., 0.], 2);
+        let held = frames.iter().filter(|frame| frame.0).count();
+        assert_eq!(held, TAP_FRAMES);
+    }
+
+    #[test]
+    fn selector_wraps_round_the_top () {
+        // 0 degrees is straight up, so just either side of it is the top
+        // item, whichever way round the crank got there
+        assert!(Pick::at_angle(0.) == Pick::Start);
+        assert!(Pick::at_angle(359.9) == Pick::Start);
+        assert!(Pick::at_angle(89.9) == Pick::Start);
+        assert!(Pick::at_angle(90.) == Pick::Select);
+        assert!(Pick::at_angle(269.9) == Pick::Select);
+        assert!(Pick::at_angle(270.) == Pick::Start);
+    }
+
+    #[test]
+    fn dead_zone_steps_wrap () {
+        assert_eq!(next_dead_zone(2), 3);
+        assert_eq!(next_dead_zone(8), 0);
+        // Something set by hand in the file carries on from the next step
+        assert_eq!(next_dead_zone(4), 5);
+    }
+}
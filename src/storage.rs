@@ -0,0 +1,345 @@
+use alloc::{format, string::String, vec, vec::Vec};
+use anyhow::{anyhow, Error};
+use core::sync::atomic::{AtomicBool, Ordering};
+use crankstart::file::FileSystem;
+use crankstart_sys::FileOptions;
+
+#[cfg(test)]
+use alloc::collections::BTreeMap;
+#[cfg(test)]
+use core::cell::{Cell, RefCell};
+
+// Set whenever a write fails, cleared again by the next one that succeeds.
+// A full Playdate fails every write, so this is our "storage full" signal.
+static WRITES_FAILING: AtomicBool = AtomicBool::new(false);
+
+pub fn writes_failing () -> bool {
+    WRITES_FAILING.load(Ordering::Relaxed)
+}
+
+// Everything Playboy makes for itself is kept in these, out of the way of
+// the ROMs and the files people write by hand (settings, splits, manuals).
+// Each one is made the first time something is written to it.
+pub const SAVES_DIR: &str = "saves";
+pub const STATES_DIR: &str = "states";
+pub const SCREENSHOTS_DIR: &str = "screenshots";
+pub const PRINTS_DIR: &str = "prints";
+pub const SYSTEM_DIR: &str = "system";
+
+pub const GENERATED_DIRS: &[&str] = &[
+    SAVES_DIR, STATES_DIR, SCREENSHOTS_DIR, PRINTS_DIR, SYSTEM_DIR
+];
+
+// Header titles can have characters in that the filesystem won't take, or
+// that mean something in a path, so they're swapped for underscores before
+// anything's named after one. Trailing dots and spaces go too.
+pub fn safe_name (name: &str) -> String {
+    let replaced: String = name
+        .chars()
+        .map(|c| {
+            if c.is_control() || "/\\:*?\"<>|".contains(c) { '_' } else { c }
+        })
+        .collect();
+    let trimmed = replaced.trim_end_matches(|c| c == ' ' || c == '.');
+    String::from(if trimmed.len() == 0 { "untitled" } else { trimmed })
+}
+
+pub fn in_dir (dir: &str, filename: &str) -> String {
+    format!("{}/{}", dir, filename)
+}
+
+// "saves/Tetris.sav" -> "Tetris.sav"
+pub fn file_name (path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+// The handful of filesystem operations Playboy's file pipeline needs.
+// Everything above this (atomic writes, short read/write detection, the save
+// file formats) is written against the trait rather than crankstart, so it
+// can be pointed at something other than the real Playdate filesystem.
+pub trait FileStore {
+    // Size in bytes of the file at path, or an error if it doesn't exist
+    fn size (&self, path: &str) -> Result<usize, Error>;
+    // Returns how many bytes were actually read/written
+    fn read (&self, path: &str, buffer: &mut [u8]) -> Result<usize, Error>;
+    fn write (&self, path: &str, data: &[u8]) -> Result<usize, Error>;
+    // Replaces anything already at to
+    fn rename (&self, from: &str, to: &str) -> Result<(), Error>;
+    fn remove (&self, path: &str) -> Result<(), Error>;
+    // Fine to call on a folder that's already there
+    fn make_dir (&self, path: &str) -> Result<(), Error>;
+}
+
+// The real thing
+pub struct PlaydateFiles;
+
+impl FileStore for PlaydateFiles {
+    fn size (&self, path: &str) -> Result<usize, Error> {
+        let stat = FileSystem::get()
+            .stat(path)
+            .map_err(|e| anyhow!("{} not found: {}", path, e))?;
+        Ok(stat.size as usize)
+    }
+
+    fn read (&self, path: &str, buffer: &mut [u8]) -> Result<usize, Error> {
+        let file = FileSystem::get()
+            .open(path, FileOptions::kFileRead | FileOptions::kFileReadData)
+            .map_err(|e| anyhow!("opening {} for reading: {}", path, e))?;
+        let read = file
+            .read(buffer)
+            .map_err(|e| anyhow!("reading {}: {}", path, e))?;
+        Ok(read)
+    }
+
+    fn write (&self, path: &str, data: &[u8]) -> Result<usize, Error> {
+        let file = FileSystem::get()
+            .open(path, FileOptions::kFileWrite)
+            .map_err(|e| anyhow!("opening {} for writing: {}", path, e))?;
+        let written = file
+            .write(data)
+            .map_err(|e| anyhow!("writing {}: {}", path, e))?;
+        Ok(written)
+    }
+
+    fn rename (&self, from: &str, to: &str) -> Result<(), Error> {
+        FileSystem::get()
+            .rename(from, to)
+            .map_err(|e| anyhow!("renaming {} to {}: {}", from, to, e))
+    }
+
+    fn remove (&self, path: &str) -> Result<(), Error> {
+        FileSystem::get()
+            .unlink(path, false)
+            .map_err(|e| anyhow!("deleting {}: {}", path, e))
+    }
+
+    fn make_dir (&self, path: &str) -> Result<(), Error> {
+        FileSystem::get()
+            .mkdir(path)
+            .map_err(|e| anyhow!("making folder {}: {}", path, e))
+    }
+}
+
+// Writes a whole file via a temporary file and a rename, so a failure part
+// way through never leaves a half-written file in place of a good one.
+pub fn write_file_in (
+    store: &impl FileStore,
+    path: &str,
+    data: &[u8]
+) -> Result<(), Error> {
+    let temp_path = &format!("{}.tmp", path)[..];
+
+    // Folders are made on demand. If this fails, so will the write, and
+    // that's the error worth reporting.
+    if let Some(split) = path.rfind('/') {
+        if let Err(e) = store.make_dir(&path[..split]) {
+            log!("{}", e);
+        }
+    }
+
+    // Build with `--features simulate-write-failure` to make every write
+    // fail, so the error handling can be exercised without actually filling
+    // a Playdate.
+    let attempt = if cfg!(feature = "simulate-write-failure") {
+        Err(anyhow!("simulated write failure"))
+    } else {
+        store.write(temp_path, data)
+    };
+
+    let result = attempt.and_then(|written| {
+        // A full disk tends to show up as a short write rather than an error
+        if written != data.len() {
+            return Err(anyhow!(
+                "short write to {} ({} of {} bytes)",
+                temp_path, written, data.len()
+            ))
+        }
+        store.rename(temp_path, path)
+    });
+
+    if result.is_err() {
+        // Don't leave partial files lying around eating what little space
+        // there is left.
+        if store.size(temp_path).is_ok() {
+            if let Err(e) = store.remove(temp_path) {
+                log!("Couldn't clean up {}: {}", temp_path, e);
+            }
+        }
+    }
+
+    WRITES_FAILING.store(result.is_err(), Ordering::Relaxed);
+    result
+}
+
+// Reads a whole file, treating a short read as a failure rather than handing
+// back a buffer with zeroes on the end.
+pub fn read_file_in (
+    store: &impl FileStore,
+    path: &str
+) -> Result<Vec<u8>, Error> {
+    let size = store.size(path)?;
+    let mut buffer = vec![0; size];
+    let read = store.read(path, &mut buffer)?;
+
+    if read != size {
+        return Err(anyhow!(
+            "short read from {} ({} of {} bytes)", path, read, size
+        ))
+    }
+
+    Ok(buffer)
+}
+
+// Every file Playboy writes should go through here
+pub fn write_file (path: &str, data: &[u8]) -> Result<(), Error> {
+    write_file_in(&PlaydateFiles, path, data)
+}
+
+pub fn read_file (path: &str) -> Result<Vec<u8>, Error> {
+    read_file_in(&PlaydateFiles, path)
+}
+
+// An in-memory FileStore for tests, with switches for the ways the real one
+// goes wrong
+#[cfg(test)]
+pub struct MemoryFiles {
+    pub files: RefCell<BTreeMap<String, Vec<u8>>>,
+    // Every rename fails, like a full disk can make them
+    pub fail_renames: Cell<bool>,
+    // Reads stop after this many bytes
+    pub read_limit: Cell<Option<usize>>,
+    // How many writes have been asked for
+    pub writes: Cell<usize>
+}
+
+#[cfg(test)]
+impl MemoryFiles {
+    pub fn new () -> Self {
+        Self {
+            files: RefCell::new(BTreeMap::new()),
+            fail_renames: Cell::new(false),
+            read_limit: Cell::new(None),
+            writes: Cell::new(0)
+        }
+    }
+
+    pub fn get (&self, path: &str) -> Option<Vec<u8>> {
+        self.files.borrow().get(path).cloned()
+    }
+
+    pub fn put (&self, path: &str, data: &[u8]) {
+        self.files.borrow_mut().insert(String::from(path), data.to_vec());
+    }
+}
+
+#[cfg(test)]
+impl FileStore for MemoryFiles {
+    fn size (&self, path: &str) -> Result<usize, Error> {
+        self.get(path)
+            .map(|data| data.len())
+            .ok_or_else(|| anyhow!("{} not found", path))
+    }
+
+    fn read (&self, path: &str, buffer: &mut [u8]) -> Result<usize, Error> {
+        let data = self.get(path)
+            .ok_or_else(|| anyhow!("{} not found", path))?;
+        let mut length = data.len().min(buffer.len());
+        if let Some(limit) = self.read_limit.get() {
+            length = length.min(limit);
+        }
+        buffer[..length].copy_from_slice(&data[..length]);
+        Ok(length)
+    }
+
+    fn write (&self, path: &str, data: &[u8]) -> Result<usize, Error> {
+        self.writes.set(self.writes.get() + 1);
+        self.put(path, data);
+        Ok(data.len())
+    }
+
+    fn rename (&self, from: &str, to: &str) -> Result<(), Error> {
+        if self.fail_renames.get() {
+            return Err(anyhow!("renaming {} to {}: disk full", from, to))
+        }
+        let data = self.files
+            .borrow_mut()
+            .remove(from)
+            .ok_or_else(|| anyhow!("{} not found", from))?;
+        self.put(to, &data[..]);
+        Ok(())
+    }
+
+    fn remove (&self, path: &str) -> Result<(), Error> {
+        self.files
+            .borrow_mut()
+            .remove(path)
+            .map(|_| ())
+            .ok_or_else(|| anyhow!("{} not found", path))
+    }
+
+    fn make_dir (&self, _path: &str) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg_attr(feature = "simulate-write-failure", ignore)]
+    fn round_trips () {
+        let store = MemoryFiles::new();
+        for size in [0, 1, 0x2000, 0x8000, 0x20000].iter() {
+            let data: Vec<u8> = (0..*size).map(|i| (i * 7) as u8).collect();
+            write_file_in(&store, "saves/game.sav", &data[..]).unwrap();
+            assert_eq!(read_file_in(&store, "saves/game.sav").unwrap(), data);
+        }
+        // Nothing's left behind from the atomic write
+        assert!(store.get("saves/game.sav.tmp").is_none());
+    }
+
+    #[test]
+    fn rejects_short_reads () {
+        let store = MemoryFiles::new();
+        store.put("saves/game.sav", &[1; 100][..]);
+        store.read_limit.set(Some(60));
+        assert!(read_file_in(&store, "saves/game.sav").is_err());
+    }
+
+    #[test]
+    fn missing_files_are_errors () {
+        let store = MemoryFiles::new();
+        assert!(read_file_in(&store, "saves/nothing.sav").is_err());
+    }
+
+    #[test]
+    fn failed_rename_cleans_up_and_keeps_the_old_file () {
+        let store = MemoryFiles::new();
+        store.put("saves/game.sav", b"old");
+        store.fail_renames.set(true);
+
+        assert!(write_file_in(&store, "saves/game.sav", b"new").is_err());
+        assert!(store.get("saves/game.sav.tmp").is_none());
+        assert_eq!(store.get("saves/game.sav").unwrap(), b"old");
+    }
+
+    #[test]
+    fn safe_names () {
+        assert_eq!(safe_name("POKEMON RED"), "POKEMON RED");
+        assert_eq!(safe_name("A/B:C?"), "A_B_C_");
+        assert_eq!(safe_name("THE END. "), "THE END");
+        assert_eq!(safe_name("..."), "untitled");
+    }
+
+    #[test]
+    #[cfg(feature = "simulate-write-failure")]
+    fn simulated_failures_fail_every_write () {
+        let store = MemoryFiles::new();
+        store.put("saves/game.sav", b"old");
+        assert!(write_file_in(&store, "saves/game.sav", b"new").is_err());
+        assert!(writes_failing());
+        assert_eq!(store.get("saves/game.sav").unwrap(), b"old");
+        assert_eq!(store.writes.get(), 0);
+    }
+}
@@ -0,0 +1,283 @@
+use alloc::{format, string::String, vec, vec::Vec};
+use anyhow::Error;
+use crankstart::file::FileSystem;
+
+use crate::bugreport::BUGREPORT_PATH;
+use crate::log::LOG_PATH;
+use crate::menu::{ListMenu, MenuEvent};
+use crate::rom_picker;
+use crate::screenshot;
+use crate::serial;
+use crate::storage::{self, FileStore, PlaydateFiles};
+use crate::strings::{tr, trf, Text};
+
+// Shows how much of the data folder each kind of Playboy file is taking up,
+// and lets you clear out the kinds that are safe to lose.
+
+#[derive(Clone, Copy, PartialEq)]
+enum Category {
+    Roms,
+    BatterySaves,
+    SaveStates,
+    Screenshots,
+    Logs,
+    Other
+}
+
+const CATEGORIES: &[Category] = &[
+    Category::Roms,
+    Category::BatterySaves,
+    Category::SaveStates,
+    Category::Screenshots,
+    Category::Logs,
+    Category::Other
+];
+
+impl Category {
+    // Goes by the name rather than the folder, so anything left over from
+    // before the folders (see migrate.rs) is still counted properly
+    fn of (path: &str) -> Self {
+        let filename = storage::file_name(path);
+        if rom_picker::is_rom(filename) {
+            Category::Roms
+        // Checksum sidecars and backups count with the save they belong to
+        } else if filename.contains(".sav") {
+            Category::BatterySaves
+        } else if filename.ends_with(".state") {
+            Category::SaveStates
+        // Printer prints count as screenshots
+        } else if screenshot::is_screenshot(filename) ||
+            serial::is_print(filename)
+        {
+            Category::Screenshots
+        } else if path == LOG_PATH || path == BUGREPORT_PATH {
+            Category::Logs
+        } else {
+            Category::Other
+        }
+    }
+
+    fn name (&self) -> &'static str {
+        tr(match self {
+            Category::Roms => Text::CategoryRoms,
+            Category::BatterySaves => Text::CategoryBatterySaves,
+            Category::SaveStates => Text::CategorySaveStates,
+            Category::Screenshots => Text::CategoryScreenshots,
+            Category::Logs => Text::CategoryLogs,
+            Category::Other => Text::CategoryOther
+        })
+    }
+
+    // Battery saves are real progress, and ROMs aren't ours to delete, so
+    // only these can be cleared from here
+    fn can_clear (&self) -> bool {
+        match self {
+            Category::SaveStates | Category::Screenshots | Category::Logs =>
+                true,
+            _ => false
+        }
+    }
+}
+
+struct Usage {
+    category: Category,
+    files: Vec<String>,
+    bytes: usize
+}
+
+fn human_size (bytes: usize) -> String {
+    const KB: usize = 1024;
+    const MB: usize = 1024 * 1024;
+
+    if bytes < KB {
+        format!("{} B", bytes)
+    } else if bytes < MB {
+        format!("{} KB", bytes / KB)
+    } else {
+        format!("{}.{} MB", bytes / MB, bytes % MB * 10 / MB)
+    }
+}
+
+fn measure () -> Result<Vec<Usage>, Error> {
+    let store = PlaydateFiles;
+    let mut usage: Vec<Usage> = CATEGORIES
+        .iter()
+        .map(|category| Usage {
+            category: *category,
+            files: vec![],
+            bytes: 0
+        })
+        .collect();
+
+    let file_system = FileSystem::get();
+    let mut paths = vec![];
+    for filename in file_system.listfiles(".")? {
+        // Folders come back with a trailing slash. Ours are gone through
+        // below, and anyone else's aren't our business.
+        if !filename.ends_with('/') { paths.push(filename) }
+    }
+    for dir in storage::GENERATED_DIRS {
+        // Not there until something's been written to it
+        let filenames = file_system.listfiles(dir).unwrap_or_default();
+        for filename in filenames {
+            if filename.ends_with('/') { continue }
+            paths.push(storage::in_dir(dir, &filename[..]));
+        }
+    }
+
+    for path in paths {
+        let category = Category::of(&path[..]);
+        let entry = usage
+            .iter_mut()
+            .find(|usage| usage.category == category)
+            .unwrap();
+        entry.bytes += store.size(&path[..]).unwrap_or(0);
+        entry.files.push(path);
+    }
+
+    Ok(usage)
+}
+
+#[derive(Clone, Copy)]
+enum Item {
+    Info,
+    Clear(Category)
+}
+
+pub struct StorageScreen {
+    // Files belonging to this game are left alone, since it still has them
+    // open (or will write them again when you leave it)
+    running_game: Option<String>,
+    usage: Vec<Usage>,
+    menu: ListMenu,
+    items: Vec<Item>,
+    // Up while asking whether to go ahead with a clear
+    confirm: Option<(Category, ListMenu)>
+}
+
+impl StorageScreen {
+    // Like the other screens, this draws straight away
+    pub fn new (running_game: Option<&str>) -> Result<Self, Error> {
+        let usage = measure()?;
+        let (menu, items) = Self::build_menu(&usage[..])?;
+        Ok(Self {
+            running_game: running_game.map(String::from),
+            usage,
+            menu,
+            items,
+            confirm: None
+        })
+    }
+
+    fn build_menu (usage: &[Usage]) -> Result<(ListMenu, Vec<Item>), Error> {
+        let mut labels = vec![];
+        let mut items = vec![];
+
+        for entry in usage {
+            if entry.files.len() == 0 { continue }
+            labels.push(trf(Text::StorageUsage, &[
+                &entry.category.name(),
+                &human_size(entry.bytes),
+                &entry.files.len()
+            ]));
+            items.push(Item::Info);
+        }
+        for entry in usage {
+            if !entry.category.can_clear() || entry.files.len() == 0 {
+                continue
+            }
+            labels.push(trf(Text::ClearCategory, &[&entry.category.name()]));
+            items.push(Item::Clear(entry.category));
+        }
+
+        let total: usize = usage.iter().map(|entry| entry.bytes).sum();
+        let title = trf(Text::StorageTitle, &[&human_size(total)]);
+        Ok((ListMenu::new(&title[..], labels)?, items))
+    }
+
+    fn is_protected (&self, path: &str) -> bool {
+        let filename = storage::file_name(path);
+        match &self.running_game {
+            Some(game) => {
+                filename.starts_with(&game[..]) &&
+                    filename[game.len()..].starts_with('.')
+            },
+            None => false
+        }
+    }
+
+    fn clear (&mut self, category: Category) -> Result<(), Error> {
+        let store = PlaydateFiles;
+        let entry = self
+            .usage
+            .iter()
+            .find(|usage| usage.category == category)
+            .unwrap();
+
+        for filename in &entry.files {
+            if self.is_protected(&filename[..]) {
+                log!("Keeping {}, it belongs to the running game", filename);
+                continue
+            }
+            match store.remove(&filename[..]) {
+                Ok(()) => log!("Deleted {}", filename),
+                Err(e) => log!("Couldn't delete {}: {}", filename, e)
+            }
+        }
+
+        self.usage = measure()?;
+        let (menu, items) = Self::build_menu(&self.usage[..])?;
+        self.menu = menu;
+        self.items = items;
+        Ok(())
+    }
+
+    pub fn redraw (&self) -> Result<(), Error> {
+        match &self.confirm {
+            Some((_, confirm)) => confirm.draw(),
+            None => self.menu.draw()
+        }
+    }
+
+    // Returns true once they've backed out
+    pub fn update (&mut self) -> Result<bool, Error> {
+        if let Some((category, confirm)) = &mut self.confirm {
+            let category = *category;
+            match confirm.update()? {
+                Some(MenuEvent::Picked(1)) => {
+                    self.confirm = None;
+                    self.clear(category)?;
+                },
+                Some(_) => {
+                    self.confirm = None;
+                    self.menu.draw()?;
+                },
+                None => {}
+            }
+            return Ok(false)
+        }
+
+        match self.menu.update()? {
+            Some(MenuEvent::Picked(index)) => {
+                if let Item::Clear(category) = self.items[index] {
+                    let mut title =
+                        trf(Text::ClearQuestion, &[&category.name()]);
+                    if self.running_game.is_some() {
+                        title.push_str(tr(Text::NotThisGames));
+                    }
+                    let confirm = ListMenu::new(
+                        &title[..],
+                        vec![
+                            String::from(tr(Text::KeepThem)),
+                            String::from(tr(Text::Clear))
+                        ]
+                    )?;
+                    self.confirm = Some((category, confirm));
+                }
+                Ok(false)
+            },
+            Some(MenuEvent::Closed) => Ok(true),
+            None => Ok(false)
+        }
+    }
+}
@@ -0,0 +1,525 @@
+use alloc::{boxed::Box, format, string::String, vec, vec::Vec};
+use core::fmt::Display;
+use core::ptr::addr_of_mut;
+use crankstart::{file::FileSystem, system::System};
+use crankstart_sys::PDLanguage;
+
+use crate::storage::{self, FileStore, PlaydateFiles};
+
+// Every piece of text Playboy shows on screen goes through here, so it can
+// be translated. Translations are plain key=value text files in LANG_DIR
+// (shipped in the pdx, or dropped into the data folder), keyed by the names
+// below - so adding a language doesn't need any Rust. "\n" in a value is a
+// line break, and "{}" is filled in with whatever the text is about.
+//
+// Log lines and the bug report stay in English, since they're for us.
+pub const LANG_DIR: &str = "assets/lang";
+
+macro_rules! strings {
+    ($($id:ident $key:literal $english:literal,)*) => {
+        #[derive(Clone, Copy)]
+        pub enum Text { $($id,)* }
+
+        // Both in the same order as Text
+        const KEYS: &[&str] = &[$($key,)*];
+        const ENGLISH: &[&str] = &[$($english,)*];
+    };
+}
+
+strings! {
+    QuitGame "quit_game" "quit game",
+    Options "options" "options",
+    On "on" "on",
+    Off "off" "off",
+
+    PickerTitle "picker_title" "Playboy - Select a game",
+    DuplicateBadge "duplicate_badge" "copy",
+    DuplicateOf "duplicate_of" "Same ROM as {} - B to tidy up",
+    TidyDuplicatesTitle "tidy_duplicates_title" "Keep which file?",
+    KeepCopy "keep_copy" "Keep {}",
+    RomBadHeader "rom_bad_header" "{} has a damaged header and won't run",
+    RomUnsupportedCart "rom_unsupported_cart" "{} needs a {} cart, which isn't emulated",
+    RomColourOnly "rom_colour_only" "{} only runs on a Gameboy Color, which isn't emulated",
+    BootSplash "boot_splash" "Loading {} - {}",
+    CartErrorTitle "cart_error_title" "Playboy - Can't run this game",
+    CartError "cart_error" "{}\n\nCart type: {}\n\n{}\n\n\
+        Press A or B to go back to the list.",
+    CartErrorUnsupported "cart_error_unsupported" "Playboy doesn't emulate \
+        this kind of cart yet, so the game can't run. Try another game.",
+    CartErrorBadHeader "cart_error_bad_header" "The ROM's header is damaged, \
+        so the file's probably a bad copy. Try dumping or downloading it again.",
+    CartErrorColourOnly "cart_error_colour_only" "This game only runs on a \
+        Gameboy Color, and Playboy only runs Gameboy Color games made to work \
+        on the original too. Try another game.",
+    CartErrorUnreadable "cart_error_unreadable" "Couldn't read {}:\n\n{}\n\n\
+        The file may be cut short, or not copied over properly. Try copying \
+        it to the Playdate again.\n\nPress A or B to go back to the list.",
+    NoRoms "no_roms" "No game ROMs found.\n\n\
+        Please copy some Gameboy games into\nPlayboy's data folder.\n\n\
+        See:\nhttps://github.com/adamsoutar/playboy\n\
+        For more detailed steps :)",
+
+    NoRomsNearMiss "no_roms_near_miss" "Found {} - is that a game? \
+        Game files need to end in .gb, .gbc or .dmg.",
+
+    OptionsTitle "options_title" "Playboy - Options",
+    BorderLabel "border_label" "Border: {}",
+    BorderPlain "border_plain" "plain",
+    BorderFrame "border_frame" "frame",
+    BorderShell "border_shell" "shell",
+    ModelLabel "model_label" "System: {}",
+    CartPrefers "cart_prefers" " (cart prefers {})",
+    ModelDmg "model_dmg" "DMG",
+    ModelMgb "model_mgb" "Pocket",
+    ModelGbc "model_gbc" "GBC",
+    ModelNextBoot "model_next_boot" "System applies when a game next starts",
+    InvertLabel "invert_label" "Invert colours: {}",
+    DisplayModeLabel "display_mode_label" "Display: {}",
+    DisplayStretched "display_stretched" "stretched",
+    DisplayPixelPerfect "display_pixel_perfect" "1:1",
+    DisplayFullWidth "display_full_width" "full width",
+    DisplaySideways "display_sideways" "sideways",
+    AutoOrientationLabel "auto_orientation_label" "Turn with the Playdate: {}",
+    DitherLabel "dither_label" "Greys: {}",
+    DitherCrossHatch "dither_cross_hatch" "cross-hatch",
+    DitherBayer "dither_bayer" "Bayer",
+    DitherAnchored "dither_anchored" "cross-hatch, scrolls along",
+    TemporalDitherLabel "temporal_dither_label" "Smooth greys: {}",
+    FrameBlendLabel "frame_blend_label" "Frame blending: {}",
+    AutoZoomLabel "auto_zoom_label" "Auto zoom: {}",
+    MinimapLabel "minimap_label" "Minimap when zoomed: {}",
+    CornerTopLeft "corner_top_left" "top left",
+    CornerTopRight "corner_top_right" "top right",
+    CornerBottomLeft "corner_bottom_left" "bottom left",
+    CornerBottomRight "corner_bottom_right" "bottom right",
+    AdaptiveContrastLabel "adaptive_contrast_label"
+        "Adaptive contrast: {}",
+    NightModeLabel "night_mode_label" "Night mode: {}",
+    NightFromLabel "night_from_label" "Night from: {}",
+    NightUntilLabel "night_until_label" "Night until: {}",
+    KeepQuickSaveLabel "keep_quick_save_label" "Keep quick save: {}",
+    SplitsResetLabel "splits_reset_label" "Loads reset splits: {}",
+    LanguageLabel "language_label" "Language: {}",
+    LanguageAuto "language_auto" "automatic",
+    UndoLoad "undo_load" "Undo last load",
+    Screenshots "screenshots" "Screenshots",
+    PhotoMode "photo_mode" "Photo mode",
+    Storage "storage" "Storage",
+    Manual "manual" "Manual",
+    ManualUnreadable "manual_unreadable" "Couldn't open the manual",
+    ExportSaveQr "export_save_qr" "Export save via QR",
+    RecordBootMacro "record_boot_macro" "Record boot macro",
+    StopBootMacro "stop_boot_macro" "Stop recording, save boot macro",
+    PerfOverlayLabel "perf_overlay_label" "Frame timings: {}",
+    DumpLog "dump_log" "Dump log to file",
+    CreateBugReport "create_bug_report" "Create bug report",
+
+    NightModeStarted "night_mode_started" "Night mode - colours inverted",
+    NightModeOver "night_mode_over" "Night mode over",
+    QuickSaved "quick_saved" "Quick saved",
+    NothingQuickSaved "nothing_quick_saved" "Nothing quick saved yet",
+    QuickSaveOtherRom "quick_save_other_rom"
+        "Quick save is from a different ROM",
+    QuickLoaded "quick_loaded" "Quick loaded - undo it from options",
+    QuickLoadFailed "quick_load_failed" "Quick load failed",
+    StartSelectLabel "start_select_label" "Start/Select: {}",
+    StartSelectCrankTurn "start_select_crank_turn" "crank turn",
+    StartSelectCrankFlick "start_select_crank_flick" "crank flick",
+    StartSelectChords "start_select_chords" "B+A / B+Up",
+    StartSelectCrankSelector "start_select_crank_selector" "crank selector",
+    SelectorStart "selector_start" "START",
+    SelectorSelect "selector_select" "SELECT",
+    CrankDeadZoneLabel "crank_dead_zone_label" "Crank dead zone: {}°",
+    CrankFastForwardLabel "crank_fast_forward_label" "Spin crank to fast forward: {}",
+    TurboALabel "turbo_a_label" "Turbo A: {}",
+    TurboBLabel "turbo_b_label" "Turbo B: {}",
+    TurboSpeedLabel "turbo_speed_label" "Turbo speed: {}",
+    CheatsLabel "cheats_label" "Cheats: {}",
+    PerGameLabel "per_game_label" "Per-game picture & controls: {}",
+    GameGenieNextBoot "game_genie_next_boot"
+        "Game Genie codes change when the game next starts",
+    SaveState "save_state" "Save state",
+    LoadState "load_state" "Load state",
+    StateSaved "state_saved" "State saved",
+    StateSaveFailed "state_save_failed" "Couldn't save the state",
+    NoSavedState "no_saved_state" "No saved state for this game",
+    StateOtherRom "state_other_rom" "Saved state is from a different ROM",
+    StateLoaded "state_loaded" "State loaded - undo it from options",
+    StateLoadFailed "state_load_failed" "Couldn't load the state",
+    LoadUndone "load_undone" "Load undone",
+    NothingToUndo "nothing_to_undo" "Nothing to undo",
+    UndoFailed "undo_failed" "Couldn't undo the load",
+    NoGameRunning "no_game_running" "No game is running",
+    LogWritten "log_written" "Log written to system/playboy.log",
+    LogFailed "log_failed" "Couldn't write the log",
+    BugReportWritten "bug_report_written" "Bug report written to system/bugreport.txt",
+    BugReportFailed "bug_report_failed" "Couldn't write the bug report",
+    SaveFailed "save_failed" "Save failed - progress may not be kept",
+    StorageFull "storage_full" "Storage full - see options > Storage",
+    RunFinished "run_finished" "Run finished",
+    RunFinishedPb "run_finished_pb" "Run finished - new personal best!",
+    RunNotCounted "run_not_counted" "Run finished (not counted)",
+    RunNotSaved "run_not_saved" "Run finished, but couldn't be saved",
+    FastForwardOn "fast_forward_on" "Fast forward on",
+    FastForwardOff "fast_forward_off" "Fast forward off",
+    Paused "paused" "Paused - press A to carry on",
+    NothingToRewind "nothing_to_rewind" "Can't rewind any further",
+    TurnedSideways "turned_sideways" "Turned sideways",
+    TurnedLandscape "turned_landscape" "Turned back",
+    RewindNow "rewind_now" "Now",
+    RewindAgo "rewind_ago" "{}s ago",
+    ScrubberHint "scrubber_hint" "Crank to pick, A go back, B cancel",
+    RecordingBootMacro "recording_boot_macro"
+        "Recording boot macro - stop it from options",
+    BootMacroSaved "boot_macro_saved" "Boot macro saved",
+    BootMacroFailed "boot_macro_failed" "Couldn't save the boot macro",
+    BootMacroPlaying "boot_macro_playing"
+        "Skipping the intro - press anything to stop",
+    BootMacroStopped "boot_macro_stopped" "Boot macro stopped",
+    RestartFailed "restart_failed" "Couldn't restart the game",
+    GameFrozenTitle "game_frozen_title" "The game seems to be frozen",
+    SaveEmergencyState "save_emergency_state" "Save an emergency state",
+    EmergencyStateSaved "emergency_state_saved" "Emergency state saved",
+    EmergencyStateFailed "emergency_state_failed"
+        "Couldn't save the emergency state",
+    PressStart "press_start" "Press Start",
+    PressSelect "press_select" "Press Select",
+    ResetGame "reset_game" "Reset the game",
+    KeepWaiting "keep_waiting" "Keep waiting",
+
+    QuickMenuHint "quick_menu_hint" "Crank to pick, let go to run",
+    QuickSaveAction "quick_save_action" "Quick save",
+    QuickLoadAction "quick_load_action" "Quick load",
+    FastForwardAction "fast_forward_action" "Fast forward",
+    InvertAction "invert_action" "Invert",
+    PauseAction "pause_action" "Pause",
+    ScreenshotAction "screenshot_action" "Screenshot",
+    ScreenshotSaved "screenshot_saved" "Saved screenshot",
+    ScreenshotFailed "screenshot_failed" "Couldn't save screenshot",
+    Printed "printed" "Printed - see the prints folder",
+    PrintFailed "print_failed" "Couldn't save the print",
+    OptionsAction "options_action" "Options",
+
+    NoScreenshots "no_screenshots" "No screenshots yet.",
+    GalleryBack "gallery_back" "B back",
+    ScreenshotUnreadable "screenshot_unreadable"
+        "Couldn't read this screenshot.",
+    GalleryHelp "gallery_help" "{}  < > browse  A delete  Up rename  B back",
+    RenameScreenshot "rename_screenshot" "Rename screenshot",
+    KeyboardHelp "keyboard_help"
+        "Crank to pick  A type  B rub out  < > jump  Up done  Down cancel",
+    SpaceKey "space_key" "space",
+    PhotoHelp "photo_help" "Crank\nzoom\n\nA snap\nB back",
+    PhotoSaved "photo_saved" "Saved",
+    PhotoFailed "photo_failed" "Failed",
+    DeleteQuestion "delete_question" "Delete {}?",
+    KeepIt "keep_it" "Keep it",
+    Delete "delete" "Delete",
+
+    BadBatterySave "bad_battery_save" "Battery save is corrupt",
+    RestoreBackup "restore_backup" "Restore backup",
+    RestoreBackupFrom "restore_backup_from" "Restore backup from {}",
+    DeleteSave "delete_save" "Delete the save",
+    PlayAnyway "play_anyway" "Play anyway",
+
+    StorageTitle "storage_title" "Storage - {} used",
+    StorageUsage "storage_usage" "{}: {} ({})",
+    ClearCategory "clear_category" "Clear {}",
+    ClearQuestion "clear_question" "Clear {}?",
+    NotThisGames "not_this_games" " (not this game's)",
+    KeepThem "keep_them" "Keep them",
+    Clear "clear" "Clear",
+    CategoryRoms "category_roms" "Game ROMs",
+    CategoryBatterySaves "category_battery_saves" "Battery saves",
+    CategorySaveStates "category_save_states" "Save states",
+    CategoryScreenshots "category_screenshots" "Screenshots",
+    CategoryLogs "category_logs" "Logs",
+    CategoryOther "category_other" "Other",
+
+    NoBatterySave "no_battery_save" "This game has no battery save",
+    SaveTooBigForQr "save_too_big_for_qr" "Save is too big to export via QR",
+    QrProgress "qr_progress" "Code {} of {}",
+    QrHelp "qr_help" "A / > next\n< previous\nDown auto: {}\nB back",
+
+    TestSuiteTitle "test_suite_title" "Playboy - Test suite",
+    TestSuiteProgress "test_suite_progress" "Running {} of {}:\n{}",
+    TestSuiteDone "test_suite_done"
+        "Done: {} passed, {} failed, {} unknown.\n\n\
+        See testsuite/report.txt for the details.",
+    TestSuiteStop "test_suite_stop" "B stop / leave",
+}
+
+struct Translations {
+    // Every language switched to so far, by code, each indexed like
+    // ENGLISH. tr hands out &'static strs, so a language's strings are
+    // leaked the first time it's loaded and kept from then on, which means
+    // flicking through languages only ever costs one copy of each.
+    loaded: Vec<(String, Vec<&'static str>)>,
+    // Which of those is in use. None means English.
+    current: Option<usize>
+}
+
+static mut TRANSLATIONS: Translations = Translations::new();
+
+// The only way at TRANSLATIONS
+fn translations () -> &'static mut Translations {
+    unsafe { &mut *addr_of_mut!(TRANSLATIONS) }
+}
+
+pub fn tr (text: Text) -> &'static str {
+    translations().get(text)
+}
+
+pub fn tr_bool (value: bool) -> &'static str {
+    tr(if value { Text::On } else { Text::Off })
+}
+
+// tr with each "{}" filled in from args, in order
+pub fn trf (text: Text, args: &[&dyn Display]) -> String {
+    let mut result = String::new();
+    let mut args = args.iter();
+
+    for (i, piece) in tr(text).split("{}").enumerate() {
+        if i > 0 {
+            if let Some(arg) = args.next() {
+                result.push_str(&format!("{}", arg));
+            }
+        }
+        result.push_str(piece);
+    }
+    result
+}
+
+fn unescape (value: &str) -> String {
+    value.replace("\\n", "\n")
+}
+
+impl Translations {
+    const fn new () -> Self {
+        Self { loaded: Vec::new(), current: None }
+    }
+
+    fn get (&self, text: Text) -> &'static str {
+        match self.current {
+            Some(index) => self.loaded[index].1[text as usize],
+            None => ENGLISH[text as usize]
+        }
+    }
+
+    // Switches to the language whose code is given ("de" for
+    // LANG_DIR/de.txt). Anything the file doesn't translate stays in
+    // English.
+    fn select (&mut self, store: &impl FileStore, code: &str) {
+        self.current = None;
+        if code == "en" { return }
+
+        let cached = self.loaded.iter().position(|(loaded, _)| loaded == code);
+        if let Some(index) = cached {
+            self.current = Some(index);
+            return
+        }
+
+        let path = &format!("{}/{}.txt", LANG_DIR, code)[..];
+        let bytes = match storage::read_file_in(store, path) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                log!("No translation for {}: {}", code, e);
+                return
+            }
+        };
+        let text = match core::str::from_utf8(&bytes[..]) {
+            Ok(text) => text,
+            Err(_) => {
+                log!("{} isn't valid text, staying in English", path);
+                return
+            }
+        };
+
+        self.loaded.push((String::from(code), parse_language(text, path)));
+        self.current = Some(self.loaded.len() - 1);
+    }
+}
+
+// A language file's strings, indexed like ENGLISH
+fn parse_language (text: &str, path: &str) -> Vec<&'static str> {
+    let mut loaded: Vec<&'static str> = ENGLISH.to_vec();
+    let mut translated = 0;
+    for line in text.lines() {
+        let line = line.trim();
+        if line.len() == 0 || line.starts_with('#') { continue }
+
+        let split = match line.find('=') {
+            Some(split) => split,
+            None => continue
+        };
+        let key = line[..split].trim();
+        match KEYS.iter().position(|known| *known == key) {
+            Some(index) => {
+                // Not trimmed, since a few strings start with a space
+                let value = unescape(&line[split + 1..]);
+                loaded[index] = Box::leak(value.into_boxed_str());
+                translated += 1;
+            },
+            None => log!("{} has unknown key {}", path, key)
+        }
+    }
+
+    if translated < KEYS.len() {
+        log!(
+            "{} translates {} of {} strings, the rest stay in English",
+            path, translated, KEYS.len()
+        );
+    }
+    loaded
+}
+
+pub fn set_language (code: &str) {
+    translations().select(&PlaydateFiles, code)
+}
+
+// The code to use when the language setting is "auto"
+pub fn system_language () -> &'static str {
+    match System::get().get_language() {
+        Ok(PDLanguage::kPDLanguageJapanese) => "ja",
+        _ => "en"
+    }
+}
+
+// Codes of every language we have a file for, plus English
+pub fn languages () -> Vec<String> {
+    let mut codes = vec![String::from("en")];
+    if let Ok(files) = FileSystem::get().listfiles(LANG_DIR) {
+        for filename in files {
+            if filename.ends_with(".txt") {
+                codes.push(String::from(&filename[..filename.len() - 4]));
+            }
+        }
+    }
+    codes
+}
+
+// NOTE: crankstart can only measure text in a font we've loaded ourselves,
+//   and we draw in the system font, so widths are worked out from its
+//   average glyph width instead. It errs on the generous side.
+const AVERAGE_GLYPH_WIDTH: i32 = 9;
+
+fn fits (text: &str, width: i32) -> bool {
+    text.chars().count() as i32 * AVERAGE_GLYPH_WIDTH <= width
+}
+
+// Cuts text down to fit in width pixels, with "..." on the end if anything
+// had to go
+pub fn ellipsize (text: &str, width: i32) -> String {
+    if fits(text, width) { return String::from(text) }
+
+    let max_chars = (width / AVERAGE_GLYPH_WIDTH - 3).max(0) as usize;
+    let mut result: String = text.chars().take(max_chars).collect();
+    result.push_str("...");
+    result
+}
+
+// Breaks text into lines no wider than width pixels, at spaces where it can.
+// Existing line breaks are kept.
+pub fn wrap (text: &str, width: i32) -> String {
+    let mut result = String::new();
+
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 { result.push('\n') }
+
+        let mut current = String::new();
+        for word in line.split(' ') {
+            let candidate = if current.len() == 0 {
+                String::from(word)
+            } else {
+                format!("{} {}", current, word)
+            };
+
+            if fits(&candidate[..], width) || current.len() == 0 {
+                current = candidate;
+            } else {
+                result.push_str(&current[..]);
+                result.push('\n');
+                current = String::from(word);
+            }
+        }
+        result.push_str(&current[..]);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryFiles;
+
+    const GERMAN: &str = "\
+        # Deutsch\n\
+        quit_game=Spiel beenden\n\
+        not_this_games= (nicht von diesem Spiel)\n\
+        test_suite_progress=Test {} von {}:\\n{}\n\
+        no_such_key=Hallo\n\
+        just some words\n";
+
+    fn with_german () -> MemoryFiles {
+        let files = MemoryFiles::new();
+        files.put(&format!("{}/de.txt", LANG_DIR)[..], GERMAN.as_bytes());
+        files
+    }
+
+    #[test]
+    fn every_text_has_a_key_and_english () {
+        assert_eq!(KEYS.len(), ENGLISH.len());
+        for (i, key) in KEYS.iter().enumerate() {
+            assert!(!KEYS[..i].contains(key), "{} is there twice", key);
+        }
+    }
+
+    #[test]
+    fn reads_language_files () {
+        let loaded = parse_language(GERMAN, "de.txt");
+        assert_eq!(loaded[Text::QuitGame as usize], "Spiel beenden");
+        // Leading spaces are kept, and \n is a line break
+        assert_eq!(
+            loaded[Text::NotThisGames as usize], " (nicht von diesem Spiel)"
+        );
+        assert_eq!(
+            loaded[Text::TestSuiteProgress as usize], "Test {} von {}:\n{}"
+        );
+        // The rest stay in English
+        assert_eq!(loaded[Text::Options as usize], "options");
+    }
+
+    #[test]
+    fn switches_language () {
+        let files = with_german();
+        let mut translations = Translations::new();
+        assert_eq!(translations.get(Text::QuitGame), "quit game");
+        translations.select(&files, "de");
+        assert_eq!(translations.get(Text::QuitGame), "Spiel beenden");
+        translations.select(&files, "en");
+        assert_eq!(translations.get(Text::QuitGame), "quit game");
+        // No file is English too
+        translations.select(&files, "fr");
+        assert_eq!(translations.get(Text::QuitGame), "quit game");
+    }
+
+    #[test]
+    fn loads_each_language_once () {
+        let files = with_german();
+        let mut translations = Translations::new();
+        translations.select(&files, "de");
+        let first = translations.get(Text::QuitGame).as_ptr();
+        for code in ["en", "de", "fr", "de"].iter() {
+            translations.select(&files, code);
+        }
+        assert_eq!(translations.loaded.len(), 1);
+        assert_eq!(translations.get(Text::QuitGame).as_ptr(), first);
+    }
+
+    #[test]
+    fn fills_in_arguments () {
+        assert_eq!(trf(Text::QrProgress, &[&1, &3]), "Code 1 of 3");
+        // Missing ones are left out
+        assert_eq!(trf(Text::QrProgress, &[&1]), "Code 1 of ");
+    }
+}
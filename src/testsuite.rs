@@ -0,0 +1,267 @@
+use alloc::{format, string::String, vec, vec::Vec};
+use anyhow::Error;
+use crankstart::{
+    file::FileSystem,
+    graphics::{Graphics, LCDColor, LCDSolidColor},
+    system::System
+};
+use crankstart_sys::{LCDBitmapDrawMode, PDButtons, LCD_COLUMNS};
+use euclid::point2;
+use gbrs_core::cpu::Cpu;
+
+use crate::blit;
+use crate::checksum;
+use crate::input;
+use crate::storage;
+use crate::strings::{self, tr, trf, Text};
+
+// If there's a testsuite folder in the data folder, Playboy boots every ROM
+// in it in turn instead of showing the picker, runs each for a fixed amount
+// of emulated time, and writes how they did to testsuite/report.txt. That
+// way accuracy suites (Blargg, Mooneye...) can be left running on hardware.
+//
+// A ROM passes if its final frame matches the hash for it in
+// testsuite/expected.txt, or, failing an entry there, if it finished with
+// Mooneye's pass signature in the registers. Report lines start with
+// "<hash> <rom>", the same as expected.txt, so a known-good report can be
+// copied straight over.
+pub const SUITE_DIR: &str = "testsuite";
+
+const EMULATED_SECONDS: usize = 30;
+// How long each update spends emulating before drawing progress
+const TIME_SLICE_MS: usize = 25;
+
+// Serial registers. Test ROMs print their results down the link cable too.
+const SERIAL_DATA: u16 = 0xFF01;
+const SERIAL_CONTROL: u16 = 0xFF02;
+
+pub fn present () -> bool {
+    FileSystem::get().listfiles(SUITE_DIR).is_ok()
+}
+
+fn suite_path (filename: &str) -> String {
+    format!("{}/{}", SUITE_DIR, filename)
+}
+
+// "<hash> <rom>" lines
+fn load_expectations () -> Vec<(u32, String)> {
+    let bytes = match storage::read_file(&suite_path("expected.txt")[..]) {
+        Ok(bytes) => bytes,
+        Err(_) => return vec![]
+    };
+    let text = core::str::from_utf8(&bytes[..]).unwrap_or("");
+
+    text.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let hash = u32::from_str_radix(fields.next()?, 16).ok()?;
+            Some((hash, String::from(fields.next()?)))
+        })
+        .collect()
+}
+
+fn frame_hash (cpu: &Cpu) -> u32 {
+    let shades: Vec<u8> = cpu.gpu.finished_frame
+        .iter()
+        .map(blit::shade_value)
+        .collect();
+    checksum::crc32(&shades[..])
+}
+
+// Mooneye's tests load the Fibonacci numbers into B-L when they pass
+fn mooneye_passed (cpu: &Cpu) -> bool {
+    let registers = &cpu.registers;
+    registers.b == 3 && registers.c == 5 && registers.d == 8 &&
+        registers.e == 13 && registers.h == 21 && registers.l == 34
+}
+
+pub struct TestSuite {
+    roms: Vec<String>,
+    expectations: Vec<(u32, String)>,
+    // Index into roms of the one running now
+    index: usize,
+    cpu: Option<Cpu>,
+    frames_run: usize,
+    frame_rate: usize,
+    serial: String,
+    report: String,
+    passed: usize,
+    failed: usize,
+    // Neither passed nor failed, for want of an expectation
+    unknown: usize,
+    // Set once every ROM has run (or they pressed B)
+    finished: bool
+}
+
+impl TestSuite {
+    pub fn new (frame_rate: usize) -> Result<Self, Error> {
+        let mut roms: Vec<String> = FileSystem::get()
+            .listfiles(SUITE_DIR)?
+            .into_iter()
+            .filter(|filename| filename.ends_with(".gb"))
+            .collect();
+        roms.sort();
+        log!("Running {} test ROMs", roms.len());
+
+        let suite = Self {
+            roms,
+            expectations: load_expectations(),
+            index: 0,
+            cpu: None,
+            frames_run: 0,
+            frame_rate,
+            serial: String::new(),
+            report: String::new(),
+            passed: 0,
+            failed: 0,
+            unknown: 0,
+            finished: false
+        };
+        // Like the other screens, this draws straight away
+        suite.draw()?;
+        Ok(suite)
+    }
+
+    fn boot_current (&mut self) -> Result<(), Error> {
+        let path = &suite_path(&self.roms[self.index][..])[..];
+        let mut cpu = Cpu::from_rom_bytes(storage::read_file(path)?);
+        cpu.frame_rate = self.frame_rate;
+        self.cpu = Some(cpu);
+        self.frames_run = 0;
+        self.serial.clear();
+        Ok(())
+    }
+
+    // We only look once per frame, so a ROM that prints faster than that
+    // loses characters. The final frame is what counts; this is a hint.
+    fn capture_serial (cpu: &mut Cpu, serial: &mut String) {
+        if cpu.mem.read(SERIAL_CONTROL) == 0x81 {
+            let byte = cpu.mem.read(SERIAL_DATA);
+            if byte == b'\n' || (0x20..0x7F).contains(&byte) {
+                serial.push(byte as char);
+            }
+            // Finish the transfer, as if nothing was on the other end
+            cpu.mem.write(SERIAL_CONTROL, 0x01);
+        }
+    }
+
+    fn judge_current (&mut self) {
+        let cpu = match &self.cpu {
+            Some(cpu) => cpu,
+            None => return
+        };
+        let rom = &self.roms[self.index];
+        let hash = frame_hash(cpu);
+
+        let expected = self.expectations
+            .iter()
+            .find(|(_, name)| name == rom)
+            .map(|(hash, _)| *hash);
+        let verdict = match expected {
+            Some(expected) if expected == hash => "PASS",
+            Some(_) => "FAIL",
+            None if mooneye_passed(cpu) => "PASS",
+            None => "?"
+        };
+        match verdict {
+            "PASS" => self.passed += 1,
+            "FAIL" => self.failed += 1,
+            _ => self.unknown += 1
+        }
+
+        let serial = self.serial.replace('\n', " ");
+        self.report.push_str(&format!(
+            "{:08x} {} {} {}\n", hash, rom, verdict, serial.trim()
+        ));
+        log!("{}: {}", rom, verdict);
+    }
+
+    fn finish (&mut self) {
+        self.finished = true;
+        self.cpu = None;
+
+        let mut report = format!(
+            "{} passed, {} failed, {} unknown\n\n",
+            self.passed, self.failed, self.unknown
+        );
+        report.push_str(&self.report[..]);
+        let path = &suite_path("report.txt")[..];
+        if let Err(e) = storage::write_file(path, report.as_bytes()) {
+            log!("Couldn't write the test report: {}", e);
+        }
+    }
+
+    fn draw (&self) -> Result<(), Error> {
+        let graphics = Graphics::get();
+        graphics.clear(LCDColor::Solid(LCDSolidColor::kColorWhite))?;
+        graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeCopy)?;
+
+        let text = if self.finished {
+            trf(Text::TestSuiteDone, &[
+                &self.passed, &self.failed, &self.unknown
+            ])
+        } else {
+            let rom: &str = self.roms
+                .get(self.index)
+                .map_or("", |rom| &rom[..]);
+            trf(Text::TestSuiteProgress, &[
+                &(self.index + 1), &self.roms.len(), &rom
+            ])
+        };
+        let text = strings::wrap(&text[..], LCD_COLUMNS as i32 - 40);
+
+        graphics.draw_text(tr(Text::TestSuiteTitle), point2(20, 20))?;
+        graphics.draw_text(&text[..], point2(20, 60))?;
+        graphics.draw_text(tr(Text::TestSuiteStop), point2(20, 200))?;
+        Ok(())
+    }
+
+    // Returns true once the suite is over and they've pressed B to leave
+    pub fn update (&mut self) -> Result<bool, Error> {
+        let system = System::get();
+        let (_, btns_down, _) = input::button_state()?;
+        let b_pressed =
+            (btns_down & PDButtons::kButtonB) == PDButtons::kButtonB;
+
+        if self.finished { return Ok(b_pressed) }
+        if b_pressed {
+            log!("Test suite stopped early");
+            self.finish();
+            return self.draw().map(|_| false)
+        }
+
+        if self.index >= self.roms.len() {
+            self.finish();
+            return self.draw().map(|_| false)
+        }
+        if self.cpu.is_none() {
+            if let Err(e) = self.boot_current() {
+                log!("Couldn't boot {}: {}", self.roms[self.index], e);
+                self.failed += 1;
+                self.index += 1;
+                return Ok(false)
+            }
+            self.draw()?;
+        }
+
+        let frames_wanted = EMULATED_SECONDS * self.frame_rate;
+        let started = system.get_current_time_milliseconds()?;
+        while self.frames_run < frames_wanted {
+            if let Some(cpu) = self.cpu.as_mut() {
+                cpu.step_one_frame();
+                Self::capture_serial(cpu, &mut self.serial);
+            }
+            self.frames_run += 1;
+
+            let now = system.get_current_time_milliseconds()?;
+            if now.wrapping_sub(started) >= TIME_SLICE_MS { break }
+        }
+
+        if self.frames_run >= frames_wanted {
+            self.judge_current();
+            self.cpu = None;
+            self.index += 1;
+        }
+        Ok(false)
+    }
+}
@@ -0,0 +1,135 @@
+use alloc::string::String;
+use anyhow::Error;
+use crankstart::graphics::{Graphics, LCDColor, LCDSolidColor};
+use crankstart_sys::{LCDBitmapDrawMode, LCD_COLUMNS, LCD_ROWS};
+use euclid::{point2, rect};
+
+use crate::strings;
+
+const TOAST_HEIGHT: i32 = 24;
+const TOAST_TOP: i32 = LCD_ROWS as i32 - TOAST_HEIGHT;
+const BANNER_HEIGHT: i32 = 24;
+
+// NOTE: The blit only covers the middle of the screen, so when a toast or
+//   banner goes away, whatever it left in the borders has to be put back by
+//   the caller. That's what take_expired / take_hidden are for.
+
+// Both bars are drawn every frame they're up, so they keep the message
+// they were last given, cut down to fit, and only work it out again when
+// it changes. Showing the same toast every frame costs nothing.
+struct Fitted {
+    message: String,
+    fitted: String
+}
+
+impl Fitted {
+    fn new () -> Self {
+        Self { message: String::new(), fitted: String::new() }
+    }
+
+    fn fit (&mut self, message: &str) -> &str {
+        if self.message != message {
+            self.message.clear();
+            self.message.push_str(message);
+            self.fitted = strings::ellipsize(message, LCD_COLUMNS as i32 - 12);
+        }
+        &self.fitted[..]
+    }
+}
+
+// A short message drawn in a bar along the bottom of the screen, over the
+// top of the game, for a few frames.
+pub struct Toast {
+    text: Fitted,
+    showing: bool,
+    frames_left: usize
+}
+
+impl Toast {
+    pub fn new () -> Self {
+        Self {
+            text: Fitted::new(),
+            showing: false,
+            frames_left: 0
+        }
+    }
+
+    pub fn show (&mut self, message: &str, frames: usize) {
+        self.text.fit(message);
+        self.showing = true;
+        self.frames_left = frames;
+    }
+
+    // Returns true once, on the frame the toast runs out
+    pub fn take_expired (&mut self) -> bool {
+        if self.showing && self.frames_left == 0 {
+            self.showing = false;
+            true
+        } else {
+            false
+        }
+    }
+
+    // This needs calling every frame *after* the Gameboy screen has been
+    // drawn, since the blit will happily paint over us.
+    pub fn draw (&mut self) -> Result<(), Error> {
+        self.redraw()?;
+        self.frames_left = self.frames_left.saturating_sub(1);
+        Ok(())
+    }
+
+    // The same without counting down, for refreshes in between frames
+    pub fn redraw (&self) -> Result<(), Error> {
+        if !self.showing { return Ok(()) }
+
+        let graphics = Graphics::get();
+        let bar = rect(0, TOAST_TOP, LCD_COLUMNS as i32, TOAST_HEIGHT);
+
+        graphics.fill_rect(bar, LCDColor::Solid(LCDSolidColor::kColorBlack))?;
+        graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeInverted)?;
+        graphics.draw_text(&self.text.fitted[..], point2(6, TOAST_TOP + 4))?;
+        Ok(())
+    }
+}
+
+// Unlike a toast, the banner sticks around for as long as the condition
+// behind it holds. It lives along the top of the screen so the two can be
+// shown at the same time.
+pub struct Banner {
+    text: Fitted,
+    visible: bool
+}
+
+impl Banner {
+    pub fn new () -> Self {
+        Self { text: Fitted::new(), visible: false }
+    }
+
+    // Returns true once, on the frame the banner stops being wanted
+    pub fn take_hidden (&mut self, wanted: bool) -> bool {
+        if self.visible && !wanted {
+            self.visible = false;
+            true
+        } else {
+            false
+        }
+    }
+
+    // Like Toast::draw, call this after the blit
+    pub fn draw (&mut self, message: Option<&str>) -> Result<(), Error> {
+        let message = match message {
+            Some(message) => message,
+            None => return Ok(())
+        };
+
+        let graphics = Graphics::get();
+        let bar = rect(0, 0, LCD_COLUMNS as i32, BANNER_HEIGHT);
+
+        graphics.fill_rect(bar, LCDColor::Solid(LCDSolidColor::kColorBlack))?;
+        graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeInverted)?;
+        graphics.draw_text(self.text.fit(message), point2(6, 4))?;
+        self.visible = true;
+
+        Ok(())
+    }
+}
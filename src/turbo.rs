@@ -0,0 +1,116 @@
+use alloc::{format, string::String};
+use anyhow::Error;
+use crankstart::graphics::{Graphics, LCDColor, LCDSolidColor};
+use crankstart_sys::LCDBitmapDrawMode;
+use euclid::{point2, rect};
+use gbrs_core::cpu::Cpu;
+
+use crate::layout::GAME_RIGHT;
+
+// Autofire for shmups and menus that want a lot of presses. With turbo_a or
+// turbo_b on, holding that button presses and lets go of it over and over,
+// one press every turbo_period emulated frames, rather than holding it.
+// It's counted in emulated frames from when the button went down, so the
+// first press is straight away and fast forward presses just as often per
+// frame the game sees. Each press is a new edge for the joypad interrupt,
+// since that's raised after this has set the joypad.
+
+// What the options menu steps turbo_period through. 2 is a press every
+// other frame, the fastest there is with a frame let go in between.
+const PERIODS: &[usize] = &[2, 3, 4, 6];
+
+pub fn next_period (period: usize) -> usize {
+    PERIODS
+        .iter()
+        .copied()
+        .find(|next| *next > period)
+        .unwrap_or(PERIODS[0])
+}
+
+// Presses a second at frame_rate, for the options menu: "7.5/s"
+pub fn rate_label (period: usize, frame_rate: usize) -> String {
+    let tenths = frame_rate * 10 / period.max(2);
+    if tenths % 10 == 0 {
+        format!("{}/s", tenths / 10)
+    } else {
+        format!("{}.{}/s", tenths / 10, tenths % 10)
+    }
+}
+
+// "TB" and "TA" in the right border, in the same order as the Playdate's
+// buttons, under the Start/Select selector
+const INDICATOR_LEFT: i32 = GAME_RIGHT as i32 + 6;
+const INDICATOR_TOP: i32 = 176;
+const INDICATOR_WIDTH: i32 = 28;
+
+pub struct Turbo {
+    // Emulated frames each button's been held for
+    a_frames: usize,
+    b_frames: usize,
+    // Whether the markers are on screen. They're only drawn again when
+    // something might have drawn over them.
+    drawn: bool
+}
+
+// Whether a button held for frames is pressed on this one
+fn pulse (frames: usize, period: usize) -> bool {
+    frames % period.max(2) < period.max(2) / 2
+}
+
+impl Turbo {
+    pub fn new () -> Self {
+        Self { a_frames: 0, b_frames: 0, drawn: false }
+    }
+
+    // Call once per emulated frame, after the joypad's been set from the
+    // buttons, with whether A and B are held and have turbo on
+    pub fn update (
+        &mut self,
+        a_held: bool,
+        b_held: bool,
+        period: usize,
+        cpu: &mut Cpu
+    ) {
+        let joypad = &mut cpu.mem.joypad;
+        if a_held {
+            joypad.a_pressed = pulse(self.a_frames, period);
+            self.a_frames += 1;
+        } else {
+            self.a_frames = 0;
+        }
+        if b_held {
+            joypad.b_pressed = pulse(self.b_frames, period);
+            self.b_frames += 1;
+        } else {
+            self.b_frames = 0;
+        }
+    }
+
+    // Call after the blit, with forced set if it covered the whole screen.
+    // Nothing's drawn for a button without turbo, and turning one off is
+    // done from the options menu, which redraws the border on the way out.
+    pub fn draw_indicator (
+        &mut self,
+        turbo_a: bool,
+        turbo_b: bool,
+        forced: bool
+    ) -> Result<(), Error> {
+        if !turbo_a && !turbo_b { return Ok(()) }
+        if self.drawn && !forced { return Ok(()) }
+        self.drawn = true;
+
+        let graphics = Graphics::get();
+        let markers = [(turbo_b, "TB"), (turbo_a, "TA")];
+        for (index, (on, label)) in markers.iter().enumerate() {
+            if !*on { continue }
+            let left = INDICATOR_LEFT + index as i32 * (INDICATOR_WIDTH + 4);
+            graphics.fill_rect(
+                rect(left, INDICATOR_TOP, INDICATOR_WIDTH, 20),
+                LCDColor::Solid(LCDSolidColor::kColorWhite)
+            )?;
+            graphics.set_draw_mode(LCDBitmapDrawMode::kDrawModeCopy)?;
+            graphics.draw_text(label, point2(left + 2, INDICATOR_TOP + 1))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,93 @@
+use alloc::{format, string::String, vec::Vec};
+use anyhow::Error;
+use gbrs_core::cpu::Cpu;
+
+use crate::savestate::{self, RomId};
+use crate::storage;
+
+// Every state load goes through here, and the state it replaces is kept so
+// that loading over an hour of unsaved progress can be taken back. Only the
+// most recent load can be undone.
+
+pub fn undo_state_path (game_name: &str) -> String {
+    let filename = format!("{}.undo.state", game_name);
+    storage::in_dir(storage::STATES_DIR, &filename[..])
+}
+
+pub struct UndoSlot {
+    state: Option<Vec<u8>>,
+    // How many times a load or an undo has replaced the running game, for
+    // things that need to notice (like the split timer)
+    loads: usize
+}
+
+impl UndoSlot {
+    pub fn new () -> Self {
+        Self {
+            state: None,
+            loads: 0
+        }
+    }
+
+    // Like the quick slot, this only survives between sessions when it's
+    // been flushed out
+    pub fn load_from_disk (game_name: &str) -> Self {
+        Self {
+            state: storage::read_file(&undo_state_path(game_name)[..]).ok(),
+            loads: 0
+        }
+    }
+
+    pub fn flush_to_disk (&self, game_name: &str) -> Result<(), Error> {
+        match &self.state {
+            Some(state) => storage::write_file(
+                &undo_state_path(game_name)[..], &state[..]
+            ),
+            None => Ok(())
+        }
+    }
+
+    // Loads state into cpu, keeping what was there before for undo
+    pub fn load_over (
+        &mut self,
+        cpu: &mut Cpu,
+        state: &[u8],
+        rom_id: RomId,
+        force: bool
+    ) -> Result<(), Error> {
+        let before = savestate::capture(cpu, rom_id)?;
+
+        if let Err(e) = savestate::restore(cpu, state, rom_id, force) {
+            // We can't be sure gbrs didn't get halfway through, so put
+            // everything back the way it was
+            if let Err(e) = savestate::restore(cpu, &before[..], rom_id, true) {
+                log!("Couldn't put the game back after a failed load: {}", e);
+            }
+            return Err(e)
+        }
+
+        self.state = Some(before);
+        self.loads += 1;
+        Ok(())
+    }
+
+    pub fn loads (&self) -> usize {
+        self.loads
+    }
+
+    // Returns false if there's no load to undo
+    pub fn undo (
+        &mut self,
+        cpu: &mut Cpu,
+        rom_id: RomId
+    ) -> Result<bool, Error> {
+        match &self.state {
+            Some(state) => {
+                savestate::restore(cpu, &state[..], rom_id, false)?;
+                self.loads += 1;
+                Ok(true)
+            },
+            None => Ok(false)
+        }
+    }
+}
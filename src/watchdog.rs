@@ -0,0 +1,76 @@
+// Notices when the game has locked up, so there's a chance to grab a save
+// state before force quitting loses everything since the last battery save.
+//
+// Lots of games sit on a still screen spinning in a little loop while they
+// wait for a button, so neither condition means much on its own. It takes
+// both - the PC staying inside a few bytes and the frame not changing at all
+// - for STALL_SECONDS of emulated time in a row. A game that's waiting for a
+// button still reacts when one's pressed, which starts the count again.
+
+// Bytes the PC can wander over and still count as the same loop
+const PC_WINDOW: u16 = 16;
+const STALL_SECONDS: usize = 15;
+
+pub struct Watchdog {
+    // Lowest and highest PC seen since the frame last changed
+    window: Option<(u16, u16)>,
+    still_frames: usize,
+    // Goes up each time they'd rather keep waiting
+    limit_seconds: usize
+}
+
+impl Watchdog {
+    pub fn new () -> Self {
+        Self {
+            window: None,
+            still_frames: 0,
+            limit_seconds: STALL_SECONDS
+        }
+    }
+
+    fn restart (&mut self, pc: u16) {
+        self.window = Some((pc, pc));
+        self.still_frames = 0;
+    }
+
+    // Call once per update, with how many frames were just emulated and
+    // whether the picture changed. Returns true when the game looks frozen.
+    pub fn update (
+        &mut self,
+        pc: u16,
+        frames_run: usize,
+        frame_changed: bool,
+        frame_rate: usize
+    ) -> bool {
+        // Paused, or a boot macro is busy
+        if frames_run == 0 { return false }
+
+        let (low, high) = match self.window {
+            Some((low, high)) => (low.min(pc), high.max(pc)),
+            None => (pc, pc)
+        };
+        if frame_changed || high - low > PC_WINDOW {
+            self.restart(pc);
+            return false
+        }
+
+        self.window = Some((low, high));
+        self.still_frames += frames_run;
+        if self.still_frames < self.limit_seconds * frame_rate {
+            return false
+        }
+
+        log!(
+            "Game looks frozen: PC in {:04x}-{:04x} and the same frame for \
+            {} seconds",
+            low, high, self.limit_seconds
+        );
+        self.restart(pc);
+        true
+    }
+
+    // Twice as long before asking again
+    pub fn snooze (&mut self) {
+        self.limit_seconds *= 2;
+    }
+}